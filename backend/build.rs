@@ -0,0 +1,4 @@
+// Migrations are embedded with `sqlx::migrate!`, so rebuild whenever one is added or edited.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
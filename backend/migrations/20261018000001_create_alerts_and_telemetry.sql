@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS alerts (
+    id SERIAL PRIMARY KEY,
+    event_id INTEGER NOT NULL REFERENCES events (id),
+    status TEXT NOT NULL,
+    raised_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS alerts_status_idx ON alerts (status);
+
+CREATE TABLE IF NOT EXISTS telemetry (
+    id BIGSERIAL PRIMARY KEY,
+    device_id TEXT NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL,
+    x DOUBLE PRECISION NOT NULL,
+    y DOUBLE PRECISION NOT NULL,
+    z DOUBLE PRECISION NOT NULL,
+    g_force DOUBLE PRECISION NOT NULL,
+    wifi INTEGER NOT NULL DEFAULT 0,
+    temp DOUBLE PRECISION NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS telemetry_device_time_idx ON telemetry (device_id, recorded_at);
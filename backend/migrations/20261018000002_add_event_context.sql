@@ -0,0 +1,8 @@
+ALTER TABLE events ADD COLUMN IF NOT EXISTS device_id TEXT;
+ALTER TABLE events ADD COLUMN IF NOT EXISTS patient_id TEXT;
+ALTER TABLE events ADD COLUMN IF NOT EXISTS ward TEXT;
+
+CREATE INDEX IF NOT EXISTS events_detected_at_idx ON events (detected_at, id);
+CREATE INDEX IF NOT EXISTS events_device_idx ON events (device_id, detected_at);
+CREATE INDEX IF NOT EXISTS events_patient_idx ON events (patient_id, detected_at);
+CREATE INDEX IF NOT EXISTS events_ward_idx ON events (ward, detected_at);
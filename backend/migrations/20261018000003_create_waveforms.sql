@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS waveforms (
+    id SERIAL PRIMARY KEY,
+    event_id INTEGER NOT NULL UNIQUE REFERENCES events (id),
+    device_id TEXT NOT NULL,
+    impact_at TIMESTAMPTZ NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    ended_at TIMESTAMPTZ NOT NULL,
+    points TEXT NOT NULL -- JSON array of WaveformPoint
+);
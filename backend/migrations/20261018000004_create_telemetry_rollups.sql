@@ -0,0 +1,21 @@
+-- Pre-aggregated telemetry per device and bucket, maintained on every telemetry write.
+-- Sums (not means) are stored so buckets can be merged into coarser resolutions.
+CREATE TABLE IF NOT EXISTS telemetry_rollups (
+    device_id TEXT NOT NULL,
+    resolution_seconds BIGINT NOT NULL,
+    bucket_start TIMESTAMPTZ NOT NULL,
+    sample_count BIGINT NOT NULL,
+    g_min DOUBLE PRECISION NOT NULL,
+    g_max DOUBLE PRECISION NOT NULL,
+    g_sum DOUBLE PRECISION NOT NULL,
+    x_min DOUBLE PRECISION NOT NULL,
+    x_max DOUBLE PRECISION NOT NULL,
+    x_sum DOUBLE PRECISION NOT NULL,
+    y_min DOUBLE PRECISION NOT NULL,
+    y_max DOUBLE PRECISION NOT NULL,
+    y_sum DOUBLE PRECISION NOT NULL,
+    z_min DOUBLE PRECISION NOT NULL,
+    z_max DOUBLE PRECISION NOT NULL,
+    z_sum DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (device_id, resolution_seconds, bucket_start)
+);
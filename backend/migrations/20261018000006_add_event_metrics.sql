@@ -0,0 +1,4 @@
+-- Measurements taken while a detection was validated (NULL for nurse actions)
+ALTER TABLE events ADD COLUMN IF NOT EXISTS stillness_variance DOUBLE PRECISION;
+ALTER TABLE events ADD COLUMN IF NOT EXISTS validation_ms BIGINT;
+ALTER TABLE events ADD COLUMN IF NOT EXISTS orientation_change_deg DOUBLE PRECISION;
@@ -0,0 +1,16 @@
+-- FHIR rest-hook subscriptions (R4, subscriptions backport)
+CREATE TABLE IF NOT EXISTS subscriptions (
+    id SERIAL PRIMARY KEY,
+    topic TEXT NOT NULL, -- Canonical URL of one of the server's topics
+    reason TEXT NOT NULL,
+    endpoint TEXT NOT NULL, -- Where notifications are POSTed
+    headers TEXT, -- JSON array of "Name: value" strings sent with every notification
+    payload TEXT NOT NULL DEFAULT 'id-only', -- empty | id-only | full-resource
+    patient_id TEXT, -- Only events about this patient (NULL = every patient)
+    heartbeat_seconds INTEGER, -- NULL = no heartbeats
+    status TEXT NOT NULL DEFAULT 'requested', -- requested | active | error | off
+    events_since_start BIGINT NOT NULL DEFAULT 0,
+    error TEXT, -- Why the last delivery failed
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_notified_at TIMESTAMPTZ
+);
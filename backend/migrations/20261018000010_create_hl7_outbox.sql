@@ -0,0 +1,17 @@
+-- HL7 v2 messages for the interface engine, kept until acknowledged (and afterwards as a log)
+CREATE TABLE IF NOT EXISTS hl7_outbox (
+    id SERIAL PRIMARY KEY,
+    event_id INTEGER NOT NULL, -- The event reported (no foreign key: events may be purged first)
+    message_type TEXT NOT NULL, -- MSH-9, e.g. ORU^R01
+    control_id TEXT NOT NULL UNIQUE, -- MSH-10, echoed in the ACK
+    message TEXT NOT NULL, -- Segments separated by carriage returns
+    status TEXT NOT NULL DEFAULT 'pending', -- pending | sent | failed
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL,
+    last_error TEXT, -- Why the last attempt failed (NACK text or connection error)
+    ack_code TEXT, -- MSA-1 of the last ACK received
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    sent_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_hl7_outbox_status ON hl7_outbox (status, id);
@@ -0,0 +1,5 @@
+-- Where the patient is and the current stay, kept up to date by the HL7 v2 ADT feed
+ALTER TABLE patients ADD COLUMN IF NOT EXISTS room TEXT;
+ALTER TABLE patients ADD COLUMN IF NOT EXISTS bed TEXT;
+ALTER TABLE patients ADD COLUMN IF NOT EXISTS admitted_at TIMESTAMPTZ;
+ALTER TABLE patients ADD COLUMN IF NOT EXISTS discharged_at TIMESTAMPTZ; -- Set at discharge, cleared on the next admission
@@ -0,0 +1,18 @@
+-- Post-fall assessments: one per confirmed alert, answered against the configured form
+CREATE TABLE IF NOT EXISTS assessments (
+    id SERIAL PRIMARY KEY,
+    alert_id INTEGER NOT NULL UNIQUE REFERENCES alerts (id),
+    event_id INTEGER NOT NULL, -- Detection that raised the alert
+    patient_id TEXT,
+    form_id TEXT NOT NULL, -- Form (FHIR Questionnaire) answered, and its version
+    form_version TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'in-progress', -- in-progress | completed | amended
+    answers TEXT NOT NULL DEFAULT '{}', -- JSON object keyed by item link_id
+    author TEXT, -- Who last saved the answers
+    due_at TIMESTAMPTZ NOT NULL, -- Overdue while still in progress after this
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    completed_at TIMESTAMPTZ -- First completion (kept when amended)
+);
+
+CREATE INDEX IF NOT EXISTS idx_assessments_status_due ON assessments (status, due_at);
@@ -0,0 +1,35 @@
+-- Morse Fall Scale entries made by clinicians (item points as scored on the scale)
+CREATE TABLE IF NOT EXISTS morse_scores (
+    id SERIAL PRIMARY KEY,
+    patient_id TEXT NOT NULL,
+    history_of_falling INTEGER NOT NULL, -- 0 | 25
+    secondary_diagnosis INTEGER NOT NULL, -- 0 | 15
+    ambulatory_aid INTEGER NOT NULL, -- 0 | 15 | 30
+    iv_therapy INTEGER NOT NULL, -- 0 | 20
+    gait INTEGER NOT NULL, -- 0 | 10 | 20
+    mental_status INTEGER NOT NULL, -- 0 | 15
+    total INTEGER NOT NULL, -- 0-125
+    assessed_by TEXT NOT NULL,
+    assessed_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_morse_scores_patient ON morse_scores (patient_id, assessed_at);
+
+-- Fall risk scores: every recalculation is kept, the newest row is the current score
+CREATE TABLE IF NOT EXISTS risk_scores (
+    id SERIAL PRIMARY KEY,
+    patient_id TEXT NOT NULL,
+    calculated_at TIMESTAMPTZ NOT NULL,
+    probability DOUBLE PRECISION NOT NULL, -- Of a fall, 0-1
+    rating TEXT NOT NULL, -- low | moderate | high
+    near_misses INTEGER NOT NULL, -- Factors, over the last window_days
+    falls INTEGER NOT NULL, -- Detected falls not dismissed as false alarms
+    false_alarms INTEGER NOT NULL,
+    night_share DOUBLE PRECISION NOT NULL, -- Share of those detections at night (ward time)
+    morse_total INTEGER, -- Latest Morse Fall Scale entry in the window, if any
+    window_days INTEGER NOT NULL,
+    reason TEXT NOT NULL, -- event | morse | request
+    event_id INTEGER -- Event that triggered the recalculation
+);
+
+CREATE INDEX IF NOT EXISTS idx_risk_scores_patient ON risk_scores (patient_id, id);
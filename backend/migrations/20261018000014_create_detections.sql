@@ -0,0 +1,18 @@
+-- Every outcome of the fall detector with the features it was decided on, including the
+-- impacts that never became an event (kept for near-miss trends and re-labelling missed falls)
+CREATE TABLE IF NOT EXISTS detections (
+    id SERIAL PRIMARY KEY,
+    detected_at TIMESTAMPTZ NOT NULL,
+    outcome TEXT NOT NULL, -- validating | critical | near-miss
+    device_id TEXT,
+    patient_id TEXT,
+    ward TEXT,
+    peak_g DOUBLE PRECISION NOT NULL, -- Impact G for validating, highest G in the window otherwise
+    stillness_variance DOUBLE PRECISION, -- Features of the validation window (unset while validating)
+    validation_ms BIGINT, -- Window length: impact to verdict
+    orientation_change_deg DOUBLE PRECISION,
+    event_id INTEGER -- Event stored for a critical or near-miss outcome
+);
+
+CREATE INDEX IF NOT EXISTS idx_detections_detected_at ON detections (detected_at);
+CREATE INDEX IF NOT EXISTS idx_detections_device ON detections (device_id, detected_at);
@@ -0,0 +1,12 @@
+-- Nurse decisions on alerts kept as ground-truth labels for the detection that raised them
+-- (its waveform is stored under the same event id)
+CREATE TABLE IF NOT EXISTS labels (
+    id SERIAL PRIMARY KEY,
+    event_id INTEGER NOT NULL UNIQUE, -- The labelled detection
+    alert_id INTEGER NOT NULL,
+    label TEXT NOT NULL, -- fall | no-fall
+    command TEXT NOT NULL, -- CONFIRM_FALL | CANCEL_ALERT
+    labelled_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_labels_labelled_at ON labels (labelled_at);
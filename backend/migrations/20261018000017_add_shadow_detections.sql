@@ -0,0 +1,6 @@
+-- Outcomes of shadow-mode candidate detectors are logged next to production's: which detector
+-- profile decided, and whether it was a candidate (never alerts)
+ALTER TABLE detections ADD COLUMN IF NOT EXISTS detector TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE detections ADD COLUMN IF NOT EXISTS shadow BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_detections_shadow ON detections (shadow, detector, detected_at);
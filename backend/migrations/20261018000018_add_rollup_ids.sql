@@ -0,0 +1,4 @@
+-- Rollups get a row id so retention can archive and purge them like the other data classes
+ALTER TABLE telemetry_rollups ADD COLUMN IF NOT EXISTS id BIGSERIAL;
+
+CREATE INDEX IF NOT EXISTS idx_telemetry_rollups_bucket_start ON telemetry_rollups (bucket_start);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    detected_at TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    g_force_value REAL NOT NULL,
+    is_false_alarm BOOLEAN NOT NULL DEFAULT FALSE
+);
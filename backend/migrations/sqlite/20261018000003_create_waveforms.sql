@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS waveforms (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_id INTEGER NOT NULL UNIQUE REFERENCES events (id),
+    device_id TEXT NOT NULL,
+    impact_at TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    ended_at TEXT NOT NULL,
+    points TEXT NOT NULL -- JSON array of WaveformPoint
+);
@@ -0,0 +1,21 @@
+-- Pre-aggregated telemetry per device and bucket, maintained on every telemetry write.
+-- Sums (not means) are stored so buckets can be merged into coarser resolutions.
+CREATE TABLE IF NOT EXISTS telemetry_rollups (
+    device_id TEXT NOT NULL,
+    resolution_seconds INTEGER NOT NULL,
+    bucket_start TEXT NOT NULL,
+    sample_count INTEGER NOT NULL,
+    g_min REAL NOT NULL,
+    g_max REAL NOT NULL,
+    g_sum REAL NOT NULL,
+    x_min REAL NOT NULL,
+    x_max REAL NOT NULL,
+    x_sum REAL NOT NULL,
+    y_min REAL NOT NULL,
+    y_max REAL NOT NULL,
+    y_sum REAL NOT NULL,
+    z_min REAL NOT NULL,
+    z_max REAL NOT NULL,
+    z_sum REAL NOT NULL,
+    PRIMARY KEY (device_id, resolution_seconds, bucket_start)
+);
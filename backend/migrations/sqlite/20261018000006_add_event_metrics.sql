@@ -0,0 +1,4 @@
+-- Measurements taken while a detection was validated (NULL for nurse actions)
+ALTER TABLE events ADD COLUMN stillness_variance REAL;
+ALTER TABLE events ADD COLUMN validation_ms INTEGER;
+ALTER TABLE events ADD COLUMN orientation_change_deg REAL;
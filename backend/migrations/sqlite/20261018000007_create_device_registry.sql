@@ -0,0 +1,27 @@
+-- Patients known to the ward (FHIR Patient)
+CREATE TABLE IF NOT EXISTS patients (
+    id TEXT PRIMARY KEY,
+    family_name TEXT,
+    given_name TEXT,
+    birth_date TEXT, -- YYYY-MM-DD
+    gender TEXT, -- male | female | other | unknown
+    ward TEXT,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Sensors, who wears them and their last reported status (FHIR Device / DeviceMetric)
+CREATE TABLE IF NOT EXISTS devices (
+    id TEXT PRIMARY KEY,
+    model TEXT,
+    patient_id TEXT,
+    ward TEXT,
+    calibration_state TEXT NOT NULL DEFAULT 'unspecified', -- not-calibrated | calibration-required | calibrated | unspecified
+    calibrated_at TEXT,
+    battery_level REAL, -- %
+    wifi_signal INTEGER,
+    temperature REAL, -- °C at the sensor
+    last_seen_at TEXT,
+    registered_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS devices_patient_idx ON devices (patient_id);
@@ -0,0 +1,3 @@
+-- Set when a nurse cancels the alert of a detection as a false alarm
+-- (its FHIR Observation becomes entered-in-error)
+ALTER TABLE events ADD COLUMN dismissed_at TEXT;
@@ -0,0 +1,5 @@
+-- Where the patient is and the current stay, kept up to date by the HL7 v2 ADT feed
+ALTER TABLE patients ADD COLUMN room TEXT;
+ALTER TABLE patients ADD COLUMN bed TEXT;
+ALTER TABLE patients ADD COLUMN admitted_at TEXT;
+ALTER TABLE patients ADD COLUMN discharged_at TEXT; -- Set at discharge, cleared on the next admission
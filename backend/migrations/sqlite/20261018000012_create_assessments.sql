@@ -0,0 +1,18 @@
+-- Post-fall assessments: one per confirmed alert, answered against the configured form
+CREATE TABLE IF NOT EXISTS assessments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    alert_id INTEGER NOT NULL UNIQUE REFERENCES alerts (id),
+    event_id INTEGER NOT NULL, -- Detection that raised the alert
+    patient_id TEXT,
+    form_id TEXT NOT NULL, -- Form (FHIR Questionnaire) answered, and its version
+    form_version TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'in-progress', -- in-progress | completed | amended
+    answers TEXT NOT NULL DEFAULT '{}', -- JSON object keyed by item link_id
+    author TEXT, -- Who last saved the answers
+    due_at TEXT NOT NULL, -- Overdue while still in progress after this
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    completed_at TEXT -- First completion (kept when amended)
+);
+
+CREATE INDEX IF NOT EXISTS idx_assessments_status_due ON assessments (status, due_at);
@@ -0,0 +1,18 @@
+-- Every outcome of the fall detector with the features it was decided on, including the
+-- impacts that never became an event (kept for near-miss trends and re-labelling missed falls)
+CREATE TABLE IF NOT EXISTS detections (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    detected_at TEXT NOT NULL,
+    outcome TEXT NOT NULL, -- validating | critical | near-miss
+    device_id TEXT,
+    patient_id TEXT,
+    ward TEXT,
+    peak_g REAL NOT NULL, -- Impact G for validating, highest G in the window otherwise
+    stillness_variance REAL, -- Features of the validation window (unset while validating)
+    validation_ms INTEGER, -- Window length: impact to verdict
+    orientation_change_deg REAL,
+    event_id INTEGER -- Event stored for a critical or near-miss outcome
+);
+
+CREATE INDEX IF NOT EXISTS idx_detections_detected_at ON detections (detected_at);
+CREATE INDEX IF NOT EXISTS idx_detections_device ON detections (device_id, detected_at);
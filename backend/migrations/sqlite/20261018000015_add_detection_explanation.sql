@@ -0,0 +1,3 @@
+-- Why the detector reported an outcome (rules evaluated against their thresholds, confidence), as JSON
+ALTER TABLE events ADD COLUMN explanation TEXT;
+ALTER TABLE detections ADD COLUMN explanation TEXT;
@@ -0,0 +1,33 @@
+-- Rollups get a row id so retention can archive and purge them like the other data classes
+-- (SQLite cannot add a generated column, so the table is rebuilt)
+CREATE TABLE telemetry_rollups_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    device_id TEXT NOT NULL,
+    resolution_seconds INTEGER NOT NULL,
+    bucket_start TEXT NOT NULL,
+    sample_count INTEGER NOT NULL,
+    g_min REAL NOT NULL,
+    g_max REAL NOT NULL,
+    g_sum REAL NOT NULL,
+    x_min REAL NOT NULL,
+    x_max REAL NOT NULL,
+    x_sum REAL NOT NULL,
+    y_min REAL NOT NULL,
+    y_max REAL NOT NULL,
+    y_sum REAL NOT NULL,
+    z_min REAL NOT NULL,
+    z_max REAL NOT NULL,
+    z_sum REAL NOT NULL,
+    UNIQUE (device_id, resolution_seconds, bucket_start)
+);
+
+INSERT INTO telemetry_rollups_new (device_id, resolution_seconds, bucket_start, sample_count,
+    g_min, g_max, g_sum, x_min, x_max, x_sum, y_min, y_max, y_sum, z_min, z_max, z_sum)
+SELECT device_id, resolution_seconds, bucket_start, sample_count,
+    g_min, g_max, g_sum, x_min, x_max, x_sum, y_min, y_max, y_sum, z_min, z_max, z_sum
+FROM telemetry_rollups;
+
+DROP TABLE telemetry_rollups;
+ALTER TABLE telemetry_rollups_new RENAME TO telemetry_rollups;
+
+CREATE INDEX IF NOT EXISTS idx_telemetry_rollups_bucket_start ON telemetry_rollups (bucket_start);
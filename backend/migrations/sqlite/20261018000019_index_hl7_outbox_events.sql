@@ -0,0 +1,2 @@
+-- The outbound interface looks up which events already have a message queued
+CREATE INDEX IF NOT EXISTS idx_hl7_outbox_event_id ON hl7_outbox (event_id);
@@ -0,0 +1,1861 @@
+use crate::assessment;
+use crate::fhir::{self, Bundle};
+use crate::labels;
+use crate::model::{FallLog, MorseScore, RiskScore};
+use crate::risk::{self, MorseItems};
+use crate::shadow;
+use crate::storage::detections::DETECTION_OUTCOMES;
+use crate::storage::labels::LABELS;
+use crate::storage::query::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+use crate::storage::rollup::{self, DEFAULT_RANGE_HOURS};
+use crate::storage::{
+    AssessmentQuery, DetectionQuery, DeviceRegistration, EventCursor, EventFilter, EventQuery,
+    EventSort, LabelQuery, NewAuditEntry, NewLegalHold, NewPatient, RiskQuery, StatsQuery,
+    StorageError, StorageResult, TelemetryQuery,
+};
+use crate::websockets::ws_handler;
+use crate::AppState;
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Number of rows returned by the history endpoints.
+const HISTORY_LIMIT: i64 = 20;
+
+/// The values of a comma-separated parameter, trimmed (none when it is absent). An empty
+/// value, as in `a,,b` or a bare `name=`, is rejected.
+fn comma_list(name: &str, value: Option<&str>) -> StorageResult<Vec<String>> {
+    let Some(value) = value else {
+        return Ok(Vec::new());
+    };
+    value
+        .split(',')
+        .map(|v| match v.trim() {
+            "" => Err(StorageError::InvalidQuery(format!(
+                "{} has an empty value: {:?}",
+                name, value
+            ))),
+            v => Ok(v.to_string()),
+        })
+        .collect()
+}
+
+/// **Event Query Parameters**
+///
+/// Shared by `/api/events` and `/api/fhir/history`:
+/// `?from=&to=&severity=Critical,Near Miss&device_id=&patient_id=&ward=&false_alarm=&sort=-detected_at&limit=&cursor=`
+#[derive(Debug, Default, Deserialize)]
+pub struct EventQueryParams {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub severity: Option<String>, // Comma-separated list
+    pub device_id: Option<String>,
+    pub patient_id: Option<String>,
+    pub ward: Option<String>,
+    pub false_alarm: Option<bool>,
+    pub sort: Option<String>,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+impl EventQueryParams {
+    pub fn to_query(&self, default_limit: i64) -> StorageResult<EventQuery> {
+        let sort = match &self.sort {
+            Some(s) => s.parse::<EventSort>()?,
+            None => EventSort::default(),
+        };
+        let cursor = match &self.cursor {
+            Some(token) => Some(EventCursor::decode(token, &sort)?),
+            None => None,
+        };
+        let severities = comma_list("severity", self.severity.as_deref())?;
+
+        Ok(EventQuery {
+            filter: EventFilter {
+                from: self.from,
+                to: self.to,
+                severities,
+                device_id: self.device_id.clone(),
+                patient_id: self.patient_id.clone(),
+                ward: self.ward.clone(),
+                is_false_alarm: self.false_alarm,
+                ..EventFilter::default()
+            },
+            sort,
+            cursor,
+            limit: self.limit.unwrap_or(default_limit).clamp(1, MAX_PAGE_SIZE),
+        })
+    }
+}
+
+/// **Statistics Parameters**
+///
+/// `/api/stats?from=&to=&device_id=&patient_id=&ward=&utc_offset_minutes=60`
+#[derive(Debug, Default, Deserialize)]
+pub struct StatsParams {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub device_id: Option<String>,
+    pub patient_id: Option<String>,
+    pub ward: Option<String>,
+    pub utc_offset_minutes: Option<i32>, // Local time for the hour/weekday histograms
+}
+
+impl StatsParams {
+    pub fn to_query(&self) -> StorageResult<StatsQuery> {
+        let utc_offset_minutes = self.utc_offset_minutes.unwrap_or(0);
+        if utc_offset_minutes.abs() > 14 * 60 {
+            return Err(StorageError::InvalidQuery(format!(
+                "utc_offset_minutes out of range: {}",
+                utc_offset_minutes
+            )));
+        }
+        Ok(StatsQuery {
+            filter: EventFilter {
+                from: self.from,
+                to: self.to,
+                device_id: self.device_id.clone(),
+                patient_id: self.patient_id.clone(),
+                ward: self.ward.clone(),
+                ..EventFilter::default()
+            },
+            utc_offset_minutes,
+        })
+    }
+}
+
+/// **Telemetry Chart Parameters**
+///
+/// `/api/devices/{id}/telemetry?from=&to=&resolution=5m`
+/// `to` defaults to now and `from` to 24 hours before it. `resolution` accepts `30s`, `5m`,
+/// `1h`, `1d` or seconds; when omitted it is picked to give a few hundred points.
+#[derive(Debug, Default, Deserialize)]
+pub struct TelemetryParams {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub resolution: Option<String>,
+}
+
+impl TelemetryParams {
+    pub fn to_query(&self, device_id: String) -> StorageResult<TelemetryQuery> {
+        let to = self.to.unwrap_or_else(Utc::now);
+        let from = self
+            .from
+            .unwrap_or_else(|| to - Duration::hours(DEFAULT_RANGE_HOURS));
+        let resolution = match &self.resolution {
+            Some(r) => Some(rollup::parse_resolution(r)?),
+            None => None,
+        };
+        TelemetryQuery::new(device_id, from, to, resolution)
+    }
+}
+
+/// **Legal Hold Request**
+///
+/// Body of `POST /api/legal-holds`: a patient, an alert, or both, plus who asked and why.
+#[derive(Debug, Deserialize)]
+pub struct LegalHoldRequest {
+    pub patient_id: Option<String>,
+    pub alert_id: Option<i32>,
+    pub reason: String,
+    pub placed_by: String,
+}
+
+impl LegalHoldRequest {
+    pub fn to_hold(&self, at: DateTime<Utc>) -> StorageResult<NewLegalHold> {
+        if self.patient_id.is_none() && self.alert_id.is_none() {
+            return Err(StorageError::InvalidQuery(
+                "a legal hold needs a patient_id or an alert_id".to_string(),
+            ));
+        }
+        if self.reason.trim().is_empty() || self.placed_by.trim().is_empty() {
+            return Err(StorageError::InvalidQuery(
+                "reason and placed_by are required".to_string(),
+            ));
+        }
+        Ok(NewLegalHold {
+            patient_id: self.patient_id.clone(),
+            alert_id: self.alert_id,
+            reason: self.reason.clone(),
+            placed_by: self.placed_by.clone(),
+            placed_at: at,
+        })
+    }
+}
+
+/// `/api/legal-holds?include_released=true`
+#[derive(Debug, Default, Deserialize)]
+pub struct LegalHoldListParams {
+    #[serde(default)]
+    pub include_released: bool,
+}
+
+/// `DELETE /api/legal-holds/{id}?released_by=`
+#[derive(Debug, Deserialize)]
+pub struct LegalHoldReleaseParams {
+    pub released_by: String,
+}
+
+/// **Device Request**
+///
+/// Body of `PUT /api/devices/{id}`: registers the sensor or updates it. Omitted fields keep
+/// their current value; `calibration_state` is a FHIR DeviceMetric calibration state.
+#[derive(Debug, Default, Deserialize)]
+pub struct DeviceRequest {
+    pub model: Option<String>,
+    pub patient_id: Option<String>,
+    pub ward: Option<String>,
+    pub calibration_state: Option<String>,
+    pub calibrated_at: Option<DateTime<Utc>>,
+}
+
+impl DeviceRequest {
+    pub fn to_registration(&self, id: String, at: DateTime<Utc>) -> DeviceRegistration {
+        DeviceRegistration {
+            id,
+            model: self.model.clone(),
+            patient_id: self.patient_id.clone(),
+            ward: self.ward.clone(),
+            calibration_state: self.calibration_state.clone(),
+            calibrated_at: self.calibrated_at,
+            at,
+        }
+    }
+}
+
+/// **Patient Request**
+///
+/// Body of `PUT /api/patients/{id}`: replaces the patient's demographics and location
+/// (the same record the ADT feed maintains).
+#[derive(Debug, Default, Deserialize)]
+pub struct PatientRequest {
+    pub family_name: Option<String>,
+    pub given_name: Option<String>,
+    pub birth_date: Option<NaiveDate>,
+    pub gender: Option<String>, // male | female | other | unknown
+    pub ward: Option<String>,
+    pub room: Option<String>,
+    pub bed: Option<String>,
+    pub admitted_at: Option<DateTime<Utc>>,
+    pub discharged_at: Option<DateTime<Utc>>,
+}
+
+impl PatientRequest {
+    pub fn to_patient(&self, id: String, at: DateTime<Utc>) -> NewPatient {
+        NewPatient {
+            id,
+            family_name: self.family_name.clone(),
+            given_name: self.given_name.clone(),
+            birth_date: self.birth_date,
+            gender: self.gender.clone(),
+            ward: self.ward.clone(),
+            room: self.room.clone(),
+            bed: self.bed.clone(),
+            admitted_at: self.admitted_at,
+            discharged_at: self.discharged_at,
+            updated_at: at,
+        }
+    }
+}
+
+/// **Assessment Request**
+///
+/// Body of `PUT /api/alerts/{id}/assessment`: every answer given so far, keyed by item
+/// `link_id` (see `/api/assessments/form`). `complete: true` finishes the form.
+#[derive(Debug, Deserialize)]
+pub struct AssessmentRequest {
+    pub author: String,
+    #[serde(default)]
+    pub answers: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    pub complete: bool,
+}
+
+/// `/api/assessments?status=in-progress,completed&patient_id=&alert_id=`
+#[derive(Debug, Default, Deserialize)]
+pub struct AssessmentListParams {
+    pub status: Option<String>, // Comma-separated list
+    pub patient_id: Option<String>,
+    pub alert_id: Option<i32>,
+}
+
+impl AssessmentListParams {
+    pub fn to_query(&self) -> StorageResult<AssessmentQuery> {
+        Ok(AssessmentQuery {
+            statuses: comma_list("status", self.status.as_deref())?,
+            patient_id: self.patient_id.clone(),
+            alert_id: self.alert_id,
+            ..AssessmentQuery::default()
+        })
+    }
+}
+
+/// `/api/detections?outcome=near-miss,critical&device_id=&patient_id=&detector=&shadow=&from=&to=&limit=`
+#[derive(Debug, Default, Deserialize)]
+pub struct DetectionListParams {
+    pub outcome: Option<String>, // Comma-separated list of DETECTION_OUTCOMES
+    pub device_id: Option<String>,
+    pub patient_id: Option<String>,
+    pub detector: Option<String>,
+    #[serde(default)]
+    pub shadow: bool, // Candidate outcomes instead of production's
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+impl DetectionListParams {
+    pub fn to_query(&self) -> StorageResult<DetectionQuery> {
+        let outcomes = comma_list("outcome", self.outcome.as_deref())?;
+        if let Some(unknown) = outcomes
+            .iter()
+            .find(|o| !DETECTION_OUTCOMES.contains(&o.as_str()))
+        {
+            return Err(StorageError::InvalidQuery(format!(
+                "unknown outcome: {} (expected one of {})",
+                unknown,
+                DETECTION_OUTCOMES.join(", ")
+            )));
+        }
+        Ok(DetectionQuery {
+            outcomes,
+            device_id: self.device_id.clone(),
+            patient_id: self.patient_id.clone(),
+            detector: self.detector.clone(),
+            shadow: Some(self.shadow),
+            from: self.from,
+            to: self.to,
+            limit: self
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        })
+    }
+}
+
+/// `/api/labels?label=fall&from=&to=&limit=` (also `/api/labels/export`)
+#[derive(Debug, Default, Deserialize)]
+pub struct LabelListParams {
+    pub label: Option<String>, // One of LABELS
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+impl LabelListParams {
+    pub fn to_query(&self, default_limit: i64, max_limit: i64) -> StorageResult<LabelQuery> {
+        if let Some(label) = self.label.as_deref() {
+            if !LABELS.contains(&label) {
+                return Err(StorageError::InvalidQuery(format!(
+                    "unknown label: {} (expected one of {})",
+                    label,
+                    LABELS.join(", ")
+                )));
+            }
+        }
+        Ok(LabelQuery {
+            label: self.label.clone(),
+            event_ids: Vec::new(),
+            from: self.from,
+            to: self.to,
+            limit: self.limit.unwrap_or(default_limit).clamp(1, max_limit),
+        })
+    }
+}
+
+/// `/api/shadow/disagreements?detector=&from=&to=&limit=`
+#[derive(Debug, Default, Deserialize)]
+pub struct ShadowReportParams {
+    pub detector: Option<String>, // A shadow profile name (see SHADOW_PROFILES)
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+/// **Morse Fall Scale Request**
+///
+/// Body of `POST /api/patients/{id}/morse`: who assessed the patient and the points scored on
+/// each item (see `risk::MorseItems`).
+#[derive(Debug, Deserialize)]
+pub struct MorseRequest {
+    pub assessed_by: String,
+    #[serde(flatten)]
+    pub items: MorseItems,
+}
+
+/// Response body of `POST /api/patients/{id}/morse`: the entry and the score it produced.
+#[derive(Debug, Serialize)]
+pub struct MorseResponse {
+    pub morse: MorseScore,
+    pub risk: RiskScore,
+}
+
+/// `/api/patients/{id}/risk/history?limit=`
+#[derive(Debug, Default, Deserialize)]
+pub struct RiskHistoryParams {
+    pub limit: Option<i64>,
+}
+
+/// Response body of `/api/events`.
+#[derive(Debug, Serialize)]
+pub struct EventListResponse {
+    pub items: Vec<FallLog>,
+    pub total: i64,
+    pub next_cursor: Option<String>,
+}
+
+/// Maps a storage failure to an HTTP response (bad filters are the client's fault).
+fn storage_error_response(e: StorageError, context: &str) -> HttpResponse {
+    match e {
+        StorageError::InvalidQuery(msg) => HttpResponse::BadRequest().body(msg),
+        StorageError::NotFound(what) => {
+            HttpResponse::NotFound().body(format!("{} not found", what))
+        }
+        other => {
+            eprintln!("❌ {}: {:?}", context, other);
+            HttpResponse::InternalServerError().body(context.to_string())
+        }
+    }
+}
+
+/// Registers every HTTP and WebSocket route on the app (shared by `main` and the tests).
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/api/history", web::get().to(get_history)) // REST API
+        .route("/api/events", web::get().to(list_events))
+        .route("/api/events/{id}/waveform", web::get().to(get_waveform))
+        .route("/api/detections", web::get().to(list_detections))
+        .route("/api/labels", web::get().to(list_labels))
+        .route("/api/labels/export", web::get().to(export_labels))
+        .route(
+            "/api/shadow/disagreements",
+            web::get().to(shadow_disagreements),
+        )
+        .route("/api/stats", web::get().to(get_stats))
+        .route(
+            "/api/devices/{id}/telemetry",
+            web::get().to(get_device_telemetry),
+        )
+        .route("/api/devices", web::get().to(list_devices))
+        .route("/api/devices/{id}", web::put().to(register_device))
+        .route("/api/patients", web::get().to(list_patients))
+        .route("/api/patients/{id}", web::put().to(update_patient))
+        .route("/api/patients/{id}/risk", web::get().to(get_risk))
+        .route("/api/patients/{id}/risk", web::post().to(recalculate_risk))
+        .route(
+            "/api/patients/{id}/risk/history",
+            web::get().to(risk_history),
+        )
+        .route("/api/patients/{id}/morse", web::post().to(record_morse))
+        .route("/api/assessments", web::get().to(list_assessments))
+        .route("/api/assessments/form", web::get().to(get_assessment_form))
+        .route(
+            "/api/assessments/overdue",
+            web::get().to(list_overdue_assessments),
+        )
+        .route("/api/assessments/{id}", web::get().to(get_assessment))
+        .route(
+            "/api/alerts/{id}/assessment",
+            web::put().to(save_assessment),
+        )
+        .route("/api/legal-holds", web::get().to(list_legal_holds))
+        .route("/api/legal-holds", web::post().to(place_legal_hold))
+        .route(
+            "/api/legal-holds/{id}",
+            web::delete().to(release_legal_hold),
+        )
+        .service(
+            // FHIR API (errors, including malformed query strings, come back as OperationOutcome)
+            web::resource("/api/fhir/history")
+                .app_data(web::QueryConfig::default().error_handler(fhir::query_error_handler))
+                .route(web::get().to(get_fhir_history)),
+        )
+        .configure(fhir::configure) // FHIR REST API (/fhir/...)
+        .route("/ws", web::get().to(ws_handler)); // WebSocket API
+}
+
+/// **GET /api/history**
+///
+/// Retrieves the last 20 detected fall events from the database.
+/// This is used by the frontend to populate the "Event Log" panel on startup.
+pub async fn get_history(data: web::Data<AppState>) -> impl Responder {
+    let query = EventQuery {
+        limit: HISTORY_LIMIT,
+        ..EventQuery::default()
+    };
+    match data.db.query_events(&query).await {
+        Ok(page) => HttpResponse::Ok().json(page.items),
+        Err(e) => storage_error_response(e, "Error fetching logs"),
+    }
+}
+
+/// **GET /api/events**
+///
+/// Filterable event history with cursor pagination. Pass `next_cursor` back as `cursor`
+/// to fetch the following page; `total` counts every match regardless of the cursor.
+pub async fn list_events(
+    data: web::Data<AppState>,
+    params: web::Query<EventQueryParams>,
+) -> impl Responder {
+    let query = match params.to_query(DEFAULT_PAGE_SIZE) {
+        Ok(q) => q,
+        Err(e) => return storage_error_response(e, "Error fetching events"),
+    };
+    match data.db.query_events(&query).await {
+        Ok(page) => HttpResponse::Ok().json(EventListResponse {
+            items: page.items,
+            total: page.total,
+            next_cursor: page.next_cursor.map(|c| c.encode()),
+        }),
+        Err(e) => storage_error_response(e, "Error fetching events"),
+    }
+}
+
+/// **GET /api/events/{id}/waveform**
+///
+/// Sensor signal captured around a detection: from a few seconds before the impact to the
+/// end of the validation window. Point offsets are in milliseconds relative to the impact.
+pub async fn get_waveform(data: web::Data<AppState>, path: web::Path<i32>) -> impl Responder {
+    let event_id = path.into_inner();
+    match data.db.get_waveform(event_id).await {
+        Ok(Some(waveform)) => HttpResponse::Ok().json(waveform),
+        Ok(None) => HttpResponse::NotFound().body(format!("No waveform for event {}", event_id)),
+        Err(e) => storage_error_response(e, "Error fetching waveform"),
+    }
+}
+
+/// **GET /api/detections**
+///
+/// The detector's outcome log, newest first: every impact it started validating and how the
+/// window ended, with the features (peak G, stillness variance, window length, orientation
+/// change) it was decided on. Critical and near-miss outcomes link to their event.
+pub async fn list_detections(
+    data: web::Data<AppState>,
+    params: web::Query<DetectionListParams>,
+) -> impl Responder {
+    let query = match params.to_query() {
+        Ok(q) => q,
+        Err(e) => return storage_error_response(e, "Error fetching detections"),
+    };
+    match data.db.query_detections(&query).await {
+        Ok(detections) => HttpResponse::Ok().json(detections),
+        Err(e) => storage_error_response(e, "Error fetching detections"),
+    }
+}
+
+/// **GET /api/labels**
+///
+/// Nurse verdicts kept as ground truth, newest first: `fall` for a confirmed alert, `no-fall`
+/// for a cancelled one. `event_id` is the labelled detection, whose waveform is at
+/// `/api/events/{event_id}/waveform`.
+pub async fn list_labels(
+    data: web::Data<AppState>,
+    params: web::Query<LabelListParams>,
+) -> impl Responder {
+    let query = match params.to_query(DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE) {
+        Ok(q) => q,
+        Err(e) => return storage_error_response(e, "Error fetching labels"),
+    };
+    match data.db.query_labels(&query).await {
+        Ok(labels) => HttpResponse::Ok().json(labels),
+        Err(e) => storage_error_response(e, "Error fetching labels"),
+    }
+}
+
+/// **GET /api/labels/export**
+///
+/// The labelled waveforms as a de-identified dataset `evaluate` reads directly (see
+/// `labels::LabelledDataset`), downloaded as `labelled_dataset.json`. Every export draws a new
+/// time shift.
+pub async fn export_labels(
+    data: web::Data<AppState>,
+    params: web::Query<LabelListParams>,
+) -> impl Responder {
+    let query = match params.to_query(labels::EXPORT_LIMIT, labels::EXPORT_LIMIT) {
+        Ok(q) => q,
+        Err(e) => return storage_error_response(e, "Error exporting labels"),
+    };
+    match labels::export(data.db.as_ref(), &query, labels::random_shift()).await {
+        Ok(dataset) => HttpResponse::Ok()
+            .insert_header((
+                "Content-Disposition",
+                "attachment; filename=\"labelled_dataset.json\"",
+            ))
+            .json(dataset),
+        Err(e) => storage_error_response(e, "Error exporting labels"),
+    }
+}
+
+/// **GET /api/shadow/disagreements**
+///
+/// Where a shadow-mode candidate detector decided differently from production: impacts it
+/// would have alerted on that production did not, and alerts it would have stayed silent on,
+/// each with the nurse's verdict on production's alert (see `shadow::ShadowReport`). The
+/// candidate must be named (`detector`); without it the request is refused.
+pub async fn shadow_disagreements(
+    data: web::Data<AppState>,
+    params: web::Query<ShadowReportParams>,
+) -> impl Responder {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let detector = match params.detector.as_deref().map(str::trim) {
+        Some(detector) if !detector.is_empty() => detector,
+        _ => {
+            return HttpResponse::BadRequest()
+                .body("detector is required: the name of a shadow-mode profile")
+        }
+    };
+    match shadow::disagreements(
+        data.db.as_ref(),
+        detector,
+        params.from,
+        params.to,
+        limit as usize,
+    )
+    .await
+    {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => storage_error_response(e, "Error comparing detectors"),
+    }
+}
+
+/// **GET /api/stats**
+///
+/// Fall statistics over any date range: counts per severity (`RiskReport`), false-alarm and
+/// near-miss rates, hour-of-day / day-of-week histograms and per-ward / per-patient breakdowns.
+/// Aggregation runs in the database so it stays fast on large `events` tables.
+pub async fn get_stats(
+    data: web::Data<AppState>,
+    params: web::Query<StatsParams>,
+) -> impl Responder {
+    let query = match params.to_query() {
+        Ok(q) => q,
+        Err(e) => return storage_error_response(e, "Error computing statistics"),
+    };
+    match data.db.fall_statistics(&query).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => storage_error_response(e, "Error computing statistics"),
+    }
+}
+
+/// **GET /api/devices/{id}/telemetry**
+///
+/// Downsampled sensor signal for charts: one bucket per `resolution` with min / max / mean
+/// of G-force and each axis plus the sample count. Served from the precomputed rollups
+/// whenever the resolution allows, so long ranges don't scan raw telemetry.
+pub async fn get_device_telemetry(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    params: web::Query<TelemetryParams>,
+) -> impl Responder {
+    let query = match params.to_query(path.into_inner()) {
+        Ok(q) => q,
+        Err(e) => return storage_error_response(e, "Error fetching telemetry"),
+    };
+    match rollup::downsample(data.db.as_ref(), &query).await {
+        Ok(series) => HttpResponse::Ok().json(series),
+        Err(e) => storage_error_response(e, "Error fetching telemetry"),
+    }
+}
+
+/// **GET /api/devices**
+///
+/// The device registry: assignment, calibration and the status each sensor last reported.
+pub async fn list_devices(data: web::Data<AppState>) -> impl Responder {
+    match data.db.list_devices().await {
+        Ok(devices) => HttpResponse::Ok().json(devices),
+        Err(e) => storage_error_response(e, "Error fetching devices"),
+    }
+}
+
+/// **PUT /api/devices/{id}**
+///
+/// Registers a sensor or updates its model, assignment or calibration.
+pub async fn register_device(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<DeviceRequest>,
+) -> impl Responder {
+    let registration = body.to_registration(path.into_inner(), Utc::now());
+    match data.db.register_device(&registration).await {
+        Ok(device) => HttpResponse::Ok().json(device),
+        Err(e) => storage_error_response(e, "Error registering device"),
+    }
+}
+
+/// **GET /api/patients**
+pub async fn list_patients(data: web::Data<AppState>) -> impl Responder {
+    match data.db.list_patients().await {
+        Ok(patients) => HttpResponse::Ok().json(patients),
+        Err(e) => storage_error_response(e, "Error fetching patients"),
+    }
+}
+
+/// **PUT /api/patients/{id}**
+///
+/// Creates or replaces a patient's demographics (served as the FHIR Patient).
+pub async fn update_patient(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<PatientRequest>,
+) -> impl Responder {
+    let patient = body.to_patient(path.into_inner(), Utc::now());
+    match data.db.upsert_patient(&patient).await {
+        Ok(patient) => HttpResponse::Ok().json(patient),
+        Err(e) => storage_error_response(e, "Error updating patient"),
+    }
+}
+
+/// **GET /api/patients/{id}/risk**
+///
+/// The patient's current fall risk score (the newest one calculated).
+pub async fn get_risk(data: web::Data<AppState>, path: web::Path<String>) -> impl Responder {
+    let patient_id = path.into_inner();
+    let query = RiskQuery {
+        patient_id: Some(patient_id.clone()),
+        limit: 1,
+    };
+    match data.db.query_risk_scores(&query).await {
+        Ok(scores) => match scores.into_iter().next() {
+            Some(score) => HttpResponse::Ok().json(score),
+            None => HttpResponse::NotFound()
+                .body(format!("No fall risk score for patient {}", patient_id)),
+        },
+        Err(e) => storage_error_response(e, "Error fetching fall risk"),
+    }
+}
+
+/// **POST /api/patients/{id}/risk**
+///
+/// Recalculates the patient's fall risk now (scores are otherwise recalculated on every
+/// detection and Morse entry).
+pub async fn recalculate_risk(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let patient_id = path.into_inner();
+    let score = risk::recalculate(
+        data.db.as_ref(),
+        &data.risk,
+        &patient_id,
+        "request",
+        None,
+        Utc::now(),
+    )
+    .await;
+    match score {
+        Ok(score) => HttpResponse::Ok().json(score),
+        Err(e) => storage_error_response(e, "Error calculating fall risk"),
+    }
+}
+
+/// **GET /api/patients/{id}/risk/history**
+///
+/// Past fall risk scores of the patient, newest first.
+pub async fn risk_history(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    params: web::Query<RiskHistoryParams>,
+) -> impl Responder {
+    let query = RiskQuery {
+        patient_id: Some(path.into_inner()),
+        limit: params
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE),
+    };
+    match data.db.query_risk_scores(&query).await {
+        Ok(scores) => HttpResponse::Ok().json(scores),
+        Err(e) => storage_error_response(e, "Error fetching fall risk history"),
+    }
+}
+
+/// **POST /api/patients/{id}/morse**
+///
+/// Records a Morse Fall Scale assessment (every item must score points the scale gives) and
+/// recalculates the patient's fall risk with it. The entry is written to the audit log.
+pub async fn record_morse(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<MorseRequest>,
+) -> impl Responder {
+    let recorded = risk::record_morse(
+        data.db.as_ref(),
+        &data.risk,
+        &path.into_inner(),
+        &body.items,
+        &body.assessed_by,
+        Utc::now(),
+    )
+    .await;
+    match recorded {
+        Ok((morse, risk)) => HttpResponse::Created().json(MorseResponse { morse, risk }),
+        Err(e) => storage_error_response(e, "Error recording Morse Fall Scale"),
+    }
+}
+
+/// **GET /api/assessments/form**
+///
+/// The post-fall assessment form (also served as the FHIR Questionnaire).
+pub async fn get_assessment_form(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(data.assessment_form.as_ref())
+}
+
+/// **GET /api/assessments**
+///
+/// Post-fall assessments, newest first, filtered by status, patient or alert.
+pub async fn list_assessments(
+    data: web::Data<AppState>,
+    params: web::Query<AssessmentListParams>,
+) -> impl Responder {
+    let query = match params.to_query() {
+        Ok(q) => q,
+        Err(e) => return storage_error_response(e, "Error fetching assessments"),
+    };
+    match data.db.query_assessments(&query).await {
+        Ok(assessments) => HttpResponse::Ok().json(assessments),
+        Err(e) => storage_error_response(e, "Error fetching assessments"),
+    }
+}
+
+/// **GET /api/assessments/overdue**
+///
+/// Assessments still in progress past their due time, longest overdue first.
+pub async fn list_overdue_assessments(data: web::Data<AppState>) -> impl Responder {
+    let query = AssessmentQuery {
+        statuses: vec!["in-progress".to_string()],
+        due_before: Some(Utc::now()),
+        ..AssessmentQuery::default()
+    };
+    match data.db.query_assessments(&query).await {
+        Ok(mut assessments) => {
+            assessments.sort_by_key(|a| a.due_at);
+            HttpResponse::Ok().json(assessments)
+        }
+        Err(e) => storage_error_response(e, "Error fetching overdue assessments"),
+    }
+}
+
+/// **GET /api/assessments/{id}**
+pub async fn get_assessment(data: web::Data<AppState>, path: web::Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    match data.db.get_assessment(id).await {
+        Ok(Some(assessment)) => HttpResponse::Ok().json(assessment),
+        Ok(None) => HttpResponse::NotFound().body(format!("Assessment {} not found", id)),
+        Err(e) => storage_error_response(e, "Error fetching assessment"),
+    }
+}
+
+/// **PUT /api/alerts/{id}/assessment**
+///
+/// Saves the post-fall assessment of an alert (started when the nurse confirmed the fall).
+/// Answers are checked against the form; `complete: true` also requires every required item
+/// and takes the assessment off the overdue list. Saving a completed assessment amends it.
+pub async fn save_assessment(
+    data: web::Data<AppState>,
+    path: web::Path<i32>,
+    body: web::Json<AssessmentRequest>,
+) -> impl Responder {
+    let saved = assessment::save(
+        data.db.as_ref(),
+        &data.assessment_form,
+        path.into_inner(),
+        &body.answers,
+        &body.author,
+        body.complete,
+        Utc::now(),
+    )
+    .await;
+    match saved {
+        Ok(assessment) => HttpResponse::Ok().json(assessment),
+        Err(e) => storage_error_response(e, "Error saving assessment"),
+    }
+}
+
+/// **GET /api/legal-holds**
+///
+/// Active legal holds (pass `include_released=true` for the full history).
+pub async fn list_legal_holds(
+    data: web::Data<AppState>,
+    params: web::Query<LegalHoldListParams>,
+) -> impl Responder {
+    match data.db.legal_holds(params.include_released).await {
+        Ok(holds) => HttpResponse::Ok().json(holds),
+        Err(e) => storage_error_response(e, "Error fetching legal holds"),
+    }
+}
+
+/// **POST /api/legal-holds**
+///
+/// Exempts a patient's records, or one alert's event and waveform, from retention purges.
+/// Placing and releasing holds is recorded in the audit log.
+pub async fn place_legal_hold(
+    data: web::Data<AppState>,
+    body: web::Json<LegalHoldRequest>,
+) -> impl Responder {
+    let now = Utc::now();
+    let hold = match body.to_hold(now) {
+        Ok(h) => h,
+        Err(e) => return storage_error_response(e, "Error placing legal hold"),
+    };
+    let hold = match data.db.place_legal_hold(hold).await {
+        Ok(h) => h,
+        Err(e) => return storage_error_response(e, "Error placing legal hold"),
+    };
+    let audit = NewAuditEntry {
+        recorded_at: now,
+        actor: hold.placed_by.clone(),
+        action: "legal_hold.placed".to_string(),
+        entity_type: "LegalHold".to_string(),
+        entity_id: Some(hold.id.to_string()),
+        detail: serde_json::to_value(&hold).ok(),
+    };
+    if let Err(e) = data.db.record_audit(audit).await {
+        return storage_error_response(e, "Error recording legal hold");
+    }
+    HttpResponse::Created().json(hold)
+}
+
+/// **DELETE /api/legal-holds/{id}**
+///
+/// Releases a hold; its records become subject to retention again on the next run.
+pub async fn release_legal_hold(
+    data: web::Data<AppState>,
+    path: web::Path<i32>,
+    params: web::Query<LegalHoldReleaseParams>,
+) -> impl Responder {
+    let now = Utc::now();
+    let hold = match data.db.release_legal_hold(path.into_inner(), now).await {
+        Ok(h) => h,
+        Err(e) => return storage_error_response(e, "Error releasing legal hold"),
+    };
+    let audit = NewAuditEntry {
+        recorded_at: now,
+        actor: params.released_by.clone(),
+        action: "legal_hold.released".to_string(),
+        entity_type: "LegalHold".to_string(),
+        entity_id: Some(hold.id.to_string()),
+        detail: None,
+    };
+    if let Err(e) = data.db.record_audit(audit).await {
+        return storage_error_response(e, "Error recording legal hold");
+    }
+    HttpResponse::Ok().json(hold)
+}
+
+/// **GET /api/fhir/history**
+///
+/// Retrieves fall events as clinical FHIR R4 "Observation" resources, wrapped in a
+/// `searchset` Bundle with `total`, `self` / `next` links and one entry per match.
+/// Code: LOINC 89020-2 (Fall risk assessment)
+/// Accepts the same filters as `/api/events` (20 results by default); errors are OperationOutcomes.
+pub async fn get_fhir_history(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    params: web::Query<EventQueryParams>,
+) -> impl Responder {
+    let query = match params.to_query(HISTORY_LIMIT) {
+        Ok(q) => q,
+        Err(e) => return fhir::storage_error_outcome(e, "Error generating FHIR data"),
+    };
+    match data.db.query_events(&query).await {
+        Ok(page) => {
+            let base = fhir::base_url(&req);
+            let self_url = fhir::request_url(&req);
+            let next_url = page
+                .next_cursor
+                .map(|c| fhir::with_query_param(&self_url, "cursor", &c.encode()));
+
+            // Transform to FHIR using model method
+            let entries = page
+                .items
+                .iter()
+                .map(|log| fhir::observation_entry(&base, log))
+                .collect();
+
+            fhir::fhir_json(
+                StatusCode::OK,
+                &Bundle::searchset(page.total, self_url, next_url, entries),
+            )
+        }
+        Err(e) => fhir::storage_error_outcome(e, "Error generating FHIR data"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::{Explanation, FallMetrics};
+    use crate::model::{Assessment, Detection};
+    use crate::model::{TelemetrySample, Waveform, WaveformPoint};
+    use crate::storage::DataClass;
+    use crate::storage::DeviceStatus;
+    use crate::storage::{NewAssessment, NewDetection, NewEvent};
+    use crate::test_support::{
+        answered_fall, call, critical_event, get, get_json, get_json_from, memory_state,
+    };
+    use crate::websockets::ConnectionParams;
+    use actix_web::dev::ServiceResponse;
+    use actix_web::test::{read_body, read_body_json, TestRequest};
+
+    #[test]
+    fn test_comma_list_trims_values() {
+        assert_eq!(
+            comma_list("severity", Some(" Critical , Near Miss")).unwrap(),
+            vec!["Critical", "Near Miss"]
+        );
+        assert!(comma_list("severity", None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_comma_list_rejects_empty_values() {
+        for value in ["", "critical,,near-miss", "critical, "] {
+            assert!(matches!(
+                comma_list("outcome", Some(value)),
+                Err(StorageError::InvalidQuery(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_list_params_reject_empty_values() {
+        let events = EventQueryParams {
+            severity: Some("Critical,".to_string()),
+            ..EventQueryParams::default()
+        };
+        assert!(events.to_query(10).is_err());
+        let assessments = AssessmentListParams {
+            status: Some(",".to_string()),
+            ..AssessmentListParams::default()
+        };
+        assert!(assessments.to_query().is_err());
+        let detections = DetectionListParams {
+            outcome: Some("critical,,".to_string()),
+            ..DetectionListParams::default()
+        };
+        assert!(detections.to_query().is_err());
+    }
+
+    // /api/history serves the latest events from any backend
+    #[actix_web::test]
+    async fn test_history() {
+        let state = memory_state();
+        state.db.insert_event(critical_event(5, 2.8)).await.unwrap();
+        let body = get_json(&state, "/api/history").await;
+        assert_eq!(body[0]["severity"], "Critical");
+    }
+
+    // /api/fhir/history serves them as Observations
+    #[actix_web::test]
+    async fn test_fhir_history() {
+        let state = memory_state();
+        state.db.insert_event(critical_event(5, 2.8)).await.unwrap();
+        let body = get_json(&state, "/api/fhir/history").await;
+        let observation = &body["entry"][0]["resource"];
+        assert_eq!(observation["resourceType"], "Observation");
+        assert_eq!(
+            observation["interpretation"][0]["text"],
+            "High Risk - Fall Detected"
+        );
+    }
+
+    // Helper: a store with three ICU events
+    async fn three_events() -> web::Data<AppState> {
+        let state = memory_state();
+        for i in 0..3 {
+            state.db.insert_event(critical_event(i, 2.0)).await.unwrap();
+        }
+        state
+    }
+
+    // /api/fhir/history is a searchset Bundle of absolute Observation entries
+    #[actix_web::test]
+    async fn test_fhir_history_bundle() {
+        let state = three_events().await;
+        let bundle = get_json_from(
+            &state,
+            "ehr.example.org",
+            "/api/fhir/history?limit=2&ward=ICU",
+        )
+        .await;
+        assert_eq!(bundle["resourceType"], "Bundle");
+        assert_eq!(bundle["type"], "searchset");
+        assert_eq!(bundle["total"], 3);
+        assert_eq!(bundle["entry"].as_array().unwrap().len(), 2);
+        let entry = &bundle["entry"][0];
+        assert_eq!(entry["search"]["mode"], "match");
+        assert_eq!(
+            entry["fullUrl"],
+            format!(
+                "http://ehr.example.org/fhir/Observation/{}",
+                entry["resource"]["id"].as_str().unwrap()
+            )
+        );
+    }
+
+    // /api/fhir/history links to itself and to the next page, which has no further link
+    #[actix_web::test]
+    async fn test_fhir_history_links() {
+        let state = three_events().await;
+        let bundle = get_json_from(
+            &state,
+            "ehr.example.org",
+            "/api/fhir/history?limit=2&ward=ICU",
+        )
+        .await;
+        assert_eq!(bundle["link"][0]["relation"], "self");
+        assert_eq!(
+            bundle["link"][0]["url"],
+            "http://ehr.example.org/api/fhir/history?limit=2&ward=ICU"
+        );
+        assert_eq!(bundle["link"][1]["relation"], "next");
+
+        let next = bundle["link"][1]["url"].as_str().unwrap();
+        let page = get_json(&state, next.trim_start_matches("http://ehr.example.org")).await;
+        assert_eq!(page["entry"].as_array().unwrap().len(), 1);
+        assert_eq!(page["link"].as_array().unwrap().len(), 1);
+    }
+
+    // /api/fhir/history reports bad parameters as an OperationOutcome
+    #[actix_web::test]
+    async fn test_fhir_history_rejects_bad_parameters() {
+        let state = memory_state();
+        for uri in [
+            "/api/fhir/history?sort=severity",
+            "/api/fhir/history?from=yesterday",
+        ] {
+            let response = get(&state, uri).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+            let outcome: serde_json::Value = read_body_json(response).await;
+            assert_eq!(outcome["resourceType"], "OperationOutcome");
+            assert_eq!(outcome["issue"][0]["code"], "invalid");
+        }
+    }
+
+    // /api/events pages through results with a cursor
+    #[actix_web::test]
+    async fn test_events_pages() {
+        let state = three_events().await;
+        let body = get_json(
+            &state,
+            "/api/events?limit=2&device_id=pi-01&false_alarm=false",
+        )
+        .await;
+        assert_eq!(body["total"], 3);
+        assert_eq!(body["items"].as_array().unwrap().len(), 2);
+        let cursor = body["next_cursor"].as_str().unwrap();
+
+        let body = get_json(&state, &format!("/api/events?limit=2&cursor={}", cursor)).await;
+        assert_eq!(body["items"].as_array().unwrap().len(), 1);
+        assert!(body["next_cursor"].is_null());
+    }
+
+    // /api/events only sorts by known fields
+    #[actix_web::test]
+    async fn test_events_rejects_unknown_sort() {
+        let response = get(&memory_state(), "/api/events?sort=severity").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // /api/fhir/history takes the same filters
+    #[actix_web::test]
+    async fn test_fhir_history_filters() {
+        let state = memory_state();
+        state.db.insert_event(critical_event(5, 2.8)).await.unwrap();
+        let body = get_json(&state, "/api/fhir/history?patient_id=P-9999").await;
+        assert_eq!(body["total"], 0);
+        assert_eq!(body["entry"].as_array().unwrap().len(), 0);
+    }
+
+    // /api/stats reports on the events matching its filters
+    #[actix_web::test]
+    async fn test_stats() {
+        let state = memory_state();
+        state.db.insert_event(critical_event(5, 2.8)).await.unwrap();
+        let body = get_json(&state, "/api/stats?ward=ICU").await;
+        assert_eq!(body["total_events"], 1);
+        assert_eq!(body["by_severity"][0]["severity"], "Critical");
+    }
+
+    // /api/stats rejects a UTC offset no time zone has
+    #[actix_web::test]
+    async fn test_stats_rejects_offset() {
+        let response = get(&memory_state(), "/api/stats?utc_offset_minutes=100000").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // /api/events/{id}/waveform serves the event's stored waveform
+    #[actix_web::test]
+    async fn test_waveform() {
+        let state = memory_state();
+        let event = state.db.insert_event(critical_event(1, 3.0)).await.unwrap();
+        let now = Utc::now();
+        state
+            .db
+            .save_waveform(&Waveform {
+                event_id: event.id,
+                device_id: "pi-01".to_string(),
+                impact_at: now,
+                started_at: now - Duration::seconds(5),
+                ended_at: now + Duration::seconds(2),
+                points: Vec::new(),
+            })
+            .await
+            .unwrap();
+        let uri = format!("/api/events/{}/waveform", event.id);
+        let body = get_json(&state, &uri).await;
+        assert_eq!(body["device_id"], "pi-01");
+    }
+
+    // /api/events/{id}/waveform is a 404 for an event without one
+    #[actix_web::test]
+    async fn test_waveform_not_found() {
+        let response = get(&memory_state(), "/api/events/999/waveform").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    // /api/devices/{id}/telemetry defaults to the last 24 hours at a resolution giving a few
+    // hundred points
+    #[actix_web::test]
+    async fn test_device_telemetry() {
+        let state = memory_state();
+        let now = Utc::now();
+        let samples: Vec<TelemetrySample> = (0..20)
+            .map(|i| TelemetrySample {
+                device_id: "pi-01".to_string(),
+                recorded_at: now - Duration::seconds(i),
+                x: 0.0,
+                y: 0.0,
+                z: 9.8,
+                g_force: 1.0,
+                wifi: 90,
+                temp: 36.5,
+            })
+            .collect();
+        state.db.insert_telemetry(&samples).await.unwrap();
+        let body = get_json(&state, "/api/devices/pi-01/telemetry").await;
+        assert_eq!(body["resolution_seconds"], 300);
+        assert_eq!(body["source"], "rollup_60s");
+        let counted: i64 = body["buckets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|b| b["count"].as_i64().unwrap())
+            .sum();
+        assert_eq!(counted, 20);
+    }
+
+    // /api/devices/{id}/telemetry rejects an unknown resolution
+    #[actix_web::test]
+    async fn test_device_telemetry_rejects_resolution() {
+        let uri = "/api/devices/pi-01/telemetry?resolution=fast";
+        assert_eq!(
+            get(&memory_state(), uri).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    // /api/devices/{id}/telemetry rejects queries for too many points
+    #[actix_web::test]
+    async fn test_device_telemetry_rejects_oversized() {
+        let uri = "/api/devices/pi-01/telemetry?from=2026-01-01T00:00:00Z&to=2026-02-01T00:00:00Z&resolution=1s";
+        assert_eq!(
+            get(&memory_state(), uri).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    // /api/devices/{id}/telemetry rejects a range that ends before it starts
+    #[actix_web::test]
+    async fn test_device_telemetry_rejects_reversed_range() {
+        let uri = "/api/devices/pi-01/telemetry?from=2026-02-01T00:00:00Z&to=2026-01-01T00:00:00Z";
+        assert_eq!(
+            get(&memory_state(), uri).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    // Helper: places a legal hold on P-1001 through the API
+    async fn place_hold(state: &web::Data<AppState>) -> serde_json::Value {
+        let req = TestRequest::post()
+            .uri("/api/legal-holds")
+            .set_json(serde_json::json!({
+                "patient_id": "P-1001", "reason": "Lawsuit", "placed_by": "legal"
+            }));
+        read_body_json(call(state, req).await).await
+    }
+
+    // A legal hold names a patient or an alert
+    #[actix_web::test]
+    async fn test_legal_hold_needs_subject() {
+        let req = TestRequest::post()
+            .uri("/api/legal-holds")
+            .set_json(serde_json::json!({ "reason": "Lawsuit", "placed_by": "legal" }));
+        let response = call(&memory_state(), req).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // Holds are placed, listed while active and released
+    #[actix_web::test]
+    async fn test_legal_hold_release() {
+        let state = memory_state();
+        let hold = place_hold(&state).await;
+        assert_eq!(hold["patient_id"], "P-1001");
+        assert!(hold["released_at"].is_null());
+        let active = get_json(&state, "/api/legal-holds").await;
+        assert_eq!(active.as_array().unwrap().len(), 1);
+
+        let uri = format!("/api/legal-holds/{}?released_by=legal", hold["id"]);
+        let released: serde_json::Value =
+            read_body_json(call(&state, TestRequest::delete().uri(&uri)).await).await;
+        assert!(!released["released_at"].is_null());
+        let active = get_json(&state, "/api/legal-holds").await;
+        assert_eq!(active.as_array().unwrap().len(), 0);
+    }
+
+    // Releasing an unknown hold is a 404
+    #[actix_web::test]
+    async fn test_legal_hold_not_found() {
+        let req = TestRequest::delete().uri("/api/legal-holds/99?released_by=legal");
+        let response = call(&memory_state(), req).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    // Placing and releasing a hold are audited
+    #[actix_web::test]
+    async fn test_legal_hold_audit() {
+        let state = memory_state();
+        let hold = place_hold(&state).await;
+        let uri = format!("/api/legal-holds/{}?released_by=legal", hold["id"]);
+        call(&state, TestRequest::delete().uri(&uri)).await;
+        let audit = state
+            .db
+            .expired_records(DataClass::Audit, Utc::now() + Duration::hours(1), 10)
+            .await
+            .unwrap();
+        assert_eq!(audit.len(), 2);
+        assert_eq!(audit[0].record["action"], "legal_hold.placed");
+        assert_eq!(audit[1].record["action"], "legal_hold.released");
+    }
+
+    // Helper: PUT `body` to `uri`
+    async fn put(
+        state: &web::Data<AppState>,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> ServiceResponse {
+        call(state, TestRequest::put().uri(uri).set_json(body)).await
+    }
+
+    // PUT /api/devices/{id} registers the sensor and returns it with its last readings
+    #[actix_web::test]
+    async fn test_register_device() {
+        let state = memory_state();
+        state
+            .db
+            .record_device_status(&DeviceStatus {
+                device_id: "pi-01".to_string(),
+                seen_at: Utc::now(),
+                wifi_signal: Some(88),
+                temperature: None,
+                battery_level: Some(91.0),
+            })
+            .await
+            .unwrap();
+        let body = serde_json::json!({ "patient_id": "P-1001", "ward": "ICU", "calibration_state": "calibrated" });
+        let device: serde_json::Value =
+            read_body_json(put(&state, "/api/devices/pi-01", body).await).await;
+        assert_eq!(device["patient_id"], "P-1001");
+        assert_eq!(device["calibration_state"], "calibrated");
+        assert_eq!(device["battery_level"], 91.0);
+    }
+
+    // PUT /api/devices/{id} rejects an unknown calibration state
+    #[actix_web::test]
+    async fn test_register_device_rejects_calibration_state() {
+        let body = serde_json::json!({ "calibration_state": "shaken" });
+        let response = put(&memory_state(), "/api/devices/pi-01", body).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // PUT /api/patients/{id} stores the patient's demographics
+    #[actix_web::test]
+    async fn test_update_patient() {
+        let state = memory_state();
+        let body = serde_json::json!({ "family_name": "Perera", "given_name": "Nimal", "birth_date": "1941-05-02", "gender": "male" });
+        let response = put(&state, "/api/patients/P-1001", body).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let patient = state.db.get_patient("P-1001").await.unwrap().unwrap();
+        assert_eq!(patient.family_name.as_deref(), Some("Perera"));
+    }
+
+    // Helper: an assessment started two hours ago and due an hour ago
+    async fn overdue_assessment(state: &web::Data<AppState>) -> Assessment {
+        let event = state
+            .db
+            .insert_event(critical_event(180, 3.1))
+            .await
+            .unwrap();
+        let alert = state
+            .db
+            .open_alert(event.id, event.detected_at)
+            .await
+            .unwrap();
+        let form = &state.assessment_form;
+        state
+            .db
+            .create_assessment(&NewAssessment {
+                alert_id: alert.id,
+                event_id: event.id,
+                patient_id: Some("P-1001".to_string()),
+                form_id: form.id.clone(),
+                form_version: form.version.clone(),
+                due_at: Utc::now() - Duration::minutes(60),
+                created_at: Utc::now() - Duration::minutes(120),
+            })
+            .await
+            .unwrap()
+    }
+
+    // Helper: nurse:ward-3 saving `answers` for `alert_id`
+    async fn save_answers(
+        state: &web::Data<AppState>,
+        alert_id: i32,
+        answers: serde_json::Value,
+        complete: bool,
+    ) -> ServiceResponse {
+        let uri = format!("/api/alerts/{}/assessment", alert_id);
+        let body = serde_json::json!({
+            "author": "nurse:ward-3", "answers": answers, "complete": complete
+        });
+        put(state, &uri, body).await
+    }
+
+    // GET /api/assessments/form serves the assessment form
+    #[actix_web::test]
+    async fn test_assessment_form() {
+        let state = memory_state();
+        let form = get_json(&state, "/api/assessments/form").await;
+        assert_eq!(form["id"], "post-fall-assessment");
+        assert_eq!(
+            form["items"].as_array().unwrap().len(),
+            state.assessment_form.items.len()
+        );
+    }
+
+    // GET /api/assessments/overdue lists assessments in progress past their due time
+    #[actix_web::test]
+    async fn test_overdue_assessments() {
+        let state = memory_state();
+        answered_fall(&state, "CONFIRM_FALL").await;
+        let overdue = overdue_assessment(&state).await;
+        let listed = get_json(&state, "/api/assessments/overdue").await;
+        let listed = listed.as_array().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0]["id"], overdue.id);
+    }
+
+    // PUT /api/alerts/{id}/assessment saves the answers and returns the assessment
+    #[actix_web::test]
+    async fn test_save_assessment() {
+        let state = memory_state();
+        let alert = answered_fall(&state, "CONFIRM_FALL").await;
+        let answers = serde_json::json!({ "witnessed": false, "location": "bathroom" });
+        let draft: serde_json::Value =
+            read_body_json(save_answers(&state, alert.id, answers, false).await).await;
+        assert_eq!(draft["alert_id"], alert.id);
+        assert_eq!(draft["status"], "in-progress");
+        assert_eq!(draft["answers"]["location"], "bathroom");
+        assert!(draft["completed_at"].is_null());
+    }
+
+    // PUT /api/alerts/{id}/assessment rejects answers the form does not take, saying why
+    #[actix_web::test]
+    async fn test_save_assessment_rejects_answers() {
+        let state = memory_state();
+        let alert = answered_fall(&state, "CONFIRM_FALL").await;
+        let answers = serde_json::json!({ "injury": "grave" });
+        let response = save_answers(&state, alert.id, answers, false).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let text = String::from_utf8(read_body(response).await.to_vec()).unwrap();
+        assert!(text.contains("injury: expected one of"), "{}", text);
+    }
+
+    // PUT /api/alerts/{id}/assessment rejects a cancelled alert
+    #[actix_web::test]
+    async fn test_save_assessment_rejects_cancelled_alert() {
+        let state = memory_state();
+        let alert = answered_fall(&state, "CANCEL_ALERT").await;
+        let response = save_answers(&state, alert.id, serde_json::json!({}), false).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // PUT /api/alerts/{id}/assessment is a 404 for an unknown alert
+    #[actix_web::test]
+    async fn test_save_assessment_unknown_alert() {
+        let state = memory_state();
+        let response = save_answers(&state, 999, serde_json::json!({}), false).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    // GET /api/assessments filters by status and alert
+    #[actix_web::test]
+    async fn test_list_assessments() {
+        let state = memory_state();
+        let alert = answered_fall(&state, "CONFIRM_FALL").await;
+        overdue_assessment(&state).await;
+        let uri = format!("/api/assessments?status=in-progress&alert_id={}", alert.id);
+        let listed = get_json(&state, &uri).await;
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+        assert_eq!(listed[0]["alert_id"], alert.id);
+        let uri = format!(
+            "/api/assessments?status=completed,amended&alert_id={}",
+            alert.id
+        );
+        let finished = get_json(&state, &uri).await;
+        assert!(finished.as_array().unwrap().is_empty());
+    }
+
+    // Helper: POST a Morse entry for P-3003, `change` made to rn:jones's 65-point one
+    async fn post_morse(
+        state: &web::Data<AppState>,
+        change: (&str, serde_json::Value),
+    ) -> ServiceResponse {
+        let mut body = serde_json::json!({
+            "assessed_by": "rn:jones",
+            "history_of_falling": 25,
+            "secondary_diagnosis": 15,
+            "ambulatory_aid": 15,
+            "iv_therapy": 0,
+            "gait": 10,
+            "mental_status": 0,
+        });
+        body[change.0] = change.1;
+        let req = TestRequest::post()
+            .uri("/api/patients/P-3003/morse")
+            .set_json(body);
+        call(state, req).await
+    }
+
+    // Helper: POST /api/patients/P-3003/risk
+    async fn post_risk(state: &web::Data<AppState>) -> ServiceResponse {
+        let req = TestRequest::post().uri("/api/patients/P-3003/risk");
+        call(state, req).await
+    }
+
+    // POST /api/patients/{id}/morse records the entry and returns the recalculated score
+    #[actix_web::test]
+    async fn test_record_morse() {
+        let response = post_morse(&memory_state(), ("gait", serde_json::json!(10))).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let recorded: serde_json::Value = read_body_json(response).await;
+        assert_eq!(recorded["morse"]["total"], 65);
+        assert_eq!(recorded["risk"]["reason"], "morse");
+        assert_eq!(recorded["risk"]["morse_total"], 65);
+        assert_eq!(recorded["risk"]["rating"], "high");
+    }
+
+    // POST /api/patients/{id}/morse rejects points the scale does not give, saying which
+    #[actix_web::test]
+    async fn test_record_morse_rejects_points() {
+        let response = post_morse(&memory_state(), ("gait", serde_json::json!(5))).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let text = String::from_utf8(read_body(response).await.to_vec()).unwrap();
+        assert_eq!(text, "gait: expected one of [0, 10, 20], got 5");
+    }
+
+    // POST /api/patients/{id}/morse needs who assessed the patient
+    #[actix_web::test]
+    async fn test_record_morse_requires_assessor() {
+        let response = post_morse(&memory_state(), ("assessed_by", serde_json::json!(" "))).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let text = String::from_utf8(read_body(response).await.to_vec()).unwrap();
+        assert_eq!(text, "assessed_by is required");
+    }
+
+    // POST /api/patients/{id}/risk recalculates, and GET serves the newest score
+    #[actix_web::test]
+    async fn test_recalculate_risk() {
+        let state = memory_state();
+        post_morse(&state, ("gait", serde_json::json!(10))).await;
+        assert_eq!(post_risk(&state).await.status(), StatusCode::OK);
+        let current = get_json(&state, "/api/patients/P-3003/risk").await;
+        assert_eq!(current["reason"], "request");
+        assert_eq!(current["morse_total"], 65);
+    }
+
+    // GET /api/patients/{id}/risk is a 404 for a patient never scored
+    #[actix_web::test]
+    async fn test_risk_not_found() {
+        let response = get(&memory_state(), "/api/patients/P-9999/risk").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    // GET /api/patients/{id}/risk/history lists the scores newest first, up to the limit
+    #[actix_web::test]
+    async fn test_risk_history() {
+        let state = memory_state();
+        post_risk(&state).await;
+        post_morse(&state, ("gait", serde_json::json!(10))).await;
+        post_risk(&state).await;
+        let history = get_json(&state, "/api/patients/P-3003/risk/history").await;
+        let reasons: Vec<&str> = history
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["reason"].as_str().unwrap())
+            .collect();
+        assert_eq!(reasons, vec!["request", "morse", "request"]);
+        let page = get_json(&state, "/api/patients/P-3003/risk/history?limit=2").await;
+        assert_eq!(page.as_array().unwrap().len(), 2);
+    }
+
+    // Helper: P-4004's near miss (stillness 7.9) then fall on pi-09, as the detector logs them
+    async fn two_verdicts(state: &web::Data<AppState>) -> Vec<Detection> {
+        let source = ConnectionParams {
+            device_id: Some("pi-09".to_string()),
+            patient_id: Some("P-4004".to_string()),
+            ward: Some("Ward-C".to_string()),
+        };
+        let mut logged = Vec::new();
+        for (outcome, stillness_variance) in [("near-miss", 7.9), ("critical", 0.6)] {
+            let metrics = FallMetrics {
+                stillness_variance,
+                validation_ms: 2012,
+                orientation_change_deg: 12.0,
+            };
+            let why = Explanation::verdict(2.3, &metrics);
+            let detection =
+                source.detection("default", outcome, 2.3, Some(metrics), &why, Utc::now());
+            logged.push(state.db.record_detection(&detection).await.unwrap());
+        }
+        logged
+    }
+
+    // GET /api/detections filters by outcome, newest first, with each verdict's features
+    #[actix_web::test]
+    async fn test_list_detections() {
+        let state = memory_state();
+        two_verdicts(&state).await;
+        let uri = "/api/detections?outcome=near-miss,critical";
+        let listed = get_json(&state, uri).await;
+        let listed = listed.as_array().unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0]["outcome"], "critical");
+        assert_eq!(listed[1]["outcome"], "near-miss");
+        assert_eq!(listed[1]["stillness_variance"], 7.9);
+        assert_eq!(listed[1]["validation_ms"], 2012);
+    }
+
+    // GET /api/detections filters by patient, up to the limit
+    #[actix_web::test]
+    async fn test_list_detections_by_patient() {
+        let state = memory_state();
+        let logged = two_verdicts(&state).await;
+        let listed = get_json(&state, "/api/detections?patient_id=P-4004&limit=1").await;
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+        assert_eq!(listed[0]["id"], logged[1].id);
+    }
+
+    // GET /api/detections filters by device
+    #[actix_web::test]
+    async fn test_list_detections_by_device() {
+        let state = memory_state();
+        two_verdicts(&state).await;
+        let listed = get_json(&state, "/api/detections?device_id=pi-01").await;
+        assert_eq!(listed, serde_json::json!([]));
+    }
+
+    // GET /api/detections rejects outcomes the detector never reports
+    #[actix_web::test]
+    async fn test_list_detections_rejects_outcome() {
+        let response = get(&memory_state(), "/api/detections?outcome=fall").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // GET /api/events serves each detection's explanation
+    #[actix_web::test]
+    async fn test_events_explanation() {
+        let state = memory_state();
+        let explanation = Explanation::impact(3.2);
+        let event = NewEvent {
+            explanation: Some(explanation.clone()),
+            ..critical_event(1, 3.2)
+        };
+        state.db.insert_event(event).await.unwrap();
+        let events = get_json(&state, "/api/events").await;
+        assert_eq!(
+            events["items"][0]["explanation"],
+            serde_json::to_value(&explanation).unwrap()
+        );
+    }
+
+    // Helper: a confirmed fall and a cancelled alert, each with a one-point waveform (returns
+    // the fall's impact time)
+    async fn labelled_waveforms(state: &web::Data<AppState>) -> DateTime<Utc> {
+        let fall_at = Utc::now();
+        for (command, now) in [
+            ("CONFIRM_FALL", fall_at),
+            ("CANCEL_ALERT", fall_at + Duration::minutes(5)),
+        ] {
+            let alert = answered_fall(state, command).await;
+            let point = WaveformPoint {
+                offset_ms: 0,
+                x: 0.0,
+                y: 0.0,
+                z: 24.5,
+                g_force: 2.5,
+            };
+            let waveform = Waveform {
+                event_id: alert.event_id,
+                device_id: "pi-01".to_string(),
+                impact_at: now,
+                started_at: now,
+                ended_at: now,
+                points: vec![point],
+            };
+            state.db.save_waveform(&waveform).await.unwrap();
+        }
+        fall_at
+    }
+
+    // GET /api/labels lists the verdicts newest first, filtered by label
+    #[actix_web::test]
+    async fn test_list_labels() {
+        let state = memory_state();
+        labelled_waveforms(&state).await;
+        let listed = get_json(&state, "/api/labels").await;
+        let commands: Vec<&str> = listed
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|l| l["command"].as_str().unwrap())
+            .collect();
+        assert_eq!(commands, vec!["CANCEL_ALERT", "CONFIRM_FALL"]);
+        let falls = get_json(&state, "/api/labels?label=fall").await;
+        assert_eq!(falls.as_array().unwrap().len(), 1);
+        assert_eq!(falls[0]["label"], "fall");
+    }
+
+    // GET /api/labels rejects an unknown label
+    #[actix_web::test]
+    async fn test_list_labels_rejects_label() {
+        let response = get(&memory_state(), "/api/labels?label=maybe").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // GET /api/labels/export downloads the dataset, shifted back whole days, a year at least
+    #[actix_web::test]
+    async fn test_export_labels() {
+        let state = memory_state();
+        let impact_at = labelled_waveforms(&state).await;
+        let response = get(&state, "/api/labels/export").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("Content-Disposition").unwrap(),
+            "attachment; filename=\"labelled_dataset.json\""
+        );
+        let body: serde_json::Value = read_body_json(response).await;
+        assert_eq!(body["recordings"].as_array().unwrap().len(), 2);
+        let fall_at = body["recordings"][0]["fall_at"].as_f64().unwrap();
+        let moved = impact_at.timestamp_millis() as f64 / 1000.0 - fall_at;
+        assert!(moved >= 365.0 * 86400.0 && moved % 86400.0 == 0.0);
+    }
+
+    // Helper: "sensitive" would have alerted twice in shadow mode, a minute apart, where
+    // production saw nothing; returns the verdicts oldest first
+    async fn shadow_alerts(state: &web::Data<AppState>) -> Vec<Detection> {
+        let source = ConnectionParams {
+            device_id: Some("pi-05".to_string()),
+            patient_id: Some("P-5005".to_string()),
+            ward: Some("Ward 5".to_string()),
+        };
+        let mut logged = Vec::new();
+        for minutes_ago in [2, 1] {
+            let at = Utc::now() - chrono::Duration::minutes(minutes_ago);
+            let why = Explanation::impact(1.5);
+            let detection = NewDetection {
+                shadow: true,
+                ..source.detection("sensitive", "critical", 1.5, None, &why, at)
+            };
+            logged.push(state.db.record_detection(&detection).await.unwrap());
+        }
+        logged
+    }
+
+    // GET /api/detections leaves out shadow-mode verdicts by default
+    #[actix_web::test]
+    async fn test_list_detections_hides_shadow() {
+        let state = memory_state();
+        shadow_alerts(&state).await;
+        let listed = get_json(&state, "/api/detections").await;
+        assert_eq!(listed, serde_json::json!([]));
+    }
+
+    // GET /api/detections lists a candidate's shadow-mode verdicts when asked
+    #[actix_web::test]
+    async fn test_list_detections_shadow() {
+        let state = memory_state();
+        shadow_alerts(&state).await;
+        let uri = "/api/detections?shadow=true&detector=sensitive";
+        let listed = get_json(&state, uri).await;
+        assert_eq!(listed.as_array().unwrap().len(), 2);
+        assert_eq!(listed[0]["detector"], "sensitive");
+        assert_eq!(listed[0]["shadow"], true);
+    }
+
+    // GET /api/shadow/disagreements compares the candidate with production, newest case first
+    #[actix_web::test]
+    async fn test_shadow_disagreements() {
+        let state = memory_state();
+        let logged = shadow_alerts(&state).await;
+        let uri = "/api/shadow/disagreements?detector=sensitive";
+        let report = get_json(&state, uri).await;
+        assert_eq!(report["detector"], "sensitive");
+        assert_eq!(report["production_verdicts"], 0);
+        assert_eq!(report["candidate_fired"], 2);
+        assert_eq!(report["cases"][0]["kind"], "candidate-fired");
+        assert_eq!(report["cases"][0]["candidate"]["id"], logged[1].id);
+        assert_eq!(report["cases"][0]["production"], serde_json::Value::Null);
+    }
+
+    // GET /api/shadow/disagreements compares only verdicts within the range
+    #[actix_web::test]
+    async fn test_shadow_disagreements_in_range() {
+        let state = memory_state();
+        let logged = shadow_alerts(&state).await;
+        let from = logged[1].detected_at.to_rfc3339().replace('+', "%2B");
+        let uri = format!("/api/shadow/disagreements?detector=sensitive&from={}", from);
+        let report = get_json(&state, &uri).await;
+        assert_eq!(report["candidate_verdicts"], 1);
+    }
+
+    // GET /api/shadow/disagreements keeps `limit` cases but counts them all
+    #[actix_web::test]
+    async fn test_shadow_disagreements_limit() {
+        let state = memory_state();
+        shadow_alerts(&state).await;
+        let uri = "/api/shadow/disagreements?detector=sensitive&limit=1";
+        let report = get_json(&state, uri).await;
+        assert_eq!(report["cases"].as_array().unwrap().len(), 1);
+        assert_eq!(report["candidate_fired"], 2);
+    }
+
+    // GET /api/shadow/disagreements is refused without a candidate
+    #[actix_web::test]
+    async fn test_shadow_disagreements_requires_detector() {
+        let response = get(&memory_state(), "/api/shadow/disagreements").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = read_body(response).await;
+        assert!(String::from_utf8_lossy(&body).starts_with("detector is required"));
+    }
+
+    // GET /api/shadow/disagreements is refused for a blank candidate
+    #[actix_web::test]
+    async fn test_shadow_disagreements_blank_detector() {
+        let uri = "/api/shadow/disagreements?detector=%20";
+        let response = get(&memory_state(), uri).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
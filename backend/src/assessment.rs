@@ -0,0 +1,542 @@
+use crate::model::{Alert, Assessment, FallLog};
+use crate::storage::assessments::ASSESSMENT_STATUSES;
+use crate::storage::{
+    AssessmentQuery, AssessmentUpdate, NewAssessment, NewAuditEntry, Repository, StorageError,
+    StorageResult,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// Form used when `ASSESSMENT_FORM` is not set (also a template for custom forms).
+pub const DEFAULT_FORM: &str = include_str!("../forms/post_fall_assessment.json");
+
+/// Item types a form may use (a subset of FHIR Questionnaire item types).
+pub const ITEM_TYPES: [&str; 6] = ["boolean", "integer", "string", "text", "dateTime", "choice"];
+
+/// Audit log entity type of assessments.
+pub const ASSESSMENT_ENTITY: &str = "Assessment";
+
+/// One answer a `choice` item offers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnswerOption {
+    pub code: String,
+    pub display: String,
+}
+
+/// **Form Item**
+///
+/// One question. `repeats` items are answered with an array; `choice` items with option codes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormItem {
+    pub link_id: String,
+    pub text: String,
+    #[serde(rename = "type")]
+    pub item_type: String, // One of ITEM_TYPES
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub repeats: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<AnswerOption>,
+}
+
+/// **Assessment Form**
+///
+/// The post-fall assessment nurses complete after a confirmed fall (served as a FHIR
+/// Questionnaire). Loaded from the JSON file named by `ASSESSMENT_FORM`, else `DEFAULT_FORM`.
+/// - `ASSESSMENT_FORM`: path of a form definition in the same format as `forms/post_fall_assessment.json`.
+///
+/// Assessments are due `due_minutes` after the nurse confirms the fall; after that, unfinished
+/// ones are listed as overdue.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssessmentForm {
+    pub id: String,
+    pub version: String,
+    pub title: String,
+    pub due_minutes: i64,
+    pub items: Vec<FormItem>,
+}
+
+impl Default for AssessmentForm {
+    fn default() -> Self {
+        Self::parse(DEFAULT_FORM).expect("the built-in assessment form is valid")
+    }
+}
+
+impl AssessmentForm {
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("ASSESSMENT_FORM") {
+            Ok(path) => {
+                let text = std::fs::read_to_string(&path)
+                    .map_err(|e| format!("ASSESSMENT_FORM: cannot read {}: {}", path, e))?;
+                Self::parse(&text).map_err(|e| format!("ASSESSMENT_FORM: {}: {}", path, e))
+            }
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Reads a form definition and checks it can be answered.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let form: Self = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        if form.id.trim().is_empty() || form.version.trim().is_empty() {
+            return Err("the form needs an id and a version".to_string());
+        }
+        if form.due_minutes < 0 {
+            return Err(format!(
+                "due_minutes must not be negative: {}",
+                form.due_minutes
+            ));
+        }
+        if form.items.is_empty() {
+            return Err("the form has no items".to_string());
+        }
+        for (i, item) in form.items.iter().enumerate() {
+            if item.link_id.trim().is_empty() {
+                return Err(format!("item {} has no link_id", i + 1));
+            }
+            if form.items[..i].iter().any(|o| o.link_id == item.link_id) {
+                return Err(format!("duplicate link_id: {}", item.link_id));
+            }
+            if !ITEM_TYPES.contains(&item.item_type.as_str()) {
+                return Err(format!(
+                    "item {}: unsupported type {:?} (expected one of {})",
+                    item.link_id,
+                    item.item_type,
+                    ITEM_TYPES.join(", ")
+                ));
+            }
+            if (item.item_type == "choice") == item.options.is_empty() {
+                return Err(format!(
+                    "item {}: options are required for choice items and only allowed there",
+                    item.link_id
+                ));
+            }
+        }
+        Ok(form)
+    }
+
+    pub fn item(&self, link_id: &str) -> Option<&FormItem> {
+        self.items.iter().find(|i| i.link_id == link_id)
+    }
+
+    pub fn due_at(&self, confirmed_at: DateTime<Utc>) -> DateTime<Utc> {
+        confirmed_at + Duration::minutes(self.due_minutes)
+    }
+
+    /// Checks answers against the items: known link ids, values of the item's type (arrays
+    /// for `repeats` items) and, for a completed form, every required item answered.
+    /// Returns the answers to store (nulls and empty arrays count as unanswered and are dropped).
+    pub fn check_answers(
+        &self,
+        answers: &Map<String, Value>,
+        complete: bool,
+    ) -> Result<Map<String, Value>, String> {
+        let mut checked = Map::new();
+        for (link_id, value) in answers {
+            let item = self
+                .item(link_id)
+                .ok_or_else(|| format!("unknown item: {}", link_id))?;
+            let values = match (item.repeats, value) {
+                (_, Value::Null) => continue,
+                (true, Value::Array(values)) if values.is_empty() => continue,
+                (true, Value::Array(values)) => values.iter().collect(),
+                (true, _) => return Err(format!("{}: expected a list of answers", link_id)),
+                (false, Value::Array(_)) => {
+                    return Err(format!("{}: only one answer is allowed", link_id))
+                }
+                (false, value) => vec![value],
+            };
+            for value in values {
+                check_value(item, value)?;
+            }
+            checked.insert(link_id.clone(), value.clone());
+        }
+        if complete {
+            let missing: Vec<&str> = self
+                .items
+                .iter()
+                .filter(|i| i.required && !checked.contains_key(&i.link_id))
+                .map(|i| i.link_id.as_str())
+                .collect();
+            if !missing.is_empty() {
+                return Err(format!("required items unanswered: {}", missing.join(", ")));
+            }
+        }
+        Ok(checked)
+    }
+}
+
+fn check_value(item: &FormItem, value: &Value) -> Result<(), String> {
+    let valid = match item.item_type.as_str() {
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64(),
+        "string" | "text" => value.is_string(),
+        "dateTime" => value
+            .as_str()
+            .is_some_and(|v| DateTime::parse_from_rfc3339(v).is_ok()),
+        "choice" => value
+            .as_str()
+            .is_some_and(|v| item.options.iter().any(|o| o.code == v)),
+        _ => false,
+    };
+    if valid {
+        return Ok(());
+    }
+    let expected = match item.item_type.as_str() {
+        "choice" => format!(
+            "one of {}",
+            item.options
+                .iter()
+                .map(|o| o.code.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        "dateTime" => "an RFC 3339 date and time".to_string(),
+        other => format!("a {} value", other),
+    };
+    Err(format!(
+        "{}: expected {}, got {}",
+        item.link_id, expected, value
+    ))
+}
+
+/// The assessment of an alert, if one was started.
+pub async fn for_alert(db: &dyn Repository, alert_id: i32) -> StorageResult<Option<Assessment>> {
+    let query = AssessmentQuery {
+        alert_id: Some(alert_id),
+        ..AssessmentQuery::default()
+    };
+    Ok(db.query_assessments(&query).await?.into_iter().next())
+}
+
+/// **Open Assessment**
+///
+/// Starts the assessment owed for a confirmed fall (due `due_minutes` from now). Confirming
+/// the same alert again keeps the assessment already started.
+pub async fn open(
+    db: &dyn Repository,
+    form: &AssessmentForm,
+    alert: &Alert,
+    event: Option<&FallLog>,
+    now: DateTime<Utc>,
+) -> StorageResult<Assessment> {
+    if let Some(existing) = for_alert(db, alert.id).await? {
+        return Ok(existing);
+    }
+    db.create_assessment(&NewAssessment {
+        alert_id: alert.id,
+        event_id: alert.event_id,
+        patient_id: event.and_then(|e| e.patient_id.clone()),
+        form_id: form.id.clone(),
+        form_version: form.version.clone(),
+        due_at: form.due_at(now),
+        created_at: now,
+    })
+    .await
+}
+
+/// **Save Assessment**
+///
+/// Replaces the answers of an alert's assessment, starting it first if the alert was never
+/// confirmed from the dashboard. `complete` finishes the form (required items must be
+/// answered); saving a finished form again amends it. Every save is written to the audit log.
+pub async fn save(
+    db: &dyn Repository,
+    form: &AssessmentForm,
+    alert_id: i32,
+    answers: &Map<String, Value>,
+    author: &str,
+    complete: bool,
+    now: DateTime<Utc>,
+) -> StorageResult<Assessment> {
+    if author.trim().is_empty() {
+        return Err(StorageError::InvalidQuery("author is required".to_string()));
+    }
+    let alert = db
+        .get_alert(alert_id)
+        .await?
+        .ok_or_else(|| StorageError::NotFound(format!("alert {}", alert_id)))?;
+    if alert.status == "Cancelled" {
+        return Err(StorageError::InvalidQuery(format!(
+            "alert {} was cancelled as a false alarm",
+            alert_id
+        )));
+    }
+    let answers = form
+        .check_answers(answers, complete)
+        .map_err(StorageError::InvalidQuery)?;
+
+    let assessment = match for_alert(db, alert_id).await? {
+        Some(existing) => existing,
+        None => {
+            let event = db.get_event(alert.event_id).await?;
+            open(db, form, &alert, event.as_ref(), now).await?
+        }
+    };
+    let finished = assessment.status != ASSESSMENT_STATUSES[0];
+    let status = match (complete, finished) {
+        (false, false) => "in-progress",
+        (true, false) => "completed",
+        (true, true) => "amended",
+        (false, true) => {
+            return Err(StorageError::InvalidQuery(format!(
+                "assessment {} is already complete: amend it with complete=true",
+                assessment.id
+            )))
+        }
+    };
+    let update = AssessmentUpdate {
+        status: status.to_string(),
+        answers: Value::Object(answers).to_string(),
+        author: Some(author.to_string()),
+        updated_at: now,
+        completed_at: assessment.completed_at.or(complete.then_some(now)),
+    };
+    let saved = db.update_assessment(assessment.id, &update).await?;
+
+    let action = match status {
+        "in-progress" => "saved",
+        other => other,
+    };
+    db.record_audit(NewAuditEntry {
+        recorded_at: now,
+        actor: author.to_string(),
+        action: format!("assessment.{}", action),
+        entity_type: ASSESSMENT_ENTITY.to_string(),
+        entity_id: Some(saved.id.to_string()),
+        detail: Some(json!({
+            "alert_id": saved.alert_id,
+            "event_id": saved.event_id,
+            "status": saved.status,
+        })),
+    })
+    .await?;
+    Ok(saved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::AuditQuery;
+    use crate::test_support::{answered_fall, memory_state};
+
+    // Helper: `answers` (a JSON object) checked against the built-in form
+    fn check(answers: Value, complete: bool) -> Result<Map<String, Value>, String> {
+        let answers = answers.as_object().unwrap();
+        AssessmentForm::default().check_answers(answers, complete)
+    }
+
+    // Helper: every item of the built-in form answered
+    fn all_answered() -> Map<String, Value> {
+        let answers = json!({
+            "witnessed": false,
+            "location": "bathroom",
+            "head-strike": "unknown",
+            "injury": "minor",
+            "physician-notified": true,
+            "contributing-factors": ["toileting", "footwear"],
+            "care-plan-changes": null,
+        });
+        answers.as_object().unwrap().clone()
+    }
+
+    // Helper: `answers` saved by nurse:ward-3 for `alert_id`
+    async fn save_as_nurse(
+        db: &dyn Repository,
+        alert_id: i32,
+        answers: &Map<String, Value>,
+        complete: bool,
+    ) -> StorageResult<Assessment> {
+        let form = AssessmentForm::default();
+        save(
+            db,
+            &form,
+            alert_id,
+            answers,
+            "nurse:ward-3",
+            complete,
+            Utc::now(),
+        )
+        .await
+    }
+
+    // Answers must name items of the form
+    #[test]
+    fn test_check_answers_unknown_item() {
+        let error = check(json!({ "mood": "ok" }), false).unwrap_err();
+        assert!(error.contains("unknown item: mood"), "{}", error);
+    }
+
+    // Choice items take one of their option codes
+    #[test]
+    fn test_check_answers_choice() {
+        let error = check(json!({ "injury": "grave" }), false).unwrap_err();
+        assert!(error.contains("injury: expected one of"), "{}", error);
+    }
+
+    // Boolean items take a boolean
+    #[test]
+    fn test_check_answers_boolean() {
+        let error = check(json!({ "witnessed": "yes" }), false).unwrap_err();
+        assert!(error.contains("witnessed: expected a boolean"), "{}", error);
+    }
+
+    // Repeating items take a list
+    #[test]
+    fn test_check_answers_repeats() {
+        let error = check(json!({ "contributing-factors": "toileting" }), false).unwrap_err();
+        assert!(error.contains("expected a list"), "{}", error);
+    }
+
+    // A completed form answers every required item
+    #[test]
+    fn test_check_answers_required() {
+        let error = check(json!({ "witnessed": false }), true).unwrap_err();
+        assert_eq!(
+            error,
+            "required items unanswered: location, head-strike, injury, physician-notified"
+        );
+    }
+
+    // Unanswered items (nulls) are dropped
+    #[test]
+    fn test_check_answers_drops_unanswered() {
+        let checked = AssessmentForm::default()
+            .check_answers(&all_answered(), true)
+            .unwrap();
+        assert!(!checked.contains_key("care-plan-changes"));
+        assert_eq!(checked["injury"], "minor");
+    }
+
+    // Only the confirmed fall owes an assessment, due `due_minutes` after the nurse confirmed it
+    #[actix_web::test]
+    async fn test_confirmed_fall_opens_assessment() {
+        let state = memory_state();
+        let confirmed = answered_fall(&state, "CONFIRM_FALL").await;
+        answered_fall(&state, "CANCEL_ALERT").await;
+        let started = state
+            .db
+            .query_assessments(&AssessmentQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(started.len(), 1);
+        let assessment = &started[0];
+        assert_eq!(assessment.alert_id, confirmed.id);
+        assert_eq!(assessment.patient_id.as_deref(), Some("P-1001"));
+        assert_eq!(assessment.status, "in-progress");
+        assert_eq!(
+            assessment.due_at,
+            assessment.created_at + Duration::minutes(state.assessment_form.due_minutes)
+        );
+    }
+
+    // Saving needs an author
+    #[actix_web::test]
+    async fn test_save_requires_author() {
+        let state = memory_state();
+        let alert = answered_fall(&state, "CONFIRM_FALL").await;
+        let form = AssessmentForm::default();
+        let db = state.db.as_ref();
+        let saved = save(db, &form, alert.id, &Map::new(), " ", false, Utc::now()).await;
+        match saved {
+            Err(StorageError::InvalidQuery(message)) => assert_eq!(message, "author is required"),
+            other => panic!("expected an invalid query, got {:?}", other),
+        }
+    }
+
+    // A false alarm owes no assessment
+    #[actix_web::test]
+    async fn test_save_rejects_cancelled_alert() {
+        let state = memory_state();
+        let alert = answered_fall(&state, "CANCEL_ALERT").await;
+        let saved = save_as_nurse(state.db.as_ref(), alert.id, &Map::new(), false).await;
+        assert!(matches!(saved, Err(StorageError::InvalidQuery(_))));
+    }
+
+    // Saving for an unknown alert is NotFound
+    #[actix_web::test]
+    async fn test_save_unknown_alert() {
+        let saved = save_as_nurse(memory_state().db.as_ref(), 999, &Map::new(), false).await;
+        assert!(matches!(saved, Err(StorageError::NotFound(_))));
+    }
+
+    // A draft keeps the assessment in progress
+    #[actix_web::test]
+    async fn test_save_draft() {
+        let state = memory_state();
+        let alert = answered_fall(&state, "CONFIRM_FALL").await;
+        let answers = json!({ "witnessed": false, "location": "bathroom" });
+        let draft = save_as_nurse(
+            state.db.as_ref(),
+            alert.id,
+            answers.as_object().unwrap(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(draft.status, "in-progress");
+        assert_eq!(draft.answer_map()["location"], "bathroom");
+        assert!(draft.completed_at.is_none());
+    }
+
+    // Completing the form records when it was completed
+    #[actix_web::test]
+    async fn test_save_complete() {
+        let state = memory_state();
+        let alert = answered_fall(&state, "CONFIRM_FALL").await;
+        let done = save_as_nurse(state.db.as_ref(), alert.id, &all_answered(), true)
+            .await
+            .unwrap();
+        assert_eq!(done.status, "completed");
+        assert!(done.completed_at.is_some());
+    }
+
+    // A completed form can only be amended, and keeps its completion time
+    #[actix_web::test]
+    async fn test_save_amends_completed() {
+        let state = memory_state();
+        let db = state.db.as_ref();
+        let alert = answered_fall(&state, "CONFIRM_FALL").await;
+        let done = save_as_nurse(db, alert.id, &all_answered(), true)
+            .await
+            .unwrap();
+        let draft = save_as_nurse(db, alert.id, &all_answered(), false).await;
+        assert!(matches!(draft, Err(StorageError::InvalidQuery(_))));
+        let amended = save_as_nurse(db, alert.id, &all_answered(), true)
+            .await
+            .unwrap();
+        assert_eq!(amended.status, "amended");
+        assert_eq!(amended.completed_at, done.completed_at);
+    }
+
+    // Every save is audited with what it did
+    #[actix_web::test]
+    async fn test_save_audited() {
+        let state = memory_state();
+        let db = state.db.as_ref();
+        let alert = answered_fall(&state, "CONFIRM_FALL").await;
+        for complete in [false, true, true] {
+            save_as_nurse(db, alert.id, &all_answered(), complete)
+                .await
+                .unwrap();
+        }
+        let audit = db
+            .audit_entries(&AuditQuery {
+                entity_type: Some(ASSESSMENT_ENTITY.to_string()),
+                limit: 10,
+                ..AuditQuery::default()
+            })
+            .await
+            .unwrap();
+        let actions: Vec<&str> = audit.iter().map(|a| a.action.as_str()).collect();
+        assert_eq!(
+            actions,
+            vec![
+                "assessment.amended",
+                "assessment.completed",
+                "assessment.saved"
+            ]
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use backend::evaluation::{self, Recording};
+use backend::logic::DetectorProfile;
+use backend::tuning::{self, Goal, Objective, ParamRange, Search, SearchSpace};
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: evaluate [--profile PROFILE.json]... [--format table|json] PATH...
+       evaluate tune [OPTIONS] PATH...
+
+Replays labelled recordings through each detector profile (the default profile when none is
+given) and reports the confusion matrix, sensitivity, specificity, detection latency and false
+alarms per hour. PATH is a .csv, .json or SisFall .txt file, or a directory of them.
+
+`tune` searches the detector thresholds for the profile that best meets an objective, writes it
+as a profile file (usable as DETECTOR_PROFILE) and its ROC curve as CSV:
+  --objective sensitivity|specificity|youden   What to maximise (default youden)
+  --max-false-alarms-per-day N                 Constraint, e.g. 2
+  --min-sensitivity S                          Constraint, e.g. 0.95
+  --impact MIN:MAX:STEP                        Impact threshold in g (default 1.2:3.0:0.1)
+  --stillness MIN:MAX:STEP                     Stillness variance (default 0.5:6.0:0.5)
+  --window MIN:MAX:STEP                        Validation window in ms (default 2000)
+  --random N [--seed S]                        Try N random candidates instead of the grid
+  --name NAME                                  Profile name (default tuned)
+  --output FILE                                Profile file (default detector_profile.json)
+  --roc FILE                                   ROC curve (default roc.csv)
+  --format table|json                          Summary on stdout";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let result = if args.first().map(String::as_str) == Some("tune") {
+        args.remove(0);
+        tune(args).await
+    } else {
+        evaluate(args).await
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("evaluate: {}\n\n{}", e, USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn evaluate(args: Vec<String>) -> Result<(), String> {
+    let mut profiles = Vec::new();
+    let mut json = false;
+    let mut paths = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--profile" => profiles.push(DetectorProfile::load(&value(&mut args, &arg)?)?),
+            "--format" => json = is_json(&value(&mut args, &arg)?)?,
+            _ => paths.push(arg),
+        }
+    }
+    if profiles.is_empty() {
+        profiles.push(DetectorProfile::default());
+    }
+    let recordings = load(&paths)?;
+
+    let mut reports = Vec::new();
+    for profile in &profiles {
+        reports.push(evaluation::evaluate(profile, &recordings).await);
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports).unwrap());
+    } else {
+        let tables: Vec<_> = reports.iter().map(|r| r.table()).collect();
+        print!("{}", tables.join("\n"));
+    }
+    Ok(())
+}
+
+async fn tune(args: Vec<String>) -> Result<(), String> {
+    let mut space = SearchSpace::default();
+    let mut objective = Objective::default();
+    let mut random = None;
+    let mut seed = 1;
+    let mut name = "tuned".to_string();
+    let mut output = "detector_profile.json".to_string();
+    let mut roc = "roc.csv".to_string();
+    let mut json = false;
+    let mut paths = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--objective" => objective.goal = value(&mut args, &arg)?.parse::<Goal>()?,
+            "--max-false-alarms-per-day" => {
+                objective.max_false_alarms_per_day = Some(number(&value(&mut args, &arg)?)?)
+            }
+            "--min-sensitivity" => {
+                objective.min_sensitivity = Some(number(&value(&mut args, &arg)?)?)
+            }
+            "--impact" => space.impact_threshold_g = ParamRange::parse(&value(&mut args, &arg)?)?,
+            "--stillness" => {
+                space.stillness_threshold_variance = ParamRange::parse(&value(&mut args, &arg)?)?
+            }
+            "--window" => space.validation_ms = ParamRange::parse(&value(&mut args, &arg)?)?,
+            "--random" => random = Some(number(&value(&mut args, &arg)?)? as usize),
+            "--seed" => seed = number(&value(&mut args, &arg)?)? as u64,
+            "--name" => name = value(&mut args, &arg)?,
+            "--output" => output = value(&mut args, &arg)?,
+            "--roc" => roc = value(&mut args, &arg)?,
+            "--format" => json = is_json(&value(&mut args, &arg)?)?,
+            _ => paths.push(arg),
+        }
+    }
+    let search = match random {
+        Some(samples) => Search::Random { samples, seed },
+        None => Search::Grid,
+    };
+    let recordings = load(&paths)?;
+
+    let tuned = tuning::tune(&recordings, &space, search, &objective, &name).await?;
+    let profile = serde_json::to_string_pretty(&tuned.best.profile).unwrap();
+    std::fs::write(&output, profile + "\n")
+        .map_err(|e| format!("cannot write {}: {}", output, e))?;
+    std::fs::write(&roc, tuned.roc_csv()).map_err(|e| format!("cannot write {}: {}", roc, e))?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&tuned).unwrap());
+    } else {
+        print!("{}", tuned.table());
+        println!("\nWrote {} and {}", output, roc);
+    }
+    Ok(())
+}
+
+fn load(paths: &[String]) -> Result<Vec<Recording>, String> {
+    if paths.is_empty() {
+        return Err("no recordings given".to_string());
+    }
+    let mut recordings = Vec::new();
+    for path in paths {
+        recordings.extend(evaluation::load(Path::new(path))?);
+    }
+    Ok(recordings)
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("{} needs a value", flag))
+}
+
+fn number(text: &str) -> Result<f64, String> {
+    text.parse()
+        .map_err(|_| format!("expected a number: {}", text))
+}
+
+fn is_json(format: &str) -> Result<bool, String> {
+    match format {
+        "json" => Ok(true),
+        "table" => Ok(false),
+        _ => Err("--format is table or json".to_string()),
+    }
+}
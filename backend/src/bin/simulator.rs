@@ -7,7 +7,10 @@ use url::Url;
 #[tokio::main]
 async fn main() {
     // --- UPDATED URL WITH SECURITY KEY ---
-    let url = Url::parse("ws://127.0.0.1:8080/ws?key=admin123").unwrap();
+    // Override with SIMULATOR_URL to target another server (e.g. a CI instance).
+    let target = std::env::var("SIMULATOR_URL")
+        .unwrap_or_else(|_| "ws://127.0.0.1:8080/ws?key=admin123&device_id=simulator".to_string());
+    let url = Url::parse(&target).unwrap();
 
     println!("🔌 Connecting to FallGuard Server...");
 
@@ -26,14 +29,11 @@ async fn main() {
             "x": 0.1,
             "y": 0.9,
             "z": 0.2,
-            "timestamp": 170000000 + i
+            "t": 170000000 + i
         });
 
         // Convert JSON to String, then into WebSocket Message
-        write
-            .send(Message::Text(packet.to_string().into()))
-            .await
-            .unwrap();
+        write.send(Message::Text(packet.to_string())).await.unwrap();
 
         println!("🚶 Sending Normal Data... ({}/10)", i);
         sleep(Duration::from_millis(500)).await;
@@ -45,11 +45,11 @@ async fn main() {
         "x": 24.5, // High G-Force (~2.5G * 9.8)
         "y": 2.0,
         "z": 1.0,
-        "timestamp": 170000100
+        "t": 170000100
     });
 
     write
-        .send(Message::Text(fall_packet.to_string().into()))
+        .send(Message::Text(fall_packet.to_string()))
         .await
         .unwrap();
 
@@ -0,0 +1,609 @@
+use crate::logic::{DetectionEvent, DetectorProfile, GRAVITY};
+use crate::model::SensorData;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Sampling rate of SisFall trials (Hz).
+pub const SISFALL_RATE_HZ: f64 = 200.0;
+
+/// SisFall ADXL345 counts to G (±16 g over 13 bits).
+const SISFALL_G_PER_COUNT: f64 = 2.0 * 16.0 / 8192.0;
+
+/// **Labelled Recording**
+///
+/// One trial to replay through a detector: samples in the live ingress format (m/s², `t` in
+/// seconds), whether it contains a fall and, when known, when the fall started.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    #[serde(default)]
+    pub name: String,
+    pub fall: bool,
+    #[serde(default)]
+    pub fall_at: Option<f64>, // Seconds, same clock as the samples
+    pub samples: Vec<SensorData>,
+}
+
+impl Recording {
+    pub fn duration_s(&self) -> f64 {
+        match (self.samples.first(), self.samples.last()) {
+            (Some(first), Some(last)) => last.timestamp - first.timestamp,
+            _ => 0.0,
+        }
+    }
+
+    /// When the fall started: the labelled onset, else the sample with the strongest impact.
+    pub fn onset(&self) -> Option<f64> {
+        if !self.fall {
+            return None;
+        }
+        self.fall_at.or_else(|| {
+            self.samples
+                .iter()
+                .max_by(|a, b| g_force(a).total_cmp(&g_force(b)))
+                .map(|s| s.timestamp)
+        })
+    }
+}
+
+fn g_force(s: &SensorData) -> f64 {
+    crate::logic::calculate_g_force(s.x, s.y, s.z)
+}
+
+fn sample(x: f64, y: f64, z: f64, t: f64) -> SensorData {
+    SensorData {
+        x,
+        y,
+        z,
+        timestamp: t,
+        wifi: 0,
+        temp: 0.0,
+        battery: None,
+    }
+}
+
+/// Reads labelled CSV: a header with `t`, `x`, `y` and `z`, an optional `fall` (or `label`)
+/// column marking the samples of a fall (1/true), and an optional `recording` column that
+/// splits the file into several recordings. The first marked sample is the fall onset.
+pub fn parse_csv(name: &str, text: &str) -> Result<Vec<Recording>, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty());
+    let (_, header) = lines.next().ok_or("empty file")?;
+    let columns: Vec<String> = header
+        .split(',')
+        .map(|c| c.trim().to_ascii_lowercase())
+        .collect();
+    let find = |names: &[&str]| columns.iter().position(|c| names.contains(&c.as_str()));
+    let required =
+        |names: &[&str]| find(names).ok_or_else(|| format!("missing column {}", names[0]));
+    let (t, x, y, z) = (
+        required(&["t", "timestamp"])?,
+        required(&["x"])?,
+        required(&["y"])?,
+        required(&["z"])?,
+    );
+    let (label, group) = (find(&["fall", "label"]), find(&["recording"]));
+
+    let mut recordings: Vec<Recording> = Vec::new();
+    for (i, line) in lines {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let number = |c: usize| {
+            fields
+                .get(c)
+                .and_then(|v| v.parse::<f64>().ok())
+                .ok_or_else(|| format!("line {}: {} is not a number", i + 1, columns[c]))
+        };
+        let data = sample(number(x)?, number(y)?, number(z)?, number(t)?);
+        let marked = label
+            .and_then(|c| fields.get(c))
+            .is_some_and(|v| matches!(v.to_ascii_lowercase().as_str(), "1" | "true" | "fall"));
+        let key = match group.and_then(|c| fields.get(c)) {
+            Some(id) => format!("{}:{}", name, id),
+            None => name.to_string(),
+        };
+        let recording = match recordings.iter_mut().position(|r| r.name == key) {
+            Some(i) => &mut recordings[i],
+            None => {
+                recordings.push(Recording {
+                    name: key,
+                    fall: false,
+                    fall_at: None,
+                    samples: Vec::new(),
+                });
+                recordings.last_mut().expect("just pushed")
+            }
+        };
+        if marked && !recording.fall {
+            recording.fall = true;
+            recording.fall_at = Some(data.timestamp);
+        }
+        recording.samples.push(data);
+    }
+    Ok(recordings)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RecordingsJson {
+    List(Vec<Recording>),
+    Wrapped { recordings: Vec<Recording> },
+}
+
+/// Reads labelled JSON: a list of recordings (or `{"recordings": [...]}`), each with `fall`,
+/// an optional `fall_at` and the `samples` as sent by the sensors.
+pub fn parse_json(name: &str, text: &str) -> Result<Vec<Recording>, String> {
+    let parsed: RecordingsJson = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let mut recordings = match parsed {
+        RecordingsJson::List(r) | RecordingsJson::Wrapped { recordings: r } => r,
+    };
+    for (i, r) in recordings.iter_mut().enumerate() {
+        if r.name.is_empty() {
+            r.name = format!("{}#{}", name, i + 1);
+        }
+    }
+    Ok(recordings)
+}
+
+/// Reads a SisFall trial (`F05_SA01_R01.txt`): one `;`-terminated line of raw counts per
+/// sample at 200 Hz, of which the first three columns (the ADXL345) are used. Trials named
+/// `F…` are falls and `D…` activities of daily living; the onset is not labelled.
+pub fn parse_sisfall(name: &str, text: &str) -> Result<Recording, String> {
+    let fall = match name.chars().next() {
+        Some('F') => true,
+        Some('D') => false,
+        _ => return Err("SisFall trials are named F… (falls) or D… (activities)".to_string()),
+    };
+    let mut samples = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim().trim_end_matches(';');
+        if line.is_empty() {
+            continue;
+        }
+        let counts: Vec<f64> = line
+            .split(',')
+            .take(3)
+            .map(|v| v.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        if counts.len() < 3 {
+            return Err(format!("line {}: expected at least 3 columns", i + 1));
+        }
+        let [x, y, z] =
+            [counts[0], counts[1], counts[2]].map(|c| c * SISFALL_G_PER_COUNT * GRAVITY);
+        samples.push(sample(x, y, z, samples.len() as f64 / SISFALL_RATE_HZ));
+    }
+    Ok(Recording {
+        name: name.to_string(),
+        fall,
+        fall_at: None,
+        samples,
+    })
+}
+
+/// Loads a file (`.csv`, `.json`, or a SisFall `.txt` trial) or every such file under a
+/// directory, in name order.
+pub fn load(path: &Path) -> Result<Vec<Recording>, String> {
+    if path.is_dir() {
+        let mut entries: Vec<_> = std::fs::read_dir(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .collect();
+        entries.sort();
+        let mut recordings = Vec::new();
+        for entry in entries {
+            // Dataset folders also hold notes (e.g. SisFall's Readme.txt): only F…/D… trials
+            let trial = entry
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(['F', 'D']));
+            let known = match entry.extension().and_then(|e| e.to_str()) {
+                Some("csv" | "json") => true,
+                Some("txt") => trial,
+                _ => false,
+            };
+            if entry.is_dir() || known {
+                recordings.extend(load(&entry)?);
+            }
+        }
+        return Ok(recordings);
+    }
+
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let name = path
+        .file_stem()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    let parsed = match path.extension().and_then(|e| e.to_str()) {
+        Some("csv") => parse_csv(name, &text),
+        Some("json") => parse_json(name, &text),
+        Some("txt") => parse_sisfall(name, &text).map(|r| vec![r]),
+        _ => Err("unsupported file type (expected .csv, .json or .txt)".to_string()),
+    };
+    parsed.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// What the detector did with one recording.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingResult {
+    pub name: String,
+    pub fall: bool,
+    pub alerts: usize, // CRITICAL_FALL verdicts
+    pub near_misses: usize,
+    pub latency_ms: Option<i64>, // Onset to the first alert, for detected falls
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ConfusionMatrix {
+    pub true_positives: usize,
+    pub false_negatives: usize,
+    pub true_negatives: usize,
+    pub false_positives: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencySummary {
+    pub mean_ms: f64,
+    pub median_ms: i64,
+    pub max_ms: i64,
+}
+
+/// **Evaluation Report**
+///
+/// A fall recording is a true positive when the detector raised at least one alert on it; an
+/// activity recording with an alert is a false positive. Every alert raised on activity
+/// recordings counts as a false alarm, per hour of activity recordings replayed. Rates are
+/// `None` when there is nothing to divide by.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub profile: DetectorProfile,
+    pub recordings: usize,
+    pub confusion: ConfusionMatrix,
+    pub sensitivity: Option<f64>,
+    pub specificity: Option<f64>,
+    pub latency: Option<LatencySummary>,
+    pub false_alarms: usize,
+    pub activity_hours: f64,
+    pub false_alarms_per_hour: Option<f64>,
+    pub results: Vec<RecordingResult>,
+}
+
+/// Replays one recording through a fresh detector, timed by the sample timestamps.
+pub async fn replay(profile: &DetectorProfile, recording: &Recording) -> RecordingResult {
+    let mut detector = profile.detector();
+    let mut alerts = Vec::new();
+    let mut near_misses = 0;
+    for s in &recording.samples {
+        let at = (s.timestamp * 1000.0).round() as i64;
+        match detector.process_at(s.clone(), at).await {
+            Some(DetectionEvent::CriticalFall { .. }) => alerts.push(at),
+            Some(DetectionEvent::NearMiss { .. }) => near_misses += 1,
+            _ => {}
+        }
+    }
+    let latency_ms = recording
+        .onset()
+        .zip(alerts.first())
+        .map(|(onset, at)| at - (onset * 1000.0).round() as i64);
+    RecordingResult {
+        name: recording.name.clone(),
+        fall: recording.fall,
+        alerts: alerts.len(),
+        near_misses,
+        latency_ms,
+    }
+}
+
+pub async fn evaluate(profile: &DetectorProfile, recordings: &[Recording]) -> Report {
+    let mut results: Vec<RecordingResult> = Vec::with_capacity(recordings.len());
+    for recording in recordings {
+        results.push(replay(profile, recording).await);
+    }
+
+    let mut confusion = ConfusionMatrix::default();
+    for r in &results {
+        match (r.fall, r.alerts > 0) {
+            (true, true) => confusion.true_positives += 1,
+            (true, false) => confusion.false_negatives += 1,
+            (false, false) => confusion.true_negatives += 1,
+            (false, true) => confusion.false_positives += 1,
+        }
+    }
+    let ratio = |n: usize, d: usize| (d > 0).then(|| n as f64 / d as f64);
+
+    let mut latencies: Vec<i64> = results.iter().filter_map(|r| r.latency_ms).collect();
+    latencies.sort_unstable();
+    let latency = (!latencies.is_empty()).then(|| LatencySummary {
+        mean_ms: latencies.iter().sum::<i64>() as f64 / latencies.len() as f64,
+        median_ms: latencies[latencies.len() / 2],
+        max_ms: latencies[latencies.len() - 1],
+    });
+
+    let false_alarms = results.iter().filter(|r| !r.fall).map(|r| r.alerts).sum();
+    let activity_hours = recordings
+        .iter()
+        .filter(|r| !r.fall)
+        .map(Recording::duration_s)
+        .sum::<f64>()
+        / 3600.0;
+
+    Report {
+        profile: profile.clone(),
+        recordings: recordings.len(),
+        confusion,
+        sensitivity: ratio(
+            confusion.true_positives,
+            confusion.true_positives + confusion.false_negatives,
+        ),
+        specificity: ratio(
+            confusion.true_negatives,
+            confusion.true_negatives + confusion.false_positives,
+        ),
+        latency,
+        false_alarms,
+        activity_hours,
+        false_alarms_per_hour: (activity_hours > 0.0).then(|| false_alarms as f64 / activity_hours),
+        results,
+    }
+}
+
+impl Report {
+    /// The summary as a plain-text table.
+    pub fn table(&self) -> String {
+        let rate = |r: Option<f64>| r.map_or("n/a".to_string(), |r| format!("{:.1}%", r * 100.0));
+        let c = &self.confusion;
+        let mut out = format!(
+            "Profile: {} (impact > {} g, stillness < {} m²/s⁴, window {} ms)\n\
+             Recordings: {}\n\n\
+             {:<16}{:>14}{:>14}\n\
+             {:<16}{:>14}{:>14}\n\
+             {:<16}{:>14}{:>14}\n\n",
+            self.profile.name,
+            self.profile.impact_threshold_g,
+            self.profile.stillness_threshold_variance,
+            self.profile.validation_ms,
+            self.recordings,
+            "",
+            "Alerted",
+            "Silent",
+            "Fall",
+            c.true_positives,
+            c.false_negatives,
+            "Activity",
+            c.false_positives,
+            c.true_negatives,
+        );
+        let rows = [
+            ("Sensitivity", rate(self.sensitivity)),
+            ("Specificity", rate(self.specificity)),
+            (
+                "Latency",
+                self.latency.as_ref().map_or("n/a".to_string(), |l| {
+                    format!(
+                        "mean {:.0} ms, median {} ms, max {} ms",
+                        l.mean_ms, l.median_ms, l.max_ms
+                    )
+                }),
+            ),
+            (
+                "False alarms",
+                format!(
+                    "{} in {:.2} h ({} per hour)",
+                    self.false_alarms,
+                    self.activity_hours,
+                    self.false_alarms_per_hour
+                        .map_or("n/a".to_string(), |r| format!("{:.2}", r))
+                ),
+            ),
+        ];
+        for (label, value) in rows {
+            out.push_str(&format!("{:<16}{}\n", label, value));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // Helper: CSV at 50 Hz: a fall at 1 s (labelled) then lying still (`ward:a`); a stumble
+    // followed by walking on (`ward:b`)
+    fn ward_csv() -> String {
+        let mut csv = "recording,t,x,y,z,fall\n".to_string();
+        for i in 0..200 {
+            let t = i as f64 / 50.0;
+            let (x, z, fall) = match i {
+                0..=49 => (0.0, 9.8, 0),
+                50 => (25.0, 3.0, 1),
+                _ => (9.8, 0.0, 1),
+            };
+            csv.push_str(&format!("a,{},{},0,{},{}\n", t, x, z, fall));
+            let x = match i {
+                50 => 20.0,
+                51.. => 8.0 * if i % 2 == 0 { 1.0 } else { -1.0 },
+                _ => 0.0,
+            };
+            csv.push_str(&format!("b,{},{},0,9.8,0\n", t, x));
+        }
+        csv
+    }
+
+    // Helper: JSON: an hour of activity with one hard sit-down the detector takes for a fall
+    fn adl_json() -> String {
+        let mut samples = vec![serde_json::json!({"x": 0.0, "y": 0.0, "z": 9.8, "t": 0.0})];
+        for i in 0..=150 {
+            let x = if i == 0 { 20.0 } else { 0.0 };
+            samples.push(
+                serde_json::json!({"x": x, "y": 0.0, "z": 9.8, "t": 100.0 + i as f64 / 50.0}),
+            );
+        }
+        samples.push(serde_json::json!({"x": 0.0, "y": 0.0, "z": 9.8, "t": 3600.0}));
+        let recordings =
+            [serde_json::json!({"name": "sit-down", "fall": false, "samples": samples})];
+        serde_json::json!({ "recordings": recordings }).to_string()
+    }
+
+    // Helper: SisFall: raw ADXL345 counts at 200 Hz (256 per g), a 2.5 g impact at 1 s
+    fn sisfall_trial() -> String {
+        (0..800)
+            .map(|i| match i {
+                200 => " 640,0,0,1,2,3,640,0,0;\n",
+                0..=199 => " 0,-256,0,1,2,3,0,-256,0;\n",
+                _ => " 256,0,0,1,2,3,256,0,0;\n",
+            })
+            .collect()
+    }
+
+    // Helper: the three files' recordings, in name order as `load` reads them
+    fn recordings() -> Vec<Recording> {
+        let mut recordings = vec![parse_sisfall("F01_SA01_R01", &sisfall_trial()).unwrap()];
+        recordings.extend(parse_json("adl", &adl_json()).unwrap());
+        recordings.extend(parse_csv("ward", &ward_csv()).unwrap());
+        recordings
+    }
+
+    // Helper: the three files in a SisFall-like dataset folder, removed when dropped
+    struct Dataset(PathBuf);
+
+    impl Dataset {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("fallguard-eval-{}", uuid::Uuid::new_v4()));
+            let sisfall = dir.join("SisFall_dataset");
+            std::fs::create_dir_all(&sisfall).unwrap();
+            std::fs::write(dir.join("ward.csv"), ward_csv()).unwrap();
+            std::fs::write(dir.join("adl.json"), adl_json()).unwrap();
+            std::fs::write(sisfall.join("F01_SA01_R01.txt"), sisfall_trial()).unwrap();
+            std::fs::write(sisfall.join("Readme.txt"), "Not a trial").unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for Dataset {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // Helper: the stricter profile, which misses the 2.5 g fall and the sit-down
+    fn strict() -> DetectorProfile {
+        DetectorProfile::parse(
+            r#"{"name": "strict", "impact_threshold_g": 2.55, "stillness_threshold_variance": 3.5, "validation_ms": 2000}"#,
+        )
+        .unwrap()
+    }
+
+    // A directory loads every recording under it in name order, skipping dataset notes
+    #[test]
+    fn test_load() {
+        let dataset = Dataset::new();
+        let recordings = load(&dataset.0).unwrap();
+        let names: Vec<&str> = recordings.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["F01_SA01_R01", "sit-down", "ward:a", "ward:b"]);
+    }
+
+    // CSV recordings are split by the recording column, the fall onset labelled
+    #[test]
+    fn test_parse_csv() {
+        let recordings = parse_csv("ward", &ward_csv()).unwrap();
+        assert_eq!(recordings[0].name, "ward:a");
+        assert_eq!(recordings[0].fall_at, Some(1.0));
+        assert!(!recordings[1].fall);
+    }
+
+    // CSV without the sensor columns is rejected
+    #[test]
+    fn test_parse_csv_rejects_columns() {
+        assert!(parse_csv("bad", "t,x,y\n0,1,2").is_err());
+    }
+
+    // SisFall counts are converted to m/s²
+    #[test]
+    fn test_parse_sisfall() {
+        let trial = parse_sisfall("F01_SA01_R01", &sisfall_trial()).unwrap();
+        assert!(trial.fall);
+        assert_eq!(trial.samples.len(), 800);
+        assert!((trial.samples[200].x / 9.8 - 2.5).abs() < 1e-9);
+    }
+
+    // Only F… and D… files are SisFall trials
+    #[test]
+    fn test_parse_sisfall_rejects_name() {
+        assert!(parse_sisfall("Readme", "1,2,3;").is_err());
+    }
+
+    // The default profile catches both falls and takes the sit-down for one
+    #[actix_web::test]
+    async fn test_confusion_matrix() {
+        let report = evaluate(&DetectorProfile::default(), &recordings()).await;
+        let c = report.confusion;
+        assert_eq!(
+            (
+                c.true_positives,
+                c.false_negatives,
+                c.true_negatives,
+                c.false_positives
+            ),
+            (2, 0, 1, 1)
+        );
+        assert_eq!(report.sensitivity, Some(1.0));
+        assert_eq!(report.specificity, Some(0.5));
+    }
+
+    // Falls are caught 2 s (the validation window) after the impact
+    #[actix_web::test]
+    async fn test_latency() {
+        let report = evaluate(&DetectorProfile::default(), &recordings()).await;
+        let latency = report.latency.as_ref().unwrap();
+        assert_eq!((latency.median_ms, latency.max_ms), (2000, 2000));
+        assert_eq!(report.results[0].latency_ms, Some(2000));
+    }
+
+    // False alarms are counted per hour of activity recorded
+    #[actix_web::test]
+    async fn test_false_alarms_per_hour() {
+        let report = evaluate(&DetectorProfile::default(), &recordings()).await;
+        assert_eq!(report.false_alarms, 1);
+        assert!((report.activity_hours - (3600.0 + 3.98) / 3600.0).abs() < 1e-9);
+        assert!((report.false_alarms_per_hour.unwrap() - 1.0).abs() < 0.01);
+    }
+
+    // A stumble the wearer recovers from is a near miss, not an alert
+    #[actix_web::test]
+    async fn test_near_miss() {
+        let report = evaluate(&DetectorProfile::default(), &recordings()).await;
+        let stumble = &report.results[3];
+        assert_eq!((stumble.alerts, stumble.near_misses), (0, 1));
+    }
+
+    // Another profile gives another report
+    #[actix_web::test]
+    async fn test_profile() {
+        let report = evaluate(&strict(), &recordings()).await;
+        assert_eq!(report.sensitivity, Some(0.5));
+        assert_eq!(report.specificity, Some(1.0));
+        assert_eq!(report.false_alarms_per_hour, Some(0.0));
+    }
+
+    // The report prints as a table naming the profile
+    #[actix_web::test]
+    async fn test_table() {
+        let table = evaluate(&DetectorProfile::default(), &recordings())
+            .await
+            .table();
+        assert!(table.contains("Profile: default (impact > 1.6 g"));
+        assert!(table.contains("Sensitivity     100.0%"));
+        assert!(table.contains("Specificity     50.0%"));
+    }
+
+    // The report serializes as JSON
+    #[actix_web::test]
+    async fn test_report_json() {
+        let report = evaluate(&DetectorProfile::default(), &recordings()).await;
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["confusion"]["false_positives"], 1);
+        assert_eq!(json["results"][0]["latency_ms"], 2000);
+    }
+}
@@ -0,0 +1,781 @@
+use super::capability::{ResourceCapability, SearchParam};
+use super::{
+    base_url, fhir_json, negotiate, not_found, reference_id, search_params, searchset,
+    storage_error_outcome, BundleEntry, DEFAULT_SEARCH_COUNT,
+};
+use crate::assessment::ASSESSMENT_ENTITY;
+use crate::model::{Alert, AuditEntry, FallLog, FALL_OBSERVATION_CODINGS};
+use crate::storage::alerts::ALERT_STATUSES;
+use crate::storage::query::MAX_PAGE_SIZE;
+use crate::storage::{
+    AlertQuery, AuditQuery, Repository, StorageError, StorageResult, OPEN_ALERT_STATUSES,
+};
+use crate::AppState;
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, Responder};
+use serde_json::{json, Value};
+
+/// Standard extension pointing a Flag at the record that explains it.
+pub const FLAG_DETAIL_EXTENSION: &str = "http://hl7.org/fhir/StructureDefinition/flag-detail";
+
+/// Code system of audit actions (`alert.cancelled`, `legal_hold.placed`, `retention.purged`, ...).
+pub const AUDIT_ACTION_SYSTEM: &str = "urn:fallguard:audit-action";
+
+/// Entity type of the audit entries written for nurse decisions on an alert.
+pub const ALERT_ENTITY: &str = "Alert";
+
+const FLAG_CATEGORY_SYSTEM: &str = "http://terminology.hl7.org/CodeSystem/flag-category";
+const OBJECT_ROLE_SYSTEM: &str = "http://terminology.hl7.org/CodeSystem/object-role";
+
+/// Display name of this server as the observer of its own audit trail.
+const AUDIT_OBSERVER: &str = "FallGuard";
+
+const STATUS_PARAM: SearchParam = SearchParam {
+    name: "status",
+    kind: "token",
+    documentation: "The resource's own status codes (comma-separated)",
+};
+
+pub const FLAG_CAPABILITY: ResourceCapability = ResourceCapability {
+    resource_type: "Flag",
+    interactions: &["read", "search-type"],
+    search_params: &[
+        SearchParam {
+            name: "subject",
+            kind: "reference",
+            documentation: "Patient/<id> or a bare patient id",
+        },
+        SearchParam {
+            name: "patient",
+            kind: "reference",
+            documentation: "Same as subject",
+        },
+        STATUS_PARAM,
+    ],
+    operations: &[],
+};
+
+pub const DETECTED_ISSUE_CAPABILITY: ResourceCapability = ResourceCapability {
+    resource_type: "DetectedIssue",
+    interactions: &["read", "search-type"],
+    search_params: &[
+        SearchParam {
+            name: "patient",
+            kind: "reference",
+            documentation: "Patient/<id> or a bare patient id",
+        },
+        STATUS_PARAM,
+    ],
+    operations: &[],
+};
+
+pub const AUDIT_EVENT_CAPABILITY: ResourceCapability = ResourceCapability {
+    resource_type: "AuditEvent",
+    interactions: &["read", "search-type"],
+    search_params: &[
+        SearchParam {
+            name: "entity",
+            kind: "reference",
+            documentation:
+                "Observation/<id>, Flag/<id> or DetectedIssue/<id>: decisions on that alert",
+        },
+        SearchParam {
+            name: "_count",
+            kind: "number",
+            documentation: "Newest entries returned (default 20)",
+        },
+    ],
+    operations: &[],
+};
+
+// --- Status mapping ---
+
+/// Flag status of an alert: open alerts are `active`; a cancelled alert was a false alarm.
+pub fn flag_status(alert_status: &str) -> &'static str {
+    match alert_status {
+        "Cancelled" => "entered-in-error",
+        s if OPEN_ALERT_STATUSES.contains(&s) => "active",
+        _ => "inactive",
+    }
+}
+
+/// DetectedIssue status of an alert: `preliminary` until a nurse confirms or cancels it.
+pub fn detected_issue_status(alert_status: &str) -> &'static str {
+    match alert_status {
+        "Active" => "preliminary",
+        "Cancelled" => "entered-in-error",
+        _ => "final",
+    }
+}
+
+/// What the ward did about an alert, once a nurse has acted on it.
+fn mitigation(alert_status: &str) -> Option<&'static str> {
+    match alert_status {
+        "Confirmed" => Some("Assistance sent"),
+        "Resolved" => Some("Patient checked and alert resolved"),
+        "Cancelled" => Some("Dismissed as a false alarm"),
+        _ => None,
+    }
+}
+
+// --- Resources ---
+
+/// The SNOMED "Fall (event)" concept shared by Flags and DetectedIssues.
+fn fall_code() -> Value {
+    let coding: Vec<Value> = FALL_OBSERVATION_CODINGS
+        .iter()
+        .filter(|(system, _, _)| *system == "http://snomed.info/sct")
+        .map(
+            |(system, code, display)| json!({ "system": system, "code": code, "display": display }),
+        )
+        .collect();
+    json!({ "coding": coding, "text": "Fall detected" })
+}
+
+fn observation_reference(event_id: i32) -> Value {
+    json!({ "reference": format!("Observation/{}", event_id) })
+}
+
+/// Flag resource for an alert, pointing at the Observation of the detection that raised it.
+pub fn flag_resource(alert: &Alert, event: Option<&FallLog>) -> Value {
+    let status = flag_status(&alert.status);
+    let mut period = json!({ "start": alert.raised_at.to_rfc3339() });
+    if status != "active" {
+        period["end"] = json!(alert.updated_at.to_rfc3339());
+    }
+    let subject = match event.and_then(|e| e.patient_id.as_ref()) {
+        Some(patient) => json!({ "reference": format!("Patient/{}", patient) }),
+        None => json!({ "display": "Unidentified patient" }),
+    };
+    let mut resource = json!({
+        "resourceType": "Flag",
+        "id": alert.id.to_string(),
+        "meta": { "lastUpdated": alert.updated_at.to_rfc3339() },
+        "extension": [{
+            "url": FLAG_DETAIL_EXTENSION,
+            "valueReference": observation_reference(alert.event_id)
+        }],
+        "status": status,
+        "category": [{
+            "coding": [{ "system": FLAG_CATEGORY_SYSTEM, "code": "safety", "display": "Safety" }]
+        }],
+        "code": fall_code(),
+        "subject": subject,
+        "period": period,
+    });
+    if let Some(device) = event.and_then(|e| e.device_id.as_ref()) {
+        resource["author"] = json!({ "reference": format!("Device/{}", device) });
+    }
+    resource
+}
+
+/// DetectedIssue resource for an alert, with the detection as evidence and the nurse's
+/// decision as mitigation.
+pub fn detected_issue_resource(alert: &Alert, event: Option<&FallLog>) -> Value {
+    let mut resource = json!({
+        "resourceType": "DetectedIssue",
+        "id": alert.id.to_string(),
+        "meta": { "lastUpdated": alert.updated_at.to_rfc3339() },
+        "status": detected_issue_status(&alert.status),
+        "code": fall_code(),
+        "severity": "high",
+        "identifiedDateTime": alert.raised_at.to_rfc3339(),
+        "evidence": [{ "detail": [observation_reference(alert.event_id)] }],
+    });
+    if let Some(event) = event {
+        resource["detail"] = json!(format!("Fall detected at {:.2} g", event.g_force_value));
+        if let Some(patient) = &event.patient_id {
+            resource["patient"] = json!({ "reference": format!("Patient/{}", patient) });
+        }
+        if let Some(device) = &event.device_id {
+            resource["author"] = json!({ "reference": format!("Device/{}", device) });
+        }
+    }
+    if let Some(action) = mitigation(&alert.status) {
+        resource["mitigation"] = json!([{
+            "action": { "text": action },
+            "date": alert.updated_at.to_rfc3339()
+        }]);
+    }
+    resource
+}
+
+/// AuditEvent resource for an audit log entry. Nurse decisions reference the original
+/// Observation and the alert's Flag and DetectedIssue, assessments their QuestionnaireResponse;
+/// other entries name their record.
+pub fn audit_event_resource(entry: &AuditEntry) -> Value {
+    let role = |code: &str, display: &str| json!({ "system": OBJECT_ROLE_SYSTEM, "code": code, "display": display });
+    let mut entities = Vec::new();
+    match (entry.entity_type.as_str(), &entry.entity_id) {
+        (ALERT_ENTITY, Some(alert_id)) => {
+            let detail: Option<Value> = entry
+                .detail
+                .as_deref()
+                .and_then(|d| serde_json::from_str(d).ok());
+            if let Some(event_id) = detail.as_ref().and_then(|d| d["event_id"].as_i64()) {
+                entities.push(json!({
+                    "what": { "reference": format!("Observation/{}", event_id) },
+                    "role": role("4", "Domain Resource")
+                }));
+            }
+            for resource_type in ["Flag", "DetectedIssue"] {
+                entities.push(json!({
+                    "what": { "reference": format!("{}/{}", resource_type, alert_id) },
+                    "role": role("4", "Domain Resource")
+                }));
+            }
+        }
+        (ASSESSMENT_ENTITY, Some(assessment_id)) => entities.push(json!({
+            "what": { "reference": format!("QuestionnaireResponse/{}", assessment_id) },
+            "role": role("4", "Domain Resource")
+        })),
+        (entity_type, id) => {
+            let display = match id {
+                Some(id) => format!("{} {}", entity_type, id),
+                None => entity_type.to_string(),
+            };
+            entities.push(
+                json!({ "what": { "display": display }, "role": role("13", "Security Resource") }),
+            );
+        }
+    }
+    if let (Some(detail), Some(first)) = (&entry.detail, entities.first_mut()) {
+        first["detail"] = json!([{ "type": "detail", "valueString": detail }]);
+    }
+
+    json!({
+        "resourceType": "AuditEvent",
+        "id": entry.id.to_string(),
+        "type": { "system": AUDIT_ACTION_SYSTEM, "code": entry.action },
+        "action": audit_action_code(&entry.action),
+        "recorded": entry.recorded_at.to_rfc3339(),
+        "outcome": "0",
+        "agent": [{ "who": { "display": entry.actor }, "requestor": true }],
+        "source": { "observer": { "display": AUDIT_OBSERVER } },
+        "entity": entities,
+    })
+}
+
+/// FHIR action code (C/R/U/D/E) of an audit action.
+fn audit_action_code(action: &str) -> &'static str {
+    match action.rsplit('.').next() {
+        Some("placed") | Some("admitted") => "C",
+        Some("purged") => "D",
+        _ => "U",
+    }
+}
+
+// --- Flag & DetectedIssue ---
+
+type AlertResource = fn(&Alert, Option<&FallLog>) -> Value;
+
+async fn read_alert(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    id: String,
+    resource_type: &str,
+    build: AlertResource,
+) -> HttpResponse {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let Ok(alert_id) = id.parse::<i32>() else {
+        return not_found(resource_type, &id);
+    };
+    let alert = match data.db.get_alert(alert_id).await {
+        Ok(Some(alert)) => alert,
+        Ok(None) => return not_found(resource_type, &id),
+        Err(e) => return storage_error_outcome(e, "Error reading alert"),
+    };
+    match data.db.get_event(alert.event_id).await {
+        Ok(event) => fhir_json(StatusCode::OK, &build(&alert, event.as_ref())),
+        Err(e) => storage_error_outcome(e, "Error reading alert"),
+    }
+}
+
+/// Alert query for `subject` / `patient` and `status` (in the resource's own status codes).
+/// `None` when nothing can match.
+fn alert_query(req: &HttpRequest, status_of: fn(&str) -> &'static str) -> Option<AlertQuery> {
+    let mut query = AlertQuery::default();
+    let mut statuses: Option<Vec<&str>> = None;
+    for (name, value) in search_params(req) {
+        match name.as_str() {
+            "subject" | "patient" => match reference_id(&value, "Patient") {
+                Some(patient) if query.patient_id.as_deref().is_none_or(|p| p == patient) => {
+                    query.patient_id = Some(patient.to_string())
+                }
+                _ => return None,
+            },
+            "status" => {
+                let matching = ALERT_STATUSES
+                    .into_iter()
+                    .filter(|s| value.split(',').any(|token| token.trim() == status_of(s)))
+                    .filter(|s| statuses.as_ref().is_none_or(|prev| prev.contains(s)))
+                    .collect();
+                statuses = Some(matching);
+            }
+            _ => {}
+        }
+    }
+    if let Some(statuses) = statuses {
+        if statuses.is_empty() {
+            return None;
+        }
+        query.statuses = statuses.into_iter().map(String::from).collect();
+    }
+    Some(query)
+}
+
+async fn search_alerts(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    resource_type: &str,
+    status_of: fn(&str) -> &'static str,
+    build: AlertResource,
+) -> HttpResponse {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let Some(query) = alert_query(&req, status_of) else {
+        return searchset(&req, Vec::new());
+    };
+    let alerts = match data.db.query_alerts(&query).await {
+        Ok(a) => a,
+        Err(e) => return storage_error_outcome(e, "Error searching alerts"),
+    };
+    let base = base_url(&req);
+    let mut entries = Vec::with_capacity(alerts.len());
+    for alert in &alerts {
+        let event = match data.db.get_event(alert.event_id).await {
+            Ok(event) => event,
+            Err(e) => return storage_error_outcome(e, "Error searching alerts"),
+        };
+        entries.push(BundleEntry::matched(
+            format!("{}/{}/{}", base, resource_type, alert.id),
+            &build(alert, event.as_ref()),
+        ));
+    }
+    searchset(&req, entries)
+}
+
+/// **GET /fhir/Flag/{id}**
+///
+/// One Flag per alert (same id): `active` while the alert is open, `inactive` once resolved,
+/// `entered-in-error` when a nurse cancelled it as a false alarm.
+pub async fn read_flag(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    read_alert(req, data, path.into_inner(), "Flag", flag_resource).await
+}
+
+/// **GET /fhir/Flag?subject=&status=**
+///
+/// Alerts as Flags, newest first (`status=active` lists the falls awaiting the ward).
+pub async fn search_flags(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    search_alerts(req, data, "Flag", flag_status, flag_resource).await
+}
+
+/// **GET /fhir/DetectedIssue/{id}**
+///
+/// One DetectedIssue per alert (same id): `preliminary` until a nurse acts on it, then
+/// `final`, or `entered-in-error` for a false alarm.
+pub async fn read_detected_issue(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    read_alert(
+        req,
+        data,
+        path.into_inner(),
+        "DetectedIssue",
+        detected_issue_resource,
+    )
+    .await
+}
+
+/// **GET /fhir/DetectedIssue?patient=&status=**
+///
+/// Alerts as DetectedIssues, newest first.
+pub async fn search_detected_issues(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    search_alerts(
+        req,
+        data,
+        "DetectedIssue",
+        detected_issue_status,
+        detected_issue_resource,
+    )
+    .await
+}
+
+// --- AuditEvent ---
+
+/// **GET /fhir/AuditEvent/{id}**
+///
+/// One AuditEvent per audit log entry (same id).
+pub async fn read_audit_event(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let id = path.into_inner();
+    let Ok(entry_id) = id.parse::<i64>() else {
+        return not_found("AuditEvent", &id);
+    };
+    match data.db.get_audit_entry(entry_id).await {
+        Ok(Some(entry)) => fhir_json(StatusCode::OK, &audit_event_resource(&entry)),
+        Ok(None) => not_found("AuditEvent", &id),
+        Err(e) => storage_error_outcome(e, "Error reading audit event"),
+    }
+}
+
+/// Alert ids an `entity` reference points at: the alert itself (`Flag/<id>`,
+/// `DetectedIssue/<id>`) or the alerts raised by an `Observation/<id>`.
+async fn referenced_alerts(db: &dyn Repository, reference: &str) -> StorageResult<Vec<String>> {
+    let Some((prefix, id)) = reference.rsplit_once('/') else {
+        return Ok(Vec::new());
+    };
+    match prefix.rsplit('/').next() {
+        Some("Flag" | "DetectedIssue") => Ok(vec![id.to_string()]),
+        Some("Observation") => {
+            let Ok(event_id) = id.parse() else {
+                return Ok(Vec::new());
+            };
+            let query = AlertQuery {
+                event_id: Some(event_id),
+                ..AlertQuery::default()
+            };
+            let alerts = db.query_alerts(&query).await?;
+            Ok(alerts.iter().map(|a| a.id.to_string()).collect())
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// **GET /fhir/AuditEvent?entity=&_count=**
+///
+/// The newest `_count` audit entries (default 20). `entity=Observation/<id>` (or the alert's
+/// `Flag/<id>` / `DetectedIssue/<id>`) lists the nurse decisions on that detection.
+pub async fn search_audit_events(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let mut query = AuditQuery {
+        limit: DEFAULT_SEARCH_COUNT,
+        ..AuditQuery::default()
+    };
+    for (name, value) in search_params(&req) {
+        match name.as_str() {
+            "entity" => {
+                query.entity_type = Some(ALERT_ENTITY.to_string());
+                for reference in value.split(',') {
+                    match referenced_alerts(data.db.as_ref(), reference.trim()).await {
+                        Ok(ids) => query.entity_ids.extend(ids),
+                        Err(e) => return storage_error_outcome(e, "Error searching audit events"),
+                    }
+                }
+                if query.entity_ids.is_empty() {
+                    return searchset(&req, Vec::new());
+                }
+            }
+            "_count" => match value.parse::<i64>() {
+                Ok(count) if count >= 0 => query.limit = count.min(MAX_PAGE_SIZE),
+                _ => {
+                    let e = StorageError::InvalidQuery(format!("invalid _count: {}", value));
+                    return storage_error_outcome(e, "Error searching audit events");
+                }
+            },
+            _ => {}
+        }
+    }
+    let entries = match data.db.audit_entries(&query).await {
+        Ok(e) => e,
+        Err(e) => return storage_error_outcome(e, "Error searching audit events"),
+    };
+    let base = base_url(&req);
+    let entries = entries
+        .iter()
+        .map(|entry| {
+            BundleEntry::matched(
+                format!("{}/AuditEvent/{}", base, entry.id),
+                &audit_event_resource(entry),
+            )
+        })
+        .collect();
+    searchset(&req, entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assessment::{self, ASSESSMENT_ENTITY};
+    use crate::storage::AuditQuery;
+    use crate::test_support::{answered_fall, critical_event, get, get_json, memory_state};
+    use crate::websockets::{self, NURSE_ACTOR};
+    use crate::AppState;
+    use actix_web::http::StatusCode;
+    use actix_web::web;
+    use chrono::Utc;
+
+    // Helper: three of P-1001's falls, as the nurse answered them
+    struct Answered {
+        state: web::Data<AppState>,
+        resolved: i32,        // Event confirmed, then resolved
+        resolved_alert: i32,  // Its alert
+        false_alarm: i32,     // Event cancelled as a false alarm
+        cancelled_alert: i32, // Its alert
+        active_alert: i32,    // Alert still waiting for an answer
+    }
+
+    async fn answered() -> Answered {
+        let state = memory_state();
+        let db = state.db.clone();
+        let mut falls = Vec::new();
+        for (minutes_ago, commands) in [
+            (
+                30,
+                vec![
+                    ("CONFIRM_FALL", "Assistance Sent", false, "Confirmed"),
+                    ("RESET_SYSTEM", "Resolved", false, "Resolved"),
+                ],
+            ),
+            (20, vec![("CANCEL_ALERT", "Refused", true, "Cancelled")]),
+            (10, vec![]),
+        ] {
+            let event = db
+                .insert_event(critical_event(minutes_ago, 2.5))
+                .await
+                .unwrap();
+            let alert = db.open_alert(event.id, event.detected_at).await.unwrap();
+            for (command, severity, is_false_alarm, status) in commands {
+                let answered = db.get_alert(alert.id).await.unwrap().unwrap();
+                websockets::record_action(
+                    state.clone(),
+                    answered,
+                    command,
+                    severity,
+                    is_false_alarm,
+                    status,
+                )
+                .await;
+            }
+            falls.push((event.id, alert.id));
+        }
+        Answered {
+            state,
+            resolved: falls[0].0,
+            resolved_alert: falls[0].1,
+            false_alarm: falls[1].0,
+            cancelled_alert: falls[1].1,
+            active_alert: falls[2].1,
+        }
+    }
+
+    // A false alarm moves its Observation to entered-in-error, a confirmed fall stays final
+    #[actix_web::test]
+    async fn test_false_alarm_observation() {
+        let falls = answered().await;
+        let obs = get_json(
+            &falls.state,
+            &format!("/fhir/Observation/{}", falls.false_alarm),
+        )
+        .await;
+        assert_eq!(obs["status"], "entered-in-error");
+        let obs = get_json(
+            &falls.state,
+            &format!("/fhir/Observation/{}", falls.resolved),
+        )
+        .await;
+        assert_eq!(obs["status"], "final");
+    }
+
+    // Observations searched by status=entered-in-error include dismissed detections
+    #[actix_web::test]
+    async fn test_search_dismissed_observations() {
+        let falls = answered().await;
+        let bundle = get_json(&falls.state, "/fhir/Observation?status=entered-in-error").await;
+        assert_eq!(bundle["total"], 1);
+        assert_eq!(
+            bundle["entry"][0]["resource"]["id"],
+            falls.false_alarm.to_string()
+        );
+    }
+
+    // A cancelled alert is an entered-in-error Flag pointing at its Observation
+    #[actix_web::test]
+    async fn test_flag() {
+        let falls = answered().await;
+        let flag = get_json(
+            &falls.state,
+            &format!("/fhir/Flag/{}", falls.cancelled_alert),
+        )
+        .await;
+        assert_eq!(flag["resourceType"], "Flag");
+        assert_eq!(flag["status"], "entered-in-error");
+        assert_eq!(
+            flag["extension"][0]["valueReference"]["reference"],
+            format!("Observation/{}", falls.false_alarm)
+        );
+        assert_eq!(flag["subject"]["reference"], "Patient/P-1001");
+        assert!(flag["period"]["end"].is_string());
+    }
+
+    // Active Flags are the unanswered alerts, with an open period
+    #[actix_web::test]
+    async fn test_search_active_flags() {
+        let falls = answered().await;
+        let bundle = get_json(
+            &falls.state,
+            "/fhir/Flag?status=active&subject=Patient/P-1001",
+        )
+        .await;
+        assert_eq!(bundle["total"], 1);
+        assert_eq!(
+            bundle["entry"][0]["resource"]["id"],
+            falls.active_alert.to_string()
+        );
+        assert!(bundle["entry"][0]["resource"]["period"]
+            .get("end")
+            .is_none());
+    }
+
+    // A resolved alert is a final DetectedIssue with its evidence and mitigation
+    #[actix_web::test]
+    async fn test_detected_issue() {
+        let falls = answered().await;
+        let issue = get_json(
+            &falls.state,
+            &format!("/fhir/DetectedIssue/{}", falls.resolved_alert),
+        )
+        .await;
+        assert_eq!(issue["status"], "final");
+        assert_eq!(
+            issue["evidence"][0]["detail"][0]["reference"],
+            format!("Observation/{}", falls.resolved)
+        );
+        assert_eq!(
+            issue["mitigation"][0]["action"]["text"],
+            "Patient checked and alert resolved"
+        );
+    }
+
+    // DetectedIssues search by status and patient
+    #[actix_web::test]
+    async fn test_search_detected_issues() {
+        let falls = answered().await;
+        for (query, total) in [
+            ("", 3),
+            ("status=preliminary", 1),
+            ("status=final,entered-in-error", 2),
+            ("patient=P-2002", 0),
+        ] {
+            let bundle = get_json(&falls.state, &format!("/fhir/DetectedIssue?{}", query)).await;
+            assert_eq!(bundle["total"], total, "{}", query);
+        }
+    }
+
+    // Every nurse decision is an AuditEvent linked to the Observation and the Flag
+    #[actix_web::test]
+    async fn test_audit_events() {
+        let falls = answered().await;
+        let bundle = get_json(
+            &falls.state,
+            &format!("/fhir/AuditEvent?entity=Observation/{}", falls.resolved),
+        )
+        .await;
+        assert_eq!(bundle["total"], 2);
+        let audit = &bundle["entry"][0]["resource"];
+        assert_eq!(audit["type"]["code"], "alert.resolved");
+        assert_eq!(audit["agent"][0]["who"]["display"], NURSE_ACTOR);
+        assert_eq!(
+            audit["entity"][0]["what"]["reference"],
+            format!("Observation/{}", falls.resolved)
+        );
+        assert_eq!(
+            audit["entity"][1]["what"]["reference"],
+            format!("Flag/{}", falls.resolved_alert)
+        );
+    }
+
+    // An AuditEvent reads back as it was found
+    #[actix_web::test]
+    async fn test_read_audit_event() {
+        let falls = answered().await;
+        let bundle = get_json(&falls.state, "/fhir/AuditEvent?_count=1").await;
+        let audit = &bundle["entry"][0]["resource"];
+        let id = audit["id"].as_str().unwrap();
+        let read = get_json(&falls.state, &format!("/fhir/AuditEvent/{}", id)).await;
+        assert_eq!(&read, audit);
+    }
+
+    // AuditEvent entities are the Observation and the Flag, never the patient; _count pages
+    #[actix_web::test]
+    async fn test_search_audit_events() {
+        let falls = answered().await;
+        for (query, total) in [
+            (format!("entity=Flag/{}", falls.cancelled_alert), 1),
+            ("entity=Patient/P-1001".to_string(), 0),
+            ("_count=2".to_string(), 2),
+        ] {
+            let bundle = get_json(&falls.state, &format!("/fhir/AuditEvent?{}", query)).await;
+            assert_eq!(bundle["total"], total, "{}", query);
+        }
+    }
+
+    // Unknown Flags, DetectedIssues and AuditEvents are 404
+    #[actix_web::test]
+    async fn test_unknown_ids() {
+        let state = memory_state();
+        for uri in [
+            "/fhir/Flag/999",
+            "/fhir/DetectedIssue/abc",
+            "/fhir/AuditEvent/999",
+        ] {
+            let response = get(&state, uri).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+        }
+    }
+
+    // AuditEvent searches reject a malformed _count
+    #[actix_web::test]
+    async fn test_search_audit_events_rejects_count() {
+        let response = get(&memory_state(), "/fhir/AuditEvent?_count=many").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // An assessment's AuditEvent references the QuestionnaireResponse it saved
+    #[actix_web::test]
+    async fn test_assessment_audit_event() {
+        let state = memory_state();
+        let alert = answered_fall(&state, "CONFIRM_FALL").await;
+        let assessment = assessment::save(
+            state.db.as_ref(),
+            &state.assessment_form,
+            alert.id,
+            &serde_json::Map::new(),
+            "nurse:ward-3",
+            false,
+            Utc::now(),
+        )
+        .await
+        .unwrap();
+        let audit = state
+            .db
+            .audit_entries(&AuditQuery {
+                entity_type: Some(ASSESSMENT_ENTITY.to_string()),
+                limit: 1,
+                ..AuditQuery::default()
+            })
+            .await
+            .unwrap();
+        let uri = format!("/fhir/AuditEvent/{}", audit[0].id);
+        let event = get_json(&state, &uri).await;
+        assert_eq!(
+            event["entity"][0]["what"]["reference"],
+            format!("QuestionnaireResponse/{}", assessment.id)
+        );
+    }
+}
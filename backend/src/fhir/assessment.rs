@@ -0,0 +1,459 @@
+use super::capability::{ResourceCapability, SearchParam};
+use super::{
+    base_url, fhir_json, negotiate, not_found, reference_id, search_params, searchset,
+    storage_error_outcome, BundleEntry,
+};
+use crate::assessment::{AssessmentForm, FormItem};
+use crate::model::Assessment;
+use crate::storage::assessments::ASSESSMENT_STATUSES;
+use crate::storage::AssessmentQuery;
+use crate::AppState;
+use actix_web::{http::StatusCode, web, HttpRequest, Responder};
+use serde_json::{json, Value};
+
+/// Canonical URL prefix of our forms (`urn:fallguard:questionnaire:<form id>`).
+pub const QUESTIONNAIRE_URL_PREFIX: &str = "urn:fallguard:questionnaire:";
+
+/// Extension pointing a QuestionnaireResponse at the Flag of the alert it assesses.
+pub const ALERT_EXTENSION: &str = "urn:fallguard:fhir:extension:alert";
+
+pub const QUESTIONNAIRE_CAPABILITY: ResourceCapability = ResourceCapability {
+    resource_type: "Questionnaire",
+    interactions: &["read", "search-type"],
+    search_params: &[],
+    operations: &[],
+};
+
+pub const QUESTIONNAIRE_RESPONSE_CAPABILITY: ResourceCapability = ResourceCapability {
+    resource_type: "QuestionnaireResponse",
+    interactions: &["read", "search-type"],
+    search_params: &[
+        SearchParam {
+            name: "subject",
+            kind: "reference",
+            documentation: "Patient/<id> or a bare patient id",
+        },
+        SearchParam {
+            name: "patient",
+            kind: "reference",
+            documentation: "Same as subject",
+        },
+        SearchParam {
+            name: "part-of",
+            kind: "reference",
+            documentation: "Observation/<id>: the assessment of that detection",
+        },
+        SearchParam {
+            name: "status",
+            kind: "token",
+            documentation: "in-progress | completed | amended (comma-separated)",
+        },
+    ],
+    operations: &[],
+};
+
+fn canonical_url(form_id: &str) -> String {
+    format!("{}{}", QUESTIONNAIRE_URL_PREFIX, form_id)
+}
+
+// --- Resources ---
+
+/// Questionnaire resource for the configured form.
+pub fn questionnaire_resource(form: &AssessmentForm) -> Value {
+    let items: Vec<Value> = form
+        .items
+        .iter()
+        .map(|item| {
+            let mut entry = json!({
+                "linkId": item.link_id,
+                "text": item.text,
+                "type": item.item_type,
+                "required": item.required,
+                "repeats": item.repeats,
+            });
+            if !item.options.is_empty() {
+                entry["answerOption"] = item
+                    .options
+                    .iter()
+                    .map(|o| json!({ "valueCoding": { "code": o.code, "display": o.display } }))
+                    .collect();
+            }
+            entry
+        })
+        .collect();
+    json!({
+        "resourceType": "Questionnaire",
+        "id": form.id,
+        "url": canonical_url(&form.id),
+        "version": form.version,
+        "name": form.id.replace('-', "_"),
+        "title": form.title,
+        "status": "active",
+        "subjectType": ["Patient"],
+        "item": items,
+    })
+}
+
+/// One stored answer as a QuestionnaireResponse `answer`. Items missing from the current form
+/// (it may have changed since) are written from the JSON type alone.
+fn answer_value(item: Option<&FormItem>, value: &Value) -> Value {
+    let item_type = item.map(|i| i.item_type.as_str());
+    match (item_type, value) {
+        (Some("choice"), Value::String(code)) => {
+            let display = item
+                .and_then(|i| i.options.iter().find(|o| &o.code == code))
+                .map(|o| o.display.clone());
+            json!({ "valueCoding": { "code": code, "display": display } })
+        }
+        (Some("dateTime"), _) => json!({ "valueDateTime": value }),
+        (_, Value::Bool(b)) => json!({ "valueBoolean": b }),
+        (_, Value::Number(n)) if n.is_i64() => json!({ "valueInteger": n }),
+        (_, Value::Number(n)) => json!({ "valueDecimal": n }),
+        (_, Value::String(s)) => json!({ "valueString": s }),
+        (_, other) => json!({ "valueString": other.to_string() }),
+    }
+}
+
+/// QuestionnaireResponse resource for an assessment: part of the detection's Observation,
+/// linked to the alert's Flag, answers in form order.
+pub fn questionnaire_response_resource(assessment: &Assessment, form: &AssessmentForm) -> Value {
+    let answers = assessment.answer_map();
+    let mut link_ids: Vec<&String> = form
+        .items
+        .iter()
+        .map(|i| &i.link_id)
+        .filter(|id| answers.contains_key(*id))
+        .collect();
+    link_ids.extend(answers.keys().filter(|id| form.item(id).is_none()));
+    let items: Vec<Value> = link_ids
+        .into_iter()
+        .map(|link_id| {
+            let item = form.item(link_id);
+            let values = match &answers[link_id] {
+                Value::Array(values) => values.iter().collect(),
+                value => vec![value],
+            };
+            let mut entry = json!({
+                "linkId": link_id,
+                "answer": values
+                    .into_iter()
+                    .map(|v| answer_value(item, v))
+                    .collect::<Vec<_>>(),
+            });
+            if let Some(item) = item {
+                entry["text"] = json!(item.text);
+            }
+            entry
+        })
+        .collect();
+
+    let mut resource = json!({
+        "resourceType": "QuestionnaireResponse",
+        "id": assessment.id.to_string(),
+        "meta": { "lastUpdated": assessment.updated_at.to_rfc3339() },
+        "extension": [{
+            "url": ALERT_EXTENSION,
+            "valueReference": { "reference": format!("Flag/{}", assessment.alert_id) }
+        }],
+        "questionnaire": format!("{}|{}", canonical_url(&assessment.form_id), assessment.form_version),
+        "status": assessment.status,
+        "partOf": [{ "reference": format!("Observation/{}", assessment.event_id) }],
+        "authored": assessment.completed_at.unwrap_or(assessment.updated_at).to_rfc3339(),
+    });
+    resource["subject"] = match &assessment.patient_id {
+        Some(patient) => json!({ "reference": format!("Patient/{}", patient) }),
+        None => json!({ "display": "Unidentified patient" }),
+    };
+    if let Some(author) = &assessment.author {
+        resource["author"] = json!({ "display": author });
+    }
+    if !items.is_empty() {
+        resource["item"] = json!(items);
+    }
+    resource
+}
+
+// --- Questionnaire ---
+
+/// **GET /fhir/Questionnaire/{id}**
+///
+/// The configured post-fall assessment form (its `id` is the form id).
+pub async fn read_questionnaire(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let id = path.into_inner();
+    if id != data.assessment_form.id {
+        return not_found("Questionnaire", &id);
+    }
+    fhir_json(
+        StatusCode::OK,
+        &questionnaire_resource(&data.assessment_form),
+    )
+}
+
+/// **GET /fhir/Questionnaire**
+///
+/// Lists the one form in use.
+pub async fn search_questionnaires(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let form = &data.assessment_form;
+    let entry = BundleEntry::matched(
+        format!("{}/Questionnaire/{}", base_url(&req), form.id),
+        &questionnaire_resource(form),
+    );
+    searchset(&req, vec![entry])
+}
+
+// --- QuestionnaireResponse ---
+
+/// **GET /fhir/QuestionnaireResponse/{id}**
+///
+/// One QuestionnaireResponse per assessment (same id).
+pub async fn read_questionnaire_response(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let id = path.into_inner();
+    let Ok(assessment_id) = id.parse::<i32>() else {
+        return not_found("QuestionnaireResponse", &id);
+    };
+    match data.db.get_assessment(assessment_id).await {
+        Ok(Some(assessment)) => fhir_json(
+            StatusCode::OK,
+            &questionnaire_response_resource(&assessment, &data.assessment_form),
+        ),
+        Ok(None) => not_found("QuestionnaireResponse", &id),
+        Err(e) => storage_error_outcome(e, "Error reading assessment"),
+    }
+}
+
+/// Assessment query for `subject` / `patient`, `part-of` and `status`; `None` when nothing
+/// can match.
+fn assessment_query(req: &HttpRequest) -> Option<AssessmentQuery> {
+    let mut query = AssessmentQuery::default();
+    for (name, value) in search_params(req) {
+        match name.as_str() {
+            "subject" | "patient" => match reference_id(&value, "Patient") {
+                Some(patient) if query.patient_id.as_deref().is_none_or(|p| p == patient) => {
+                    query.patient_id = Some(patient.to_string())
+                }
+                _ => return None,
+            },
+            "part-of" => match reference_id(&value, "Observation").map(str::parse::<i32>) {
+                Some(Ok(event_id)) if query.event_id.is_none_or(|e| e == event_id) => {
+                    query.event_id = Some(event_id)
+                }
+                _ => return None,
+            },
+            "status" => {
+                let matching: Vec<String> = ASSESSMENT_STATUSES
+                    .into_iter()
+                    .filter(|s| value.split(',').any(|token| token.trim() == *s))
+                    .filter(|s| query.statuses.is_empty() || query.statuses.iter().any(|p| p == s))
+                    .map(String::from)
+                    .collect();
+                if matching.is_empty() {
+                    return None;
+                }
+                query.statuses = matching;
+            }
+            _ => {}
+        }
+    }
+    Some(query)
+}
+
+/// **GET /fhir/QuestionnaireResponse?subject=&part-of=&status=**
+///
+/// Post-fall assessments, newest first (`status=in-progress` lists those still to finish).
+pub async fn search_questionnaire_responses(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let Some(query) = assessment_query(&req) else {
+        return searchset(&req, Vec::new());
+    };
+    let assessments = match data.db.query_assessments(&query).await {
+        Ok(a) => a,
+        Err(e) => return storage_error_outcome(e, "Error searching assessments"),
+    };
+    let base = base_url(&req);
+    let entries = assessments
+        .iter()
+        .map(|assessment| {
+            BundleEntry::matched(
+                format!("{}/QuestionnaireResponse/{}", base, assessment.id),
+                &questionnaire_response_resource(assessment, &data.assessment_form),
+            )
+        })
+        .collect();
+    searchset(&req, entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assessment;
+    use crate::test_support::{answered_fall, get_json, memory_state};
+    use crate::AppState;
+    use actix_web::web;
+    use chrono::Utc;
+    use serde_json::json;
+
+    // Helper: two of P-1001's confirmed falls, the first assessed (returned) and the second
+    // still in progress
+    struct Assessed {
+        state: web::Data<AppState>,
+        event: i32,       // First fall
+        alert: i32,       // Its alert
+        completed: i32,   // Its completed assessment
+        in_progress: i32, // The second fall's assessment
+    }
+
+    async fn assessed() -> Assessed {
+        let state = memory_state();
+        let first = answered_fall(&state, "CONFIRM_FALL").await;
+        let second = answered_fall(&state, "CONFIRM_FALL").await;
+        let answers = json!({
+            "witnessed": false,
+            "location": "bathroom",
+            "head-strike": "unknown",
+            "injury": "minor",
+            "physician-notified": true,
+            "contributing-factors": ["toileting", "footwear"],
+        });
+        let completed = assessment::save(
+            state.db.as_ref(),
+            &state.assessment_form,
+            first.id,
+            answers.as_object().unwrap(),
+            "nurse:ward-3",
+            true,
+            Utc::now(),
+        )
+        .await
+        .unwrap();
+        let in_progress = assessment::for_alert(state.db.as_ref(), second.id)
+            .await
+            .unwrap()
+            .unwrap();
+        Assessed {
+            state,
+            event: first.event_id,
+            alert: first.id,
+            completed: completed.id,
+            in_progress: in_progress.id,
+        }
+    }
+
+    // The form is served as a Questionnaire, choice items with their options
+    #[actix_web::test]
+    async fn test_questionnaire() {
+        let uri = "/fhir/Questionnaire/post-fall-assessment";
+        let questionnaire = get_json(&memory_state(), uri).await;
+        assert_eq!(
+            questionnaire["url"],
+            "urn:fallguard:questionnaire:post-fall-assessment"
+        );
+        let injury = &questionnaire["item"][3];
+        assert_eq!(injury["linkId"], "injury");
+        assert_eq!(injury["type"], "choice");
+        assert_eq!(injury["answerOption"][1]["valueCoding"]["code"], "minor");
+    }
+
+    // A QuestionnaireResponse references its form version, patient, Observation, Flag and author
+    #[actix_web::test]
+    async fn test_questionnaire_response_references() {
+        let assessed = assessed().await;
+        let uri = format!("/fhir/QuestionnaireResponse/{}", assessed.completed);
+        let response = get_json(&assessed.state, &uri).await;
+        assert_eq!(response["status"], "completed");
+        assert_eq!(
+            response["questionnaire"],
+            "urn:fallguard:questionnaire:post-fall-assessment|1"
+        );
+        assert_eq!(response["subject"]["reference"], "Patient/P-1001");
+        assert_eq!(
+            response["partOf"][0]["reference"],
+            format!("Observation/{}", assessed.event)
+        );
+        assert_eq!(
+            response["extension"][0]["valueReference"]["reference"],
+            format!("Flag/{}", assessed.alert)
+        );
+        assert_eq!(response["author"]["display"], "nurse:ward-3");
+    }
+
+    // Answers are listed in form order, typed as their items
+    #[actix_web::test]
+    async fn test_questionnaire_response_items() {
+        let assessed = assessed().await;
+        let uri = format!("/fhir/QuestionnaireResponse/{}", assessed.completed);
+        let response = get_json(&assessed.state, &uri).await;
+        let items = response["item"].as_array().unwrap();
+        let link_ids: Vec<&str> = items
+            .iter()
+            .map(|i| i["linkId"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            link_ids,
+            vec![
+                "witnessed",
+                "location",
+                "head-strike",
+                "injury",
+                "contributing-factors",
+                "physician-notified"
+            ]
+        );
+        assert_eq!(items[0]["answer"][0]["valueBoolean"], false);
+        assert_eq!(
+            items[3]["answer"][0]["valueCoding"]["display"],
+            "Minor (dressing, ice, cleaning)"
+        );
+        assert_eq!(items[4]["answer"].as_array().unwrap().len(), 2);
+    }
+
+    // QuestionnaireResponses are searched by Observation, status and patient, newest first
+    #[actix_web::test]
+    async fn test_search_questionnaire_responses() {
+        let assessed = assessed().await;
+        for (query, expected) in [
+            (
+                format!("part-of=Observation/{}", assessed.event),
+                vec![assessed.completed],
+            ),
+            ("status=in-progress".to_string(), vec![assessed.in_progress]),
+            (
+                "subject=Patient/P-1001".to_string(),
+                vec![assessed.in_progress, assessed.completed],
+            ),
+            ("patient=P-2002".to_string(), vec![]),
+            ("status=stopped".to_string(), vec![]),
+        ] {
+            let uri = format!("/fhir/QuestionnaireResponse?{}", query);
+            let bundle = get_json(&assessed.state, &uri).await;
+            let ids: Vec<String> = bundle["entry"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|e| e["resource"]["id"].as_str().unwrap().to_string())
+                .collect();
+            let expected: Vec<String> = expected.iter().map(|id| id.to_string()).collect();
+            assert_eq!(ids, expected, "{}", query);
+        }
+    }
+}
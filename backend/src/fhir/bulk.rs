@@ -0,0 +1,785 @@
+use super::capability::Operation;
+use super::registry::{device_resource, patient_resource};
+use super::{base_url, negotiate, outcome_response, request_url};
+use crate::model::FallLog;
+use crate::storage::{Repository, StorageError, StorageResult};
+use crate::AppState;
+use actix_web::{http::header, http::StatusCode, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Content type of the export files.
+pub const NDJSON: &str = "application/fhir+ndjson";
+
+/// Resource types that can be exported, in the order they are written.
+pub const EXPORT_TYPES: [&str; 3] = ["Observation", "Patient", "Device"];
+
+/// Bulk Data Access IG (http://hl7.org/fhir/uv/bulkdata/).
+pub const BULK_DATA_CAPABILITY: &str =
+    "http://hl7.org/fhir/uv/bulkdata/CapabilityStatement/bulk-data";
+pub const SYSTEM_OPERATIONS: [Operation; 1] = [Operation {
+    name: "export",
+    definition: "http://hl7.org/fhir/uv/bulkdata/OperationDefinition/export",
+}];
+pub const PATIENT_EXPORT: Operation = Operation {
+    name: "export",
+    definition: "http://hl7.org/fhir/uv/bulkdata/OperationDefinition/patient-export",
+};
+
+/// `_outputFormat` values accepted (all mean NDJSON).
+const OUTPUT_FORMATS: [&str; 3] = ["application/fhir+ndjson", "application/ndjson", "ndjson"];
+
+/// Kick-off parameters that narrow an export in ways not implemented: rejected rather than
+/// ignored, so a client never mistakes a full export for a filtered one.
+const UNSUPPORTED_PARAMETERS: [&str; 4] = [
+    "_typeFilter",
+    "_elements",
+    "patient",
+    "includeAssociatedData",
+];
+
+/// Events read per page while writing the Observation file.
+const EXPORT_BATCH: i64 = 500;
+
+/// Seconds a client is asked to wait between status polls.
+const RETRY_AFTER_SECONDS: u64 = 2;
+
+/// **Export Configuration**
+///
+/// - `EXPORT_DIR`: where export files are written, one directory per job (default `./exports`).
+/// - `EXPORT_RETENTION_HOURS`: how long finished exports stay available (default 24).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportConfig {
+    pub dir: PathBuf,
+    pub retention: Duration,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("exports"),
+            retention: Duration::hours(24),
+        }
+    }
+}
+
+impl ExportConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            dir: std::env::var("EXPORT_DIR")
+                .map(PathBuf::from)
+                .unwrap_or(defaults.dir),
+            retention: std::env::var("EXPORT_RETENTION_HOURS")
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|h| *h > 0)
+                .map(Duration::hours)
+                .unwrap_or(defaults.retention),
+        }
+    }
+}
+
+/// Which resources an export covers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportLevel {
+    System,  // GET /fhir/$export: everything
+    Patient, // GET /fhir/Patient/$export: data in a patient compartment
+}
+
+/// **Export Request**
+///
+/// Kick-off parameters: `_type` (comma-separated, default every `EXPORT_TYPES`), `_since`
+/// (only resources created or changed since that instant) and `_outputFormat` (NDJSON only).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportRequest {
+    pub level: ExportLevel,
+    pub types: Vec<String>,
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl ExportRequest {
+    pub fn parse(query_string: &str, level: ExportLevel) -> StorageResult<Self> {
+        let invalid = |msg: String| StorageError::InvalidQuery(msg);
+        let mut request = Self {
+            level,
+            types: EXPORT_TYPES.iter().map(|t| t.to_string()).collect(),
+            since: None,
+        };
+        for (name, value) in url::form_urlencoded::parse(query_string.as_bytes()) {
+            let value = value.trim();
+            match name.as_ref() {
+                "_type" => {
+                    let mut types = Vec::new();
+                    for resource_type in value.split(',').map(str::trim) {
+                        if !EXPORT_TYPES.contains(&resource_type) {
+                            return Err(invalid(format!(
+                                "unsupported _type: {} (expected one of {})",
+                                resource_type,
+                                EXPORT_TYPES.join(", ")
+                            )));
+                        }
+                        if !types.iter().any(|t| t == resource_type) {
+                            types.push(resource_type.to_string());
+                        }
+                    }
+                    // Keep the standard order whatever order they were asked in
+                    request.types = EXPORT_TYPES
+                        .iter()
+                        .filter(|t| types.iter().any(|wanted| wanted == *t))
+                        .map(|t| t.to_string())
+                        .collect();
+                }
+                "_since" => {
+                    let since = DateTime::parse_from_rfc3339(value)
+                        .map_err(|_| invalid(format!("invalid _since: {}", value)))?;
+                    request.since = Some(since.with_timezone(&Utc));
+                }
+                "_outputFormat" if !OUTPUT_FORMATS.contains(&value) => {
+                    return Err(invalid(format!("unsupported _outputFormat: {}", value)));
+                }
+                other if UNSUPPORTED_PARAMETERS.contains(&other) => {
+                    return Err(invalid(format!("{} is not supported", other)));
+                }
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+
+    fn includes_event(&self, log: &FallLog) -> bool {
+        (self.level == ExportLevel::System || log.patient_id.is_some())
+            && self.since.is_none_or(|since| {
+                log.detected_at >= since || log.dismissed_at.is_some_and(|at| at >= since)
+            })
+    }
+}
+
+/// One NDJSON file of a completed export (`output` of the manifest).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExportFile {
+    #[serde(rename = "type")]
+    pub resource_type: String,
+    pub url: String,
+    pub count: usize,
+}
+
+/// Where a job is at.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportState {
+    InProgress(String), // Progress shown in `X-Progress`
+    Complete(Vec<ExportFile>),
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+struct ExportJob {
+    request_url: String,
+    transaction_time: DateTime<Utc>,
+    state: ExportState,
+}
+
+/// **Export Jobs**
+///
+/// Jobs kicked off on this server. Files live under `<EXPORT_DIR>/<job id>/<Type>.ndjson`;
+/// jobs and their files are dropped once `retention` has passed (checked at each kick-off)
+/// or when the client deletes the job.
+#[derive(Clone, Default)]
+pub struct ExportJobs {
+    config: Arc<ExportConfig>,
+    jobs: Arc<Mutex<HashMap<String, ExportJob>>>,
+}
+
+impl ExportJobs {
+    pub fn new(config: ExportConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            jobs: Arc::default(),
+        }
+    }
+
+    /// Registers a new job and returns its id.
+    fn start(&self, request_url: String, at: DateTime<Utc>) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        let mut jobs = self.jobs.lock().unwrap();
+        let expired: Vec<String> = jobs
+            .iter()
+            .filter(|(_, job)| at - job.transaction_time > self.config.retention)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for old in expired {
+            jobs.remove(&old);
+            let _ = fs::remove_dir_all(self.config.dir.join(&old));
+        }
+        jobs.insert(
+            id.clone(),
+            ExportJob {
+                request_url,
+                transaction_time: at,
+                state: ExportState::InProgress("queued".to_string()),
+            },
+        );
+        id
+    }
+
+    /// Updates a job; `false` once it was deleted (the export should stop).
+    fn set_state(&self, id: &str, state: ExportState) -> bool {
+        match self.jobs.lock().unwrap().get_mut(id) {
+            Some(job) => {
+                job.state = state;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn state(&self, id: &str) -> Option<ExportState> {
+        self.jobs.lock().unwrap().get(id).map(|j| j.state.clone())
+    }
+
+    /// Forgets a job and deletes its files; `false` when there was no such job.
+    fn remove(&self, id: &str) -> bool {
+        let removed = self.jobs.lock().unwrap().remove(id).is_some();
+        if removed {
+            let _ = fs::remove_dir_all(self.config.dir.join(id));
+        }
+        removed
+    }
+
+    fn job_dir(&self, id: &str) -> PathBuf {
+        self.config.dir.join(id)
+    }
+}
+
+/// Writes resources as NDJSON; the file appears under its final name once complete.
+struct NdjsonWriter {
+    path: PathBuf,
+    partial: PathBuf,
+    out: BufWriter<File>,
+    count: usize,
+}
+
+impl NdjsonWriter {
+    fn create(dir: &std::path::Path, resource_type: &str) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}.ndjson", resource_type));
+        let partial = dir.join(format!("{}.ndjson.partial", resource_type));
+        Ok(Self {
+            out: BufWriter::new(File::create(&partial)?),
+            path,
+            partial,
+            count: 0,
+        })
+    }
+
+    fn write(&mut self, resource: &Value) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, resource)?;
+        self.out.write_all(b"\n")?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Resources written; an empty file is removed (the manifest leaves it out).
+    fn finish(self) -> io::Result<usize> {
+        let file = self.out.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        if self.count == 0 {
+            fs::remove_file(&self.partial)?;
+        } else {
+            fs::rename(&self.partial, &self.path)?;
+        }
+        Ok(self.count)
+    }
+}
+
+#[derive(Debug)]
+enum ExportError {
+    Storage(StorageError),
+    Io(io::Error),
+    Cancelled,
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Storage(e) => write!(f, "storage error: {}", e),
+            Self::Io(e) => write!(f, "cannot write the export files: {}", e),
+            Self::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl From<StorageError> for ExportError {
+    fn from(e: StorageError) -> Self {
+        Self::Storage(e)
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// **Run Export**
+///
+/// Writes one file per requested type. Observations stop at the newest event that existed at
+/// kick-off, so the export is consistent with its `transactionTime`.
+pub async fn run_export(
+    db: &dyn Repository,
+    jobs: &ExportJobs,
+    id: &str,
+    request: &ExportRequest,
+    base: &str,
+) {
+    let result = write_files(db, jobs, id, request, base).await;
+    let state = match result {
+        Ok(files) => ExportState::Complete(files),
+        Err(ExportError::Cancelled) => return,
+        Err(_) if jobs.state(id).is_none() => return, // Deleted while its files were written
+        Err(e) => {
+            eprintln!("❌ Bulk export {} failed: {}", id, e);
+            let _ = fs::remove_dir_all(jobs.job_dir(id));
+            ExportState::Failed("export failed; see the server log".to_string())
+        }
+    };
+    jobs.set_state(id, state);
+}
+
+async fn write_files(
+    db: &dyn Repository,
+    jobs: &ExportJobs,
+    id: &str,
+    request: &ExportRequest,
+    base: &str,
+) -> Result<Vec<ExportFile>, ExportError> {
+    let dir = jobs.job_dir(id);
+    let last_event = db.latest_event_id().await?.unwrap_or(0);
+    let mut files = Vec::new();
+    for resource_type in &request.types {
+        let progress = |written: usize| {
+            ExportState::InProgress(format!("{}: {} written", resource_type, written))
+        };
+        if !jobs.set_state(id, progress(0)) {
+            return Err(ExportError::Cancelled);
+        }
+        let mut writer = NdjsonWriter::create(&dir, resource_type)?;
+        match resource_type.as_str() {
+            "Observation" => {
+                let mut cursor = 0;
+                while cursor < last_event {
+                    let events = db.events_after(cursor, EXPORT_BATCH).await?;
+                    let Some(last) = events.last() else {
+                        break;
+                    };
+                    cursor = last.id;
+                    for log in events.iter().filter(|l| l.id <= last_event) {
+                        if request.includes_event(log) {
+                            writer
+                                .write(&serde_json::to_value(log.to_fhir()).unwrap_or_default())?;
+                        }
+                    }
+                    if !jobs.set_state(id, progress(writer.count)) {
+                        return Err(ExportError::Cancelled);
+                    }
+                }
+            }
+            "Patient" => {
+                for patient in db.list_patients().await? {
+                    if request
+                        .since
+                        .is_none_or(|since| patient.updated_at >= since)
+                    {
+                        writer.write(&patient_resource(&patient))?;
+                    }
+                }
+            }
+            "Device" => {
+                for device in db.list_devices().await? {
+                    let in_scope =
+                        request.level == ExportLevel::System || device.patient_id.is_some();
+                    let changed = request.since.is_none_or(|since| {
+                        device.registered_at >= since
+                            || device.last_seen_at.is_some_and(|seen| seen >= since)
+                    });
+                    if in_scope && changed {
+                        writer.write(&device_resource(&device))?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        let count = writer.finish()?;
+        if count > 0 {
+            files.push(ExportFile {
+                resource_type: resource_type.clone(),
+                url: format!("{}/$export-file/{}/{}.ndjson", base, id, resource_type),
+                count,
+            });
+        }
+    }
+    Ok(files)
+}
+
+// --- Handlers ---
+
+/// **GET /fhir/$export**
+///
+/// Kicks off a system-level export: every Observation, Patient and Device.
+pub async fn export_system(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    kick_off(req, data, ExportLevel::System).await
+}
+
+/// **GET /fhir/Patient/$export**
+///
+/// Kicks off a patient-level export: Observations and Devices that belong to a patient,
+/// and every Patient.
+pub async fn export_patients(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    kick_off(req, data, ExportLevel::Patient).await
+}
+
+/// Validates the kick-off request (it must ask for `Prefer: respond-async`), starts the job
+/// in the background and answers `202 Accepted` with the status URL in `Content-Location`.
+async fn kick_off(req: HttpRequest, data: web::Data<AppState>, level: ExportLevel) -> HttpResponse {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let respond_async = req
+        .headers()
+        .get_all("Prefer")
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|pref| pref.trim() == "respond-async");
+    if !respond_async {
+        return outcome_response(
+            StatusCode::BAD_REQUEST,
+            "invalid",
+            "$export requires the header Prefer: respond-async".to_string(),
+        );
+    }
+    let request = match ExportRequest::parse(req.query_string(), level) {
+        Ok(r) => r,
+        Err(StorageError::InvalidQuery(msg)) => {
+            return outcome_response(StatusCode::BAD_REQUEST, "invalid", msg)
+        }
+        Err(e) => return super::storage_error_outcome(e, "Error starting export"),
+    };
+
+    let base = base_url(&req);
+    let id = data.exports.start(request_url(&req), Utc::now());
+    let db = data.db.clone();
+    let jobs = data.exports.clone();
+    let job_id = id.clone();
+    let job_base = base.clone();
+    tokio::spawn(async move {
+        run_export(db.as_ref(), &jobs, &job_id, &request, &job_base).await;
+    });
+    HttpResponse::Accepted()
+        .insert_header((
+            header::CONTENT_LOCATION,
+            format!("{}/$export-status/{}", base, id),
+        ))
+        .finish()
+}
+
+/// **GET /fhir/$export-status/{id}**
+///
+/// `202` with `X-Progress` while running, `200` with the manifest once complete, `500` with an
+/// OperationOutcome if the export failed, `404` for unknown or deleted jobs.
+pub async fn status(data: web::Data<AppState>, path: web::Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    let job = data.exports.jobs.lock().unwrap().get(&id).cloned();
+    let Some(job) = job else {
+        return super::not_found("export", &id);
+    };
+    match job.state {
+        ExportState::InProgress(progress) => HttpResponse::Accepted()
+            .insert_header(("X-Progress", progress))
+            .insert_header((header::RETRY_AFTER, RETRY_AFTER_SECONDS.to_string()))
+            .finish(),
+        ExportState::Complete(output) => {
+            let expires = job.transaction_time + data.exports.config.retention;
+            HttpResponse::Ok()
+                .insert_header((header::EXPIRES, expires.to_rfc2822()))
+                .json(serde_json::json!({
+                    "transactionTime": job.transaction_time.to_rfc3339(),
+                    "request": job.request_url,
+                    "requiresAccessToken": false,
+                    "output": output,
+                    "error": [],
+                }))
+        }
+        ExportState::Failed(message) => {
+            outcome_response(StatusCode::INTERNAL_SERVER_ERROR, "exception", message)
+        }
+    }
+}
+
+/// **DELETE /fhir/$export-status/{id}**
+///
+/// Cancels a running export or deletes a finished one's files.
+pub async fn cancel(data: web::Data<AppState>, path: web::Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    if data.exports.remove(&id) {
+        HttpResponse::Accepted().finish()
+    } else {
+        super::not_found("export", &id)
+    }
+}
+
+/// **GET /fhir/$export-file/{id}/{file}**
+///
+/// One NDJSON file listed in a completed export's manifest.
+pub async fn file(data: web::Data<AppState>, path: web::Path<(String, String)>) -> HttpResponse {
+    let (id, name) = path.into_inner();
+    let listed = match data.exports.state(&id) {
+        Some(ExportState::Complete(output)) => output
+            .iter()
+            .any(|f| format!("{}.ndjson", f.resource_type) == name),
+        _ => false,
+    };
+    if !listed {
+        return super::not_found("export file", &format!("{}/{}", id, name));
+    }
+    match fs::read(data.exports.job_dir(&id).join(&name)) {
+        Ok(body) => HttpResponse::Ok().content_type(NDJSON).body(body),
+        Err(e) => {
+            eprintln!("❌ Bulk export {}: cannot read {}: {}", id, name, e);
+            outcome_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "exception",
+                "Error reading export file".to_string(),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{DeviceRegistration, NewEvent, NewPatient};
+    use crate::test_support::{call, critical_event, memory_app_state};
+    use actix_web::body::to_bytes;
+    use actix_web::dev::ServiceResponse;
+    use actix_web::test::TestRequest;
+    use std::path::Path;
+
+    // Helper: a server exporting to its own scratch directory (removed when dropped), holding
+    // two events (one without a patient), P-1001 and two sensors (one unassigned). `fall` is
+    // P-1001's event.
+    struct Exports {
+        state: web::Data<AppState>,
+        dir: PathBuf,
+        fall: i32,
+    }
+
+    impl Drop for Exports {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    async fn exports() -> Exports {
+        let dir = std::env::temp_dir().join(format!("fallguard-export-{}", uuid::Uuid::new_v4()));
+        let state = web::Data::new(AppState {
+            exports: ExportJobs::new(ExportConfig {
+                dir: dir.clone(),
+                ..ExportConfig::default()
+            }),
+            ..memory_app_state()
+        });
+        let db = &state.db;
+        let fall = db.insert_event(critical_event(30, 2.4)).await.unwrap();
+        db.insert_event(NewEvent {
+            patient_id: None,
+            ..critical_event(10, 1.9)
+        })
+        .await
+        .unwrap();
+        db.upsert_patient(&NewPatient {
+            id: "P-1001".to_string(),
+            family_name: Some("Perera".to_string()),
+            given_name: None,
+            birth_date: None,
+            gender: None,
+            ward: Some("ICU".to_string()),
+            room: None,
+            bed: None,
+            admitted_at: None,
+            discharged_at: None,
+            updated_at: Utc::now(),
+        })
+        .await
+        .unwrap();
+        for (device, patient) in [("pi-01", Some("P-1001")), ("pi-spare", None)] {
+            db.register_device(&DeviceRegistration {
+                id: device.to_string(),
+                model: None,
+                patient_id: patient.map(String::from),
+                ward: None,
+                calibration_state: None,
+                calibrated_at: None,
+                at: Utc::now(),
+            })
+            .await
+            .unwrap();
+        }
+        Exports {
+            state,
+            dir,
+            fall: fall.id,
+        }
+    }
+
+    // Helper: an asynchronous kick-off request for `uri`
+    async fn kick_off(state: &web::Data<AppState>, uri: &str) -> ServiceResponse {
+        let req = TestRequest::get()
+            .uri(uri)
+            .insert_header(("Accept", "application/fhir+json"))
+            .insert_header(("Prefer", "respond-async"));
+        call(state, req).await
+    }
+
+    // Helper: kicks off `uri`, polls until done and returns the status path and the manifest
+    async fn export(state: &web::Data<AppState>, uri: &str) -> (String, Value) {
+        let response = kick_off(state, uri).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let status_url = response.headers().get("Content-Location").unwrap();
+        let status_url = status_url.to_str().unwrap();
+        assert!(status_url.contains("/fhir/$export-status/"));
+        let status_path = format!("/fhir/{}", status_url.split_once("/fhir/").unwrap().1);
+        for _ in 0..100 {
+            let response = call(state, TestRequest::get().uri(&status_path)).await;
+            if response.status() == StatusCode::ACCEPTED {
+                assert!(response.headers().contains_key("X-Progress"));
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                continue;
+            }
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = to_bytes(response.into_body()).await.unwrap();
+            return (status_path, serde_json::from_slice(&body).unwrap());
+        }
+        panic!("export did not complete");
+    }
+
+    // Helper: the resources in the NDJSON file at `url`
+    async fn ndjson(state: &web::Data<AppState>, url: &str) -> Vec<Value> {
+        let path = format!("/fhir/{}", url.split_once("/fhir/").unwrap().1);
+        let response = call(state, TestRequest::get().uri(&path)).await;
+        let body = to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    // Helper: (type, count) of every file in a manifest
+    fn counts(manifest: &Value) -> Vec<(&str, u64)> {
+        manifest["output"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| (f["type"].as_str().unwrap(), f["count"].as_u64().unwrap()))
+            .collect()
+    }
+
+    // Helper: the directory holding the files of the job at `status_path`
+    fn job_dir(dir: &Path, status_path: &str) -> PathBuf {
+        dir.join(status_path.rsplit('/').next().unwrap())
+    }
+
+    // Kick-off must ask for an asynchronous response
+    #[actix_web::test]
+    async fn test_kick_off_must_be_async() {
+        let exports = exports().await;
+        let response = call(&exports.state, TestRequest::get().uri("/fhir/$export")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // Kick-off refuses types that are not exported and _typeFilter
+    #[actix_web::test]
+    async fn test_kick_off_rejects_unsupported_parameters() {
+        let exports = exports().await;
+        for uri in [
+            "/fhir/$export?_type=Encounter",
+            "/fhir/$export?_typeFilter=Observation%3Fcode%3D1",
+        ] {
+            let response = kick_off(&exports.state, uri).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    // A system export writes one NDJSON file per type, with everything in it
+    #[actix_web::test]
+    async fn test_system_export() {
+        let exports = exports().await;
+        let (status_path, manifest) = export(&exports.state, "/fhir/$export").await;
+        assert_eq!(manifest["requiresAccessToken"], false);
+        assert!(manifest["request"]
+            .as_str()
+            .unwrap()
+            .ends_with("/fhir/$export"));
+        assert_eq!(
+            counts(&manifest),
+            vec![("Observation", 2), ("Patient", 1), ("Device", 2)]
+        );
+        let observations = ndjson(
+            &exports.state,
+            manifest["output"][0]["url"].as_str().unwrap(),
+        )
+        .await;
+        assert_eq!(observations[0]["resourceType"], "Observation");
+        assert_eq!(observations[0]["id"], exports.fall.to_string());
+        let files = fs::read_dir(job_dir(&exports.dir, &status_path)).unwrap();
+        assert_eq!(files.count(), 3);
+    }
+
+    // A patient export holds only data in a patient compartment, of the types asked for
+    #[actix_web::test]
+    async fn test_patient_export() {
+        let exports = exports().await;
+        let (_, manifest) = export(
+            &exports.state,
+            "/fhir/Patient/$export?_type=Device,Observation",
+        )
+        .await;
+        assert_eq!(counts(&manifest), vec![("Observation", 1), ("Device", 1)]);
+        let devices = ndjson(
+            &exports.state,
+            manifest["output"][1]["url"].as_str().unwrap(),
+        )
+        .await;
+        assert_eq!(devices[0]["id"], "pi-01");
+    }
+
+    // _since after everything is an empty export
+    #[actix_web::test]
+    async fn test_export_since() {
+        let exports = exports().await;
+        let (_, manifest) =
+            export(&exports.state, "/fhir/$export?_since=2999-01-01T00:00:00Z").await;
+        assert!(manifest["output"].as_array().unwrap().is_empty());
+    }
+
+    // Deleting a job removes it and its files
+    #[actix_web::test]
+    async fn test_delete_export() {
+        let exports = exports().await;
+        let (status_path, _) = export(&exports.state, "/fhir/$export").await;
+        let delete = || TestRequest::delete().uri(&status_path);
+        let response = call(&exports.state, delete()).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = call(&exports.state, TestRequest::get().uri(&status_path)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(!job_dir(&exports.dir, &status_path).exists());
+        let response = call(&exports.state, delete()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,175 @@
+use super::{
+    alerts, assessment, bulk, fhir_json, negotiate, observation, registry, risk, subscription,
+};
+use actix_web::{http::StatusCode, HttpRequest, Responder};
+use chrono::Utc;
+use serde_json::{json, Value};
+
+/// FHIR release every resource is served in.
+pub const FHIR_VERSION: &str = "4.0.1";
+
+/// **Search Parameter**
+///
+/// One parameter a search handler understands, as advertised in the CapabilityStatement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchParam {
+    pub name: &'static str,
+    pub kind: &'static str, // FHIR search param type: token | reference | date | number | string
+    pub documentation: &'static str,
+}
+
+/// An operation and the canonical URL of its OperationDefinition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Operation {
+    pub name: &'static str,
+    pub definition: &'static str,
+}
+
+/// **Resource Capability**
+///
+/// What is implemented for one resource type. Each FHIR module declares its own next to the
+/// handlers, so the CapabilityStatement lists exactly what the routes serve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResourceCapability {
+    pub resource_type: &'static str,
+    pub interactions: &'static [&'static str], // read | search-type | create | delete
+    pub search_params: &'static [SearchParam],
+    pub operations: &'static [Operation],
+}
+
+/// Every resource type served under `/fhir`, in CapabilityStatement order.
+pub const RESOURCES: [ResourceCapability; 11] = [
+    observation::CAPABILITY,
+    registry::PATIENT_CAPABILITY,
+    registry::DEVICE_CAPABILITY,
+    registry::DEVICE_METRIC_CAPABILITY,
+    alerts::FLAG_CAPABILITY,
+    alerts::DETECTED_ISSUE_CAPABILITY,
+    alerts::AUDIT_EVENT_CAPABILITY,
+    assessment::QUESTIONNAIRE_CAPABILITY,
+    assessment::QUESTIONNAIRE_RESPONSE_CAPABILITY,
+    risk::CAPABILITY,
+    subscription::CAPABILITY,
+];
+
+/// **CapabilityStatement**
+///
+/// The server's `instance` statement: JSON only, the resources in `RESOURCES` and the
+/// system-level Bulk Data `$export`.
+pub fn capability_statement(base: &str) -> Value {
+    let resources: Vec<Value> = RESOURCES
+        .iter()
+        .map(|resource| {
+            let mut entry = json!({
+                "type": resource.resource_type,
+                "interaction": resource
+                    .interactions
+                    .iter()
+                    .map(|code| json!({ "code": code }))
+                    .collect::<Vec<_>>(),
+            });
+            if !resource.search_params.is_empty() {
+                entry["searchParam"] = resource
+                    .search_params
+                    .iter()
+                    .map(|p| json!({ "name": p.name, "type": p.kind, "documentation": p.documentation }))
+                    .collect();
+            }
+            if !resource.operations.is_empty() {
+                entry["operation"] = operations(resource.operations);
+            }
+            entry
+        })
+        .collect();
+
+    json!({
+        "resourceType": "CapabilityStatement",
+        "id": "fallguard",
+        "url": format!("{}/metadata", base),
+        "name": "FallGuardFhirServer",
+        "title": "FallGuard FHIR Server",
+        "status": "active",
+        "date": Utc::now().format("%Y-%m-%d").to_string(),
+        "publisher": "FallGuard",
+        "kind": "instance",
+        "instantiates": [bulk::BULK_DATA_CAPABILITY],
+        "software": { "name": "FallGuard", "version": env!("CARGO_PKG_VERSION") },
+        "implementation": { "description": "FallGuard fall detection server", "url": base },
+        "fhirVersion": FHIR_VERSION,
+        "format": ["json", "application/fhir+json"],
+        "rest": [{
+            "mode": "server",
+            "resource": resources,
+            "operation": operations(&bulk::SYSTEM_OPERATIONS),
+        }],
+    })
+}
+
+fn operations(operations: &[Operation]) -> Value {
+    operations
+        .iter()
+        .map(|op| json!({ "name": op.name, "definition": op.definition }))
+        .collect()
+}
+
+/// **GET /fhir/metadata**
+///
+/// The CapabilityStatement (always up to date: it is built from the modules' declarations).
+pub async fn metadata(req: HttpRequest) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    fhir_json(
+        StatusCode::OK,
+        &capability_statement(&super::base_url(&req)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{get, get_json, memory_state};
+
+    // /fhir/metadata is an R4 CapabilityStatement with every resource and the system $export
+    #[actix_web::test]
+    async fn test_metadata() {
+        let statement = get_json(&memory_state(), "/fhir/metadata").await;
+        assert_eq!(statement["resourceType"], "CapabilityStatement");
+        assert_eq!(statement["fhirVersion"], FHIR_VERSION);
+        assert_eq!(statement["rest"][0]["operation"][0]["name"], "export");
+        let resources = statement["rest"][0]["resource"].as_array().unwrap();
+        assert_eq!(resources.len(), RESOURCES.len());
+    }
+
+    // Every advertised resource answers search and read
+    #[actix_web::test]
+    async fn test_advertised_resources_are_routed() {
+        let state = memory_state();
+        let statement = capability_statement("http://localhost/fhir");
+        for resource in statement["rest"][0]["resource"].as_array().unwrap() {
+            let resource_type = resource["type"].as_str().unwrap();
+            let search = get(&state, &format!("/fhir/{}", resource_type)).await;
+            assert_eq!(search.status(), StatusCode::OK, "{} search", resource_type);
+            let read = get_json(&state, &format!("/fhir/{}/unknown-id", resource_type)).await;
+            assert_eq!(
+                read["issue"][0]["code"], "not-found",
+                "{} read",
+                resource_type
+            );
+        }
+    }
+
+    // Resources list their operations and search parameters
+    #[test]
+    fn test_patient_capability() {
+        let statement = capability_statement("http://localhost/fhir");
+        let resources = statement["rest"][0]["resource"].as_array().unwrap();
+        let patient = resources.iter().find(|r| r["type"] == "Patient").unwrap();
+        assert_eq!(patient["operation"][0]["name"], "export");
+        assert!(patient["searchParam"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|p| p["name"] == "identifier"));
+    }
+}
@@ -0,0 +1,422 @@
+use crate::storage::StorageError;
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::Serialize;
+
+pub mod alerts;
+pub mod assessment;
+pub mod bulk;
+pub mod capability;
+pub mod observation;
+pub mod registry;
+pub mod risk;
+pub mod subscription;
+
+pub use observation::{observation_entry, ObservationSearch};
+
+/// Path the FHIR resources are served under (resource URLs are `<base>/<Type>/<id>`).
+pub const FHIR_BASE_PATH: &str = "/fhir";
+
+/// Content type of every FHIR response.
+pub const FHIR_JSON: &str = "application/fhir+json; charset=utf-8";
+
+/// Page size of FHIR searches when `_count` is not given.
+pub const DEFAULT_SEARCH_COUNT: i64 = 20;
+
+/// Registers the FHIR REST API under `FHIR_BASE_PATH`.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope(FHIR_BASE_PATH)
+            .app_data(web::QueryConfig::default().error_handler(query_error_handler))
+            .route("/metadata", web::get().to(capability::metadata))
+            .route("/$export", web::get().to(bulk::export_system))
+            .route("/Patient/$export", web::get().to(bulk::export_patients))
+            .route("/$export-status/{id}", web::get().to(bulk::status))
+            .route("/$export-status/{id}", web::delete().to(bulk::cancel))
+            .route("/$export-file/{id}/{file}", web::get().to(bulk::file))
+            .route("/Observation", web::get().to(observation::search))
+            .route("/Observation/{id}", web::get().to(observation::read))
+            .route("/Patient", web::get().to(registry::search_patients))
+            .route("/Patient/{id}", web::get().to(registry::read_patient))
+            .route("/Device", web::get().to(registry::search_devices))
+            .route("/Device/{id}", web::get().to(registry::read_device))
+            .route(
+                "/DeviceMetric",
+                web::get().to(registry::search_device_metrics),
+            )
+            .route(
+                "/DeviceMetric/{id}",
+                web::get().to(registry::read_device_metric),
+            )
+            .route("/Flag", web::get().to(alerts::search_flags))
+            .route("/Flag/{id}", web::get().to(alerts::read_flag))
+            .route(
+                "/DetectedIssue",
+                web::get().to(alerts::search_detected_issues),
+            )
+            .route(
+                "/DetectedIssue/{id}",
+                web::get().to(alerts::read_detected_issue),
+            )
+            .route("/AuditEvent", web::get().to(alerts::search_audit_events))
+            .route("/AuditEvent/{id}", web::get().to(alerts::read_audit_event))
+            .route(
+                "/Questionnaire",
+                web::get().to(assessment::search_questionnaires),
+            )
+            .route(
+                "/Questionnaire/{id}",
+                web::get().to(assessment::read_questionnaire),
+            )
+            .route(
+                "/QuestionnaireResponse",
+                web::get().to(assessment::search_questionnaire_responses),
+            )
+            .route(
+                "/QuestionnaireResponse/{id}",
+                web::get().to(assessment::read_questionnaire_response),
+            )
+            .route("/RiskAssessment", web::get().to(risk::search))
+            .route("/RiskAssessment/{id}", web::get().to(risk::read))
+            .route("/Subscription", web::get().to(subscription::search))
+            .route("/Subscription", web::post().to(subscription::create))
+            .route("/Subscription/{id}", web::get().to(subscription::read))
+            .route("/Subscription/{id}", web::delete().to(subscription::delete))
+            .route(
+                "/Subscription/{id}/$status",
+                web::get().to(subscription::status),
+            )
+            .default_service(web::to(unknown_endpoint)),
+    );
+}
+
+// --- Bundle (http://hl7.org/fhir/R4/bundle.html) ---
+
+/// **FHIR Bundle**
+///
+/// Only the `searchset` type is produced: one page of search results plus paging links.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bundle {
+    pub resource_type: &'static str,
+    pub id: String,
+    pub meta: Meta,
+    #[serde(rename = "type")]
+    pub bundle_type: &'static str,
+    pub total: i64, // Every match, not just this page
+    pub link: Vec<BundleLink>,
+    pub entry: Vec<BundleEntry>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Meta {
+    pub last_updated: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BundleLink {
+    pub relation: &'static str, // "self" or "next"
+    pub url: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleEntry {
+    pub full_url: String,
+    pub resource: serde_json::Value,
+    pub search: BundleSearch,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BundleSearch {
+    pub mode: &'static str, // "match" for results, "include" / "outcome" otherwise
+}
+
+impl Bundle {
+    /// A page of search results; `next_url` is set when more pages follow.
+    pub fn searchset(
+        total: i64,
+        self_url: String,
+        next_url: Option<String>,
+        entry: Vec<BundleEntry>,
+    ) -> Self {
+        let mut link = vec![BundleLink {
+            relation: "self",
+            url: self_url,
+        }];
+        if let Some(url) = next_url {
+            link.push(BundleLink {
+                relation: "next",
+                url,
+            });
+        }
+        Self {
+            resource_type: "Bundle",
+            id: uuid::Uuid::new_v4().to_string(),
+            meta: Meta {
+                last_updated: Utc::now().to_rfc3339(),
+            },
+            bundle_type: "searchset",
+            total,
+            link,
+            entry,
+        }
+    }
+}
+
+/// Unpaged `searchset` of every match, for resources that are few enough to list at once.
+fn searchset(req: &HttpRequest, entries: Vec<BundleEntry>) -> HttpResponse {
+    let total = entries.len() as i64;
+    fhir_json(
+        StatusCode::OK,
+        &Bundle::searchset(total, request_url(req), None, entries),
+    )
+}
+
+impl BundleEntry {
+    /// A search result, addressed by its absolute URL.
+    pub fn matched(full_url: String, resource: &impl Serialize) -> Self {
+        Self {
+            full_url,
+            resource: serde_json::to_value(resource).unwrap_or_default(),
+            search: BundleSearch { mode: "match" },
+        }
+    }
+}
+
+// --- OperationOutcome (http://hl7.org/fhir/R4/operationoutcome.html) ---
+
+/// **FHIR OperationOutcome**
+///
+/// Returned instead of a plain-text body whenever a FHIR request fails.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationOutcome {
+    pub resource_type: &'static str,
+    pub issue: Vec<OutcomeIssue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutcomeIssue {
+    pub severity: &'static str, // "fatal" | "error" | "warning" | "information"
+    pub code: &'static str,     // http://hl7.org/fhir/issue-type, e.g. "invalid", "not-found"
+    pub diagnostics: String,
+}
+
+impl OperationOutcome {
+    pub fn error(code: &'static str, diagnostics: impl Into<String>) -> Self {
+        Self {
+            resource_type: "OperationOutcome",
+            issue: vec![OutcomeIssue {
+                severity: "error",
+                code,
+                diagnostics: diagnostics.into(),
+            }],
+        }
+    }
+}
+
+/// Responds with `body` as `application/fhir+json`.
+pub fn fhir_json(status: StatusCode, body: &impl Serialize) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type(FHIR_JSON)
+        .json(body)
+}
+
+/// Responds with an OperationOutcome (the FHIR twin of `api::storage_error_response`).
+pub fn outcome_response(
+    status: StatusCode,
+    code: &'static str,
+    diagnostics: String,
+) -> HttpResponse {
+    fhir_json(status, &OperationOutcome::error(code, diagnostics))
+}
+
+/// 404 OperationOutcome for `<resource_type>/<id>`.
+pub fn not_found(resource_type: &str, id: &str) -> HttpResponse {
+    outcome_response(
+        StatusCode::NOT_FOUND,
+        "not-found",
+        format!("{}/{} not found", resource_type, id),
+    )
+}
+
+/// Maps a storage failure to an OperationOutcome (bad parameters are the client's fault).
+pub fn storage_error_outcome(e: StorageError, context: &str) -> HttpResponse {
+    match e {
+        StorageError::InvalidQuery(msg) => {
+            outcome_response(StatusCode::BAD_REQUEST, "invalid", msg)
+        }
+        StorageError::NotFound(what) => outcome_response(
+            StatusCode::NOT_FOUND,
+            "not-found",
+            format!("{} not found", what),
+        ),
+        other => {
+            eprintln!("❌ {}: {:?}", context, other);
+            outcome_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "exception",
+                context.to_string(),
+            )
+        }
+    }
+}
+
+/// Turns query-string parse failures (e.g. a malformed date) into an OperationOutcome.
+pub fn query_error_handler(
+    err: actix_web::error::QueryPayloadError,
+    _req: &HttpRequest,
+) -> actix_web::Error {
+    let response = outcome_response(StatusCode::BAD_REQUEST, "invalid", err.to_string());
+    actix_web::error::InternalError::from_response(err, response).into()
+}
+
+// --- URLs ---
+
+/// Absolute FHIR base URL as seen by the client (honours `Forwarded` / `X-Forwarded-*`).
+pub fn base_url(req: &HttpRequest) -> String {
+    let info = req.connection_info();
+    format!("{}://{}{}", info.scheme(), info.host(), FHIR_BASE_PATH)
+}
+
+/// Absolute URL of the current request, query string included.
+pub fn request_url(req: &HttpRequest) -> String {
+    let info = req.connection_info();
+    format!("{}://{}{}", info.scheme(), info.host(), req.uri())
+}
+
+/// `url` with `name` set to `value` (replacing any existing value), for paging links.
+pub fn with_query_param(url: &str, name: &str, value: &str) -> String {
+    let Ok(mut parsed) = url::Url::parse(url) else {
+        return url.to_string();
+    };
+    let kept: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(k, _)| k != name)
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    parsed
+        .query_pairs_mut()
+        .clear()
+        .extend_pairs(kept)
+        .append_pair(name, value);
+    parsed.to_string()
+}
+
+/// Decoded query parameters of a search, in order (repeated names are kept).
+pub fn search_params(req: &HttpRequest) -> Vec<(String, String)> {
+    url::form_urlencoded::parse(req.query_string().as_bytes())
+        .map(|(k, v)| (k.into_owned(), v.trim().to_string()))
+        .collect()
+}
+
+/// Id from a reference parameter: `<Type>/<id>` (optionally absolute) or a bare id.
+/// `None` when it references another resource type.
+pub fn reference_id<'a>(value: &'a str, resource_type: &str) -> Option<&'a str> {
+    match value.rsplit_once('/') {
+        Some((prefix, id))
+            if prefix == resource_type || prefix.ends_with(&format!("/{}", resource_type)) =>
+        {
+            Some(id)
+        }
+        Some(_) => None,
+        None => Some(value),
+    }
+}
+
+// --- Content negotiation ---
+
+/// Only JSON is served. `_format` wins over `Accept`; anything else is a 406 OperationOutcome.
+pub fn negotiate(req: &HttpRequest) -> Result<(), HttpResponse> {
+    let format = url::form_urlencoded::parse(req.query_string().as_bytes())
+        .find(|(k, _)| k == "_format")
+        .map(|(_, v)| v.into_owned());
+    let accepted = match &format {
+        Some(format) => is_json_media_type(format) || format.trim() == "json",
+        None => req
+            .headers()
+            .get(actix_web::http::header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_none_or(|accept| accept.split(',').any(is_json_media_type)),
+    };
+    if accepted {
+        return Ok(());
+    }
+    Err(outcome_response(
+        StatusCode::NOT_ACCEPTABLE,
+        "not-supported",
+        format!(
+            "only JSON is supported (requested {})",
+            format.as_deref().unwrap_or("via Accept")
+        ),
+    ))
+}
+
+fn is_json_media_type(range: &str) -> bool {
+    let media_type = range.split(';').next().unwrap_or("").trim();
+    matches!(
+        media_type,
+        "*/*"
+            | "application/*"
+            | "application/json"
+            | "application/fhir+json"
+            | "application/json+fhir"
+    )
+}
+
+/// Anything under `/fhir` that isn't implemented.
+async fn unknown_endpoint(req: HttpRequest) -> HttpResponse {
+    outcome_response(
+        StatusCode::NOT_FOUND,
+        "not-supported",
+        format!("{} {} is not supported", req.method(), req.path()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{call, get, memory_state};
+    use actix_web::http::StatusCode;
+    use actix_web::test::{read_body_json, TestRequest};
+
+    // _format=xml is refused with a 406 OperationOutcome
+    #[actix_web::test]
+    async fn test_format_not_acceptable() {
+        let response = get(&memory_state(), "/fhir/Observation?_format=xml").await;
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+        let outcome: serde_json::Value = read_body_json(response).await;
+        assert_eq!(outcome["resourceType"], "OperationOutcome");
+    }
+
+    // An Accept header without JSON is refused
+    #[actix_web::test]
+    async fn test_accept_not_acceptable() {
+        let req = TestRequest::get()
+            .uri("/fhir/Observation")
+            .insert_header(("Accept", "application/fhir+xml"));
+        let response = call(&memory_state(), req).await;
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+    }
+
+    // _format wins over the Accept header
+    #[actix_web::test]
+    async fn test_format_overrides_accept() {
+        let req = TestRequest::get()
+            .uri("/fhir/Observation?_format=json")
+            .insert_header(("Accept", "application/fhir+xml"));
+        let response = call(&memory_state(), req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // Resource types that are not served are a not-supported OperationOutcome
+    #[actix_web::test]
+    async fn test_unknown_resource_type() {
+        let response = get(&memory_state(), "/fhir/Encounter").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let outcome: serde_json::Value = read_body_json(response).await;
+        assert_eq!(outcome["resourceType"], "OperationOutcome");
+        assert_eq!(outcome["issue"][0]["code"], "not-supported");
+    }
+}
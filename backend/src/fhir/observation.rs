@@ -0,0 +1,564 @@
+use super::capability::{ResourceCapability, SearchParam};
+use super::{
+    base_url, fhir_json, negotiate, not_found, reference_id, storage_error_outcome,
+    with_query_param, Bundle, BundleEntry, DEFAULT_SEARCH_COUNT,
+};
+use crate::model::{FallLog, FALL_OBSERVATION_CODINGS, FALL_SEVERITIES};
+use crate::storage::query::{SortKey, MAX_PAGE_SIZE};
+use crate::storage::{
+    EventCursor, EventFilter, EventQuery, EventSort, StorageError, StorageResult,
+};
+use crate::AppState;
+use actix_web::{http::StatusCode, web, HttpRequest, Responder};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+
+/// Observation interactions and the parameters `ObservationSearch` applies.
+pub const CAPABILITY: ResourceCapability = ResourceCapability {
+    resource_type: "Observation",
+    interactions: &["read", "search-type"],
+    search_params: &[
+        SearchParam {
+            name: "subject",
+            kind: "reference",
+            documentation: "Patient/<id> or a bare patient id",
+        },
+        SearchParam {
+            name: "patient",
+            kind: "reference",
+            documentation: "Same as subject",
+        },
+        SearchParam {
+            name: "date",
+            kind: "date",
+            documentation:
+                "Detection time; eq, ge, gt, le and lt prefixes, repeat to bound both ends",
+        },
+        SearchParam {
+            name: "status",
+            kind: "token",
+            documentation: "preliminary, final or entered-in-error (comma-separated)",
+        },
+        SearchParam {
+            name: "code",
+            kind: "token",
+            documentation: "http://loinc.org|89020-2 or http://snomed.info/sct|1912002",
+        },
+        SearchParam {
+            name: "_count",
+            kind: "number",
+            documentation: "Page size (0 returns only the total)",
+        },
+    ],
+    operations: &[],
+};
+
+/// Bundle entry for one fall event.
+pub fn observation_entry(base: &str, log: &FallLog) -> BundleEntry {
+    BundleEntry::matched(format!("{}/Observation/{}", base, log.id), &log.to_fhir())
+}
+
+/// **Observation Search**
+///
+/// `GET /fhir/Observation?subject=&date=&status=&code=&_count=&_sort=` translated into an event query:
+/// - `subject` / `patient`: `Patient/<id>` or a bare patient id
+/// - `date`: `[eq|ge|gt|le|lt]` + `YYYY`, `YYYY-MM`, `YYYY-MM-DD` or a full dateTime; repeat to bound both ends
+/// - `status`, `code`: token lists (`final,entered-in-error`, `http://loinc.org|89020-2`)
+/// - `_count` (0 returns only `total`), `_sort` (`date` or `-date`), `_cursor` (from the `next` link)
+///
+/// Unknown parameters are ignored, as FHIR servers do by default, and left out of the `self` link.
+#[derive(Debug, Default)]
+pub struct ObservationSearch {
+    pub query: EventQuery,
+    pub summary_only: bool,
+    pub no_match: bool, // A status, code or subject no fall Observation can have
+    pub params: Vec<(String, String)>,
+}
+
+impl ObservationSearch {
+    pub fn parse(query_string: &str) -> StorageResult<Self> {
+        let mut search = Self {
+            query: EventQuery {
+                limit: DEFAULT_SEARCH_COUNT,
+                ..EventQuery::default()
+            },
+            ..Self::default()
+        };
+        let mut statuses: Option<Vec<String>> = None;
+        let mut cursor = None;
+
+        for (name, value) in url::form_urlencoded::parse(query_string.as_bytes()) {
+            let value = value.trim();
+            match name.as_ref() {
+                "subject" | "patient" => match reference_id(value, "Patient") {
+                    Some(patient)
+                        if search
+                            .query
+                            .filter
+                            .patient_id
+                            .as_deref()
+                            .is_none_or(|p| p == patient) =>
+                    {
+                        search.query.filter.patient_id = Some(patient.to_string())
+                    }
+                    _ => search.no_match = true,
+                },
+                "date" => narrow_dates(&mut search.query.filter, value)?,
+                "status" => {
+                    let wanted = value
+                        .split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| statuses.as_ref().is_none_or(|prev| prev.contains(s)))
+                        .collect();
+                    statuses = Some(wanted);
+                }
+                "code" => {
+                    if !value.split(',').any(code_matches) {
+                        search.no_match = true;
+                    }
+                }
+                "_count" => {
+                    let count: i64 = value.parse().map_err(|_| {
+                        StorageError::InvalidQuery(format!("invalid _count: {}", value))
+                    })?;
+                    if count < 0 {
+                        return Err(StorageError::InvalidQuery(format!(
+                            "invalid _count: {}",
+                            value
+                        )));
+                    }
+                    search.summary_only = count == 0;
+                    search.query.limit = count.clamp(1, MAX_PAGE_SIZE);
+                }
+                "_sort" => {
+                    let (descending, field) = match value.strip_prefix('-') {
+                        Some(field) => (true, field),
+                        None => (false, value),
+                    };
+                    let key = match field {
+                        "date" => SortKey::DetectedAt,
+                        other => {
+                            return Err(StorageError::InvalidQuery(format!(
+                                "unsupported _sort: {}",
+                                other
+                            )))
+                        }
+                    };
+                    search.query.sort = EventSort { key, descending };
+                }
+                "_cursor" => cursor = Some(value.to_string()),
+                "_format" => {}
+                _ => continue,
+            }
+            search.params.push((name.into_owned(), value.to_string()));
+        }
+
+        if let Some(statuses) = statuses {
+            search.no_match |= !narrow_statuses(&mut search.query.filter, &statuses);
+        }
+        if let Some(token) = cursor {
+            search.query.cursor = Some(EventCursor::decode(&token, &search.query.sort)?);
+        }
+        Ok(search)
+    }
+
+    /// Absolute URL of this search, with only the parameters that were applied.
+    pub fn self_url(&self, base: &str) -> String {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(&self.params)
+            .finish();
+        if query.is_empty() {
+            format!("{}/Observation", base)
+        } else {
+            format!("{}/Observation?{}", base, query)
+        }
+    }
+}
+
+/// Whether a `[system]|[code]` or `[code]` token names one of the fall Observation codings.
+fn code_matches(token: &str) -> bool {
+    let (system, code) = match token.split_once('|') {
+        Some((system, code)) => (Some(system), code),
+        None => (None, token),
+    };
+    FALL_OBSERVATION_CODINGS.iter().any(|(s, c, _)| {
+        system.is_none_or(|system| system == *s) && (code.is_empty() || code == *c)
+    })
+}
+
+/// Restricts the filter to events whose Observation has one of `statuses`.
+/// Dismissed events are `entered-in-error` whatever their severity.
+/// Returns `false` when no event can match.
+fn narrow_statuses(filter: &mut EventFilter, statuses: &[String]) -> bool {
+    let severities: Vec<String> = FALL_SEVERITIES
+        .into_iter()
+        .filter(|s| statuses.iter().any(|st| st == FallLog::fhir_status(s).0))
+        .map(String::from)
+        .collect();
+    if statuses.iter().any(|s| s == "entered-in-error") {
+        filter.include_dismissed = true;
+    } else {
+        filter.dismissed = Some(false);
+    }
+    let any = !severities.is_empty();
+    filter.severities = severities;
+    any
+}
+
+/// Narrows the filter's inclusive `detected_at` bounds by one `date` value.
+fn narrow_dates(filter: &mut EventFilter, value: &str) -> StorageResult<()> {
+    let (prefix, date) = match value.get(..2) {
+        Some(p) if p.chars().all(|c| c.is_ascii_alphabetic()) => (p, &value[2..]),
+        _ => ("eq", value),
+    };
+    let (start, end) = date_range(date)
+        .ok_or_else(|| StorageError::InvalidQuery(format!("invalid date: {}", value)))?;
+    let tick = Duration::microseconds(1);
+    let (from, to) = match prefix {
+        "eq" => (Some(start), Some(end)),
+        "ge" => (Some(start), None),
+        "gt" => (Some(end + tick), None),
+        "le" => (None, Some(end)),
+        "lt" => (None, Some(start - tick)),
+        other => {
+            return Err(StorageError::InvalidQuery(format!(
+                "unsupported date prefix: {}",
+                other
+            )))
+        }
+    };
+    if let Some(from) = from {
+        filter.from = Some(filter.from.map_or(from, |f| f.max(from)));
+    }
+    if let Some(to) = to {
+        filter.to = Some(filter.to.map_or(to, |t| t.min(to)));
+    }
+    Ok(())
+}
+
+/// Instants covered by a date at its own precision (a day covers midnight to midnight), both ends inclusive.
+fn date_range(value: &str) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let midnight = |y: i32, m: u32, d: u32| {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map(|d| d.and_utc())
+    };
+    if !value.is_ascii() {
+        return None;
+    }
+    let (start, next) = match value.len() {
+        4 => {
+            let year = value.parse().ok()?;
+            (midnight(year, 1, 1)?, midnight(year + 1, 1, 1)?)
+        }
+        7 if &value[4..5] == "-" => {
+            let year = value[..4].parse().ok()?;
+            let month = value[5..].parse().ok()?;
+            let next = if month == 12 {
+                midnight(year + 1, 1, 1)
+            } else {
+                midnight(year, month + 1, 1)
+            };
+            (midnight(year, month, 1)?, next?)
+        }
+        10 => {
+            let day = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+            let start = day.and_hms_opt(0, 0, 0)?.and_utc();
+            (start, start + Duration::days(1))
+        }
+        _ => {
+            let start = DateTime::parse_from_rfc3339(value)
+                .map(|d| d.with_timezone(&Utc))
+                .or_else(|_| {
+                    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S").map(|d| d.and_utc())
+                })
+                .ok()?;
+            let precision = if value.contains('.') {
+                Duration::microseconds(1)
+            } else {
+                Duration::seconds(1)
+            };
+            (start, start + precision)
+        }
+    };
+    Some((start, next - Duration::microseconds(1)))
+}
+
+/// **GET /fhir/Observation**
+///
+/// FHIR search over fall events; returns a `searchset` Bundle (see `ObservationSearch`).
+pub async fn search(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let search = match ObservationSearch::parse(req.query_string()) {
+        Ok(s) => s,
+        Err(e) => return storage_error_outcome(e, "Error searching observations"),
+    };
+    let base = base_url(&req);
+    let self_url = search.self_url(&base);
+    if search.no_match {
+        return fhir_json(
+            StatusCode::OK,
+            &Bundle::searchset(0, self_url, None, Vec::new()),
+        );
+    }
+
+    match data.db.query_events(&search.query).await {
+        Ok(page) => {
+            if search.summary_only {
+                return fhir_json(
+                    StatusCode::OK,
+                    &Bundle::searchset(page.total, self_url, None, Vec::new()),
+                );
+            }
+            let next_url = page
+                .next_cursor
+                .map(|c| with_query_param(&self_url, "_cursor", &c.encode()));
+            let entries = page
+                .items
+                .iter()
+                .map(|log| observation_entry(&base, log))
+                .collect();
+            fhir_json(
+                StatusCode::OK,
+                &Bundle::searchset(page.total, self_url, next_url, entries),
+            )
+        }
+        Err(e) => storage_error_outcome(e, "Error searching observations"),
+    }
+}
+
+/// **GET /fhir/Observation/{id}**
+///
+/// One fall event as an Observation; unknown ids are a 404 OperationOutcome.
+pub async fn read(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let id = path.into_inner();
+    let Ok(event_id) = id.parse::<i32>() else {
+        return not_found("Observation", &id);
+    };
+    match data.db.get_event(event_id).await {
+        Ok(Some(log)) => fhir_json(StatusCode::OK, &log.to_fhir()),
+        Ok(None) => not_found("Observation", &id),
+        Err(e) => storage_error_outcome(e, "Error reading observation"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::NewEvent;
+    use crate::test_support::{critical_event, get, get_json, get_json_from, memory_state};
+    use crate::AppState;
+    use actix_web::http::StatusCode;
+    use actix_web::test::read_body_json;
+    use actix_web::web;
+    use chrono::{Duration, SecondsFormat, Utc};
+
+    // Helper: P-1001's fall 90 minutes ago (returned), P-1001's false alarm an hour ago and
+    // P-2002's fall half an hour ago
+    async fn three_observations() -> (web::Data<AppState>, i32) {
+        let state = memory_state();
+        let first = state
+            .db
+            .insert_event(critical_event(90, 2.5))
+            .await
+            .unwrap();
+        state
+            .db
+            .insert_event(NewEvent {
+                severity: "False Alarm".to_string(),
+                is_false_alarm: true,
+                ..critical_event(60, 2.1)
+            })
+            .await
+            .unwrap();
+        state
+            .db
+            .insert_event(NewEvent {
+                patient_id: Some("P-2002".to_string()),
+                ..critical_event(30, 3.0)
+            })
+            .await
+            .unwrap();
+        (state, first.id)
+    }
+
+    // Helper: asserts the total each /fhir/Observation query matches
+    async fn assert_totals(state: &web::Data<AppState>, cases: &[(String, i64)]) {
+        for (query, total) in cases {
+            let bundle = get_json(state, &format!("/fhir/Observation?{}", query)).await;
+            assert_eq!(bundle["total"], *total, "{}", query);
+        }
+    }
+
+    // An Observation is read by id as FHIR JSON
+    #[actix_web::test]
+    async fn test_read() {
+        let (state, id) = three_observations().await;
+        let response = get(&state, &format!("/fhir/Observation/{}", id)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/fhir+json; charset=utf-8"
+        );
+        let observation: serde_json::Value = read_body_json(response).await;
+        assert_eq!(observation["id"], id.to_string());
+        assert_eq!(observation["subject"]["reference"], "Patient/P-1001");
+    }
+
+    // Unknown and malformed ids are both not-found OperationOutcomes
+    #[actix_web::test]
+    async fn test_read_unknown_id() {
+        let state = memory_state();
+        for uri in ["/fhir/Observation/999", "/fhir/Observation/abc"] {
+            let response = get(&state, uri).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+            let outcome: serde_json::Value = read_body_json(response).await;
+            assert_eq!(outcome["issue"][0]["code"], "not-found");
+        }
+    }
+
+    // subject and patient accept a Patient reference or a bare id
+    #[actix_web::test]
+    async fn test_search_by_patient() {
+        let (state, _) = three_observations().await;
+        assert_totals(
+            &state,
+            &[
+                ("".to_string(), 3),
+                ("subject=Patient/P-1001".to_string(), 2),
+                ("patient=P-2002".to_string(), 1),
+                ("subject=Device/pi-01".to_string(), 0),
+            ],
+        )
+        .await;
+    }
+
+    // status maps false alarms to entered-in-error and everything else to final
+    #[actix_web::test]
+    async fn test_search_by_status() {
+        let (state, _) = three_observations().await;
+        assert_totals(
+            &state,
+            &[
+                ("status=entered-in-error".to_string(), 1),
+                ("status=final&subject=P-1001".to_string(), 1),
+                ("status=cancelled".to_string(), 0),
+            ],
+        )
+        .await;
+    }
+
+    // code matches the LOINC code with or without its system, and a list of codes
+    #[actix_web::test]
+    async fn test_search_by_code() {
+        let (state, _) = three_observations().await;
+        assert_totals(
+            &state,
+            &[
+                ("code=http://loinc.org|89020-2".to_string(), 3),
+                ("code=89020-2,12345-6".to_string(), 3),
+                ("code=http://snomed.info/sct|89020-2".to_string(), 0),
+            ],
+        )
+        .await;
+    }
+
+    // date takes prefixed instants and partial dates, and repeats combine
+    #[actix_web::test]
+    async fn test_search_by_date() {
+        let (state, _) = three_observations().await;
+        let cutoff =
+            (Utc::now() - Duration::minutes(45)).to_rfc3339_opts(SecondsFormat::Secs, true);
+        assert_totals(
+            &state,
+            &[
+                (format!("date=ge{}", cutoff), 1),
+                (format!("date=lt{}", cutoff), 2),
+                (format!("date=gt{}&date=lt{}", cutoff, cutoff), 0),
+                ("date=ge2000&date=lt2000-02".to_string(), 0),
+                ("date=2000-01-01".to_string(), 0),
+            ],
+        )
+        .await;
+    }
+
+    // code matches the SNOMED code too
+    #[actix_web::test]
+    async fn test_search_by_snomed_code() {
+        let (state, _) = three_observations().await;
+        assert_totals(
+            &state,
+            &[("code=http://snomed.info/sct|1912002".to_string(), 3)],
+        )
+        .await;
+    }
+
+    // _count=0 reports the total without any entries
+    #[actix_web::test]
+    async fn test_search_count_only() {
+        let (state, _) = three_observations().await;
+        let bundle = get_json(&state, "/fhir/Observation?_count=0").await;
+        assert_eq!(bundle["total"], 3);
+        assert_eq!(bundle["entry"].as_array().unwrap().len(), 0);
+    }
+
+    // _sort=date pages oldest first through a _cursor link
+    #[actix_web::test]
+    async fn test_search_pages() {
+        let (state, first) = three_observations().await;
+        let bundle = get_json_from(
+            &state,
+            "ehr.example.org",
+            "/fhir/Observation?_sort=date&_count=2",
+        )
+        .await;
+        assert_eq!(bundle["entry"][0]["resource"]["id"], first.to_string());
+        let next = bundle["link"][1]["url"].as_str().unwrap();
+        assert!(next.contains("_cursor="));
+
+        let page = get_json(&state, next.trim_start_matches("http://ehr.example.org")).await;
+        assert_eq!(page["entry"].as_array().unwrap().len(), 1);
+        assert_eq!(page["entry"][0]["resource"]["valueQuantity"]["value"], 3.0);
+    }
+
+    // Unknown parameters are ignored and left out of the self link
+    #[actix_web::test]
+    async fn test_search_ignores_unknown_parameters() {
+        let (state, _) = three_observations().await;
+        let bundle = get_json_from(
+            &state,
+            "ehr.example.org",
+            "/fhir/Observation?_sort=date&_count=2&unknown=1",
+        )
+        .await;
+        assert_eq!(bundle["total"], 3);
+        assert_eq!(
+            bundle["link"][0]["url"],
+            "http://ehr.example.org/fhir/Observation?_sort=date&_count=2"
+        );
+    }
+
+    // Malformed search parameters are invalid OperationOutcomes
+    #[actix_web::test]
+    async fn test_search_rejects_bad_parameters() {
+        let state = memory_state();
+        for uri in [
+            "/fhir/Observation?_sort=value",
+            "/fhir/Observation?date=ne2026-01-01",
+            "/fhir/Observation?date=yesterday",
+            "/fhir/Observation?_count=-1",
+        ] {
+            let response = get(&state, uri).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+            let outcome: serde_json::Value = read_body_json(response).await;
+            assert_eq!(outcome["resourceType"], "OperationOutcome");
+        }
+    }
+}
@@ -0,0 +1,610 @@
+use super::capability::{ResourceCapability, SearchParam};
+use super::{
+    base_url, bulk, fhir_json, negotiate, not_found, reference_id, search_params, searchset,
+    storage_error_outcome, BundleEntry,
+};
+use crate::model::{Device, Patient, UCUM};
+use crate::storage::{EventFilter, EventQuery, Repository, StorageResult};
+use crate::AppState;
+use actix_web::{http::StatusCode, web, HttpRequest, Responder};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value};
+
+/// Identifier system of patient ids.
+pub const PATIENT_ID_SYSTEM: &str = "urn:fallguard:patient";
+
+/// Identifier system of device ids.
+pub const DEVICE_ID_SYSTEM: &str = "urn:fallguard:device";
+
+/// Code system of the DeviceMetric types.
+pub const DEVICE_METRICS_SYSTEM: &str = "urn:fallguard:device-metrics";
+
+/// Extensions carrying a metric's latest reading and when it was reported
+/// (DeviceMetric describes a measurement but has no value element of its own).
+pub const LATEST_VALUE_EXTENSION: &str = "urn:fallguard:fhir:extension:latest-value";
+pub const LATEST_VALUE_TIME_EXTENSION: &str = "urn:fallguard:fhir:extension:latest-value-time";
+
+/// A device that reported within this window is `on`, otherwise `off`.
+const ONLINE_WINDOW_MINUTES: i64 = 2;
+
+/// The DeviceMetrics of every device: (code, display, UCUM unit). Ids are `<device>-<code>`.
+pub const DEVICE_METRICS: [(&str, &str, &str); 4] = [
+    ("accelerometer", "Acceleration", "[g]"),
+    ("battery", "Battery level", "%"),
+    ("wifi-signal", "Wi-Fi signal strength", "%"),
+    ("temperature", "Sensor temperature", "Cel"),
+];
+
+const ID_PARAM: SearchParam = SearchParam {
+    name: "_id",
+    kind: "token",
+    documentation: "Resource id",
+};
+
+pub const PATIENT_CAPABILITY: ResourceCapability = ResourceCapability {
+    resource_type: "Patient",
+    interactions: &["read", "search-type"],
+    search_params: &[
+        ID_PARAM,
+        SearchParam {
+            name: "identifier",
+            kind: "token",
+            documentation: "urn:fallguard:patient|<id> or a bare id",
+        },
+    ],
+    operations: &[bulk::PATIENT_EXPORT],
+};
+
+pub const DEVICE_CAPABILITY: ResourceCapability = ResourceCapability {
+    resource_type: "Device",
+    interactions: &["read", "search-type"],
+    search_params: &[
+        ID_PARAM,
+        SearchParam {
+            name: "identifier",
+            kind: "token",
+            documentation: "urn:fallguard:device|<id> or a bare id",
+        },
+        SearchParam {
+            name: "patient",
+            kind: "reference",
+            documentation: "Sensors worn by Patient/<id>",
+        },
+    ],
+    operations: &[],
+};
+
+pub const DEVICE_METRIC_CAPABILITY: ResourceCapability = ResourceCapability {
+    resource_type: "DeviceMetric",
+    interactions: &["read", "search-type"],
+    search_params: &[
+        SearchParam {
+            name: "source",
+            kind: "reference",
+            documentation: "Metrics of Device/<id>",
+        },
+        SearchParam {
+            name: "type",
+            kind: "token",
+            documentation: "accelerometer, battery, wifi-signal or temperature",
+        },
+    ],
+    operations: &[],
+};
+
+// --- Patient ---
+
+/// Patient resource for a registered patient.
+pub fn patient_resource(patient: &Patient) -> Value {
+    let mut resource = json!({
+        "resourceType": "Patient",
+        "id": patient.id,
+        "meta": { "lastUpdated": patient.updated_at.to_rfc3339() },
+        "identifier": [{ "system": PATIENT_ID_SYSTEM, "value": patient.id }],
+        "active": true,
+    });
+    if patient.family_name.is_some() || patient.given_name.is_some() {
+        let mut name = json!({});
+        if let Some(family) = &patient.family_name {
+            name["family"] = json!(family);
+        }
+        if let Some(given) = &patient.given_name {
+            name["given"] = json!([given]);
+        }
+        resource["name"] = json!([name]);
+    }
+    if let Some(gender) = &patient.gender {
+        resource["gender"] = json!(gender);
+    }
+    if let Some(birth_date) = patient.birth_date {
+        resource["birthDate"] = json!(birth_date.format("%Y-%m-%d").to_string());
+    }
+    resource
+}
+
+/// Patient known only from events or device assignments (no demographics on file).
+fn referenced_patient(id: &str) -> Value {
+    json!({
+        "resourceType": "Patient",
+        "id": id,
+        "identifier": [{ "system": PATIENT_ID_SYSTEM, "value": id }],
+    })
+}
+
+/// Whether a patient id appears in a device assignment or an event.
+async fn patient_is_referenced(db: &dyn Repository, id: &str) -> StorageResult<bool> {
+    let devices = db.list_devices().await?;
+    if devices.iter().any(|d| d.patient_id.as_deref() == Some(id)) {
+        return Ok(true);
+    }
+    let query = EventQuery {
+        filter: EventFilter {
+            patient_id: Some(id.to_string()),
+            ..EventFilter::default()
+        },
+        limit: 1,
+        ..EventQuery::default()
+    };
+    Ok(db.query_events(&query).await?.total > 0)
+}
+
+/// **GET /fhir/Patient/{id}**
+///
+/// Registered patients carry their demographics; ids only seen in observations or device
+/// assignments resolve to a bare Patient so every reference we emit can be followed.
+pub async fn read_patient(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let id = path.into_inner();
+    match data.db.get_patient(&id).await {
+        Ok(Some(patient)) => return fhir_json(StatusCode::OK, &patient_resource(&patient)),
+        Ok(None) => {}
+        Err(e) => return storage_error_outcome(e, "Error reading patient"),
+    }
+    match patient_is_referenced(data.db.as_ref(), &id).await {
+        Ok(true) => fhir_json(StatusCode::OK, &referenced_patient(&id)),
+        Ok(false) => not_found("Patient", &id),
+        Err(e) => storage_error_outcome(e, "Error reading patient"),
+    }
+}
+
+/// **GET /fhir/Patient?_id=&identifier=**
+///
+/// Registered patients as a `searchset` Bundle.
+pub async fn search_patients(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let mut patients = match data.db.list_patients().await {
+        Ok(p) => p,
+        Err(e) => return storage_error_outcome(e, "Error searching patients"),
+    };
+    for (name, value) in search_params(&req) {
+        match name.as_str() {
+            "_id" => patients.retain(|p| p.id == value),
+            "identifier" => {
+                patients.retain(|p| identifier_matches(&value, PATIENT_ID_SYSTEM, &p.id))
+            }
+            _ => {}
+        }
+    }
+    let base = base_url(&req);
+    let entries = patients
+        .iter()
+        .map(|p| BundleEntry::matched(format!("{}/Patient/{}", base, p.id), &patient_resource(p)))
+        .collect();
+    searchset(&req, entries)
+}
+
+// --- Device ---
+
+/// Device resource for a registered sensor.
+pub fn device_resource(device: &Device) -> Value {
+    let mut resource = json!({
+        "resourceType": "Device",
+        "id": device.id,
+        "identifier": [{ "system": DEVICE_ID_SYSTEM, "value": device.id }],
+        "status": "active",
+        "deviceName": [{ "name": device.id, "type": "user-friendly-name" }],
+        "type": { "text": "Wearable fall detector" },
+    });
+    if let Some(model) = &device.model {
+        resource["modelNumber"] = json!(model);
+    }
+    if let Some(patient) = &device.patient_id {
+        resource["patient"] = json!({ "reference": format!("Patient/{}", patient) });
+    }
+    if let Some(ward) = &device.ward {
+        resource["location"] = json!({ "display": ward });
+    }
+    resource
+}
+
+/// Device known only from events (never connected since the registry was introduced).
+fn referenced_device(id: &str) -> Value {
+    json!({
+        "resourceType": "Device",
+        "id": id,
+        "identifier": [{ "system": DEVICE_ID_SYSTEM, "value": id }],
+        "status": "unknown",
+    })
+}
+
+/// **GET /fhir/Device/{id}**
+///
+/// Registered sensors, or a bare Device for ids only seen in observations.
+pub async fn read_device(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let id = path.into_inner();
+    match data.db.get_device(&id).await {
+        Ok(Some(device)) => return fhir_json(StatusCode::OK, &device_resource(&device)),
+        Ok(None) => {}
+        Err(e) => return storage_error_outcome(e, "Error reading device"),
+    }
+    let query = EventQuery {
+        filter: EventFilter {
+            device_id: Some(id.clone()),
+            ..EventFilter::default()
+        },
+        limit: 1,
+        ..EventQuery::default()
+    };
+    match data.db.query_events(&query).await {
+        Ok(page) if page.total > 0 => fhir_json(StatusCode::OK, &referenced_device(&id)),
+        Ok(_) => not_found("Device", &id),
+        Err(e) => storage_error_outcome(e, "Error reading device"),
+    }
+}
+
+/// **GET /fhir/Device?_id=&identifier=&patient=**
+///
+/// Registered sensors as a `searchset` Bundle.
+pub async fn search_devices(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let mut devices = match data.db.list_devices().await {
+        Ok(d) => d,
+        Err(e) => return storage_error_outcome(e, "Error searching devices"),
+    };
+    for (name, value) in search_params(&req) {
+        match name.as_str() {
+            "_id" => devices.retain(|d| d.id == value),
+            "identifier" => devices.retain(|d| identifier_matches(&value, DEVICE_ID_SYSTEM, &d.id)),
+            "patient" => {
+                let patient = reference_id(&value, "Patient");
+                devices.retain(|d| patient.is_some() && d.patient_id.as_deref() == patient)
+            }
+            _ => {}
+        }
+    }
+    let base = base_url(&req);
+    let entries = devices
+        .iter()
+        .map(|d| BundleEntry::matched(format!("{}/Device/{}", base, d.id), &device_resource(d)))
+        .collect();
+    searchset(&req, entries)
+}
+
+// --- DeviceMetric ---
+
+/// One of the `DEVICE_METRICS` of a device, with its latest reading when there is one.
+pub fn device_metric(device: &Device, code: &str, now: DateTime<Utc>) -> Option<Value> {
+    let (code, display, unit) = DEVICE_METRICS.into_iter().find(|(c, _, _)| *c == code)?;
+    let online = device
+        .last_seen_at
+        .is_some_and(|seen| now - seen <= Duration::minutes(ONLINE_WINDOW_MINUTES));
+    let mut resource = json!({
+        "resourceType": "DeviceMetric",
+        "id": format!("{}-{}", device.id, code),
+        "type": { "coding": [{ "system": DEVICE_METRICS_SYSTEM, "code": code, "display": display }] },
+        "unit": { "coding": [{ "system": UCUM, "code": unit }] },
+        "source": { "reference": format!("Device/{}", device.id) },
+        "operationalStatus": if online { "on" } else { "off" },
+        "category": "measurement",
+    });
+
+    let latest = match code {
+        "accelerometer" => {
+            let mut calibration =
+                json!({ "type": "unspecified", "state": device.calibration_state });
+            if let Some(at) = device.calibrated_at {
+                calibration["time"] = json!(at.to_rfc3339());
+            }
+            resource["calibration"] = json!([calibration]);
+            None
+        }
+        "battery" => device.battery_level,
+        "wifi-signal" => device.wifi_signal.map(f64::from),
+        _ => device.temperature,
+    };
+    if let (Some(value), Some(seen)) = (latest, device.last_seen_at) {
+        resource["extension"] = json!([
+            {
+                "url": LATEST_VALUE_EXTENSION,
+                "valueQuantity": { "value": value, "unit": unit, "system": UCUM, "code": unit }
+            },
+            { "url": LATEST_VALUE_TIME_EXTENSION, "valueInstant": seen.to_rfc3339() }
+        ]);
+    }
+    Some(resource)
+}
+
+/// **GET /fhir/DeviceMetric/{id}**
+///
+/// `<device>-accelerometer` (with the calibration state), `-battery`, `-wifi-signal` or `-temperature`.
+pub async fn read_device_metric(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let id = path.into_inner();
+    let Some((device_id, code)) = DEVICE_METRICS.iter().find_map(|(code, _, _)| {
+        id.strip_suffix(code)
+            .and_then(|rest| rest.strip_suffix('-'))
+            .map(|device_id| (device_id, *code))
+    }) else {
+        return not_found("DeviceMetric", &id);
+    };
+    match data.db.get_device(device_id).await {
+        Ok(Some(device)) => match device_metric(&device, code, Utc::now()) {
+            Some(metric) => fhir_json(StatusCode::OK, &metric),
+            None => not_found("DeviceMetric", &id),
+        },
+        Ok(None) => not_found("DeviceMetric", &id),
+        Err(e) => storage_error_outcome(e, "Error reading device metric"),
+    }
+}
+
+/// **GET /fhir/DeviceMetric?source=&type=**
+///
+/// Every metric of every registered sensor as a `searchset` Bundle.
+pub async fn search_device_metrics(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let mut devices = match data.db.list_devices().await {
+        Ok(d) => d,
+        Err(e) => return storage_error_outcome(e, "Error searching device metrics"),
+    };
+    let mut codes: Vec<&str> = DEVICE_METRICS.iter().map(|(code, _, _)| *code).collect();
+    for (name, value) in search_params(&req) {
+        match name.as_str() {
+            "source" => {
+                let device = reference_id(&value, "Device");
+                devices.retain(|d| Some(d.id.as_str()) == device)
+            }
+            "type" => codes.retain(|code| {
+                value
+                    .split(',')
+                    .any(|token| identifier_matches(token, DEVICE_METRICS_SYSTEM, code))
+            }),
+            _ => {}
+        }
+    }
+    let base = base_url(&req);
+    let now = Utc::now();
+    let entries = devices
+        .iter()
+        .flat_map(|d| {
+            codes
+                .iter()
+                .filter_map(move |code| device_metric(d, code, now))
+        })
+        .map(|metric| {
+            let url = format!(
+                "{}/DeviceMetric/{}",
+                base,
+                metric["id"].as_str().unwrap_or("")
+            );
+            BundleEntry::matched(url, &metric)
+        })
+        .collect();
+    searchset(&req, entries)
+}
+
+// --- Helpers ---
+
+/// Whether a `[system|]value` token names `value` in `system`.
+fn identifier_matches(token: &str, system: &str, value: &str) -> bool {
+    match token.split_once('|') {
+        Some((s, v)) => (s.is_empty() || s == system) && v == value,
+        None => token == value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::{DeviceRegistration, DeviceStatus, NewEvent, NewPatient};
+    use crate::test_support::{critical_event, get, get_json, memory_state};
+    use crate::AppState;
+    use actix_web::http::StatusCode;
+    use actix_web::web;
+    use chrono::{NaiveDate, Utc};
+
+    // Helper: P-1001 wearing the calibrated pi-01 (returns the id of their fall), plus
+    // P-0001 and pi-legacy, only known from an event long ago
+    async fn registry() -> (web::Data<AppState>, i32) {
+        let state = memory_state();
+        let fall = state.db.insert_event(critical_event(5, 2.4)).await.unwrap();
+        state
+            .db
+            .insert_event(NewEvent {
+                device_id: Some("pi-legacy".to_string()),
+                patient_id: Some("P-0001".to_string()),
+                ..critical_event(500, 2.0)
+            })
+            .await
+            .unwrap();
+        let now = Utc::now();
+        state
+            .db
+            .record_device_status(&DeviceStatus {
+                device_id: "pi-01".to_string(),
+                seen_at: now,
+                wifi_signal: Some(88),
+                temperature: Some(30.5),
+                battery_level: Some(91.0),
+            })
+            .await
+            .unwrap();
+        state
+            .db
+            .register_device(&DeviceRegistration {
+                id: "pi-01".to_string(),
+                model: None,
+                patient_id: Some("P-1001".to_string()),
+                ward: Some("ICU".to_string()),
+                calibration_state: Some("calibrated".to_string()),
+                calibrated_at: None,
+                at: now,
+            })
+            .await
+            .unwrap();
+        state
+            .db
+            .upsert_patient(&NewPatient {
+                id: "P-1001".to_string(),
+                family_name: Some("Perera".to_string()),
+                given_name: Some("Nimal".to_string()),
+                birth_date: NaiveDate::from_ymd_opt(1941, 5, 2),
+                gender: Some("male".to_string()),
+                ward: Some("ICU".to_string()),
+                room: None,
+                bed: None,
+                admitted_at: None,
+                discharged_at: None,
+                updated_at: now,
+            })
+            .await
+            .unwrap();
+        (state, fall.id)
+    }
+
+    // The Observation's subject is the Patient with their demographics
+    #[actix_web::test]
+    async fn test_observation_subject_resolves() {
+        let (state, fall) = registry().await;
+        let obs = get_json(&state, &format!("/fhir/Observation/{}", fall)).await;
+        let reference = obs["subject"]["reference"].as_str().unwrap();
+        let patient = get_json(&state, &format!("/fhir/{}", reference)).await;
+        assert_eq!(patient["resourceType"], "Patient");
+        assert_eq!(patient["name"][0]["family"], "Perera");
+        assert_eq!(patient["name"][0]["given"][0], "Nimal");
+        assert_eq!(patient["birthDate"], "1941-05-02");
+    }
+
+    // The Observation's device is the Device, pointing at the patient wearing it
+    #[actix_web::test]
+    async fn test_observation_device_resolves() {
+        let (state, fall) = registry().await;
+        let obs = get_json(&state, &format!("/fhir/Observation/{}", fall)).await;
+        let reference = obs["device"]["reference"].as_str().unwrap();
+        let device = get_json(&state, &format!("/fhir/{}", reference)).await;
+        assert_eq!(device["resourceType"], "Device");
+        assert_eq!(device["patient"]["reference"], "Patient/P-1001");
+    }
+
+    // Ids only known from older events still resolve
+    #[actix_web::test]
+    async fn test_referenced_ids_resolve() {
+        let (state, _) = registry().await;
+        let patient = get_json(&state, "/fhir/Patient/P-0001").await;
+        assert_eq!(patient["identifier"][0]["value"], "P-0001");
+        let device = get_json(&state, "/fhir/Device/pi-legacy").await;
+        assert_eq!(device["status"], "unknown");
+    }
+
+    // Unknown ids and metrics a device never reported are 404
+    #[actix_web::test]
+    async fn test_unknown_ids() {
+        let (state, _) = registry().await;
+        for uri in [
+            "/fhir/Patient/P-404",
+            "/fhir/Device/pi-404",
+            "/fhir/DeviceMetric/pi-01-humidity",
+            "/fhir/DeviceMetric/pi-legacy-battery",
+        ] {
+            let response = get(&state, uri).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+        }
+    }
+
+    // The accelerometer metric carries the device's calibration
+    #[actix_web::test]
+    async fn test_calibration_metric() {
+        let (state, _) = registry().await;
+        let metric = get_json(&state, "/fhir/DeviceMetric/pi-01-accelerometer").await;
+        assert_eq!(metric["source"]["reference"], "Device/pi-01");
+        assert_eq!(metric["calibration"][0]["state"], "calibrated");
+        assert!(metric["calibration"][0]["time"].is_string());
+        assert_eq!(metric["operationalStatus"], "on");
+    }
+
+    // The battery metric carries the last level reported, in percent
+    #[actix_web::test]
+    async fn test_battery_metric() {
+        let (state, _) = registry().await;
+        let metric = get_json(&state, "/fhir/DeviceMetric/pi-01-battery").await;
+        assert_eq!(metric["extension"][0]["valueQuantity"]["value"], 91.0);
+        assert_eq!(metric["extension"][0]["valueQuantity"]["code"], "%");
+    }
+
+    // DeviceMetric searches by source and type
+    #[actix_web::test]
+    async fn test_search_device_metrics() {
+        let (state, _) = registry().await;
+        let bundle = get_json(
+            &state,
+            "/fhir/DeviceMetric?source=Device/pi-01&type=wifi-signal,temperature",
+        )
+        .await;
+        assert_eq!(bundle["total"], 2);
+        assert_eq!(
+            bundle["entry"][0]["resource"]["extension"][0]["valueQuantity"]["value"],
+            88.0
+        );
+        assert_eq!(
+            bundle["entry"][1]["resource"]["extension"][0]["valueQuantity"]["code"],
+            "Cel"
+        );
+    }
+
+    // Device searches by the patient wearing it
+    #[actix_web::test]
+    async fn test_search_devices_by_patient() {
+        let (state, _) = registry().await;
+        let bundle = get_json(&state, "/fhir/Device?patient=Patient/P-1001").await;
+        assert_eq!(bundle["total"], 1);
+    }
+
+    // Patient searches by identifier
+    #[actix_web::test]
+    async fn test_search_patients_by_identifier() {
+        let (state, _) = registry().await;
+        let bundle = get_json(
+            &state,
+            "/fhir/Patient?identifier=urn:fallguard:patient|P-1001",
+        )
+        .await;
+        assert_eq!(bundle["total"], 1);
+        assert!(bundle["entry"][0]["fullUrl"]
+            .as_str()
+            .unwrap()
+            .ends_with("/fhir/Patient/P-1001"));
+    }
+}
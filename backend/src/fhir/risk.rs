@@ -0,0 +1,291 @@
+use super::capability::{ResourceCapability, SearchParam};
+use super::{
+    base_url, fhir_json, negotiate, not_found, reference_id, search_params, searchset,
+    storage_error_outcome, BundleEntry, DEFAULT_SEARCH_COUNT,
+};
+use crate::model::{RiskScore, FALL_METRICS_SYSTEM, FALL_OBSERVATION_CODINGS};
+use crate::risk;
+use crate::storage::query::MAX_PAGE_SIZE;
+use crate::storage::{RiskQuery, StorageError};
+use crate::AppState;
+use actix_web::{http::StatusCode, web, HttpRequest, Responder};
+use serde_json::{json, Value};
+
+/// Code system of the qualitative `prediction.qualitativeRisk`.
+pub const RISK_PROBABILITY_SYSTEM: &str = "http://terminology.hl7.org/CodeSystem/risk-probability";
+
+/// Prefix of the extensions carrying each factor of a score
+/// (`near-misses`, `falls`, `false-alarms`, `night-share`, `morse-total`).
+pub const RISK_FACTOR_EXTENSION_PREFIX: &str = "urn:fallguard:fhir:extension:risk-factor:";
+
+/// Method of every score (the model in `risk`).
+pub const RISK_METHOD_CODE: &str = "fallguard-fall-risk";
+
+pub const CAPABILITY: ResourceCapability = ResourceCapability {
+    resource_type: "RiskAssessment",
+    interactions: &["read", "search-type"],
+    search_params: &[
+        SearchParam {
+            name: "subject",
+            kind: "reference",
+            documentation: "Patient/<id> or a bare patient id",
+        },
+        SearchParam {
+            name: "patient",
+            kind: "reference",
+            documentation: "Same as subject",
+        },
+        SearchParam {
+            name: "_count",
+            kind: "number",
+            documentation: "Newest scores returned (the current score is the first)",
+        },
+    ],
+    operations: &[],
+};
+
+/// One factor of the score as an extension.
+fn factor(code: &str, value: Value) -> Value {
+    let mut extension = json!({ "url": format!("{}{}", RISK_FACTOR_EXTENSION_PREFIX, code) });
+    match value {
+        Value::Number(n) if n.is_i64() => extension["valueInteger"] = json!(n),
+        other => extension["valueDecimal"] = other,
+    }
+    extension
+}
+
+/// RiskAssessment resource for a stored score: the probability of a fall and its rating,
+/// based on the detection that prompted the recalculation (if any).
+pub fn risk_assessment_resource(score: &RiskScore) -> Value {
+    let (system, code, display) = FALL_OBSERVATION_CODINGS[0];
+    let (outcome_system, outcome_code, outcome_display) = FALL_OBSERVATION_CODINGS[1];
+    let mut factors = vec![
+        factor("near-misses", json!(score.near_misses)),
+        factor("falls", json!(score.falls)),
+        factor("false-alarms", json!(score.false_alarms)),
+        factor("night-share", json!(score.night_share)),
+    ];
+    if let Some(total) = score.morse_total {
+        factors.push(factor("morse-total", json!(total)));
+    }
+    let mut resource = json!({
+        "resourceType": "RiskAssessment",
+        "id": score.id.to_string(),
+        "meta": { "lastUpdated": score.calculated_at.to_rfc3339() },
+        "extension": factors,
+        "status": "final",
+        "method": {
+            "coding": [{ "system": FALL_METRICS_SYSTEM, "code": RISK_METHOD_CODE }],
+            "text": "FallGuard fall risk score",
+        },
+        "code": { "coding": [{ "system": system, "code": code, "display": display }] },
+        "subject": { "reference": format!("Patient/{}", score.patient_id) },
+        "occurrenceDateTime": score.calculated_at.to_rfc3339(),
+        "prediction": [{
+            "outcome": {
+                "coding": [{ "system": outcome_system, "code": outcome_code, "display": outcome_display }]
+            },
+            "probabilityDecimal": score.probability,
+            "qualitativeRisk": {
+                "coding": [{
+                    "system": RISK_PROBABILITY_SYSTEM,
+                    "code": score.rating,
+                    "display": format!("{}{} likelihood", score.rating[..1].to_uppercase(), &score.rating[1..]),
+                }]
+            },
+            "rationale": risk::rationale(score),
+        }],
+    });
+    if let Some(event_id) = score.event_id {
+        resource["basis"] = json!([{ "reference": format!("Observation/{}", event_id) }]);
+    }
+    resource
+}
+
+/// **GET /fhir/RiskAssessment/{id}**
+///
+/// One RiskAssessment per stored score (same id).
+pub async fn read(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let id = path.into_inner();
+    let Ok(score_id) = id.parse::<i32>() else {
+        return not_found("RiskAssessment", &id);
+    };
+    match data.db.get_risk_score(score_id).await {
+        Ok(Some(score)) => fhir_json(StatusCode::OK, &risk_assessment_resource(&score)),
+        Ok(None) => not_found("RiskAssessment", &id),
+        Err(e) => storage_error_outcome(e, "Error reading risk assessment"),
+    }
+}
+
+/// **GET /fhir/RiskAssessment?subject=&_count=**
+///
+/// Fall risk scores, newest first: with `_count=1`, a patient's current score; beyond that,
+/// their history.
+pub async fn search(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let mut query = RiskQuery {
+        limit: DEFAULT_SEARCH_COUNT,
+        ..RiskQuery::default()
+    };
+    for (name, value) in search_params(&req) {
+        match name.as_str() {
+            "subject" | "patient" => match reference_id(&value, "Patient") {
+                Some(patient) if query.patient_id.as_deref().is_none_or(|p| p == patient) => {
+                    query.patient_id = Some(patient.to_string())
+                }
+                _ => return searchset(&req, Vec::new()),
+            },
+            "_count" => match value.parse::<i64>() {
+                Ok(count) if count >= 0 => query.limit = count.min(MAX_PAGE_SIZE),
+                _ => {
+                    let e = StorageError::InvalidQuery(format!("invalid _count: {}", value));
+                    return storage_error_outcome(e, "Error searching risk assessments");
+                }
+            },
+            _ => {}
+        }
+    }
+    let scores = match data.db.query_risk_scores(&query).await {
+        Ok(s) => s,
+        Err(e) => return storage_error_outcome(e, "Error searching risk assessments"),
+    };
+    let base = base_url(&req);
+    let entries = scores
+        .iter()
+        .map(|score| {
+            BundleEntry::matched(
+                format!("{}/RiskAssessment/{}", base, score.id),
+                &risk_assessment_resource(score),
+            )
+        })
+        .collect();
+    searchset(&req, entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::RiskScore;
+    use crate::risk::{self, MorseItems};
+    use crate::test_support::{critical_event, get, get_json, memory_state};
+    use crate::AppState;
+    use actix_web::http::StatusCode;
+    use actix_web::web;
+    use chrono::Utc;
+
+    // Helper: P-1001's score after a fall (returned), then after a 65-point Morse entry
+    async fn scored() -> (web::Data<AppState>, RiskScore) {
+        let state = memory_state();
+        let db = state.db.as_ref();
+        let fall = db.insert_event(critical_event(30, 2.6)).await.unwrap();
+        let score = risk::recalculate(
+            db,
+            &state.risk,
+            "P-1001",
+            "event",
+            Some(fall.id),
+            Utc::now(),
+        )
+        .await
+        .unwrap();
+        let items = MorseItems {
+            history_of_falling: 25,
+            secondary_diagnosis: 15,
+            ambulatory_aid: 15,
+            iv_therapy: 0,
+            gait: 10,
+            mental_status: 0,
+        };
+        risk::record_morse(db, &state.risk, "P-1001", &items, "rn:jones", Utc::now())
+            .await
+            .unwrap();
+        (state, score)
+    }
+
+    // A score is a final RiskAssessment of the patient, based on the detection behind it
+    #[actix_web::test]
+    async fn test_risk_assessment() {
+        let (state, score) = scored().await;
+        let uri = format!("/fhir/RiskAssessment/{}", score.id);
+        let resource = get_json(&state, &uri).await;
+        assert_eq!(resource["resourceType"], "RiskAssessment");
+        assert_eq!(resource["status"], "final");
+        assert_eq!(resource["subject"]["reference"], "Patient/P-1001");
+        assert_eq!(resource["code"]["coding"][0]["code"], "89020-2");
+        assert_eq!(
+            resource["basis"][0]["reference"],
+            format!("Observation/{}", score.event_id.unwrap())
+        );
+    }
+
+    // The prediction carries the probability, the qualitative rating and the rationale
+    #[actix_web::test]
+    async fn test_risk_prediction() {
+        let (state, score) = scored().await;
+        let uri = format!("/fhir/RiskAssessment/{}", score.id);
+        let prediction = &get_json(&state, &uri).await["prediction"][0];
+        assert_eq!(prediction["outcome"]["coding"][0]["code"], "1912002");
+        assert_eq!(prediction["probabilityDecimal"], score.probability);
+        let rating = &prediction["qualitativeRisk"]["coding"][0];
+        assert_eq!(rating["code"], "moderate");
+        assert_eq!(rating["display"], "Moderate likelihood");
+        assert!(prediction["rationale"]
+            .as_str()
+            .unwrap()
+            .starts_with("Over the last 90 days: 1 fall(s) detected"));
+    }
+
+    // Each factor is an extension, the Morse total only when there is one
+    #[actix_web::test]
+    async fn test_risk_factors() {
+        let (state, score) = scored().await;
+        let uri = format!("/fhir/RiskAssessment/{}", score.id);
+        let extensions = get_json(&state, &uri).await["extension"].clone();
+        assert_eq!(
+            extensions[1]["url"],
+            "urn:fallguard:fhir:extension:risk-factor:falls"
+        );
+        assert_eq!(extensions[1]["valueInteger"], 1);
+        assert_eq!(extensions.as_array().unwrap().len(), 4);
+        let bundle = get_json(&state, "/fhir/RiskAssessment?subject=P-1001&_count=1").await;
+        assert_eq!(
+            bundle["entry"][0]["resource"]["extension"][4]["valueInteger"],
+            65
+        );
+    }
+
+    // RiskAssessments are searched by patient, newest first, up to _count
+    #[actix_web::test]
+    async fn test_search() {
+        let (state, _) = scored().await;
+        for (query, expected) in [
+            ("subject=Patient/P-1001&_count=1", 1),
+            ("patient=P-1001", 2),
+            ("patient=P-9999", 0),
+        ] {
+            let uri = format!("/fhir/RiskAssessment?{}", query);
+            let bundle = get_json(&state, &uri).await;
+            assert_eq!(
+                bundle["entry"].as_array().unwrap().len(),
+                expected,
+                "{}",
+                query
+            );
+        }
+    }
+
+    // A _count that is not a number is rejected
+    #[actix_web::test]
+    async fn test_search_rejects_count() {
+        let response = get(&memory_state(), "/fhir/RiskAssessment?_count=x").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
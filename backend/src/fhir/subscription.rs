@@ -0,0 +1,640 @@
+use super::capability::{Operation, ResourceCapability, SearchParam};
+use super::{
+    base_url, fhir_json, negotiate, not_found, outcome_response, reference_id, search_params,
+    searchset, storage_error_outcome, BundleEntry, FHIR_BASE_PATH,
+};
+use crate::model::Subscription;
+use crate::storage::subscriptions::SUBSCRIPTION_STATUSES;
+use crate::storage::{NewSubscription, StorageError, StorageResult};
+use crate::subscriptions::Trigger;
+use crate::AppState;
+use actix_web::{http::header, http::StatusCode, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+/// Topic of new fall Observations (also sent when a nurse marks one `entered-in-error`).
+pub const FALL_OBSERVATION_TOPIC: &str = "urn:fallguard:fhir:topic:fall-observation";
+
+/// Topic of alert Flags: raised, confirmed, cancelled or resolved.
+pub const FALL_FLAG_TOPIC: &str = "urn:fallguard:fhir:topic:fall-flag";
+
+/// Every topic that can be subscribed to: (canonical URL, focus resource type).
+pub const TOPICS: [(&str, &str); 2] = [
+    (FALL_OBSERVATION_TOPIC, "Observation"),
+    (FALL_FLAG_TOPIC, "Flag"),
+];
+
+/// Extensions of the R4 subscriptions backport
+/// (http://hl7.org/fhir/uv/subscriptions-backport/).
+const BACKPORT: &str = "http://hl7.org/fhir/uv/subscriptions-backport/StructureDefinition/";
+const FILTER_CRITERIA_EXTENSION: &str = "backport-filter-criteria";
+const HEARTBEAT_EXTENSION: &str = "backport-heartbeat-period";
+const PAYLOAD_EXTENSION: &str = "backport-payload-content";
+const SUBSCRIPTION_PROFILE: &str = "backport-subscription";
+const STATUS_PROFILE: &str = "backport-subscription-status-r4";
+const NOTIFICATION_PROFILE: &str = "backport-subscription-notification-r4";
+
+pub const CAPABILITY: ResourceCapability = ResourceCapability {
+    resource_type: "Subscription",
+    interactions: &["read", "search-type", "create", "delete"],
+    search_params: &[SearchParam {
+        name: "status",
+        kind: "token",
+        documentation: "requested, active, error or off (comma-separated)",
+    }],
+    operations: &[Operation {
+        name: "status",
+        definition: "http://hl7.org/fhir/uv/subscriptions-backport/OperationDefinition/backport-subscription-status",
+    }],
+};
+
+/// Payload content when the request does not say (the backport's recommended default).
+const DEFAULT_PAYLOAD: &str = "id-only";
+
+/// Focus resource type of a topic.
+pub fn topic_resource_type(topic: &str) -> Option<&'static str> {
+    TOPICS.iter().find(|(t, _)| *t == topic).map(|(_, r)| *r)
+}
+
+fn backport(name: &str) -> String {
+    format!("{}{}", BACKPORT, name)
+}
+
+/// Value of the backport extension `name` in an element's `extension` list.
+fn extension<'a>(element: &'a Value, name: &str) -> Option<&'a Value> {
+    let url = backport(name);
+    element["extension"]
+        .as_array()?
+        .iter()
+        .find(|e| e["url"] == url.as_str())
+}
+
+// --- Subscription resource ---
+
+/// Reads a POSTed R4 Subscription (backport profile) into a rest-hook subscription.
+/// - `criteria`: topic canonical URL (`FALL_OBSERVATION_TOPIC` or `FALL_FLAG_TOPIC`).
+/// - `_criteria` filter extension: `<Type>?patient=Patient/<id>` narrows it to one patient.
+/// - `channel.type` must be `rest-hook`; `channel.header` lines are sent with every notification.
+/// - `channel` heartbeat extension (seconds) and `channel._payload` content extension.
+pub fn parse_subscription(body: &Value, now: DateTime<Utc>) -> StorageResult<NewSubscription> {
+    let invalid = |message: String| StorageError::InvalidQuery(message);
+    if body["resourceType"] != "Subscription" {
+        return Err(invalid("expected a Subscription resource".to_string()));
+    }
+    let topic = body["criteria"]
+        .as_str()
+        .ok_or_else(|| invalid("criteria (the topic URL) is required".to_string()))?;
+    let resource_type = topic_resource_type(topic).ok_or_else(|| {
+        invalid(format!(
+            "unknown topic: {} (expected one of {})",
+            topic,
+            TOPICS.map(|(t, _)| t).join(", ")
+        ))
+    })?;
+
+    let mut patient_id = None;
+    if let Some(filter) = extension(&body["_criteria"], FILTER_CRITERIA_EXTENSION) {
+        let filter = filter["valueString"].as_str().unwrap_or_default();
+        let query = match filter.split_once('?') {
+            Some((target, query)) if target == resource_type => query,
+            _ => {
+                return Err(invalid(format!(
+                    "filter must be {}?patient=Patient/<id>: {}",
+                    resource_type, filter
+                )))
+            }
+        };
+        for (name, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            match name.as_ref() {
+                "patient" | "subject" => {
+                    let id = reference_id(&value, "Patient").ok_or_else(|| {
+                        invalid(format!("{} must reference a Patient: {}", name, value))
+                    })?;
+                    patient_id = Some(id.to_string());
+                }
+                other => return Err(invalid(format!("unsupported filter parameter: {}", other))),
+            }
+        }
+    }
+
+    let channel = &body["channel"];
+    if channel["type"] != "rest-hook" {
+        return Err(invalid(format!(
+            "only rest-hook channels are supported (got {})",
+            channel["type"]
+        )));
+    }
+    let endpoint = channel["endpoint"]
+        .as_str()
+        .ok_or_else(|| invalid("channel.endpoint is required".to_string()))?;
+    let headers = match &channel["header"] {
+        Value::Null => Vec::new(),
+        Value::Array(lines) => lines
+            .iter()
+            .map(|h| h.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| invalid("channel.header must be a list of strings".to_string()))?,
+        _ => {
+            return Err(invalid(
+                "channel.header must be a list of strings".to_string(),
+            ))
+        }
+    };
+    let heartbeat_seconds = match extension(channel, HEARTBEAT_EXTENSION) {
+        Some(e) => Some(
+            e["valueUnsignedInt"]
+                .as_i64()
+                .and_then(|s| i32::try_from(s).ok())
+                .ok_or_else(|| {
+                    invalid("heartbeat period must be a number of seconds".to_string())
+                })?,
+        ),
+        None => None,
+    };
+    let payload = extension(&channel["_payload"], PAYLOAD_EXTENSION)
+        .and_then(|e| e["valueCode"].as_str())
+        .unwrap_or(DEFAULT_PAYLOAD);
+
+    let subscription = NewSubscription {
+        topic: topic.to_string(),
+        reason: body["reason"].as_str().unwrap_or_default().to_string(),
+        endpoint: endpoint.to_string(),
+        headers,
+        payload: payload.to_string(),
+        patient_id,
+        heartbeat_seconds,
+        created_at: now,
+    };
+    subscription.validate()?;
+    Ok(subscription)
+}
+
+/// Subscription resource for a stored subscription. Channel headers are write-only (they
+/// usually carry credentials) and are never echoed back.
+pub fn subscription_resource(subscription: &Subscription) -> Value {
+    let mut channel = json!({
+        "type": "rest-hook",
+        "endpoint": subscription.endpoint,
+        "payload": "application/fhir+json",
+        "_payload": {
+            "extension": [{ "url": backport(PAYLOAD_EXTENSION), "valueCode": subscription.payload }],
+        },
+    });
+    if let Some(seconds) = subscription.heartbeat_seconds {
+        channel["extension"] =
+            json!([{ "url": backport(HEARTBEAT_EXTENSION), "valueUnsignedInt": seconds }]);
+    }
+    let mut resource = json!({
+        "resourceType": "Subscription",
+        "id": subscription.id.to_string(),
+        "meta": { "profile": [backport(SUBSCRIPTION_PROFILE)] },
+        "status": subscription.status,
+        "reason": subscription.reason,
+        "criteria": subscription.topic,
+        "channel": channel,
+    });
+    if let Some(patient_id) = &subscription.patient_id {
+        let resource_type = topic_resource_type(&subscription.topic).unwrap_or("Observation");
+        resource["_criteria"] = json!({
+            "extension": [{
+                "url": backport(FILTER_CRITERIA_EXTENSION),
+                "valueString": format!("{}?patient=Patient/{}", resource_type, patient_id),
+            }],
+        });
+    }
+    if let Some(error) = &subscription.error {
+        resource["error"] = json!(error);
+    }
+    resource
+}
+
+// --- Notifications ---
+
+/// One numbered event delivered to a subscription.
+#[derive(Debug, Clone)]
+pub struct NotificationEvent {
+    pub number: i64, // events_since_start after counting this event
+    pub at: DateTime<Utc>,
+    pub focus: String, // "<Type>/<id>"
+    pub resource: Value,
+}
+
+/// **Notification**
+///
+/// What a rest-hook POST carries: the handshake after creation, a heartbeat while nothing
+/// happens, or an event on the subscribed topic.
+#[derive(Debug, Clone)]
+pub enum Notification {
+    Handshake,
+    Heartbeat,
+    Event(NotificationEvent),
+}
+
+impl Notification {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Notification::Handshake => "handshake",
+            Notification::Heartbeat => "heartbeat",
+            Notification::Event(_) => "event-notification",
+        }
+    }
+}
+
+/// SubscriptionStatus (as R4 Parameters) describing `subscription` for a notification of `kind`.
+pub fn status_parameters(
+    subscription: &Subscription,
+    base: &str,
+    kind: &str,
+    event: Option<&NotificationEvent>,
+) -> Value {
+    let mut parameter = vec![
+        json!({ "name": "subscription", "valueReference": { "reference": format!("{}/Subscription/{}", base, subscription.id) } }),
+        json!({ "name": "topic", "valueCanonical": subscription.topic }),
+        json!({ "name": "status", "valueCode": subscription.status }),
+        json!({ "name": "type", "valueCode": kind }),
+        json!({ "name": "events-since-subscription-start", "valueString": subscription.events_since_start.max(event.map_or(0, |e| e.number)).to_string() }),
+    ];
+    if let Some(event) = event {
+        let mut part = vec![
+            json!({ "name": "event-number", "valueString": event.number.to_string() }),
+            json!({ "name": "timestamp", "valueInstant": event.at.to_rfc3339() }),
+        ];
+        if subscription.payload != "empty" {
+            part.push(json!({ "name": "focus", "valueReference": { "reference": format!("{}/{}", base, event.focus) } }));
+        }
+        parameter.push(json!({ "name": "notification-event", "part": part }));
+    }
+    if let Some(error) = &subscription.error {
+        parameter.push(json!({ "name": "error", "valueCodeableConcept": { "text": error } }));
+    }
+    json!({
+        "resourceType": "Parameters",
+        "id": uuid::Uuid::new_v4().to_string(),
+        "meta": { "profile": [backport(STATUS_PROFILE)] },
+        "parameter": parameter,
+    })
+}
+
+/// The `history` Bundle POSTed to the endpoint: the SubscriptionStatus first, then the focus
+/// resource (`full-resource`) or just its URL (`id-only`).
+pub fn notification_bundle(
+    subscription: &Subscription,
+    base: &str,
+    notification: &Notification,
+) -> Value {
+    let event = match notification {
+        Notification::Event(event) => Some(event),
+        _ => None,
+    };
+    let status = status_parameters(subscription, base, notification.kind(), event);
+    let mut entry = vec![json!({
+        "fullUrl": format!("urn:uuid:{}", status["id"].as_str().unwrap_or_default()),
+        "resource": status,
+        "request": { "method": "GET", "url": format!("Subscription/{}/$status", subscription.id) },
+        "response": { "status": "200" },
+    })];
+    if let Some(event) = event {
+        let full_url = format!("{}/{}", base, event.focus);
+        match subscription.payload.as_str() {
+            "full-resource" => entry.push(json!({
+                "fullUrl": full_url,
+                "resource": event.resource,
+                "request": { "method": "PUT", "url": event.focus },
+                "response": { "status": "200" },
+            })),
+            "id-only" => entry.push(json!({
+                "fullUrl": full_url,
+                "request": { "method": "PUT", "url": event.focus },
+                "response": { "status": "200" },
+            })),
+            _ => {}
+        }
+    }
+    json!({
+        "resourceType": "Bundle",
+        "id": uuid::Uuid::new_v4().to_string(),
+        "meta": {
+            "lastUpdated": Utc::now().to_rfc3339(),
+            "profile": [backport(NOTIFICATION_PROFILE)],
+        },
+        "type": "history",
+        "timestamp": Utc::now().to_rfc3339(),
+        "entry": entry,
+    })
+}
+
+// --- Handlers ---
+
+/// **POST /fhir/Subscription**
+///
+/// Creates a rest-hook subscription (status `requested`) and sends the handshake; the status
+/// becomes `active` once the endpoint accepts it, `error` if it never does.
+pub async fn create(req: HttpRequest, data: web::Data<AppState>, body: web::Bytes) -> HttpResponse {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let resource: Value = match serde_json::from_slice(&body) {
+        Ok(resource) => resource,
+        Err(e) => {
+            return outcome_response(
+                StatusCode::BAD_REQUEST,
+                "structure",
+                format!("body is not valid JSON: {}", e),
+            )
+        }
+    };
+    let new = match parse_subscription(&resource, Utc::now()) {
+        Ok(new) => new,
+        Err(e) => return storage_error_outcome(e, "Error creating subscription"),
+    };
+    match data.db.create_subscription(&new).await {
+        Ok(subscription) => {
+            data.notifier.notify(Trigger::Handshake(subscription.id));
+            let location = format!("{}/Subscription/{}", base_url(&req), subscription.id);
+            HttpResponse::Created()
+                .insert_header((header::LOCATION, location))
+                .content_type(super::FHIR_JSON)
+                .json(subscription_resource(&subscription))
+        }
+        Err(e) => storage_error_outcome(e, "Error creating subscription"),
+    }
+}
+
+/// **GET /fhir/Subscription/{id}**
+pub async fn read(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let id = path.into_inner();
+    let Ok(subscription_id) = id.parse::<i32>() else {
+        return not_found("Subscription", &id);
+    };
+    match data.db.get_subscription(subscription_id).await {
+        Ok(Some(subscription)) => fhir_json(StatusCode::OK, &subscription_resource(&subscription)),
+        Ok(None) => not_found("Subscription", &id),
+        Err(e) => storage_error_outcome(e, "Error reading subscription"),
+    }
+}
+
+/// **GET /fhir/Subscription?status=**
+///
+/// Every subscription, oldest first (`status=error` lists the failing endpoints).
+pub async fn search(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let mut statuses: Option<Vec<String>> = None;
+    for (name, value) in search_params(&req) {
+        match name.as_str() {
+            "status" => {
+                let listed: Vec<String> = value.split(',').map(|s| s.trim().to_string()).collect();
+                if let Some(unknown) = listed
+                    .iter()
+                    .find(|s| !SUBSCRIPTION_STATUSES.contains(&s.as_str()))
+                {
+                    return storage_error_outcome(
+                        StorageError::InvalidQuery(format!("unknown status: {}", unknown)),
+                        "Error searching subscriptions",
+                    );
+                }
+                statuses = Some(listed);
+            }
+            "_format" => {}
+            other => {
+                return storage_error_outcome(
+                    StorageError::InvalidQuery(format!("unsupported search parameter: {}", other)),
+                    "Error searching subscriptions",
+                )
+            }
+        }
+    }
+    let subscriptions = match data.db.list_subscriptions().await {
+        Ok(subscriptions) => subscriptions,
+        Err(e) => return storage_error_outcome(e, "Error searching subscriptions"),
+    };
+    let base = base_url(&req);
+    let entries = subscriptions
+        .iter()
+        .filter(|s| {
+            statuses
+                .as_ref()
+                .is_none_or(|list| list.contains(&s.status))
+        })
+        .map(|s| {
+            BundleEntry::matched(
+                format!("{}/Subscription/{}", base, s.id),
+                &subscription_resource(s),
+            )
+        })
+        .collect();
+    searchset(&req, entries)
+}
+
+/// **DELETE /fhir/Subscription/{id}**
+///
+/// Stops notifications; anything still queued for the endpoint is dropped.
+pub async fn delete(data: web::Data<AppState>, path: web::Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    let Ok(subscription_id) = id.parse::<i32>() else {
+        return not_found("Subscription", &id);
+    };
+    match data.db.delete_subscription(subscription_id).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => not_found("Subscription", &id),
+        Err(e) => storage_error_outcome(e, "Error deleting subscription"),
+    }
+}
+
+/// **GET /fhir/Subscription/{id}/$status**
+///
+/// Delivery state as a `searchset` holding one SubscriptionStatus: `status`, the number of
+/// events so far and, while deliveries fail, the last error.
+pub async fn status(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = negotiate(&req) {
+        return response;
+    }
+    let id = path.into_inner();
+    let Ok(subscription_id) = id.parse::<i32>() else {
+        return not_found("Subscription", &id);
+    };
+    let subscription = match data.db.get_subscription(subscription_id).await {
+        Ok(Some(subscription)) => subscription,
+        Ok(None) => return not_found("Subscription", &id),
+        Err(e) => return storage_error_outcome(e, "Error reading subscription status"),
+    };
+    let base = base_url(&req);
+    let parameters = status_parameters(&subscription, &base, "query-status", None);
+    let full_url = format!("urn:uuid:{}", parameters["id"].as_str().unwrap_or_default());
+    searchset(&req, vec![BundleEntry::matched(full_url, &parameters)])
+}
+
+/// Default FHIR base URL used in notifications (they are sent outside any request).
+pub fn default_base_url() -> String {
+    format!("http://localhost:8080{}", FHIR_BASE_PATH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{call, get, get_json, memory_state};
+    use actix_web::dev::ServiceResponse;
+    use actix_web::test::{read_body_json, TestRequest};
+
+    // Helper: POSTs `body` to /fhir/Subscription
+    async fn post(state: &web::Data<AppState>, body: Value) -> ServiceResponse {
+        call(
+            state,
+            TestRequest::post().uri("/fhir/Subscription").set_json(body),
+        )
+        .await
+    }
+
+    // Helper: a backport Subscription to P-1001's fall Observations, with a bearer token
+    fn ward_ehr() -> Value {
+        json!({
+            "resourceType": "Subscription",
+            "status": "requested",
+            "reason": "Ward EHR",
+            "criteria": FALL_OBSERVATION_TOPIC,
+            "_criteria": { "extension": [{
+                "url": backport("backport-filter-criteria"),
+                "valueString": "Observation?patient=Patient/P-1001",
+            }]},
+            "channel": {
+                "type": "rest-hook",
+                "endpoint": "https://ehr.example/notify",
+                "payload": "application/fhir+json",
+                "header": ["Authorization: Bearer s3cret"],
+                "_payload": { "extension": [{
+                    "url": backport("backport-payload-content"),
+                    "valueCode": "full-resource",
+                }]},
+                "extension": [{
+                    "url": backport("backport-heartbeat-period"),
+                    "valueUnsignedInt": 60,
+                }],
+            },
+        })
+    }
+
+    // Helper: creates the ward EHR's subscription and returns its id
+    async fn create_ward_ehr(state: &web::Data<AppState>) -> i32 {
+        let created: Value = read_body_json(post(state, ward_ehr()).await).await;
+        created["id"].as_str().unwrap().parse().unwrap()
+    }
+
+    // The backport extensions are read into the stored subscription
+    #[test]
+    fn test_parse_subscription() {
+        let new = parse_subscription(&ward_ehr(), Utc::now()).unwrap();
+        assert_eq!(new.topic, FALL_OBSERVATION_TOPIC);
+        assert_eq!(new.patient_id.as_deref(), Some("P-1001"));
+        assert_eq!(new.payload, "full-resource");
+        assert_eq!(new.heartbeat_seconds, Some(60));
+        assert_eq!(new.headers, vec!["Authorization: Bearer s3cret"]);
+    }
+
+    // A new subscription is requested, located, and never echoes its headers
+    #[actix_web::test]
+    async fn test_create() {
+        let response = post(&memory_state(), ward_ehr()).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let location = response.headers().get("Location").unwrap().to_owned();
+        let created: Value = read_body_json(response).await;
+        assert_eq!(created["status"], "requested");
+        assert!(created["channel"].get("header").is_none());
+        assert!(location.to_str().unwrap().ends_with(&format!(
+            "/fhir/Subscription/{}",
+            created["id"].as_str().unwrap()
+        )));
+    }
+
+    // Unknown topics, channel types and endpoint schemes are refused
+    #[actix_web::test]
+    async fn test_create_rejects_malformed() {
+        let state = memory_state();
+        for body in [
+            json!({ "resourceType": "Subscription", "criteria": "urn:unknown", "channel": { "type": "rest-hook", "endpoint": "https://ehr.example/notify" } }),
+            json!({ "resourceType": "Subscription", "criteria": FALL_FLAG_TOPIC, "channel": { "type": "websocket" } }),
+            json!({ "resourceType": "Subscription", "criteria": FALL_FLAG_TOPIC, "channel": { "type": "rest-hook", "endpoint": "ftp://ehr" } }),
+        ] {
+            let response = post(&state, body.clone()).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", body);
+            let outcome: Value = read_body_json(response).await;
+            assert_eq!(outcome["resourceType"], "OperationOutcome");
+        }
+    }
+
+    // $status reports a failing subscription's status and error
+    #[actix_web::test]
+    async fn test_status() {
+        let state = memory_state();
+        let id = create_ward_ehr(&state).await;
+        state
+            .db
+            .set_subscription_status(id, "error", Some("endpoint answered HTTP 503"), None)
+            .await
+            .unwrap();
+        let bundle = get_json(&state, &format!("/fhir/Subscription/{}/$status", id)).await;
+        assert_eq!(bundle["type"], "searchset");
+        let status = &bundle["entry"][0]["resource"];
+        assert_eq!(status["resourceType"], "Parameters");
+        let param = |name: &str| {
+            status["parameter"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|p| p["name"] == name)
+                .cloned()
+                .unwrap()
+        };
+        assert_eq!(param("status")["valueCode"], "error");
+        assert_eq!(param("type")["valueCode"], "query-status");
+        assert!(param("error")["valueCodeableConcept"]["text"]
+            .as_str()
+            .unwrap()
+            .contains("503"));
+    }
+
+    // Subscriptions search by status
+    #[actix_web::test]
+    async fn test_search_by_status() {
+        let state = memory_state();
+        let id = create_ward_ehr(&state).await;
+        create_ward_ehr(&state).await;
+        state
+            .db
+            .set_subscription_status(id, "error", Some("endpoint answered HTTP 503"), None)
+            .await
+            .unwrap();
+        let bundle = get_json(&state, "/fhir/Subscription?status=error").await;
+        assert_eq!(bundle["total"], 1);
+    }
+
+    // Searching by a status FHIR does not define is refused
+    #[actix_web::test]
+    async fn test_search_rejects_unknown_status() {
+        let response = get(&memory_state(), "/fhir/Subscription?status=sleeping").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // A deleted subscription is gone
+    #[actix_web::test]
+    async fn test_delete() {
+        let state = memory_state();
+        let uri = format!("/fhir/Subscription/{}", create_ward_ehr(&state).await);
+        let response = call(&state, TestRequest::delete().uri(&uri)).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(get(&state, &uri).await.status(), StatusCode::NOT_FOUND);
+        let response = call(&state, TestRequest::delete().uri(&uri)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
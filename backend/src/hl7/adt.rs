@@ -0,0 +1,265 @@
+use super::{parse_timestamp, unescape, Message};
+use crate::model::Patient;
+use crate::storage::{NewPatient, StorageError};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::fmt;
+
+/// ADT trigger events the listener applies.
+pub const ADT_TRIGGERS: [&str; 4] = ["A01", "A02", "A03", "A08"];
+
+/// HL7 null: a field sent as `""` deletes the stored value.
+const HL7_NULL: &str = "\"\"";
+
+/// **ADT Event**
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdtEvent {
+    Admit,     // A01
+    Transfer,  // A02
+    Discharge, // A03
+    Update,    // A08
+}
+
+impl AdtEvent {
+    pub fn from_trigger(trigger: &str) -> Option<Self> {
+        match trigger {
+            "A01" => Some(Self::Admit),
+            "A02" => Some(Self::Transfer),
+            "A03" => Some(Self::Discharge),
+            "A08" => Some(Self::Update),
+            _ => None,
+        }
+    }
+
+    /// Audit log action recorded for the event.
+    pub fn action(&self) -> &'static str {
+        match self {
+            Self::Admit => "patient.admitted",
+            Self::Transfer => "patient.transferred",
+            Self::Discharge => "patient.discharged",
+            Self::Update => "patient.updated",
+        }
+    }
+}
+
+/// **ADT Error**
+///
+/// Why a message was not applied; decides the ACK code and the ERR segment.
+#[derive(Debug)]
+pub enum AdtError {
+    UnsupportedMessageType(String),
+    UnsupportedEvent(String),
+    MissingField(&'static str),
+    InvalidField(&'static str, String),
+    Storage(StorageError),
+}
+
+impl AdtError {
+    /// MSA-1: messages we will never accept are rejected, the rest are errors.
+    pub fn ack_code(&self) -> &'static str {
+        match self {
+            Self::UnsupportedMessageType(_) | Self::UnsupportedEvent(_) => "AR",
+            _ => "AE",
+        }
+    }
+
+    /// ERR-3 error code (HL7 table 0357).
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            Self::UnsupportedMessageType(_) => "200",
+            Self::UnsupportedEvent(_) => "201",
+            Self::MissingField(_) => "101",
+            Self::InvalidField(..) => "102",
+            Self::Storage(_) => "207",
+        }
+    }
+}
+
+impl fmt::Display for AdtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedMessageType(t) => write!(f, "unsupported message type: {}", t),
+            Self::UnsupportedEvent(e) => write!(
+                f,
+                "unsupported ADT event: {} (expected one of {})",
+                e,
+                ADT_TRIGGERS.join(", ")
+            ),
+            Self::MissingField(field) => write!(f, "required field {} is empty", field),
+            Self::InvalidField(field, value) => write!(f, "invalid {}: {}", field, value),
+            Self::Storage(e) => write!(f, "storage error: {}", e),
+        }
+    }
+}
+
+impl From<StorageError> for AdtError {
+    fn from(e: StorageError) -> Self {
+        Self::Storage(e)
+    }
+}
+
+/// A field as sent: `None` when absent (keep the stored value), `Some(None)` for the HL7
+/// null `""` or an empty component of a field that was sent (clear it).
+pub type Sent<T> = Option<Option<T>>;
+
+/// **ADT Message**
+///
+/// What an A01/A02/A03/A08 says about the patient: PID demographics, the PV1-3 location
+/// (point of care, room, bed) and the stay times.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdtMessage {
+    pub event: AdtEvent,
+    pub control_id: String,
+    pub sending_application: String,
+    pub patient_id: String,                 // PID-3, first identifier
+    pub family_name: Sent<String>,          // PID-5.1
+    pub given_name: Sent<String>,           // PID-5.2
+    pub birth_date: Sent<NaiveDate>,        // PID-7
+    pub gender: Sent<String>,               // PID-8 as a FHIR gender
+    pub ward: Sent<String>,                 // PV1-3.1
+    pub room: Sent<String>,                 // PV1-3.2
+    pub bed: Sent<String>,                  // PV1-3.3
+    pub admitted_at: Sent<DateTime<Utc>>,   // PV1-44
+    pub discharged_at: Sent<DateTime<Utc>>, // PV1-45
+    pub occurred_at: Option<DateTime<Utc>>, // EVN-6, else EVN-2, else MSH-7
+}
+
+/// First repetition of a field, split into unescaped components; `None` when absent.
+fn sent_components(message: &Message, segment: &str, position: usize) -> Option<Vec<String>> {
+    let raw = message.field(segment, position);
+    let first = raw.split('~').next().unwrap_or("");
+    if first.is_empty() {
+        return None;
+    }
+    if first == HL7_NULL {
+        return Some(Vec::new());
+    }
+    Some(first.split('^').map(unescape).collect())
+}
+
+/// Component `n` (1-based) of a field that was sent.
+fn component(parts: &Option<Vec<String>>, n: usize) -> Sent<String> {
+    parts.as_ref().map(|parts| {
+        parts
+            .get(n - 1)
+            .map(|c| c.split('&').next().unwrap_or("").trim().to_string())
+            .filter(|c| !c.is_empty())
+    })
+}
+
+fn sent_time(
+    message: &Message,
+    segment: &str,
+    position: usize,
+    name: &'static str,
+) -> Result<Sent<DateTime<Utc>>, AdtError> {
+    match component(&sent_components(message, segment, position), 1) {
+        None => Ok(None),
+        Some(None) => Ok(Some(None)),
+        Some(Some(value)) => parse_timestamp(&value)
+            .map(|at| Some(Some(at)))
+            .ok_or(AdtError::InvalidField(name, value)),
+    }
+}
+
+/// PID-8 administrative sex (table 0001) as a FHIR gender.
+fn gender(sex: &str) -> &'static str {
+    match sex {
+        "M" => "male",
+        "F" => "female",
+        "O" | "A" => "other",
+        _ => "unknown",
+    }
+}
+
+impl AdtMessage {
+    pub fn parse(message: &Message) -> Result<Self, AdtError> {
+        let message_type = message.component("MSH", 9, 1);
+        if message_type != "ADT" {
+            return Err(AdtError::UnsupportedMessageType(message.message_type()));
+        }
+        let trigger = match message.component("MSH", 9, 2) {
+            t if t.is_empty() => message.component("EVN", 1, 1),
+            t => t,
+        };
+        let event = AdtEvent::from_trigger(&trigger).ok_or(AdtError::UnsupportedEvent(trigger))?;
+
+        let patient_id = component(&sent_components(message, "PID", 3), 1)
+            .flatten()
+            .ok_or(AdtError::MissingField("PID-3"))?;
+        let name = sent_components(message, "PID", 5);
+        let birth_date = match component(&sent_components(message, "PID", 7), 1) {
+            Some(Some(value)) => {
+                let date = value
+                    .get(..8)
+                    .and_then(|d| NaiveDate::parse_from_str(d, "%Y%m%d").ok())
+                    .ok_or(AdtError::InvalidField("PID-7", value))?;
+                Some(Some(date))
+            }
+            other => other.map(|_| None),
+        };
+        let location = sent_components(message, "PV1", 3);
+        let occurred_at =
+            [("EVN", 6), ("EVN", 2), ("MSH", 7)]
+                .into_iter()
+                .find_map(|(segment, position)| {
+                    parse_timestamp(&message.component(segment, position, 1))
+                });
+
+        Ok(Self {
+            event,
+            control_id: message.control_id(),
+            sending_application: message.component("MSH", 3, 1),
+            patient_id,
+            family_name: component(&name, 1),
+            given_name: component(&name, 2),
+            birth_date,
+            gender: component(&sent_components(message, "PID", 8), 1)
+                .map(|sex| sex.map(|s| gender(&s).to_string())),
+            ward: component(&location, 1),
+            room: component(&location, 2),
+            bed: component(&location, 3),
+            admitted_at: sent_time(message, "PV1", 44, "PV1-44")?,
+            discharged_at: sent_time(message, "PV1", 45, "PV1-45")?,
+            occurred_at,
+        })
+    }
+
+    /// The patient after this message: sent fields replace the stored ones, the rest are kept.
+    /// An admission opens a new stay, a discharge closes it and frees the bed.
+    pub fn apply_to(&self, existing: Option<&Patient>, now: DateTime<Utc>) -> NewPatient {
+        fn merge<T: Clone>(sent: &Sent<T>, stored: Option<T>) -> Option<T> {
+            match sent {
+                Some(value) => value.clone(),
+                None => stored,
+            }
+        }
+        let stored = |f: fn(&Patient) -> Option<String>| existing.and_then(f);
+        let at = self.occurred_at.unwrap_or(now);
+        let mut patient = NewPatient {
+            id: self.patient_id.clone(),
+            family_name: merge(&self.family_name, stored(|p| p.family_name.clone())),
+            given_name: merge(&self.given_name, stored(|p| p.given_name.clone())),
+            birth_date: merge(&self.birth_date, existing.and_then(|p| p.birth_date)),
+            gender: merge(&self.gender, stored(|p| p.gender.clone())),
+            ward: merge(&self.ward, stored(|p| p.ward.clone())),
+            room: merge(&self.room, stored(|p| p.room.clone())),
+            bed: merge(&self.bed, stored(|p| p.bed.clone())),
+            admitted_at: merge(&self.admitted_at, existing.and_then(|p| p.admitted_at)),
+            discharged_at: merge(&self.discharged_at, existing.and_then(|p| p.discharged_at)),
+            updated_at: now,
+        };
+        match self.event {
+            AdtEvent::Admit => {
+                patient.admitted_at = self.admitted_at.flatten().or(Some(at));
+                patient.discharged_at = None;
+            }
+            AdtEvent::Discharge => {
+                patient.discharged_at = self.discharged_at.flatten().or(Some(at));
+                patient.room = None;
+                patient.bed = None;
+            }
+            AdtEvent::Transfer | AdtEvent::Update => {}
+        }
+        patient
+    }
+}
@@ -0,0 +1,438 @@
+use super::adt::{AdtError, AdtEvent, AdtMessage};
+use super::mllp::{read_frame, write_frame};
+use super::{ack_message, error_ack_message, Message};
+use crate::model::{Device, Patient};
+use crate::storage::{DeviceRegistration, NewAuditEntry, Repository};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+
+/// **HL7 Inbound Configuration**
+///
+/// The ADT listener is off unless `HL7_LISTEN_ADDR` is set.
+/// - `HL7_LISTEN_ADDR`: `host:port` to accept MLLP connections on (e.g. `0.0.0.0:2575`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hl7InboundConfig {
+    pub address: String,
+}
+
+impl Hl7InboundConfig {
+    pub fn from_env() -> Option<Self> {
+        std::env::var("HL7_LISTEN_ADDR")
+            .ok()
+            .filter(|a| !a.trim().is_empty())
+            .map(|address| Self {
+                address: address.trim().to_string(),
+            })
+    }
+}
+
+/// What an applied ADT message changed.
+#[derive(Debug, Clone)]
+pub struct AdtOutcome {
+    pub patient: Patient,
+    pub moved: Vec<Device>, // Devices that followed the patient to a new ward
+    pub released: Vec<Device>, // Devices unassigned at discharge
+}
+
+/// **Apply ADT**
+///
+/// Creates or updates the patient, keeps the devices they wear on their ward, ends the
+/// assignments at discharge and records the event in the audit log.
+pub async fn apply(
+    db: &dyn Repository,
+    adt: &AdtMessage,
+    now: DateTime<Utc>,
+) -> Result<AdtOutcome, AdtError> {
+    let existing = db.get_patient(&adt.patient_id).await?;
+    let patient = db
+        .upsert_patient(&adt.apply_to(existing.as_ref(), now))
+        .await?;
+
+    let mut moved = Vec::new();
+    let mut released = Vec::new();
+    if adt.event == AdtEvent::Discharge {
+        released = db.release_devices(&patient.id).await?;
+    } else if let Some(ward) = &patient.ward {
+        for device in db.list_devices().await? {
+            if device.patient_id.as_deref() != Some(patient.id.as_str())
+                || device.ward.as_ref() == Some(ward)
+            {
+                continue;
+            }
+            let registration = DeviceRegistration {
+                id: device.id.clone(),
+                model: None,
+                patient_id: None,
+                ward: Some(ward.clone()),
+                calibration_state: None,
+                calibrated_at: None,
+                at: now,
+            };
+            moved.push(db.register_device(&registration).await?);
+        }
+    }
+
+    let device_ids =
+        |devices: &[Device]| -> Vec<String> { devices.iter().map(|d| d.id.clone()).collect() };
+    db.record_audit(NewAuditEntry {
+        recorded_at: now,
+        actor: format!("hl7:{}", adt.sending_application),
+        action: adt.event.action().to_string(),
+        entity_type: "Patient".to_string(),
+        entity_id: Some(patient.id.clone()),
+        detail: Some(json!({
+            "control_id": adt.control_id,
+            "ward": patient.ward,
+            "room": patient.room,
+            "bed": patient.bed,
+            "moved_devices": device_ids(&moved),
+            "released_devices": device_ids(&released),
+        })),
+    })
+    .await?;
+    Ok(AdtOutcome {
+        patient,
+        moved,
+        released,
+    })
+}
+
+/// Applies one received message and returns the ACK to send back: `AA` when applied, `AR`
+/// for messages other than A01/A02/A03/A08 and `AE` (with an ERR segment) otherwise.
+pub async fn handle(db: &dyn Repository, text: &str, now: DateTime<Utc>) -> String {
+    let message = match Message::parse(text) {
+        Ok(m) => m,
+        Err(e) => return reject(&e, now),
+    };
+    let result = match AdtMessage::parse(&message) {
+        Ok(adt) => apply(db, &adt, now).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(_) => ack_message(&message, "AA", "", now),
+        Err(e) => {
+            eprintln!(
+                "❌ HL7: {} {} not applied: {}",
+                message.message_type(),
+                message.control_id(),
+                e
+            );
+            error_ack_message(&message, e.ack_code(), e.error_code(), &e.to_string(), now)
+        }
+    }
+}
+
+/// `AR` for something that could not be read as a message; with nothing to echo back, it
+/// has an empty header.
+fn reject(text: &str, now: DateTime<Utc>) -> String {
+    let blank = Message::parse("MSH|^~\\&").expect("static header parses");
+    error_ack_message(&blank, "AR", "100", text, now)
+}
+
+/// Answers every message on one connection until the peer hangs up. A frame that cannot be
+/// read (too large, badly framed or not UTF-8) is refused with an `AR` and the connection
+/// closed, since the rest of the stream can no longer be split into messages.
+async fn serve(db: Arc<dyn Repository>, stream: TcpStream, peer: SocketAddr) {
+    let mut connection = BufReader::new(stream);
+    loop {
+        let text = match read_frame(&mut connection).await {
+            Ok(Some(text)) => text,
+            Ok(None) => return,
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                eprintln!("❌ HL7: refusing a frame from {}: {}", peer, e);
+                let refusal = reject(&e.to_string(), Utc::now());
+                let _ = write_frame(connection.get_mut(), &refusal).await;
+                return;
+            }
+            Err(e) => {
+                eprintln!("❌ HL7: dropping connection from {}: {}", peer, e);
+                return;
+            }
+        };
+        let ack = handle(db.as_ref(), &text, Utc::now()).await;
+        if let Err(e) = write_frame(connection.get_mut(), &ack).await {
+            eprintln!("❌ HL7: cannot send the ACK to {}: {}", peer, e);
+            return;
+        }
+    }
+}
+
+/// Binds the listener and accepts connections in the background; returns the bound address.
+pub async fn spawn(
+    db: Arc<dyn Repository>,
+    config: Hl7InboundConfig,
+) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(&config.address).await?;
+    let address = listener.local_addr()?;
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    tokio::spawn(serve(db.clone(), stream, peer));
+                }
+                Err(e) => eprintln!("❌ HL7: accepting a connection failed: {}", e),
+            }
+        }
+    });
+    Ok(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hl7::mllp;
+    use crate::hl7::outbound::MllpClient;
+    use crate::hl7::{parse_timestamp, Ack};
+    use crate::storage::{AuditQuery, MemoryRepository};
+    use chrono::NaiveDate;
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+
+    // Helper: an ADT message from the EHR with the given PID and PV1 (from field 3) contents
+    fn adt(trigger: &str, control_id: &str, pid: &str, pv1: &str) -> String {
+        format!(
+            "MSH|^~\\&|EPIC|GENERAL|FALLGUARD|WARD|20261018090000||ADT^{t}^ADT_A01|{c}|P|2.5.1\r\
+             EVN|{t}|20261018090000\rPID|1||{pid}\rPV1|1|I|{pv1}\r",
+            t = trigger,
+            c = control_id,
+            pid = pid,
+            pv1 = pv1
+        )
+    }
+
+    // Helper: the reply to `text`, parsed
+    async fn reply(db: &dyn Repository, text: &str) -> Message {
+        Message::parse(&handle(db, text, Utc::now()).await).unwrap()
+    }
+
+    // Helper: the reply's acknowledgment
+    async fn ack(db: &dyn Repository, text: &str) -> Ack {
+        Ack::parse(&handle(db, text, Utc::now()).await).unwrap()
+    }
+
+    // Helper: P-2001 admitted to ICU room 101, bed A, wearing the calibrated pi-07
+    async fn admitted() -> MemoryRepository {
+        let db = MemoryRepository::new();
+        let admission = adt(
+            "A01",
+            "MSG0001",
+            "P-2001^^^HOSP^MR~99887^^^NHS||Silva^Ana^M||19380214|F",
+            "ICU^101^A",
+        );
+        assert_eq!(ack(&db, &admission).await.code, "AA");
+        db.register_device(&DeviceRegistration {
+            id: "pi-07".to_string(),
+            model: None,
+            patient_id: Some("P-2001".to_string()),
+            ward: Some("ICU".to_string()),
+            calibration_state: Some("calibrated".to_string()),
+            calibrated_at: None,
+            at: Utc::now(),
+        })
+        .await
+        .unwrap();
+        db
+    }
+
+    // Helper: discharges P-2001 from ICU, at the time in PV1-45
+    fn discharge(control_id: &str) -> String {
+        let pv1 = format!("ICU^101^A{}20261020113000", "|".repeat(42));
+        adt("A03", control_id, "P-2001", &pv1)
+    }
+
+    // A01 creates the patient with their demographics and bed
+    #[actix_web::test]
+    async fn test_admit() {
+        let db = admitted().await;
+        let patient = db.get_patient("P-2001").await.unwrap().unwrap();
+        assert_eq!(patient.family_name.as_deref(), Some("Silva"));
+        assert_eq!(patient.given_name.as_deref(), Some("Ana"));
+        assert_eq!(patient.gender.as_deref(), Some("female"));
+        assert_eq!(patient.birth_date, NaiveDate::from_ymd_opt(1938, 2, 14));
+        assert_eq!(
+            (
+                patient.ward.as_deref(),
+                patient.room.as_deref(),
+                patient.bed.as_deref()
+            ),
+            (Some("ICU"), Some("101"), Some("A"))
+        );
+        assert_eq!(patient.admitted_at, parse_timestamp("20261018090000"));
+        assert_eq!(patient.discharged_at, None);
+    }
+
+    // A02 moves the patient and their sensor, keeping what it does not send
+    #[actix_web::test]
+    async fn test_transfer() {
+        let db = admitted().await;
+        let transfer = adt("A02", "MSG0002", "P-2001", "Ward-B^204^2");
+        assert_eq!(ack(&db, &transfer).await.code, "AA");
+        let patient = db.get_patient("P-2001").await.unwrap().unwrap();
+        assert_eq!(
+            (
+                patient.ward.as_deref(),
+                patient.room.as_deref(),
+                patient.bed.as_deref()
+            ),
+            (Some("Ward-B"), Some("204"), Some("2"))
+        );
+        assert_eq!(patient.family_name.as_deref(), Some("Silva"));
+        let device = db.get_device("pi-07").await.unwrap().unwrap();
+        assert_eq!(device.ward.as_deref(), Some("Ward-B"));
+        assert_eq!(device.patient_id.as_deref(), Some("P-2001"));
+        assert_eq!(device.calibration_state, "calibrated");
+    }
+
+    // A08 replaces the fields sent, and `""` clears one
+    #[actix_web::test]
+    async fn test_update() {
+        let db = admitted().await;
+        let update = adt(
+            "A08",
+            "MSG0003",
+            "P-2001||Silva-Costa^Ana||\"\"|F",
+            "Ward-B^204^2",
+        );
+        assert_eq!(ack(&db, &update).await.code, "AA");
+        let patient = db.get_patient("P-2001").await.unwrap().unwrap();
+        assert_eq!(patient.family_name.as_deref(), Some("Silva-Costa"));
+        assert_eq!(patient.birth_date, None);
+        assert_eq!(patient.admitted_at, parse_timestamp("20261018090000"));
+    }
+
+    // A03 discharges the patient, frees the bed and unassigns the sensor
+    #[actix_web::test]
+    async fn test_discharge() {
+        let db = admitted().await;
+        assert_eq!(ack(&db, &discharge("MSG0004")).await.code, "AA");
+        let patient = db.get_patient("P-2001").await.unwrap().unwrap();
+        assert_eq!(patient.discharged_at, parse_timestamp("20261020113000"));
+        assert_eq!((patient.room, patient.bed), (None, None));
+        let device = db.get_device("pi-07").await.unwrap().unwrap();
+        assert_eq!(device.patient_id, None);
+        assert_eq!(device.ward.as_deref(), Some("ICU"));
+    }
+
+    // Admitting a discharged patient opens a new stay
+    #[actix_web::test]
+    async fn test_readmission() {
+        let db = admitted().await;
+        ack(&db, &discharge("MSG0004")).await;
+        let admission = adt("A01", "MSG0005", "P-2001", "ICU^102^B");
+        assert_eq!(ack(&db, &admission).await.code, "AA");
+        let patient = db.get_patient("P-2001").await.unwrap().unwrap();
+        assert_eq!(patient.discharged_at, None);
+        assert_eq!(patient.room.as_deref(), Some("102"));
+    }
+
+    // Every message applied is audited as the sending application, discharges with the sensors
+    #[actix_web::test]
+    async fn test_audit() {
+        let db = admitted().await;
+        ack(&db, &discharge("MSG0004")).await;
+        let audit = db
+            .audit_entries(&AuditQuery {
+                entity_type: Some("Patient".to_string()),
+                entity_ids: vec!["P-2001".to_string()],
+                limit: 10,
+            })
+            .await
+            .unwrap();
+        assert_eq!(audit.len(), 2);
+        assert!(audit.iter().all(|a| a.actor == "hl7:EPIC"));
+        assert!(audit
+            .iter()
+            .any(|a| a.action == "patient.discharged"
+                && a.detail.as_deref().unwrap().contains("pi-07")));
+    }
+
+    // Triggers other than A01/A02/A03/A08 are rejected without being applied
+    #[actix_web::test]
+    async fn test_rejects_unsupported_trigger() {
+        let db = MemoryRepository::new();
+        let ack = ack(&db, &adt("A04", "MSG0006", "P-2002", "ICU")).await;
+        assert_eq!(ack.code, "AR");
+        assert!(ack.text.contains("A04"));
+        assert!(db.get_patient("P-2002").await.unwrap().is_none());
+    }
+
+    // A message without a patient id is an error with ERR code 101
+    #[actix_web::test]
+    async fn test_missing_patient_id() {
+        let reply = reply(&MemoryRepository::new(), &adt("A01", "MSG0007", "", "ICU")).await;
+        assert_eq!(reply.field("MSA", 1), "AE");
+        assert_eq!(reply.field("MSA", 2), "MSG0007");
+        assert_eq!(reply.component("ERR", 3, 1), "101");
+    }
+
+    // A malformed date is an error with ERR code 102
+    #[actix_web::test]
+    async fn test_malformed_date() {
+        let reply = reply(
+            &MemoryRepository::new(),
+            &adt("A01", "MSG0008", "P-2003||Doe^J||1938-02-14", "ICU"),
+        )
+        .await;
+        assert_eq!(reply.field("MSA", 1), "AE");
+        assert_eq!(reply.component("ERR", 3, 1), "102");
+    }
+
+    // Messages that are not ADT are rejected with ERR code 200, addressed back to the sender
+    #[actix_web::test]
+    async fn test_rejects_other_messages() {
+        let reply = reply(
+            &MemoryRepository::new(),
+            "MSH|^~\\&|LAB|GENERAL|FALLGUARD|WARD|20261018090000||ORU^R01|MSG0009|P|2.5.1\r",
+        )
+        .await;
+        assert_eq!(reply.field("MSA", 1), "AR");
+        assert_eq!(reply.component("ERR", 3, 1), "200");
+        assert_eq!(reply.field("MSH", 5), "LAB");
+    }
+
+    // The listener applies what arrives over MLLP and ACKs it
+    #[actix_web::test]
+    async fn test_listener() {
+        let db = Arc::new(MemoryRepository::new());
+        let config = Hl7InboundConfig {
+            address: "127.0.0.1:0".to_string(),
+        };
+        let address = spawn(db.clone(), config).await.unwrap();
+        let mut client = MllpClient::new(address.to_string(), Duration::from_secs(5));
+        let ack = client
+            .send(&adt("A01", "MSG0001", "P-2001", "ICU^101^A"))
+            .await
+            .unwrap();
+        assert_eq!(
+            (ack.code.as_str(), ack.control_id.as_str()),
+            ("AA", "MSG0001")
+        );
+        assert!(db.get_patient("P-2001").await.unwrap().is_some());
+    }
+
+    // The listener refuses a frame over the limit with an AR and hangs up
+    #[actix_web::test]
+    async fn test_listener_refuses_oversized_frame() {
+        let config = Hl7InboundConfig {
+            address: "127.0.0.1:0".to_string(),
+        };
+        let address = spawn(Arc::new(MemoryRepository::new()), config)
+            .await
+            .unwrap();
+        let mut connection = BufReader::new(TcpStream::connect(address).await.unwrap());
+        // One byte over the limit, with no end block
+        let mut frame = vec![mllp::START_BLOCK];
+        frame.resize(mllp::MAX_FRAME_BYTES + 2, b'x');
+        connection.get_mut().write_all(&frame).await.unwrap();
+        let refusal = read_frame(&mut connection).await.unwrap().unwrap();
+        let ack = Ack::parse(&refusal).unwrap();
+        assert_eq!(ack.code, "AR");
+        assert_eq!(ack.text, "MLLP frame too large");
+        assert_eq!(read_frame(&mut connection).await.unwrap(), None);
+    }
+}
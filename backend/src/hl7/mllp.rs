@@ -0,0 +1,159 @@
+use std::io;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// MLLP block framing: `<VT> message <FS><CR>`.
+pub const START_BLOCK: u8 = 0x0b;
+pub const END_BLOCK: u8 = 0x1c;
+pub const CARRIAGE_RETURN: u8 = 0x0d;
+
+/// Largest message accepted (guards against a peer that never sends the end block).
+pub const MAX_FRAME_BYTES: usize = 1024 * 1024;
+
+/// Most bytes skipped while looking for a start block (guards against a peer that never
+/// sends one).
+pub const MAX_SKIPPED_BYTES: usize = 64 * 1024;
+
+/// Writes one framed message.
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, message: &str) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(message.len() + 3);
+    frame.push(START_BLOCK);
+    frame.extend_from_slice(message.as_bytes());
+    frame.extend_from_slice(&[END_BLOCK, CARRIAGE_RETURN]);
+    writer.write_all(&frame).await?;
+    writer.flush().await
+}
+
+/// Reads the next framed message; `None` when the peer closed the connection between frames.
+/// Bytes before the start block are skipped. A frame over `MAX_FRAME_BYTES`, or more than
+/// `MAX_SKIPPED_BYTES` before its start block, is refused with `InvalidData` as soon as the
+/// limit is passed, without buffering the rest.
+pub async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut BufReader<R>,
+) -> io::Result<Option<String>> {
+    let mut skipped = 0;
+    loop {
+        let buffered = reader.fill_buf().await?;
+        if buffered.is_empty() {
+            return Ok(None); // Closed between frames, or mid-garbage
+        }
+        if let Some(start) = buffered.iter().position(|&b| b == START_BLOCK) {
+            reader.consume(start + 1);
+            break;
+        }
+        let read = buffered.len();
+        reader.consume(read);
+        skipped += read;
+        if skipped > MAX_SKIPPED_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "no MLLP start block",
+            ));
+        }
+    }
+    let mut frame = Vec::new();
+    loop {
+        let buffered = reader.fill_buf().await?;
+        if buffered.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed inside an MLLP frame",
+            ));
+        }
+        let end = buffered.iter().position(|&b| b == END_BLOCK);
+        let content = &buffered[..end.unwrap_or(buffered.len())];
+        if frame.len() + content.len() > MAX_FRAME_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "MLLP frame too large",
+            ));
+        }
+        frame.extend_from_slice(content);
+        let read = content.len() + usize::from(end.is_some());
+        reader.consume(read);
+        if end.is_some() {
+            break;
+        }
+    }
+    let mut trailer = [0u8; 1];
+    tokio::io::AsyncReadExt::read_exact(reader, &mut trailer).await?;
+    if trailer[0] != CARRIAGE_RETURN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "MLLP end block not followed by a carriage return",
+        ));
+    }
+    String::from_utf8(frame)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    // Helper: reads one frame from `bytes`
+    async fn read(bytes: &[u8]) -> io::Result<Option<String>> {
+        read_frame(&mut BufReader::new(bytes)).await
+    }
+
+    // A written frame reads back as the same message
+    #[actix_web::test]
+    async fn test_round_trip() {
+        let mut written = Vec::new();
+        write_frame(&mut written, "MSH|^~\\&\r").await.unwrap();
+        assert_eq!(
+            read(&written).await.unwrap().as_deref(),
+            Some("MSH|^~\\&\r")
+        );
+    }
+
+    // Bytes before the start block are skipped
+    #[actix_web::test]
+    async fn test_skips_leading_bytes() {
+        let frame = read(b"\r\n\x0bMSH\x1c\r").await.unwrap();
+        assert_eq!(frame.as_deref(), Some("MSH"));
+    }
+
+    // A connection closed between frames ends the stream
+    #[actix_web::test]
+    async fn test_closed_between_frames() {
+        assert_eq!(read(b"").await.unwrap(), None);
+    }
+
+    // A connection closed inside a frame is an error
+    #[actix_web::test]
+    async fn test_closed_inside_frame() {
+        let err = read(b"\x0bMSH").await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    // A frame with no end block is refused once it passes the limit, not read to the end
+    #[actix_web::test]
+    async fn test_oversized_frame() {
+        let endless = [START_BLOCK].chain(tokio::io::repeat(b'x'));
+        let mut reader = BufReader::new(endless);
+        let err = read_frame(&mut reader).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "MLLP frame too large");
+    }
+
+    // A frame of exactly the limit is accepted
+    #[actix_web::test]
+    async fn test_frame_at_limit() {
+        let mut bytes = vec![START_BLOCK];
+        bytes.extend(std::iter::repeat_n(b'x', MAX_FRAME_BYTES));
+        bytes.extend([END_BLOCK, CARRIAGE_RETURN]);
+        let frame = read(&bytes).await.unwrap().unwrap();
+        assert_eq!(frame.len(), MAX_FRAME_BYTES);
+    }
+
+    // A peer that never sends a start block is refused once the skipped bytes pass the limit
+    #[actix_web::test]
+    async fn test_no_start_block() {
+        let mut reader = BufReader::new(tokio::io::repeat(b'x'));
+        let err = read_frame(&mut reader).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "no MLLP start block");
+    }
+}
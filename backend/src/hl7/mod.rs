@@ -0,0 +1,386 @@
+use chrono::{DateTime, Utc};
+
+pub mod adt;
+pub mod inbound;
+pub mod mllp;
+pub mod oru;
+pub mod outbound;
+
+/// Version written to MSH-12.
+pub const HL7_VERSION: &str = "2.5.1";
+
+/// Sending application (MSH-3) and assigning authority of our identifiers.
+pub const SENDING_APPLICATION: &str = "FALLGUARD";
+
+/// Separators declared in MSH-1 / MSH-2 (the standard set).
+pub const FIELD_SEPARATOR: char = '|';
+pub const ENCODING_CHARACTERS: &str = "^~\\&";
+pub const SEGMENT_TERMINATOR: char = '\r';
+
+/// Coding system name of our local codes (HL7 reserves `99zzz` for local systems).
+pub const LOCAL_CODING_SYSTEM: &str = "99FG";
+
+/// Escapes the separators in a text value (HL7 v2 section 2.7).
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\E\\"),
+            '|' => escaped.push_str("\\F\\"),
+            '^' => escaped.push_str("\\S\\"),
+            '&' => escaped.push_str("\\T\\"),
+            '~' => escaped.push_str("\\R\\"),
+            '\r' | '\n' => escaped.push_str("\\.br\\"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Reverses `escape`; unknown escape sequences are kept as they are.
+pub fn unescape(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('\\') {
+        text.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('\\') else {
+            text.push_str(&rest[start..]);
+            return text;
+        };
+        match &after[..end] {
+            "E" => text.push('\\'),
+            "F" => text.push('|'),
+            "S" => text.push('^'),
+            "T" => text.push('&'),
+            "R" => text.push('~'),
+            ".br" => text.push('\r'),
+            other => {
+                text.push('\\');
+                text.push_str(other);
+                text.push('\\');
+            }
+        }
+        rest = &after[end + 1..];
+    }
+    text.push_str(rest);
+    text
+}
+
+/// Escaped components joined with `^` (e.g. a coded element `code^text^system`).
+pub fn components(parts: &[&str]) -> String {
+    let joined: Vec<String> = parts.iter().map(|p| escape(p)).collect();
+    joined.join("^").trim_end_matches('^').to_string()
+}
+
+/// HL7 DTM with seconds and zone, e.g. `20261018143000+0000`.
+pub fn timestamp(at: DateTime<Utc>) -> String {
+    at.format("%Y%m%d%H%M%S+0000").to_string()
+}
+
+/// Reads an HL7 DTM (`YYYY[MM[DD[HH[MM[SS[.S+]]]]]][+/-ZZZZ]`); missing parts default to the
+/// start of the period and a missing zone is taken as UTC.
+pub fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    let (local, offset) = match value.find(['+', '-']) {
+        Some(i) => (&value[..i], Some(&value[i..])),
+        None => (value, None),
+    };
+    let digits = local.split('.').next().unwrap_or("");
+    if !(4..=14).contains(&digits.len())
+        || digits.len() % 2 != 0
+        || !digits.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let padded = format!("{}{}", digits, &"0101000000"[digits.len() - 4..]);
+    let naive = chrono::NaiveDateTime::parse_from_str(&padded, "%Y%m%d%H%M%S").ok()?;
+    let fraction = match local.split_once('.') {
+        Some((_, f)) if !f.is_empty() && f.bytes().all(|b| b.is_ascii_digit()) => {
+            let micros = format!("{:0<6}", &f[..f.len().min(6)]);
+            chrono::Duration::microseconds(micros.parse().ok()?)
+        }
+        Some(_) => return None,
+        None => chrono::Duration::zero(),
+    };
+    let offset_minutes = match offset {
+        Some(zone) if zone.len() == 5 && zone[1..].bytes().all(|b| b.is_ascii_digit()) => {
+            let minutes = zone[1..3].parse::<i64>().ok()? * 60 + zone[3..].parse::<i64>().ok()?;
+            if zone.starts_with('-') {
+                -minutes
+            } else {
+                minutes
+            }
+        }
+        Some(_) => return None,
+        None => 0,
+    };
+    Some(naive.and_utc() + fraction - chrono::Duration::minutes(offset_minutes))
+}
+
+// --- Segments ---
+
+/// **Segment Builder**
+///
+/// Fields are set by their HL7 position (`MSH-9` is `field(9, ..)`); values are written as
+/// given, so escape text with `escape` / `components` first. Trailing empty fields are dropped.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    name: &'static str,
+    fields: Vec<String>,
+}
+
+impl Segment {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn field(mut self, position: usize, value: impl Into<String>) -> Self {
+        // MSH-1 is the field separator itself, so MSH fields start one position later
+        let first = if self.name == "MSH" { 2 } else { 1 };
+        let index = position.checked_sub(first).unwrap_or_else(|| {
+            panic!(
+                "{}-{} cannot be set; fields start at {}-{}",
+                self.name, position, self.name, first
+            )
+        });
+        if self.fields.len() <= index {
+            self.fields.resize(index + 1, String::new());
+        }
+        self.fields[index] = value.into();
+        self
+    }
+
+    pub fn encode(&self) -> String {
+        let last = self.fields.iter().rposition(|f| !f.is_empty());
+        let fields = &self.fields[..last.map_or(0, |i| i + 1)];
+        let mut line = self.name.to_string();
+        for field in fields {
+            line.push(FIELD_SEPARATOR);
+            line.push_str(field);
+        }
+        line
+    }
+}
+
+/// MSH segment with our standard fields: `message_type` like `ORU^R01^ORU_R01`.
+pub fn header(
+    sending_facility: &str,
+    receiving_application: &str,
+    receiving_facility: &str,
+    message_type: &str,
+    control_id: &str,
+    at: DateTime<Utc>,
+) -> Segment {
+    Segment::new("MSH")
+        .field(2, ENCODING_CHARACTERS)
+        .field(3, SENDING_APPLICATION)
+        .field(4, escape(sending_facility))
+        .field(5, escape(receiving_application))
+        .field(6, escape(receiving_facility))
+        .field(7, timestamp(at))
+        .field(9, message_type)
+        .field(10, escape(control_id))
+        .field(11, "P")
+        .field(12, HL7_VERSION)
+        .field(15, "AL") // Accept acknowledgment: always
+        .field(16, "NE") // Application acknowledgment: never
+}
+
+/// Segments joined into one message (each terminated by a carriage return).
+pub fn encode_message(segments: &[Segment]) -> String {
+    segments
+        .iter()
+        .map(|s| format!("{}{}", s.encode(), SEGMENT_TERMINATOR))
+        .collect()
+}
+
+// --- Parsing ---
+
+/// **Parsed Message**
+///
+/// Segments split into fields; `field` uses HL7 positions and returns "" when absent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    segments: Vec<Vec<String>>,
+}
+
+impl Message {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let segments: Vec<Vec<String>> = text
+            .split(['\r', '\n'])
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.split(FIELD_SEPARATOR).map(String::from).collect())
+            .collect();
+        match segments.first() {
+            Some(msh) if msh[0] == "MSH" && msh.len() > 1 => Ok(Self { segments }),
+            _ => Err("message does not start with an MSH segment".to_string()),
+        }
+    }
+
+    /// Raw (still escaped) value of `<segment>-<position>` in the first such segment.
+    pub fn field(&self, segment: &str, position: usize) -> &str {
+        self.segments
+            .iter()
+            .find(|s| s[0] == segment)
+            .map_or("", |s| field_of(s, position))
+    }
+
+    /// Unescaped component (1-based) of a field.
+    pub fn component(&self, segment: &str, position: usize, component: usize) -> String {
+        let field = self.field(segment, position);
+        unescape(field.split('^').nth(component - 1).unwrap_or(""))
+    }
+
+    pub fn control_id(&self) -> String {
+        unescape(self.field("MSH", 10))
+    }
+
+    /// MSH-9 as `TYPE^TRIGGER`, e.g. `ADT^A01`.
+    pub fn message_type(&self) -> String {
+        format!(
+            "{}^{}",
+            self.component("MSH", 9, 1),
+            self.component("MSH", 9, 2)
+        )
+    }
+}
+
+fn field_of(segment: &[String], position: usize) -> &str {
+    let index = if segment[0] == "MSH" {
+        position.checked_sub(1)
+    } else {
+        Some(position)
+    };
+    index
+        .and_then(|i| segment.get(i))
+        .map_or("", |f| f.as_str())
+}
+
+// --- Acknowledgments ---
+
+/// **ACK**
+///
+/// MSA of an acknowledgment: `AA`/`CA` accepted, `AE`/`CE` error, `AR`/`CR` rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ack {
+    pub code: String,
+    pub control_id: String, // MSA-2: the MSH-10 acknowledged
+    pub text: String,
+}
+
+impl Ack {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let message = Message::parse(text)?;
+        let code = message.field("MSA", 1).to_string();
+        if code.is_empty() {
+            return Err("acknowledgment has no MSA segment".to_string());
+        }
+        Ok(Self {
+            code,
+            control_id: unescape(message.field("MSA", 2)),
+            text: unescape(message.field("MSA", 3)),
+        })
+    }
+
+    pub fn is_accepted(&self) -> bool {
+        matches!(self.code.as_str(), "AA" | "CA")
+    }
+}
+
+/// ACK message answering `original` with `code` (and `text` for errors).
+pub fn ack_message(original: &Message, code: &str, text: &str, at: DateTime<Utc>) -> String {
+    encode_message(&ack_segments(original, code, text, at))
+}
+
+/// Error ACK (`AE`/`AR`) that also carries an ERR segment with the HL7 error code
+/// (table 0357, e.g. `201` unsupported event code) and severity `E`.
+pub fn error_ack_message(
+    original: &Message,
+    code: &str,
+    error_code: &str,
+    text: &str,
+    at: DateTime<Utc>,
+) -> String {
+    let mut segments = ack_segments(original, code, text, at);
+    segments.push(
+        Segment::new("ERR")
+            .field(3, components(&[error_code, text, "HL70357"]))
+            .field(4, "E"),
+    );
+    encode_message(&segments)
+}
+
+fn ack_segments(original: &Message, code: &str, text: &str, at: DateTime<Utc>) -> Vec<Segment> {
+    let control_id = format!("ACK{}", at.timestamp_millis());
+    let trigger = original.component("MSH", 9, 2);
+    let msh = Segment::new("MSH")
+        .field(2, ENCODING_CHARACTERS)
+        .field(3, original.field("MSH", 5))
+        .field(4, original.field("MSH", 6))
+        .field(5, original.field("MSH", 3))
+        .field(6, original.field("MSH", 4))
+        .field(7, timestamp(at))
+        .field(9, components(&["ACK", &trigger, "ACK"]))
+        .field(10, control_id)
+        .field(11, original.field("MSH", 11))
+        .field(12, original.field("MSH", 12));
+    let msa = Segment::new("MSA")
+        .field(1, code)
+        .field(2, original.field("MSH", 10))
+        .field(3, escape(text));
+    vec![msh, msa]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Timestamps with an offset are converted to UTC
+    #[test]
+    fn test_parse_timestamp_offset() {
+        assert_eq!(
+            parse_timestamp("202610180930-0500"),
+            Some("2026-10-18T14:30:00Z".parse().unwrap())
+        );
+    }
+
+    // Fractions of a second are kept
+    #[test]
+    fn test_parse_timestamp_fraction() {
+        assert_eq!(
+            parse_timestamp("20261018143000.25"),
+            Some("2026-10-18T14:30:00.25Z".parse().unwrap())
+        );
+    }
+
+    // A timestamp cut short inside a component is refused
+    #[test]
+    fn test_parse_timestamp_rejects_partial() {
+        assert_eq!(parse_timestamp("2026101"), None);
+    }
+
+    // MSH fields are set from MSH-2, after the field separator
+    #[test]
+    fn test_msh_field_positions() {
+        let msh = Segment::new("MSH").field(2, "^~\\&").field(9, "ADT^A01");
+        assert_eq!(msh.encode(), "MSH|^~\\&|||||||ADT^A01");
+    }
+
+    // MSH-1 is the separator itself and cannot be set
+    #[test]
+    #[should_panic(expected = "MSH-1 cannot be set; fields start at MSH-2")]
+    fn test_msh_field_separator_not_settable() {
+        let _ = Segment::new("MSH").field(1, "|");
+    }
+
+    // Positions start at 1
+    #[test]
+    #[should_panic(expected = "PID-0 cannot be set; fields start at PID-1")]
+    fn test_field_zero_not_settable() {
+        let _ = Segment::new("PID").field(0, "1");
+    }
+}
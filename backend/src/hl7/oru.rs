@@ -0,0 +1,273 @@
+use super::{components, encode_message, escape, header, timestamp, Segment, LOCAL_CODING_SYSTEM};
+use crate::model::{FallLog, Patient, FALL_OBSERVATION_CODINGS};
+use crate::storage::stats::DETECTION_SEVERITIES;
+use chrono::{DateTime, Utc};
+
+/// MSH-9 of the results message.
+pub const ORU_R01: &str = "ORU^R01^ORU_R01";
+
+/// Message type stored in the outbox (MSH-9 without the structure).
+pub const ORU_MESSAGE_TYPE: &str = "ORU^R01";
+
+/// Where a message is going (MSH-4 to MSH-6).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Destination {
+    pub sending_facility: String,
+    pub receiving_application: String,
+    pub receiving_facility: String,
+}
+
+/// MSH-10 of the message reporting `event_id` (one message per event, so it is stable).
+pub fn control_id(event_id: i32) -> String {
+    format!("FG{:010}", event_id)
+}
+
+/// OBX-11 / OBR-25 result status for an Observation status.
+fn result_status(fhir_status: &str) -> &'static str {
+    match fhir_status {
+        "final" => "F",
+        "entered-in-error" => "W", // Posted in error: the original result is wrong
+        _ => "P",
+    }
+}
+
+/// PID-8 administrative sex for a FHIR gender.
+fn administrative_sex(gender: Option<&str>) -> &'static str {
+    match gender {
+        Some("male") => "M",
+        Some("female") => "F",
+        Some("other") => "O",
+        _ => "U",
+    }
+}
+
+/// **ORU^R01**
+///
+/// One event as an unsolicited result: MSH, PID (with demographics when the patient is
+/// registered), PV1 (the ward), OBR (the detection, coded LOINC 89020-2) and OBX segments.
+/// Detections report the verdict, peak G and the validation measurements; nurse actions
+/// report only their outcome.
+pub fn encode(
+    log: &FallLog,
+    patient: Option<&Patient>,
+    destination: &Destination,
+    at: DateTime<Utc>,
+) -> String {
+    let (fhir_status, interpretation) = log.observation_status();
+    let status = result_status(fhir_status);
+    let observed_at = timestamp(log.detected_at);
+    let mut segments = vec![header(
+        &destination.sending_facility,
+        &destination.receiving_application,
+        &destination.receiving_facility,
+        ORU_R01,
+        &control_id(log.id),
+        at,
+    )];
+
+    let mut pid = Segment::new("PID").field(1, "1");
+    if let Some(patient_id) = &log.patient_id {
+        pid = pid.field(
+            3,
+            components(&[patient_id, "", "", super::SENDING_APPLICATION, "MR"]),
+        );
+    }
+    if let Some(patient) = patient {
+        pid = pid
+            .field(
+                5,
+                components(&[
+                    patient.family_name.as_deref().unwrap_or(""),
+                    patient.given_name.as_deref().unwrap_or(""),
+                ]),
+            )
+            .field(
+                7,
+                patient
+                    .birth_date
+                    .map(|d| d.format("%Y%m%d").to_string())
+                    .unwrap_or_default(),
+            )
+            .field(8, administrative_sex(patient.gender.as_deref()));
+    }
+    segments.push(pid);
+
+    let ward = log
+        .ward
+        .as_deref()
+        .or(patient.and_then(|p| p.ward.as_deref()));
+    if let Some(ward) = ward {
+        segments.push(
+            Segment::new("PV1")
+                .field(1, "1")
+                .field(2, "I")
+                .field(3, escape(ward)),
+        );
+    }
+
+    let (_, loinc, loinc_display) = FALL_OBSERVATION_CODINGS[0];
+    segments.push(
+        Segment::new("OBR")
+            .field(1, "1")
+            .field(
+                3,
+                components(&[&log.id.to_string(), super::SENDING_APPLICATION]),
+            )
+            .field(4, components(&[loinc, loinc_display, "LN"]))
+            .field(7, observed_at.clone())
+            .field(22, timestamp(at))
+            .field(25, status),
+    );
+
+    let (_, snomed, snomed_display) = FALL_OBSERVATION_CODINGS[1];
+    let mut observations = vec![Segment::new("OBX")
+        .field(2, "CWE")
+        .field(3, components(&[snomed, snomed_display, "SCT"]))
+        .field(
+            5,
+            components(&["", &format!("{}: {}", log.severity, interpretation)]),
+        )
+        .field(8, if log.severity == "Critical" { "A" } else { "N" })
+        .field(11, status)
+        .field(14, observed_at.clone())
+        .field(
+            18,
+            log.device_id
+                .as_deref()
+                .map(|d| components(&[d, super::SENDING_APPLICATION]))
+                .unwrap_or_default(),
+        )];
+    if DETECTION_SEVERITIES.contains(&log.severity.as_str()) {
+        let measurements = [
+            (
+                "peak-g",
+                "Peak acceleration",
+                Some(log.g_force_value),
+                "[g]",
+            ),
+            (
+                "stillness-variance",
+                "Stillness variance",
+                log.stillness_variance,
+                "m2/s4",
+            ),
+            (
+                "validation-duration",
+                "Validation duration",
+                log.validation_ms.map(|v| v as f64),
+                "ms",
+            ),
+            (
+                "orientation-change",
+                "Orientation change",
+                log.orientation_change_deg,
+                "deg",
+            ),
+        ];
+        for (code, display, value, unit) in measurements {
+            let Some(value) = value else {
+                continue;
+            };
+            observations.push(
+                Segment::new("OBX")
+                    .field(2, "NM")
+                    .field(3, components(&[code, display, LOCAL_CODING_SYSTEM]))
+                    .field(5, format!("{:.2}", value))
+                    .field(6, components(&[unit, unit, "UCUM"]))
+                    .field(11, status)
+                    .field(14, observed_at.clone()),
+            );
+        }
+    }
+    for (i, obx) in observations.into_iter().enumerate() {
+        segments.push(obx.field(1, (i + 1).to_string()));
+    }
+    encode_message(&segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hl7::{Message, SENDING_APPLICATION};
+    use crate::test_support::fall_log;
+    use chrono::NaiveDate;
+
+    // Helper: P-1001 as registered by the ADT feed
+    fn perera() -> Patient {
+        Patient {
+            id: "P-1001".to_string(),
+            family_name: Some("Perera".to_string()),
+            given_name: Some("Nimal".to_string()),
+            birth_date: NaiveDate::from_ymd_opt(1941, 5, 2),
+            gender: Some("male".to_string()),
+            ward: Some("ICU".to_string()),
+            room: None,
+            bed: None,
+            admitted_at: None,
+            discharged_at: None,
+            updated_at: Utc::now(),
+        }
+    }
+
+    // Helper: `log` encoded for the default interface engine
+    fn encoded(log: &FallLog, patient: Option<&Patient>) -> String {
+        let destination = Destination {
+            sending_facility: SENDING_APPLICATION.to_string(),
+            receiving_application: String::new(),
+            receiving_facility: String::new(),
+        };
+        encode(log, patient, &destination, Utc::now())
+    }
+
+    // Helper: the OBX segments of `message`
+    fn observations(message: &str) -> Vec<&str> {
+        message
+            .split('\r')
+            .filter(|s| s.starts_with("OBX"))
+            .collect()
+    }
+
+    // The header is v2.5.1 with a control id stable per event
+    #[test]
+    fn test_header() {
+        let message = Message::parse(&encoded(&fall_log("Critical", 2.5), None)).unwrap();
+        assert_eq!(message.message_type(), "ORU^R01");
+        assert_eq!(message.control_id(), "FG0000000007");
+        assert_eq!(message.field("MSH", 12), "2.5.1");
+    }
+
+    // PID carries the registered patient's demographics, PV1 the ward
+    #[test]
+    fn test_patient() {
+        let message = encoded(&fall_log("Critical", 2.5), Some(&perera()));
+        let message = Message::parse(&message).unwrap();
+        assert_eq!(message.component("PID", 3, 1), "P-1001");
+        assert_eq!(message.component("PID", 5, 1), "Perera");
+        assert_eq!(message.field("PID", 7), "19410502");
+        assert_eq!(message.field("PID", 8), "M");
+        assert_eq!(message.field("PV1", 3), "ICU");
+    }
+
+    // A detection reports the verdict as abnormal, then peak G and its measurements
+    #[test]
+    fn test_detection_results() {
+        let message = encoded(&fall_log("Critical", 2.5), Some(&perera()));
+        let parsed = Message::parse(&message).unwrap();
+        assert_eq!(parsed.component("OBR", 4, 1), "89020-2");
+        assert_eq!(parsed.field("OBR", 25), "F");
+        let obx = observations(&message);
+        assert_eq!(obx.len(), 5);
+        assert!(obx[0].contains("Critical: High Risk - Fall Detected"));
+        assert!(obx[0].contains("|A|"));
+        assert!(obx[1].starts_with("OBX|2|NM|peak-g^Peak acceleration^99FG||2.50|[g]^[g]^UCUM"));
+    }
+
+    // A nurse action reports only its outcome
+    #[test]
+    fn test_nurse_action_results() {
+        let message = encoded(&fall_log("Assistance Sent", 0.0), None);
+        let obx = observations(&message);
+        assert_eq!(obx.len(), 1);
+        assert!(!message.contains("peak-g"));
+    }
+}
@@ -0,0 +1,554 @@
+use super::mllp::{read_frame, write_frame};
+use super::oru::{self, Destination, ORU_MESSAGE_TYPE};
+use super::Ack;
+use crate::model::OutboundMessage;
+use crate::storage::{NewOutboundMessage, OutboundUpdate, Repository, StorageResult};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::BufReader;
+use tokio::net::TcpStream;
+
+/// Events encoded and messages attempted per pass.
+pub const OUTBOUND_BATCH: i64 = 100;
+
+/// **HL7 Outbound Configuration**
+///
+/// The interface is off unless `HL7_MLLP_ADDR` is set.
+/// - `HL7_MLLP_ADDR`: `host:port` of the interface engine's MLLP listener.
+/// - `HL7_SENDING_FACILITY`, `HL7_RECEIVING_APPLICATION`, `HL7_RECEIVING_FACILITY`: MSH-4 to MSH-6.
+/// - `HL7_MAX_ATTEMPTS`: attempts before a message is set aside as `failed` (default 10).
+/// - `HL7_RETRY_SECONDS`: delay before the first retry, doubled after each one up to an hour (default 5).
+/// - `HL7_ACK_TIMEOUT_SECONDS`: time allowed to connect, send and receive the ACK (default 30).
+/// - `HL7_POLL_SECONDS`: how often new events are picked up (default 1).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hl7OutboundConfig {
+    pub address: String,
+    pub destination: Destination,
+    pub max_attempts: i32,
+    pub retry_delay: Duration,
+    pub max_retry_delay: Duration,
+    pub ack_timeout: Duration,
+    pub poll_interval: Duration,
+}
+
+impl Hl7OutboundConfig {
+    /// Defaults for an interface engine at `address`.
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            destination: Destination {
+                sending_facility: super::SENDING_APPLICATION.to_string(),
+                receiving_application: String::new(),
+                receiving_facility: String::new(),
+            },
+            max_attempts: 10,
+            retry_delay: Duration::from_secs(5),
+            max_retry_delay: Duration::from_secs(3600),
+            ack_timeout: Duration::from_secs(30),
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+
+    pub fn from_env() -> Option<Self> {
+        let address = std::env::var("HL7_MLLP_ADDR")
+            .ok()
+            .filter(|a| !a.trim().is_empty())?;
+        let seconds = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|s| *s > 0)
+                .map(Duration::from_secs)
+        };
+        let text = |name: &str, default: String| std::env::var(name).unwrap_or(default);
+        let defaults = Self::new(address.trim());
+        Some(Self {
+            destination: Destination {
+                sending_facility: text(
+                    "HL7_SENDING_FACILITY",
+                    defaults.destination.sending_facility.clone(),
+                ),
+                receiving_application: text("HL7_RECEIVING_APPLICATION", String::new()),
+                receiving_facility: text("HL7_RECEIVING_FACILITY", String::new()),
+            },
+            max_attempts: std::env::var("HL7_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|v| v.parse::<i32>().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(defaults.max_attempts),
+            retry_delay: seconds("HL7_RETRY_SECONDS").unwrap_or(defaults.retry_delay),
+            ack_timeout: seconds("HL7_ACK_TIMEOUT_SECONDS").unwrap_or(defaults.ack_timeout),
+            poll_interval: seconds("HL7_POLL_SECONDS").unwrap_or(defaults.poll_interval),
+            ..defaults
+        })
+    }
+
+    /// Wait before the attempt after `attempts` failed ones.
+    pub fn backoff(&self, attempts: i32) -> Duration {
+        let doublings = attempts.saturating_sub(1).clamp(0, 20) as u32;
+        self.retry_delay
+            .saturating_mul(2u32.pow(doublings))
+            .min(self.max_retry_delay)
+    }
+}
+
+/// **MLLP Client**
+///
+/// Sends one message at a time and waits for its ACK. The connection is kept open between
+/// messages and re-opened after any error.
+pub struct MllpClient {
+    address: String,
+    timeout: Duration,
+    connection: Option<BufReader<TcpStream>>,
+}
+
+impl MllpClient {
+    pub fn new(address: impl Into<String>, timeout: Duration) -> Self {
+        Self {
+            address: address.into(),
+            timeout,
+            connection: None,
+        }
+    }
+
+    pub async fn send(&mut self, message: &str) -> Result<Ack, String> {
+        let result = match tokio::time::timeout(self.timeout, self.exchange(message)).await {
+            Ok(result) => result,
+            Err(_) => Err(format!("no ACK within {}s", self.timeout.as_secs_f64())),
+        };
+        if result.is_err() {
+            self.connection = None;
+        }
+        result
+    }
+
+    async fn exchange(&mut self, message: &str) -> Result<Ack, String> {
+        if self.connection.is_none() {
+            let stream = TcpStream::connect(&self.address)
+                .await
+                .map_err(|e| format!("cannot connect to {}: {}", self.address, e))?;
+            self.connection = Some(BufReader::new(stream));
+        }
+        let Some(connection) = self.connection.as_mut() else {
+            return Err("not connected".to_string());
+        };
+        write_frame(connection.get_mut(), message)
+            .await
+            .map_err(|e| format!("send failed: {}", e))?;
+        let reply = read_frame(connection)
+            .await
+            .map_err(|e| format!("reading the ACK failed: {}", e))?
+            .ok_or_else(|| "connection closed before the ACK".to_string())?;
+        Ack::parse(&reply)
+    }
+}
+
+/// What one pass of the interface did.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutboundReport {
+    pub queued: usize,
+    pub sent: usize,
+    pub failed: usize, // Set aside after the last attempt
+}
+
+/// Where the interface picks up: just before the first event it queued, or (on first start)
+/// after the newest event, so history is not replayed to the interface engine.
+pub async fn initial_cursor(db: &dyn Repository) -> StorageResult<i32> {
+    match db.first_queued_event().await? {
+        Some(first) => Ok(first - 1),
+        None => Ok(db.latest_event_id().await?.unwrap_or(0)),
+    }
+}
+
+/// Queues an ORU^R01 for every event after `cursor` that has none yet, then sends the pending
+/// messages in order. Events are found by what is missing from the outbox rather than by the
+/// highest id seen, since ids can commit out of order and a late one would be skipped.
+/// A message that fails waits for its retry, holding back the ones behind it so the engine
+/// sees events in order; once out of attempts it is marked `failed` and the queue moves on.
+pub async fn run_once(
+    db: &dyn Repository,
+    config: &Hl7OutboundConfig,
+    client: &mut MllpClient,
+    cursor: i32,
+    now: DateTime<Utc>,
+) -> StorageResult<OutboundReport> {
+    let mut report = OutboundReport::default();
+    loop {
+        let events = db.unqueued_events(cursor, OUTBOUND_BATCH).await?;
+        for log in &events {
+            let patient = match &log.patient_id {
+                Some(id) => db.get_patient(id).await?,
+                None => None,
+            };
+            db.enqueue_outbound(&NewOutboundMessage {
+                event_id: log.id,
+                message_type: ORU_MESSAGE_TYPE.to_string(),
+                control_id: oru::control_id(log.id),
+                message: oru::encode(log, patient.as_ref(), &config.destination, now),
+                created_at: now,
+            })
+            .await?;
+            report.queued += 1;
+        }
+        if (events.len() as i64) < OUTBOUND_BATCH {
+            break;
+        }
+    }
+
+    for message in db
+        .outbound_messages(Some("pending"), OUTBOUND_BATCH)
+        .await?
+    {
+        if message.next_attempt_at > now {
+            break;
+        }
+        let update = attempt(config, client, &message, now).await;
+        let delivered = update.status == "sent";
+        let failed = update.status == "failed";
+        db.update_outbound(message.id, &update).await?;
+        if delivered {
+            report.sent += 1;
+        } else if failed {
+            eprintln!(
+                "❌ HL7: giving up on {} for event {} after {} attempts: {}",
+                message.control_id,
+                message.event_id,
+                update.attempts,
+                update.last_error.as_deref().unwrap_or("")
+            );
+            report.failed += 1;
+        } else {
+            break;
+        }
+    }
+    Ok(report)
+}
+
+/// Sends one message and works out its next state.
+async fn attempt(
+    config: &Hl7OutboundConfig,
+    client: &mut MllpClient,
+    message: &OutboundMessage,
+    now: DateTime<Utc>,
+) -> OutboundUpdate {
+    let attempts = message.attempts + 1;
+    let (ack_code, error) = match client.send(&message.message).await {
+        Ok(ack) if ack.control_id != message.control_id => (
+            Some(ack.code),
+            format!(
+                "ACK is for {} instead of {}",
+                ack.control_id, message.control_id
+            ),
+        ),
+        Ok(ack) if ack.is_accepted() => {
+            return OutboundUpdate {
+                status: "sent".to_string(),
+                attempts,
+                next_attempt_at: message.next_attempt_at,
+                last_error: None,
+                ack_code: Some(ack.code),
+                sent_at: Some(now),
+            }
+        }
+        Ok(ack) => {
+            let error = format!("NACK {}: {}", ack.code, ack.text);
+            (Some(ack.code), error)
+        }
+        Err(e) => (message.ack_code.clone(), e),
+    };
+    let (status, next_attempt_at) = if attempts >= config.max_attempts {
+        ("failed", message.next_attempt_at)
+    } else {
+        let wait = chrono::Duration::from_std(config.backoff(attempts))
+            .unwrap_or(chrono::Duration::hours(1));
+        ("pending", now + wait)
+    };
+    OutboundUpdate {
+        status: status.to_string(),
+        attempts,
+        next_attempt_at,
+        last_error: Some(error),
+        ack_code,
+        sent_at: None,
+    }
+}
+
+/// Runs the interface every `config.poll_interval`.
+pub fn spawn(db: Arc<dyn Repository>, config: Hl7OutboundConfig) {
+    tokio::spawn(async move {
+        let mut client = MllpClient::new(config.address.clone(), config.ack_timeout);
+        let mut cursor = None;
+        let mut interval = tokio::time::interval(config.poll_interval);
+        loop {
+            interval.tick().await;
+            if cursor.is_none() {
+                match initial_cursor(db.as_ref()).await {
+                    Ok(start) => cursor = Some(start),
+                    Err(e) => {
+                        eprintln!("❌ HL7: cannot read the outbox: {}", e);
+                        continue;
+                    }
+                }
+            }
+            let Some(position) = cursor else {
+                continue;
+            };
+            match run_once(db.as_ref(), &config, &mut client, position, Utc::now()).await {
+                Ok(report) if report.sent > 0 => {
+                    println!("📨 HL7: {} ORU^R01 message(s) acknowledged", report.sent)
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("❌ HL7 outbound pass failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hl7::{self, mllp, Message};
+    use crate::storage::{MemoryRepository, NewEvent};
+    use crate::test_support::critical_event;
+    use std::sync::Mutex;
+    use tokio::net::TcpListener;
+
+    // Helper: an interface engine's MLLP listener on 127.0.0.1 that records every message and
+    // answers the first `nacks` of them with an AE
+    async fn mllp_listener(nacks: usize) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = received.clone();
+        actix_rt::spawn(async move {
+            let mut nacks = nacks;
+            while let Ok((stream, _)) = listener.accept().await {
+                let mut reader = BufReader::new(stream);
+                while let Ok(Some(frame)) = mllp::read_frame(&mut reader).await {
+                    log.lock().unwrap().push(frame.clone());
+                    let message = Message::parse(&frame).unwrap();
+                    let (code, text) = match nacks {
+                        0 => ("AA", ""),
+                        _ => {
+                            nacks -= 1;
+                            ("AE", "Unknown patient")
+                        }
+                    };
+                    let ack = hl7::ack_message(&message, code, text, Utc::now());
+                    mllp::write_frame(reader.get_mut(), &ack).await.unwrap();
+                }
+            }
+        });
+        (address, received)
+    }
+
+    // Helper: an address nothing listens on
+    async fn unreachable() -> String {
+        let unused = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        unused.local_addr().unwrap().to_string()
+    }
+
+    // Helper: the interface started over one old event, then a fall and a nurse action
+    struct Interface {
+        db: MemoryRepository,
+        cursor: i32,
+        fall: i32,
+        action: i32,
+    }
+
+    async fn interface() -> Interface {
+        let db = MemoryRepository::new();
+        db.insert_event(critical_event(60, 1.9)).await.unwrap();
+        let cursor = initial_cursor(&db).await.unwrap();
+        let fall = db.insert_event(critical_event(2, 2.5)).await.unwrap();
+        let action = db
+            .insert_event(NewEvent {
+                severity: "Assistance Sent".to_string(),
+                g_force_value: 0.0,
+                ..critical_event(1, 0.0)
+            })
+            .await
+            .unwrap();
+        Interface {
+            db,
+            cursor,
+            fall: fall.id,
+            action: action.id,
+        }
+    }
+
+    // Helper: three attempts per message, 30 s apart
+    fn config(address: &str) -> Hl7OutboundConfig {
+        let mut config = Hl7OutboundConfig::new(address);
+        config.max_attempts = 3;
+        config.retry_delay = Duration::from_secs(30);
+        config.ack_timeout = Duration::from_secs(2);
+        config
+    }
+
+    // Helper: one pass of the interface at `now`
+    async fn run(
+        interface: &mut Interface,
+        config: &Hl7OutboundConfig,
+        client: &mut MllpClient,
+        now: DateTime<Utc>,
+    ) -> OutboundReport {
+        run_once(&interface.db, config, client, interface.cursor, now)
+            .await
+            .unwrap()
+    }
+
+    // The interface starts after the newest event, so history is not replayed
+    #[actix_web::test]
+    async fn test_initial_cursor() {
+        let db = MemoryRepository::new();
+        assert_eq!(initial_cursor(&db).await.unwrap(), 0);
+        let history = db.insert_event(critical_event(60, 1.9)).await.unwrap();
+        assert_eq!(initial_cursor(&db).await.unwrap(), history.id);
+    }
+
+    // After a restart the interface picks up just before the first event it queued
+    #[actix_web::test]
+    async fn test_restart_cursor() {
+        let mut interface = interface().await;
+        let address = unreachable().await;
+        let config = config(&address);
+        let mut client = MllpClient::new(address, config.ack_timeout);
+        run(&mut interface, &config, &mut client, Utc::now()).await;
+        let cursor = initial_cursor(&interface.db).await.unwrap();
+        assert_eq!(cursor, interface.fall - 1);
+    }
+
+    // An event committed after a higher id was queued is still reported
+    #[actix_web::test]
+    async fn test_late_event_queued() {
+        let mut interface = interface().await;
+        let now = Utc::now();
+        // The nurse action was queued by a pass that ran before the fall committed
+        let action = NewOutboundMessage {
+            event_id: interface.action,
+            message_type: ORU_MESSAGE_TYPE.to_string(),
+            control_id: oru::control_id(interface.action),
+            message: "MSH|^~\\&|FALLGUARD\r".to_string(),
+            created_at: now,
+        };
+        interface.db.enqueue_outbound(&action).await.unwrap();
+        let address = unreachable().await;
+        let config = config(&address);
+        let mut client = MllpClient::new(address, config.ack_timeout);
+        let report = run(&mut interface, &config, &mut client, now).await;
+        assert_eq!(report.queued, 1);
+        let outbox = interface.db.outbound_messages(None, 10).await.unwrap();
+        assert_eq!(outbox[1].event_id, interface.fall);
+    }
+
+    // New events are queued; a NACKed message waits for its retry and holds the others back
+    #[actix_web::test]
+    async fn test_nack_holds_back_the_queue() {
+        let mut interface = interface().await;
+        let (address, _) = mllp_listener(1).await;
+        let config = config(&address);
+        let mut client = MllpClient::new(address, config.ack_timeout);
+        let now = Utc::now();
+
+        let report = run(&mut interface, &config, &mut client, now).await;
+        assert_eq!((report.queued, report.sent, report.failed), (2, 0, 0));
+        let outbox = interface.db.outbound_messages(None, 10).await.unwrap();
+        assert_eq!(outbox[0].event_id, interface.fall);
+        assert_eq!(outbox[0].attempts, 1);
+        assert_eq!(outbox[0].ack_code.as_deref(), Some("AE"));
+        assert_eq!(
+            outbox[0].last_error.as_deref(),
+            Some("NACK AE: Unknown patient")
+        );
+        assert_eq!(
+            outbox[0].next_attempt_at,
+            now + chrono::Duration::seconds(30)
+        );
+        assert_eq!(outbox[1].attempts, 0);
+    }
+
+    // Nothing is sent until the retry delay has passed
+    #[actix_web::test]
+    async fn test_retry_waits() {
+        let mut interface = interface().await;
+        let (address, _) = mllp_listener(1).await;
+        let config = config(&address);
+        let mut client = MllpClient::new(address, config.ack_timeout);
+        let now = Utc::now();
+        run(&mut interface, &config, &mut client, now).await;
+        let report = run(&mut interface, &config, &mut client, now).await;
+        assert_eq!(report, OutboundReport::default());
+    }
+
+    // The retry is acknowledged and the queue follows, in order
+    #[actix_web::test]
+    async fn test_retry_delivers_in_order() {
+        let mut interface = interface().await;
+        let (address, received) = mllp_listener(1).await;
+        let config = config(&address);
+        let mut client = MllpClient::new(address, config.ack_timeout);
+        let now = Utc::now();
+        run(&mut interface, &config, &mut client, now).await;
+        let later = now + chrono::Duration::minutes(1);
+        let report = run(&mut interface, &config, &mut client, later).await;
+        assert_eq!(report.sent, 2);
+        let sent = interface.db.outbound_messages(Some("sent"), 10).await;
+        assert!(sent
+            .unwrap()
+            .iter()
+            .all(|m| m.ack_code.as_deref() == Some("AA")));
+
+        // The engine saw the detection twice (NACK, then ACK) and the nurse action once
+        let frames = received.lock().unwrap().clone();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0], frames[1]);
+        let fall = Message::parse(&frames[0]).unwrap();
+        assert_eq!(fall.control_id(), oru::control_id(interface.fall));
+        let action = Message::parse(&frames[2]).unwrap();
+        assert_eq!(action.control_id(), oru::control_id(interface.action));
+    }
+
+    // With the engine unreachable, a message fails after its last attempt and the queue moves on
+    #[actix_web::test]
+    async fn test_unreachable_engine() {
+        let mut interface = interface().await;
+        let address = unreachable().await;
+        let mut config = config(&address);
+        config.max_attempts = 2;
+        let mut client = MllpClient::new(address, config.ack_timeout);
+        let now = Utc::now();
+
+        let report = run(&mut interface, &config, &mut client, now).await;
+        assert_eq!((report.queued, report.sent, report.failed), (2, 0, 0));
+        let report = run(
+            &mut interface,
+            &config,
+            &mut client,
+            now + chrono::Duration::hours(1),
+        )
+        .await;
+        assert_eq!(report.failed, 1);
+        let failed = interface
+            .db
+            .outbound_messages(Some("failed"), 10)
+            .await
+            .unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].event_id, interface.fall);
+        assert_eq!(failed[0].attempts, 2);
+        assert!(failed[0]
+            .last_error
+            .as_deref()
+            .unwrap()
+            .starts_with("cannot connect"));
+        let pending = interface
+            .db
+            .outbound_messages(Some("pending"), 10)
+            .await
+            .unwrap();
+        assert_eq!(pending[0].event_id, interface.action);
+        assert_eq!(pending[0].attempts, 1);
+    }
+}
@@ -0,0 +1,577 @@
+use crate::logic::{
+    calculate_g_force, DetectionEvent, Detector, DetectorProfile, Explanation, FallDetector,
+    FallMetrics, RuleCheck, GRAVITY,
+};
+use crate::model::SensorData;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Window features a model is given, in input order (a `[1, 6]` float tensor).
+pub const FEATURES: [&str; 6] = [
+    "peak_g",
+    "min_g",
+    "mean_g",
+    "std_g",
+    "stillness_variance",
+    "orientation_change_deg",
+];
+
+/// Signal before the impact included in the window (free fall shows up here).
+pub const PRE_IMPACT_MS: i64 = 1000;
+
+const DEFAULT_THRESHOLD: f64 = 0.5;
+const DEFAULT_LATENCY_BUDGET_MS: u64 = 50;
+
+/// A trained fall classifier: the probability of a fall from one window's `FEATURES`.
+pub trait Classifier: Send + Sync {
+    fn predict(&self, features: &[f32]) -> Result<f64, String>;
+}
+
+/// **Model Spec**
+///
+/// The `model` section of a detector profile. `path` is an ONNX file (relative to the profile
+/// file) taking the window `FEATURES` and returning the fall probability, either as one value
+/// or as `[no-fall, fall]`. It is loaded once, at startup.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ModelSpec {
+    pub path: String,
+    #[serde(default = "default_threshold")]
+    pub threshold: f64, // Fall probability above which the window is a fall
+    #[serde(default = "default_latency_budget_ms")]
+    pub latency_budget_ms: u64, // Longest an inference may take before the rules decide instead
+    #[serde(skip)]
+    classifier: Option<Arc<dyn Classifier>>,
+}
+
+fn default_threshold() -> f64 {
+    DEFAULT_THRESHOLD
+}
+
+fn default_latency_budget_ms() -> u64 {
+    DEFAULT_LATENCY_BUDGET_MS
+}
+
+impl std::fmt::Debug for ModelSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ModelSpec")
+            .field("path", &self.path)
+            .field("threshold", &self.threshold)
+            .field("latency_budget_ms", &self.latency_budget_ms)
+            .field("loaded", &self.classifier.is_some())
+            .finish()
+    }
+}
+
+impl PartialEq for ModelSpec {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.threshold == other.threshold
+            && self.latency_budget_ms == other.latency_budget_ms
+    }
+}
+
+impl ModelSpec {
+    /// A spec for an already loaded classifier (e.g. one built in code rather than from a file).
+    pub fn with_classifier(
+        path: &str,
+        threshold: f64,
+        latency_budget_ms: u64,
+        classifier: Arc<dyn Classifier>,
+    ) -> Self {
+        Self {
+            path: path.to_string(),
+            threshold,
+            latency_budget_ms,
+            classifier: Some(classifier),
+        }
+    }
+
+    pub(crate) fn check(&self) -> Result<(), String> {
+        if !(self.threshold > 0.0 && self.threshold < 1.0) {
+            return Err(format!(
+                "model threshold must be between 0 and 1: {}",
+                self.threshold
+            ));
+        }
+        if self.latency_budget_ms == 0 {
+            return Err("model latency_budget_ms must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Loads the model, resolving `path` against `dir`.
+    pub(crate) fn load(&mut self, dir: &Path) -> Result<(), String> {
+        let path = dir.join(&self.path);
+        self.classifier = Some(load_classifier(&path)?);
+        Ok(())
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.classifier.is_some()
+    }
+
+    /// The impact rule and the model's verdict. The confidence is the probability of the
+    /// outcome the model gave (a fall, or no fall).
+    pub fn explain(
+        &self,
+        profile: &DetectorProfile,
+        g_force: f64,
+        probability: f64,
+    ) -> Explanation {
+        let mut rules = profile.explain_impact(g_force).rules;
+        let model = RuleCheck::new("model", ">", probability, self.threshold, "1");
+        let confidence = if model.fired {
+            probability
+        } else {
+            1.0 - probability
+        };
+        rules.push(model);
+        Explanation { rules, confidence }
+    }
+}
+
+fn load_classifier(path: &Path) -> Result<Arc<dyn Classifier>, String> {
+    Ok(Arc::new(onnx::OnnxClassifier::load(path)?))
+}
+
+/// **Window Features**
+///
+/// What a model sees of one validated impact: the acceleration magnitude (in G) over the
+/// window from `PRE_IMPACT_MS` before the impact to the verdict, and the rules' own metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct WindowFeatures {
+    pub peak_g: f64,
+    pub min_g: f64,
+    pub mean_g: f64,
+    pub std_g: f64,
+    pub stillness_variance: f64,
+    pub orientation_change_deg: f64,
+}
+
+impl WindowFeatures {
+    pub fn extract(window: &[SensorData], metrics: &FallMetrics) -> Self {
+        let g: Vec<f64> = window
+            .iter()
+            .map(|s| calculate_g_force(s.x, s.y, s.z) / GRAVITY)
+            .collect();
+        let count = g.len().max(1) as f64;
+        let mean_g = g.iter().sum::<f64>() / count;
+        let variance = g.iter().map(|v| (v - mean_g).powi(2)).sum::<f64>() / count;
+        Self {
+            peak_g: g.iter().copied().fold(0.0, f64::max),
+            min_g: g.iter().copied().reduce(f64::min).unwrap_or(0.0),
+            mean_g,
+            std_g: variance.sqrt(),
+            stillness_variance: metrics.stillness_variance,
+            orientation_change_deg: metrics.orientation_change_deg,
+        }
+    }
+
+    /// The model input, in `FEATURES` order.
+    pub fn to_vec(&self) -> Vec<f32> {
+        [
+            self.peak_g,
+            self.min_g,
+            self.mean_g,
+            self.std_g,
+            self.stillness_variance,
+            self.orientation_change_deg,
+        ]
+        .iter()
+        .map(|v| *v as f32)
+        .collect()
+    }
+}
+
+/// **Model Detector**
+///
+/// Runs next to the rules: the profile's impact threshold and validation window still start
+/// and time each verdict, then the classifier decides it from the window's features. An
+/// inference that fails or overruns the latency budget is logged and the rules' verdict
+/// stands, so a slow model never holds back an alert.
+pub struct ModelDetector {
+    rules: FallDetector,
+    model: ModelSpec,
+    classifier: Arc<dyn Classifier>,
+    recent: VecDeque<(i64, SensorData)>, // Samples still inside the longest window
+}
+
+impl ModelDetector {
+    /// `None` unless the profile has a loaded model.
+    pub fn new(profile: DetectorProfile) -> Option<Self> {
+        let model = profile.model.clone()?;
+        let classifier = model.classifier.clone()?;
+        Some(Self {
+            rules: FallDetector::with_profile(profile),
+            model,
+            classifier,
+            recent: VecDeque::new(),
+        })
+    }
+
+    /// The fall probability, or an error when the model fails or overruns the budget. The model
+    /// runs on the blocking pool, so the budget holds whatever it does without stalling the
+    /// connection's task; a late result is dropped.
+    async fn infer(&self, features: Vec<f32>) -> Result<f64, String> {
+        let budget = self.model.latency_budget_ms;
+        let classifier = self.classifier.clone();
+        let inference = tokio::task::spawn_blocking(move || classifier.predict(&features));
+        match tokio::time::timeout(Duration::from_millis(budget), inference).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err("inference panicked".to_string()),
+            Err(_) => Err(format!("no result within the {} ms budget", budget)),
+        }
+    }
+
+    async fn decide(&self, rules: DetectionEvent, now: i64) -> DetectionEvent {
+        let (g_force, metrics) = match &rules {
+            DetectionEvent::CriticalFall {
+                g_force, metrics, ..
+            }
+            | DetectionEvent::NearMiss {
+                g_force, metrics, ..
+            } => (*g_force, *metrics),
+            DetectionEvent::Validating { .. } => return rules,
+        };
+        let start = now - metrics.validation_ms - PRE_IMPACT_MS;
+        let window: Vec<SensorData> = self
+            .recent
+            .iter()
+            .filter(|(at, _)| *at >= start)
+            .map(|(_, s)| s.clone())
+            .collect();
+        let features = WindowFeatures::extract(&window, &metrics);
+
+        let probability = match self.infer(features.to_vec()).await {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!(
+                    "⚠️ Model {} ({}): {}; the rules decide",
+                    self.profile().name,
+                    self.model.path,
+                    e
+                );
+                return rules;
+            }
+        };
+        let explanation = self.model.explain(self.profile(), g_force, probability);
+        if probability > self.model.threshold {
+            DetectionEvent::CriticalFall {
+                g_force,
+                metrics,
+                explanation,
+            }
+        } else {
+            DetectionEvent::NearMiss {
+                g_force,
+                metrics,
+                explanation,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Detector for ModelDetector {
+    fn profile(&self) -> &DetectorProfile {
+        self.rules.profile()
+    }
+
+    async fn process_at(&mut self, data: SensorData, now: i64) -> Option<DetectionEvent> {
+        let keep = self.profile().validation_ms + PRE_IMPACT_MS;
+        while self.recent.front().is_some_and(|(at, _)| *at < now - keep) {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((now, data.clone()));
+
+        let event = self.rules.process_at(data, now).await?;
+        Some(self.decide(event, now).await)
+    }
+}
+
+mod onnx {
+    use super::{Classifier, FEATURES};
+    use std::path::Path;
+    use tract_onnx::prelude::*;
+
+    /// An ONNX model run on the CPU by tract, optimised for one `[1, FEATURES]` window.
+    pub struct OnnxClassifier {
+        plan: TypedSimplePlan<TypedModel>,
+    }
+
+    impl OnnxClassifier {
+        /// Loads and optimises the model, then checks it with one inference so a model that
+        /// does not fit fails at startup.
+        pub fn load(path: &Path) -> Result<Self, String> {
+            let plan = tract_onnx::onnx()
+                .model_for_path(path)
+                .and_then(|m| m.with_input_fact(0, f32::fact([1, FEATURES.len()]).into()))
+                .and_then(|m| m.into_optimized())
+                .and_then(|m| m.into_runnable())
+                .map_err(|e| format!("cannot load model {}: {}", path.display(), e))?;
+            let classifier = Self { plan };
+            classifier
+                .predict(&[0.0; FEATURES.len()])
+                .map_err(|e| format!("model {}: {}", path.display(), e))?;
+            Ok(classifier)
+        }
+    }
+
+    impl Classifier for OnnxClassifier {
+        fn predict(&self, features: &[f32]) -> Result<f64, String> {
+            let input =
+                Tensor::from_shape(&[1, features.len()], features).map_err(|e| e.to_string())?;
+            let outputs = self
+                .plan
+                .run(tvec!(input.into()))
+                .map_err(|e| format!("inference failed: {}", e))?;
+            let output = outputs
+                .first()
+                .ok_or("the model has no output")?
+                .as_slice::<f32>()
+                .map_err(|e| format!("the output is not float: {}", e))?;
+            // One value is P(fall); with two, they are [P(no fall), P(fall)]
+            match output {
+                [p] | [_, p] if (0.0..=1.0).contains(p) => Ok(*p as f64),
+                _ => Err(format!(
+                    "expected a fall probability, got {} values",
+                    output.len()
+                )),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluation::{self, Recording};
+    use std::sync::{mpsc, Mutex};
+
+    // A logistic regression over the window features, P = sigmoid(0.1 × (orientation_change_deg
+    // - 45)), run by a profile with a 0.6 threshold
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/orientation.json"
+    );
+
+    // Helper: 1 s upright, a 2.5 g impact, then `after` for the validation window, at 50 Hz
+    // (timestamps in seconds)
+    fn impact_samples(after: (f64, f64, f64)) -> Vec<SensorData> {
+        (0..=160)
+            .map(|i: i64| {
+                let (x, y, z) = match i {
+                    ..50 => (0.0, 0.0, 9.8),
+                    50 => (24.5, 0.0, 0.0),
+                    _ => after,
+                };
+                SensorData {
+                    x,
+                    y,
+                    z,
+                    timestamp: i as f64 * 0.02,
+                    wifi: 0,
+                    temp: 0.0,
+                    battery: None,
+                }
+            })
+            .collect()
+    }
+
+    // Helper: the verdicts of a detector over samples, timed by their timestamps
+    async fn verdicts(detector: &mut dyn Detector, samples: &[SensorData]) -> Vec<DetectionEvent> {
+        let mut verdicts = Vec::new();
+        for s in samples {
+            let at = (s.timestamp * 1000.0).round() as i64;
+            match detector.process_at(s.clone(), at).await {
+                Some(DetectionEvent::Validating { .. }) | None => {}
+                Some(event) => verdicts.push(event),
+            }
+        }
+        verdicts
+    }
+
+    // Helper: a candidate profile deciding with `classifier` in a 20 ms budget
+    fn candidate(classifier: Arc<dyn Classifier>) -> DetectorProfile {
+        DetectorProfile {
+            name: "candidate".to_string(),
+            model: Some(ModelSpec::with_classifier("test.onnx", 0.5, 20, classifier)),
+            ..DetectorProfile::default()
+        }
+    }
+
+    // A model named by a profile is loaded at startup, with the default budget
+    #[test]
+    fn test_profile_loads_model() {
+        let profile = DetectorProfile::load(FIXTURE).unwrap();
+        let model = profile.model.as_ref().unwrap();
+        assert!(model.is_loaded());
+        assert_eq!((model.threshold, model.latency_budget_ms), (0.6, 50));
+    }
+
+    // The model decides a validated impact and reports its probability as the confidence
+    #[actix_web::test]
+    async fn test_model_decides_impact() {
+        let profile = DetectorProfile::load(FIXTURE).unwrap();
+        let probability = 1.0 / (1.0 + (-4.5f64).exp());
+
+        // Lying down after the impact: a fall to the model and to the rules
+        let lying = impact_samples((9.8, 0.0, 0.0));
+        let [DetectionEvent::CriticalFall {
+            g_force,
+            metrics,
+            explanation,
+        }] = &verdicts(profile.detector().as_mut(), &lying).await[..]
+        else {
+            panic!("expected one fall");
+        };
+        assert_eq!(*g_force, 2.5);
+        assert!((metrics.orientation_change_deg - 90.0).abs() < 1e-6);
+        assert_eq!(explanation.fired(), vec!["impact", "model"]);
+        let rule = &explanation.rules[1];
+        assert_eq!((rule.comparator.as_str(), rule.threshold), (">", 0.6));
+        assert!((rule.measured - probability).abs() < 1e-4);
+        assert!((explanation.confidence - probability).abs() < 1e-4);
+
+        // Standing still after a bump: the rules call it a fall, the model a near miss
+        let standing = impact_samples((0.0, 0.0, 9.8));
+        let rules = verdicts(&mut FallDetector::new(), &standing).await;
+        assert!(matches!(rules[..], [DetectionEvent::CriticalFall { .. }]));
+        let [DetectionEvent::NearMiss { explanation, .. }] =
+            &verdicts(profile.detector().as_mut(), &standing).await[..]
+        else {
+            panic!("expected one near miss");
+        };
+        assert_eq!(explanation.fired(), vec!["impact"]);
+        assert!((explanation.confidence - probability).abs() < 1e-4);
+    }
+
+    // A model profile is replayed offline like any other
+    #[actix_web::test]
+    async fn test_model_offline_evaluation() {
+        let profile = DetectorProfile::load(FIXTURE).unwrap();
+        let recordings = vec![
+            Recording {
+                name: "lying".to_string(),
+                fall: true,
+                fall_at: Some(1.0),
+                samples: impact_samples((9.8, 0.0, 0.0)),
+            },
+            Recording {
+                name: "standing".to_string(),
+                fall: false,
+                fall_at: None,
+                samples: impact_samples((0.0, 0.0, 9.8)),
+            },
+        ];
+        let report = evaluation::evaluate(&profile, &recordings).await;
+        assert_eq!(
+            (report.sensitivity, report.specificity),
+            (Some(1.0), Some(1.0))
+        );
+    }
+
+    // A model that cannot be read fails at startup
+    #[test]
+    fn test_broken_model_fails_at_startup() {
+        let dir = std::env::temp_dir().join(format!("fallguard-onnx-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("broken.onnx"), b"not a model").unwrap();
+        let broken = dir.join("broken.json");
+        std::fs::write(
+            &broken,
+            r#"{ "name": "broken", "impact_threshold_g": 1.6, "stillness_threshold_variance": 3.5,
+                 "validation_ms": 2000, "model": { "path": "broken.onnx" } }"#,
+        )
+        .unwrap();
+        let err = DetectorProfile::load(broken.to_str().unwrap()).unwrap_err();
+        assert!(err.contains("cannot load model"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // An inference still running when the budget runs out leaves the verdict to the rules
+    #[actix_web::test]
+    async fn test_model_over_budget() {
+        // Holds every inference until released, so only the budget can end it
+        struct Held(Mutex<mpsc::Receiver<()>>);
+        impl Classifier for Held {
+            fn predict(&self, _: &[f32]) -> Result<f64, String> {
+                let _ = self.0.lock().unwrap().recv();
+                Ok(0.0)
+            }
+        }
+        let (release, held) = mpsc::channel();
+        let profile = candidate(Arc::new(Held(Mutex::new(held))));
+        let lying = impact_samples((9.8, 0.0, 0.0));
+        let decided = verdicts(profile.detector().as_mut(), &lying).await;
+        release.send(()).unwrap();
+        let [DetectionEvent::CriticalFall { explanation, .. }] = &decided[..] else {
+            panic!("expected the rules' verdict");
+        };
+        assert_eq!(explanation.fired(), vec!["impact", "stillness"]);
+    }
+
+    // A model that fails leaves the verdict to the rules; one that answers decides
+    #[actix_web::test]
+    async fn test_model_failure() {
+        struct Broken;
+        impl Classifier for Broken {
+            fn predict(&self, _: &[f32]) -> Result<f64, String> {
+                Err("bad input".to_string())
+            }
+        }
+        struct Never;
+        impl Classifier for Never {
+            fn predict(&self, features: &[f32]) -> Result<f64, String> {
+                assert_eq!(features.len(), FEATURES.len());
+                Ok(0.1)
+            }
+        }
+        let lying = impact_samples((9.8, 0.0, 0.0));
+        let rules = verdicts(candidate(Arc::new(Broken)).detector().as_mut(), &lying).await;
+        let [DetectionEvent::CriticalFall { explanation, .. }] = &rules[..] else {
+            panic!("expected the rules' verdict");
+        };
+        assert_eq!(explanation.fired(), vec!["impact", "stillness"]);
+        let decided = verdicts(candidate(Arc::new(Never)).detector().as_mut(), &lying).await;
+        assert!(matches!(decided[..], [DetectionEvent::NearMiss { .. }]));
+    }
+
+    // The window runs from a second before the impact to the verdict
+    #[test]
+    fn test_window_features() {
+        let metrics = FallMetrics {
+            stillness_variance: 0.0,
+            validation_ms: 2000,
+            orientation_change_deg: 90.0,
+        };
+        let features = WindowFeatures::extract(&impact_samples((9.8, 0.0, 0.0)), &metrics);
+        assert_eq!((features.peak_g, features.min_g), (2.5, 1.0));
+        assert_eq!(features.to_vec().len(), FEATURES.len());
+    }
+
+    // Model settings are checked when the profile is read; one read without its file is not
+    // loaded, so the rules run
+    #[actix_web::test]
+    async fn test_model_settings() {
+        let with_model = |model: &str| {
+            DetectorProfile::parse(&format!(
+                r#"{{ "name": "m", "impact_threshold_g": 1.6, "stillness_threshold_variance": 3.5,
+                      "validation_ms": 2000, "model": {} }}"#,
+                model
+            ))
+        };
+        let parsed = with_model(r#"{ "path": "m.onnx" }"#).unwrap();
+        let spec = parsed.model.as_ref().unwrap();
+        assert_eq!((spec.threshold, spec.latency_budget_ms), (0.5, 50));
+        assert!(!spec.is_loaded());
+        assert!(with_model(r#"{ "path": "m.onnx", "threshold": 1.5 }"#).is_err());
+        assert!(with_model(r#"{ "path": "m.onnx", "latency_budget_ms": 0 }"#).is_err());
+        let rules = verdicts(parsed.detector().as_mut(), &impact_samples((9.8, 0.0, 0.0))).await;
+        assert!(matches!(rules[..], [DetectionEvent::CriticalFall { .. }]));
+    }
+}
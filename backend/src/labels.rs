@@ -0,0 +1,298 @@
+use crate::evaluation::Recording;
+use crate::model::{Alert, SensorData, Waveform};
+use crate::storage::{LabelQuery, NewLabel, Repository, StorageResult};
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Most labels one export includes.
+pub const EXPORT_LIMIT: i64 = 100_000;
+
+/// Waveforms are fetched this many labels at a time (keeps the `IN` list within bind limits).
+const WAVEFORM_CHUNK: usize = 1_000;
+
+/// Exports are shifted back in time by a random number of days in this range.
+const SHIFT_DAYS: std::ops::RangeInclusive<i64> = 365..=3650;
+
+/// The label a nurse command gives the detection behind the alert, if it is a verdict.
+pub fn label_for(command: &str) -> Option<&'static str> {
+    match command {
+        "CONFIRM_FALL" => Some("fall"),
+        "CANCEL_ALERT" => Some("no-fall"),
+        _ => None,
+    }
+}
+
+/// Keeps a nurse verdict on an alert as the ground-truth label of its detection. Other commands
+/// (e.g. `RESET_SYSTEM`) label nothing.
+pub async fn record(db: &dyn Repository, alert: &Alert, command: &str, now: DateTime<Utc>) {
+    let Some(label) = label_for(command) else {
+        return;
+    };
+    let label = NewLabel {
+        event_id: alert.event_id,
+        alert_id: alert.id,
+        label: label.to_string(),
+        command: command.to_string(),
+        labelled_at: now,
+    };
+    if let Err(e) = db.record_label(&label).await {
+        eprintln!("❌ Failed to label event {}: {}", alert.event_id, e);
+    }
+}
+
+/// A random shift for one export (whole days, so time of day is kept).
+pub fn random_shift() -> Duration {
+    Duration::days(rand::rng().random_range(SHIFT_DAYS))
+}
+
+/// **Labelled Dataset**
+///
+/// Labelled waveforms in the format `evaluate` reads. De-identified: recordings carry no
+/// patient, device, ward or database ids, and every time is moved back by the same `shift`
+/// (so intervals between recordings are kept).
+#[derive(Debug, Clone, Serialize)]
+pub struct LabelledDataset {
+    pub falls: usize,
+    pub non_falls: usize,
+    pub skipped: usize, // Labels whose waveform is no longer stored
+    pub recordings: Vec<Recording>,
+}
+
+/// Builds the dataset from the labels matching `query`, oldest first.
+pub async fn export(
+    db: &dyn Repository,
+    query: &LabelQuery,
+    shift: Duration,
+) -> StorageResult<LabelledDataset> {
+    let mut labels = db.query_labels(query).await?;
+    labels.reverse();
+    let seconds = |at: DateTime<Utc>| (at - shift).timestamp_millis() as f64 / 1000.0;
+
+    let mut recordings = Vec::new();
+    let mut skipped = 0;
+    for page in labels.chunks(WAVEFORM_CHUNK) {
+        let event_ids: Vec<i32> = page.iter().map(|l| l.event_id).collect();
+        let waveforms: HashMap<i32, Waveform> = db
+            .get_waveforms(&event_ids)
+            .await?
+            .into_iter()
+            .map(|w| (w.event_id, w))
+            .collect();
+        for label in page {
+            let Some(waveform) = waveforms.get(&label.event_id) else {
+                skipped += 1;
+                continue;
+            };
+            let fall = label.label == "fall";
+            let samples = waveform
+                .points
+                .iter()
+                .map(|p| SensorData {
+                    x: p.x,
+                    y: p.y,
+                    z: p.z,
+                    timestamp: seconds(waveform.impact_at + Duration::milliseconds(p.offset_ms)),
+                    wifi: 0,
+                    temp: 0.0,
+                    battery: None,
+                })
+                .collect();
+            recordings.push(Recording {
+                name: format!("{}-{:04}", label.label, recordings.len() + 1),
+                fall,
+                fall_at: fall.then(|| seconds(waveform.impact_at)),
+                samples,
+            });
+        }
+    }
+    Ok(LabelledDataset {
+        falls: recordings.iter().filter(|r| r.fall).count(),
+        non_falls: recordings.iter().filter(|r| !r.fall).count(),
+        skipped,
+        recordings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluation;
+    use crate::logic::{calculate_g_force, DetectorProfile};
+    use crate::model::WaveformPoint;
+    use crate::test_support::{answered_fall, critical_event, memory_state};
+    use crate::AppState;
+    use actix_web::web;
+    use chrono::SubsecRound;
+
+    // Helper: 1 s before an impact to the end of validation at 50 Hz: lying still, or sitting
+    // up again
+    fn waveform(event_id: i32, impact_at: DateTime<Utc>, still: bool) -> Waveform {
+        Waveform {
+            event_id,
+            device_id: "pi-01".to_string(),
+            impact_at,
+            started_at: impact_at - Duration::seconds(1),
+            ended_at: impact_at + Duration::milliseconds(2100),
+            points: (-50..=105)
+                .map(|i: i64| {
+                    let (x, z) = match i {
+                        ..0 => (0.0, 9.8),
+                        0 => (24.5, 0.0),
+                        _ if still => (9.8, 0.0),
+                        _ => (if i % 2 == 0 { 8.0 } else { -8.0 }, 9.8),
+                    };
+                    WaveformPoint {
+                        offset_ms: i * 20,
+                        x,
+                        y: 0.0,
+                        z,
+                        g_force: calculate_g_force(x, 0.0, z) / 9.8,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    // Helper: a fall confirmed with its waveform (impact two hours ago, returned), a sit-down
+    // cancelled with its waveform an hour later, and an alert cancelled without a waveform
+    async fn labelled() -> (web::Data<AppState>, DateTime<Utc>) {
+        let state = memory_state();
+        let first_impact = Utc::now().trunc_subsecs(3) - Duration::hours(2);
+        for (command, impact) in [
+            ("CONFIRM_FALL", Some((first_impact, true))),
+            (
+                "CANCEL_ALERT",
+                Some((first_impact + Duration::hours(1), false)),
+            ),
+            ("CANCEL_ALERT", None),
+        ] {
+            let alert = answered_fall(&state, command).await;
+            if let Some((impact_at, still)) = impact {
+                let waveform = waveform(alert.event_id, impact_at, still);
+                state.db.save_waveform(&waveform).await.unwrap();
+            }
+        }
+        (state, first_impact)
+    }
+
+    // Helper: every label exported, shifted back 400 days to check the times
+    async fn exported(state: &web::Data<AppState>) -> LabelledDataset {
+        let all = LabelQuery {
+            limit: EXPORT_LIMIT,
+            ..LabelQuery::default()
+        };
+        export(state.db.as_ref(), &all, Duration::days(400))
+            .await
+            .unwrap()
+    }
+
+    // Confirming is a fall, cancelling is not; other commands are no verdict
+    #[test]
+    fn test_label_for() {
+        assert_eq!(label_for("CONFIRM_FALL"), Some("fall"));
+        assert_eq!(label_for("CANCEL_ALERT"), Some("no-fall"));
+        assert_eq!(label_for("RESET_SYSTEM"), None);
+    }
+
+    // A shift is whole days, at least a year back
+    #[test]
+    fn test_random_shift() {
+        let shift = random_shift();
+        assert!(SHIFT_DAYS.contains(&shift.num_days()));
+        assert_eq!(shift, Duration::days(shift.num_days()));
+    }
+
+    // A verdict labels the alert's detection
+    #[actix_web::test]
+    async fn test_record() {
+        let state = memory_state();
+        let alert = answered_fall(&state, "CONFIRM_FALL").await;
+        let all = LabelQuery {
+            limit: 10,
+            ..LabelQuery::default()
+        };
+        let labels = state.db.query_labels(&all).await.unwrap();
+        assert_eq!(labels.len(), 1);
+        assert_eq!(labels[0].label, "fall");
+        assert_eq!(labels[0].command, "CONFIRM_FALL");
+        assert_eq!(
+            (labels[0].alert_id, labels[0].event_id),
+            (alert.id, alert.event_id)
+        );
+    }
+
+    // Resetting is not a verdict
+    #[actix_web::test]
+    async fn test_record_reset() {
+        let state = memory_state();
+        let db = state.db.as_ref();
+        let event = db.insert_event(critical_event(5, 2.5)).await.unwrap();
+        let alert = db.open_alert(event.id, event.detected_at).await.unwrap();
+        record(db, &alert, "RESET_SYSTEM", Utc::now()).await;
+        let all = LabelQuery {
+            limit: 10,
+            ..LabelQuery::default()
+        };
+        assert!(db.query_labels(&all).await.unwrap().is_empty());
+    }
+
+    // Labels whose waveform is no longer stored are counted but left out
+    #[actix_web::test]
+    async fn test_export_counts() {
+        let (state, _) = labelled().await;
+        let dataset = exported(&state).await;
+        assert_eq!(
+            (dataset.falls, dataset.non_falls, dataset.skipped),
+            (1, 1, 1)
+        );
+    }
+
+    // A fall is exported with its samples and onset, every time moved back by the shift
+    #[actix_web::test]
+    async fn test_export_fall() {
+        let (state, first_impact) = labelled().await;
+        let fall = &exported(&state).await.recordings[0];
+        assert_eq!((fall.name.as_str(), fall.fall), ("fall-0001", true));
+        let shifted = (first_impact - Duration::days(400)).timestamp_millis() as f64 / 1000.0;
+        assert_eq!(fall.fall_at, Some(shifted));
+        assert_eq!(fall.samples.len(), 156);
+        assert_eq!(fall.samples[50].timestamp, shifted);
+        assert_eq!(fall.samples[50].x, 24.5);
+    }
+
+    // A cancelled alert is exported as a recording without a fall
+    #[actix_web::test]
+    async fn test_export_non_fall() {
+        let (state, _) = labelled().await;
+        let cancelled = &exported(&state).await.recordings[1];
+        assert_eq!(
+            (cancelled.name.as_str(), cancelled.fall, cancelled.fall_at),
+            ("no-fall-0002", false, None)
+        );
+    }
+
+    // The export carries no ids, patient, device or ward
+    #[actix_web::test]
+    async fn test_export_deidentified() {
+        let (state, _) = labelled().await;
+        let json = serde_json::to_string(&exported(&state).await).unwrap();
+        for identifying in ["P-1001", "pi-01", "ICU", "event_id", "alert_id"] {
+            assert!(!json.contains(identifying), "{} leaked", identifying);
+        }
+    }
+
+    // The evaluation tool reads the export: the confirmed fall is caught, the cancelled one is not
+    #[actix_web::test]
+    async fn test_export_evaluates() {
+        let (state, _) = labelled().await;
+        let json = serde_json::to_string(&exported(&state).await).unwrap();
+        let recordings = evaluation::parse_json("export", &json).unwrap();
+        let report = evaluation::evaluate(&DetectorProfile::default(), &recordings).await;
+        assert_eq!(
+            (report.sensitivity, report.specificity),
+            (Some(1.0), Some(1.0))
+        );
+    }
+}
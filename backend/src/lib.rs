@@ -0,0 +1,58 @@
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+// Internal modules
+pub mod api;
+pub mod assessment;
+pub mod evaluation;
+pub mod fhir;
+#[cfg(feature = "hl7")]
+pub mod hl7;
+#[cfg(feature = "onnx")]
+pub mod inference;
+pub mod labels;
+pub mod logic;
+pub mod model;
+pub mod retention;
+pub mod risk;
+pub mod shadow;
+pub mod storage;
+pub mod subscriptions;
+pub mod telemetry;
+pub mod tuning;
+pub mod websockets;
+
+#[cfg(test)]
+mod test_support;
+
+use crate::assessment::AssessmentForm;
+use crate::fhir::bulk::ExportJobs;
+use crate::logic::DetectorProfile;
+use crate::risk::RiskConfig;
+use crate::storage::Repository;
+use crate::subscriptions::Notifier;
+use crate::telemetry::TelemetryConfig;
+
+/// **Global Application State**
+///
+/// This struct holds the resources that are shared across all connected clients.
+/// - `db`: The configured storage backend (Postgres, SQLite or in-memory) for history logs.
+/// - `tx`: The "Radio Station" (Broadcast Channel) used to send real-time sensor data to the frontend.
+/// - `telemetry`: Waveform capture settings.
+/// - `notifier`: Triggers FHIR subscription notifications (delivered in the background).
+/// - `exports`: FHIR Bulk Data export jobs and where their files are written.
+/// - `assessment_form`: The post-fall assessment form nurses fill in after a confirmed fall.
+/// - `risk`: How patients' fall risk scores are computed (look-back window, ward time).
+/// - `detector`: The thresholds every connection's fall detector runs with.
+/// - `shadow`: Candidate detector profiles run next to it in shadow mode (they never alert).
+pub struct AppState {
+    pub db: Arc<dyn Repository>,
+    pub tx: broadcast::Sender<String>,
+    pub telemetry: TelemetryConfig,
+    pub notifier: Notifier,
+    pub exports: ExportJobs,
+    pub assessment_form: Arc<AssessmentForm>,
+    pub risk: RiskConfig,
+    pub detector: DetectorProfile,
+    pub shadow: Vec<DetectorProfile>,
+}
@@ -1,15 +1,317 @@
+#[cfg(feature = "onnx")]
+use crate::inference::{ModelDetector, ModelSpec};
 use crate::model::SensorData;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
+#[cfg(feature = "onnx")]
+use std::path::Path;
 
 const IMPACT_THRESHOLD_G: f64 = 1.6;
 const STILLNESS_THRESHOLD_VARIANCE: f64 = 3.5; // Relaxed to allow post-fall movement
-const BUFFER_DURATION_MS: i64 = 2000; // 2 seconds
+pub const BUFFER_DURATION_MS: i64 = 2000; // 2 seconds
+pub const GRAVITY: f64 = 9.8; // m/s^2 per G
+const CONFIDENCE_SLOPE: f64 = 2.0; // How fast confidence grows with the distance from a threshold
+
+/// Magnitude of the acceleration vector, in the same unit as the inputs.
+pub fn calculate_g_force(x: f64, y: f64, z: f64) -> f64 {
+    (x.powi(2) + y.powi(2) + z.powi(2)).sqrt()
+}
+
+/// True when an impact (in G) is strong enough to start validating a fall.
+pub fn is_fall(g_force: f64) -> bool {
+    g_force > IMPACT_THRESHOLD_G
+}
+
+/// **Fall Metrics**
+///
+/// What the detector measured while validating an impact, stored with the event and in the
+/// outcome log (for near misses as well as falls).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct FallMetrics {
+    pub stillness_variance: f64,     // (m/s²)² over the validation buffer
+    pub validation_ms: i64,          // Impact to verdict
+    pub orientation_change_deg: f64, // Last sample before the impact vs. the resting orientation
+}
+
+/// One rule the detector evaluated: the value it measured against the rule's threshold.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleCheck {
+    pub rule: String,       // "impact" (peak G above) | "stillness" (variance below)
+    pub comparator: String, // ">" or "<": how `measured` must compare to `threshold` to fire
+    pub measured: f64,
+    pub threshold: f64,
+    pub unit: String, // UCUM code
+    pub fired: bool,
+}
+
+impl RuleCheck {
+    pub(crate) fn new(
+        rule: &str,
+        comparator: &str,
+        measured: f64,
+        threshold: f64,
+        unit: &str,
+    ) -> Self {
+        let fired = match comparator {
+            ">" => measured > threshold,
+            _ => measured < threshold,
+        };
+        Self {
+            rule: rule.to_string(),
+            comparator: comparator.to_string(),
+            measured,
+            threshold,
+            unit: unit.to_string(),
+            fired,
+        }
+    }
+
+    /// 0.5 on the threshold, approaching 1 as the value moves away from it (either way).
+    fn certainty(&self) -> f64 {
+        let margin = (self.measured - self.threshold).abs() / self.threshold;
+        0.5 + 0.5 * (CONFIDENCE_SLOPE * margin).tanh()
+    }
+}
+
+/// **Detection Explanation**
+///
+/// Why the detector reported an outcome: every rule it evaluated and whether it fired, and a
+/// confidence from 0.5 (a value right on its threshold) to 1, set by the least clear-cut rule.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Explanation {
+    pub rules: Vec<RuleCheck>,
+    pub confidence: f64,
+}
+
+impl Explanation {
+    pub fn new(rules: Vec<RuleCheck>) -> Self {
+        let confidence = rules.iter().map(RuleCheck::certainty).fold(1.0, f64::min);
+        Self { rules, confidence }
+    }
+
+    /// The impact rule alone (an impact that started validating), under the default profile.
+    pub fn impact(g_force: f64) -> Self {
+        DetectorProfile::default().explain_impact(g_force)
+    }
+
+    /// The impact and stillness rules behind a verdict, under the default profile.
+    pub fn verdict(g_force: f64, metrics: &FallMetrics) -> Self {
+        DetectorProfile::default().explain_verdict(g_force, metrics)
+    }
+
+    /// Names of the rules that fired.
+    pub fn fired(&self) -> Vec<&str> {
+        self.rules
+            .iter()
+            .filter(|r| r.fired)
+            .map(|r| r.rule.as_str())
+            .collect()
+    }
+}
+
+/// **Detector Profile**
+///
+/// The thresholds a `FallDetector` runs with. The default profile is the one used on live
+/// traffic; others are read from JSON files in the same format (e.g. to replay labelled
+/// recordings through candidate thresholds with the `evaluate` binary). A profile with a
+/// `model` has a trained classifier decide each validated impact instead of the stillness rule
+/// (with the `onnx` feature only).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectorProfile {
+    pub name: String,
+    pub impact_threshold_g: f64, // Peak G that starts validating
+    pub stillness_threshold_variance: f64, // (m/s²)² below which the wearer is "still"
+    pub validation_ms: i64,      // Length of the validation window
+    #[cfg(feature = "onnx")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<ModelSpec>,
+}
+
+impl Default for DetectorProfile {
+    fn default() -> Self {
+        Self::rules(
+            "default",
+            IMPACT_THRESHOLD_G,
+            STILLNESS_THRESHOLD_VARIANCE,
+            BUFFER_DURATION_MS,
+        )
+    }
+}
+
+impl DetectorProfile {
+    /// Checks the profile could be loaded: named, with positive thresholds and window.
+    pub fn check(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("the profile needs a name".to_string());
+        }
+        if !(self.impact_threshold_g > 0.0 && self.stillness_threshold_variance > 0.0) {
+            return Err("thresholds must be positive".to_string());
+        }
+        if self.validation_ms <= 0 {
+            return Err(format!(
+                "validation_ms must be positive: {}",
+                self.validation_ms
+            ));
+        }
+        #[cfg(feature = "onnx")]
+        if let Some(model) = &self.model {
+            model.check()?;
+        }
+        Ok(())
+    }
+
+    /// A profile deciding with the threshold rules alone.
+    pub fn rules(
+        name: &str,
+        impact_threshold_g: f64,
+        stillness_threshold_variance: f64,
+        validation_ms: i64,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            impact_threshold_g,
+            stillness_threshold_variance,
+            validation_ms,
+            #[cfg(feature = "onnx")]
+            model: None,
+        }
+    }
+
+    /// The profile named by `DETECTOR_PROFILE` (a JSON file, e.g. written by `evaluate tune`),
+    /// else the default.
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("DETECTOR_PROFILE") {
+            Ok(path) => Self::load(&path).map_err(|e| format!("DETECTOR_PROFILE: {}", e)),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Candidate profiles to run in shadow mode next to `production`, from the comma-separated
+    /// files in `SHADOW_PROFILES` (none when unset). Every detector needs its own name.
+    pub fn shadow_from_env(production: &DetectorProfile) -> Result<Vec<Self>, String> {
+        let Ok(paths) = std::env::var("SHADOW_PROFILES") else {
+            return Ok(Vec::new());
+        };
+        let mut candidates: Vec<Self> = Vec::new();
+        for path in paths.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let candidate = Self::load(path).map_err(|e| format!("SHADOW_PROFILES: {}", e))?;
+            if candidate.name == production.name
+                || candidates.iter().any(|c| c.name == candidate.name)
+            {
+                return Err(format!(
+                    "SHADOW_PROFILES: {}: another detector is already named {:?}",
+                    path, candidate.name
+                ));
+            }
+            candidates.push(candidate);
+        }
+        Ok(candidates)
+    }
+
+    /// Reads a profile and checks its thresholds are usable.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let profile: Self = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        profile.check()?;
+        #[cfg(not(feature = "onnx"))]
+        if serde_json::from_str::<serde_json::Value>(text).is_ok_and(|v| v.get("model").is_some()) {
+            return Err("the profile has a model, but this build has no onnx feature".to_string());
+        }
+        Ok(profile)
+    }
+
+    /// Reads a profile file and loads its model, if any (relative to the file).
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        let profile = Self::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+        #[cfg(feature = "onnx")]
+        let profile = {
+            let mut profile = profile;
+            if let Some(model) = profile.model.as_mut() {
+                let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+                model.load(dir).map_err(|e| format!("{}: {}", path, e))?;
+            }
+            profile
+        };
+        Ok(profile)
+    }
+
+    /// A fresh detector running this profile: the model when one is loaded, else the rules.
+    pub fn detector(&self) -> Box<dyn Detector> {
+        #[cfg(feature = "onnx")]
+        if let Some(detector) = ModelDetector::new(self.clone()) {
+            return Box::new(detector);
+        }
+        Box::new(FallDetector::with_profile(self.clone()))
+    }
+
+    pub fn explain_impact(&self, g_force: f64) -> Explanation {
+        Explanation::new(vec![self.impact_rule(g_force)])
+    }
+
+    pub fn explain_verdict(&self, g_force: f64, metrics: &FallMetrics) -> Explanation {
+        Explanation::new(vec![
+            self.impact_rule(g_force),
+            RuleCheck::new(
+                "stillness",
+                "<",
+                metrics.stillness_variance,
+                self.stillness_threshold_variance,
+                "m2/s4",
+            ),
+        ])
+    }
+
+    fn impact_rule(&self, g_force: f64) -> RuleCheck {
+        RuleCheck::new("impact", ">", g_force, self.impact_threshold_g, "[g]")
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum DetectionEvent {
-    Validating,
-    CriticalFall { g_force: f64 },
-    NearMiss,
+    Validating {
+        g_force: f64,
+        explanation: Explanation,
+    },
+    CriticalFall {
+        g_force: f64,
+        metrics: FallMetrics,
+        explanation: Explanation,
+    },
+    NearMiss {
+        g_force: f64,
+        metrics: FallMetrics,
+        explanation: Explanation,
+    },
+}
+
+/// **Detector**
+///
+/// Turns one connection's samples into outcomes. `FallDetector` decides with threshold rules,
+/// `inference::ModelDetector` with a trained classifier. Async, so a detector that waits on a
+/// model does so without holding a runtime thread.
+#[async_trait]
+pub trait Detector: Send {
+    fn profile(&self) -> &DetectorProfile;
+
+    /// Processes a sample received at `now` (epoch ms), e.g. when replaying a recording.
+    async fn process_at(&mut self, data: SensorData, now: i64) -> Option<DetectionEvent>;
+
+    /// Processes a live sample, timed by the server clock.
+    async fn process(&mut self, data: SensorData) -> Option<DetectionEvent> {
+        let g_force = calculate_g_force(data.x, data.y, data.z) / GRAVITY;
+        if g_force > 1.2 {
+            println!(
+                "📊 G-Force: {:.2} (Threshold: {:.2})",
+                g_force,
+                self.profile().impact_threshold_g
+            );
+        }
+
+        self.process_at(data, chrono::Utc::now().timestamp_millis())
+            .await
+    }
 }
 
 enum State {
@@ -18,49 +320,73 @@ enum State {
         start_time: i64,
         buffer: VecDeque<SensorData>,
         max_g: f64,
+        before: (f64, f64, f64), // Orientation just before the impact
     },
 }
 
 pub struct FallDetector {
+    profile: DetectorProfile,
     state: State,
+    last_sample: Option<SensorData>,
+}
+
+impl Default for FallDetector {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl FallDetector {
     pub fn new() -> Self {
+        Self::with_profile(DetectorProfile::default())
+    }
+
+    pub fn with_profile(profile: DetectorProfile) -> Self {
         Self {
+            profile,
             state: State::Monitoring,
+            last_sample: None,
         }
     }
+}
 
-    pub fn process(&mut self, data: SensorData) -> Option<DetectionEvent> {
-        // Calculate G-Force
-        let g_force = (data.x.powi(2) + data.y.powi(2) + data.z.powi(2)).sqrt() / 9.8;
+#[async_trait]
+impl Detector for FallDetector {
+    fn profile(&self) -> &DetectorProfile {
+        &self.profile
+    }
 
-        if g_force > 1.2 {
-            println!(
-                "📊 G-Force: {:.2} (Threshold: {:.2})",
-                g_force, IMPACT_THRESHOLD_G
-            );
-        }
+    async fn process_at(&mut self, data: SensorData, now: i64) -> Option<DetectionEvent> {
+        // Calculate G-Force
+        let g_force = calculate_g_force(data.x, data.y, data.z) / GRAVITY;
 
-        let now = chrono::Utc::now().timestamp_millis();
+        // Without an earlier sample the impact itself stands in for the prior orientation
+        let previous = self
+            .last_sample
+            .replace(data.clone())
+            .unwrap_or(data.clone());
 
         match &mut self.state {
             State::Monitoring => {
-                if g_force > IMPACT_THRESHOLD_G {
+                if g_force > self.profile.impact_threshold_g {
                     // Transition to PreAlert
                     self.state = State::PreAlert {
                         start_time: now,
                         buffer: VecDeque::new(),
                         max_g: g_force,
+                        before: (previous.x, previous.y, previous.z),
                     };
-                    return Some(DetectionEvent::Validating);
+                    return Some(DetectionEvent::Validating {
+                        g_force,
+                        explanation: self.profile.explain_impact(g_force),
+                    });
                 }
             }
             State::PreAlert {
                 start_time,
                 buffer,
                 max_g,
+                before,
             } => {
                 // Keep track of max impact during buffer
                 if g_force > *max_g {
@@ -70,14 +396,29 @@ impl FallDetector {
                 buffer.push_back(data);
 
                 // Check time duration
-                if now - *start_time >= BUFFER_DURATION_MS {
+                if now - *start_time >= self.profile.validation_ms {
                     // 2.0s passed. Analyze buffer for stillness.
                     let variance = calculate_variance(buffer);
+                    let metrics = FallMetrics {
+                        stillness_variance: variance,
+                        validation_ms: now - *start_time,
+                        orientation_change_deg: angle_between(*before, mean_vector(buffer)),
+                    };
 
-                    let result = if variance < STILLNESS_THRESHOLD_VARIANCE {
-                        Some(DetectionEvent::CriticalFall { g_force: *max_g })
+                    let explanation = self.profile.explain_verdict(*max_g, &metrics);
+
+                    let result = if variance < self.profile.stillness_threshold_variance {
+                        Some(DetectionEvent::CriticalFall {
+                            g_force: *max_g,
+                            metrics,
+                            explanation,
+                        })
                     } else {
-                        Some(DetectionEvent::NearMiss)
+                        Some(DetectionEvent::NearMiss {
+                            g_force: *max_g,
+                            metrics,
+                            explanation,
+                        })
                     };
 
                     // Reset to Monitoring
@@ -111,3 +452,118 @@ fn calculate_variance(buffer: &VecDeque<SensorData>) -> f64 {
     // Total variance magnitude
     (var_x + var_y + var_z) / count
 }
+
+/// Average acceleration vector of the buffer (the resting orientation after a fall).
+fn mean_vector(buffer: &VecDeque<SensorData>) -> (f64, f64, f64) {
+    let count = buffer.len().max(1) as f64;
+    (
+        buffer.iter().map(|d| d.x).sum::<f64>() / count,
+        buffer.iter().map(|d| d.y).sum::<f64>() / count,
+        buffer.iter().map(|d| d.z).sum::<f64>() / count,
+    )
+}
+
+/// Angle between two acceleration vectors, in degrees (0 when either is zero).
+pub fn angle_between(a: (f64, f64, f64), b: (f64, f64, f64)) -> f64 {
+    let norms = calculate_g_force(a.0, a.1, a.2) * calculate_g_force(b.0, b.1, b.2);
+    if norms == 0.0 {
+        return 0.0;
+    }
+    let cos = (a.0 * b.0 + a.1 * b.1 + a.2 * b.2) / norms;
+    cos.clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lying flat after standing upright is a 90 degree turn
+    #[test]
+    fn test_angle_between() {
+        assert!((angle_between((0.0, 9.8, 0.0), (0.0, 0.0, 9.8)) - 90.0).abs() < 1e-9);
+    }
+
+    // A zero vector has no direction to turn from
+    #[test]
+    fn test_angle_between_zero_vector() {
+        assert_eq!(angle_between((0.0, 0.0, 0.0), (0.0, 0.0, 9.8)), 0.0);
+    }
+
+    // A device flat on a table reads 1G, a falling one 0G
+    #[test]
+    fn test_g_force_calculation() {
+        assert_eq!(calculate_g_force(0.0, 0.0, 1.0), 1.0);
+        assert_eq!(calculate_g_force(0.0, 0.0, 0.0), 0.0);
+    }
+
+    // A 3.5G impact triggers a fall alert, walking at 1.2G does not
+    #[test]
+    fn test_fall_trigger() {
+        assert!(is_fall(3.5), "3.5G should trigger a fall alert");
+        assert!(!is_fall(1.2), "1.2G should NOT trigger a fall alert");
+    }
+
+    // Helper: the window of someone lying still after turning 88 degrees
+    fn still() -> FallMetrics {
+        FallMetrics {
+            stillness_variance: 0.5,
+            validation_ms: 2003,
+            orientation_change_deg: 88.0,
+        }
+    }
+
+    // Confidence is 0.5 on a threshold, and a rule must exceed its threshold to fire
+    #[test]
+    fn test_explanation_on_threshold() {
+        let borderline = Explanation::impact(1.6);
+        assert_eq!(borderline.confidence, 0.5);
+        assert!(borderline.fired().is_empty());
+    }
+
+    // Confidence grows with the margin over the threshold
+    #[test]
+    fn test_explanation_clear_impact() {
+        let clear = Explanation::impact(3.2);
+        assert_eq!(clear.fired(), vec!["impact"]);
+        assert!(clear.confidence > 0.9 && clear.confidence < 1.0);
+    }
+
+    // A fall verdict fires the impact and stillness rules, each against its threshold
+    #[test]
+    fn test_explanation_fall_verdict() {
+        let fall = Explanation::verdict(3.2, &still());
+        assert_eq!(fall.fired(), vec!["impact", "stillness"]);
+        let stillness = &fall.rules[1];
+        assert_eq!(
+            (
+                stillness.comparator.as_str(),
+                stillness.measured,
+                stillness.threshold
+            ),
+            ("<", 0.5, 3.5)
+        );
+    }
+
+    // Confidence follows the least clear-cut rule: 3.6 is barely above the stillness limit
+    #[test]
+    fn test_explanation_near_miss_verdict() {
+        let near_miss = Explanation::verdict(
+            3.2,
+            &FallMetrics {
+                stillness_variance: 3.6,
+                ..still()
+            },
+        );
+        assert_eq!(near_miss.fired(), vec!["impact"]);
+        assert!(near_miss.confidence < Explanation::verdict(3.2, &still()).confidence);
+    }
+
+    // A profile's thresholds must be positive
+    #[test]
+    fn test_profile_rejects_negative_threshold() {
+        let profile = DetectorProfile::parse(
+            r#"{"name": "bad", "impact_threshold_g": -1, "stillness_threshold_variance": 3.5, "validation_ms": 2000}"#,
+        );
+        assert_eq!(profile.unwrap_err(), "thresholds must be positive");
+    }
+}
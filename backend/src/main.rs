@@ -1,144 +1,175 @@
-use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer, Responder};
-use actix_ws::Message;
+use actix_web::{web, App, HttpServer};
+use backend::assessment::AssessmentForm;
+use backend::fhir::bulk::{ExportConfig, ExportJobs};
+use backend::logic::DetectorProfile;
+use backend::retention::{self, RetentionConfig};
+use backend::risk::RiskConfig;
+use backend::storage::{self, MigrationMode, StorageConfig};
+use backend::subscriptions::{self, SubscriptionConfig};
+use backend::telemetry::TelemetryConfig;
+use backend::{api, AppState};
 use dotenv::dotenv;
-use futures_util::StreamExt;
-use sqlx::postgres::PgPoolOptions;
-use sqlx::PgPool;
-use std::env;
+use std::sync::Arc;
 use tokio::sync::broadcast;
 
-// Internal modules
-mod logic;
-mod model;
-mod websockets;
+const USAGE: &str = "Usage: backend [--migrate | --migrate-only]
 
-use crate::model::{ClientCommand, FallLog};
-use crate::websockets::ws_handler;
-use chrono::Utc; // Added for timestamping
-use rand;
-use uuid::Uuid; // Added for generating IDs // For random IDs
+  --migrate        Apply pending database migrations, then start the server
+                   (same as MIGRATE_ON_STARTUP=true)
+  --migrate-only   Apply pending database migrations and exit";
 
-/// **Global Application State**
-///
-/// This struct holds the resources that are shared across all connected clients.
-/// - `db`: Connection pool to the PostgreSQL database for history logs.
-/// - `tx`: The "Radio Station" (Broadcast Channel) used to send real-time sensor data to the frontend.
-struct AppState {
-    db: PgPool,
-    tx: broadcast::Sender<String>,
+/// Command-line options (everything else is configured through the environment).
+#[derive(Debug, Default)]
+struct Cli {
+    migrate: bool,
+    migrate_only: bool,
 }
 
-/// **GET /api/history**
-///
-/// Retrieves the last 20 detected fall events from the database.
-/// This is used by the frontend to populate the "Event Log" panel on startup.
-async fn get_history(data: web::Data<AppState>) -> impl Responder {
-    // Execute SQL query to fetch recent logs
-    let result = sqlx::query_as!(
-        FallLog,
-        r#"
-        SELECT 
-            id,
-            detected_at as "detected_at!", 
-            severity as "severity!", 
-            g_force_value as "g_force_value!", 
-            is_false_alarm as "is_false_alarm!" 
-        FROM events 
-        ORDER BY detected_at DESC 
-        LIMIT 20
-        "#
-    )
-    .fetch_all(&data.db)
-    .await;
-
-    // Return JSON or Error
-    match result {
-        Ok(logs) => HttpResponse::Ok().json(logs),
-        Err(e) => {
-            eprintln!("❌ Database Error: {:?}", e);
-            HttpResponse::InternalServerError().body("Error fetching logs")
+impl Cli {
+    fn parse() -> Result<Self, String> {
+        let mut cli = Cli::default();
+        for arg in std::env::args().skip(1) {
+            match arg.as_str() {
+                "--migrate" => cli.migrate = true,
+                "--migrate-only" => cli.migrate_only = true,
+                "-h" | "--help" => return Err(USAGE.to_string()),
+                other => return Err(format!("Unknown argument: {}\n\n{}", other, USAGE)),
+            }
         }
+        Ok(cli)
     }
 }
 
-/// **GET /api/fhir/history**
+/// **Application Entry Point**
 ///
-/// Retrieves fall events and converts them into clinical FHIR R4 "Observation" resources.
-/// Code: LOINC 89020-2 (Fall risk assessment)
-async fn get_fhir_history(data: web::Data<AppState>) -> impl Responder {
-    // 1. Fetch from DB
-    let result = sqlx::query_as!(
-        FallLog,
-        r#"
-        SELECT 
-            id,
-            detected_at as "detected_at!", 
-            severity as "severity!", 
-            g_force_value as "g_force_value!", 
-            is_false_alarm as "is_false_alarm!" 
-        FROM events 
-        ORDER BY detected_at DESC 
-        LIMIT 20
-        "#
-    )
-    .fetch_all(&data.db)
-    .await;
-
-    match result {
-        Ok(logs) => {
-            // 2. Transform to FHIR using model method
-            use crate::model::FhirObservation;
-
-            let fhir_bundle: Vec<FhirObservation> =
-                logs.into_iter().map(|log| log.to_fhir()).collect();
-
-            HttpResponse::Ok().json(fhir_bundle)
-        }
-        Err(e) => {
-            eprintln!("❌ FHIR API Error: {:?}", e);
-            HttpResponse::InternalServerError().body("Error generating FHIR data")
-        }
+/// Initializes the Storage Backend, the Broadcast System, and starts the HTTP Server.
+/// Startup problems (bad configuration, schema mismatch) are printed and exit with status 1.
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("❌ {}", e);
+        std::process::exit(1);
     }
 }
 
-// ws_handler moved to websockets.rs
-
-/// **Application Entry Point**
-///
-/// Initializes the Database, the Broadcast System, and starts the HTTP Server.
-#[tokio::main]
-async fn main() -> std::io::Result<()> {
+async fn run() -> std::io::Result<()> {
     // 1. Load environment variables from .env file
     dotenv().ok();
+    let cli = Cli::parse().map_err(std::io::Error::other)?;
 
-    // 2. Database Setup (Connection Pool)
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .acquire_timeout(std::time::Duration::from_secs(3)) // Add this
-        .connect(&database_url)
+    // 2. Storage Setup (Postgres, SQLite or in-memory, chosen by DATABASE_URL)
+    let config = StorageConfig::from_env().map_err(std::io::Error::other)?;
+    if config == StorageConfig::Memory {
+        println!("⚠️ DATABASE_URL not set: using the in-memory store (history is lost on restart)");
+    }
+    let mode = if cli.migrate || cli.migrate_only {
+        MigrationMode::Apply
+    } else {
+        MigrationMode::from_env()
+    };
+    let (db, schema) = storage::connect_with(&config, mode)
         .await
-        .expect("Failed to connect to Postgres.");
+        .map_err(std::io::Error::other)?;
+    println!("🗄️ Storage backend: {}", config.backend_name());
+    if let Some(schema) = schema {
+        if schema.applied_now > 0 {
+            println!("🛠️ Applied {} migration(s)", schema.applied_now);
+        }
+        println!(
+            "🗄️ Schema version: {}",
+            schema
+                .version
+                .map_or("empty".to_string(), |v| v.to_string())
+        );
+    }
+    if cli.migrate_only {
+        return Ok(());
+    }
 
     // 3. Broadcast System Setup
     // Capacity = 100 messages (Drop oldest if system gets overwhelmed)
     let (tx, _rx) = broadcast::channel(100);
 
-    // 4. Initialize Global State
-    let app_state = web::Data::new(AppState { db: pool, tx: tx });
+    // 4. Retention (expired rows are archived to ARCHIVE_DIR, then purged in the background)
+    let retention_config = RetentionConfig::from_env().map_err(std::io::Error::other)?;
+    if retention_config.is_enabled() {
+        println!(
+            "🗃️ Retention enabled: archiving to {}",
+            retention_config.archive_dir.display()
+        );
+    }
+    retention::spawn(db.clone(), retention_config);
+    let telemetry_config = TelemetryConfig::from_env();
+
+    // 5. FHIR Subscriptions (rest-hook notifications are delivered in the background)
+    let notifier = subscriptions::spawn(db.clone(), SubscriptionConfig::from_env());
+
+    // 6. HL7 v2 interfaces over MLLP: ORU^R01 out when HL7_MLLP_ADDR is set,
+    //    ADT in when HL7_LISTEN_ADDR is set
+    #[cfg(feature = "hl7")]
+    if let Some(hl7_config) = backend::hl7::outbound::Hl7OutboundConfig::from_env() {
+        println!(
+            "📨 HL7 outbound: ORU^R01 over MLLP to {}",
+            hl7_config.address
+        );
+        backend::hl7::outbound::spawn(db.clone(), hl7_config);
+    }
+    #[cfg(feature = "hl7")]
+    if let Some(adt_config) = backend::hl7::inbound::Hl7InboundConfig::from_env() {
+        let address = adt_config.address.clone();
+        match backend::hl7::inbound::spawn(db.clone(), adt_config).await {
+            Ok(bound) => println!("🏥 HL7 inbound: ADT listener on {}", bound),
+            Err(e) => eprintln!("❌ HL7 inbound: cannot listen on {}: {}", address, e),
+        }
+    }
+
+    // 7. Initialize Global State (the post-fall assessment form comes from ASSESSMENT_FORM,
+    //    fall risk settings from RISK_*, detector thresholds from DETECTOR_PROFILE and
+    //    SHADOW_PROFILES)
+    let assessment_form = AssessmentForm::from_env().map_err(std::io::Error::other)?;
+    let detector = DetectorProfile::from_env().map_err(std::io::Error::other)?;
+    println!(
+        "🎯 Detector profile: {} (impact > {} g, stillness < {})",
+        detector.name, detector.impact_threshold_g, detector.stillness_threshold_variance
+    );
+    let shadow = DetectorProfile::shadow_from_env(&detector).map_err(std::io::Error::other)?;
+    for candidate in &shadow {
+        println!(
+            "👥 Shadow detector: {} (impact > {} g, stillness < {}), never alerts",
+            candidate.name, candidate.impact_threshold_g, candidate.stillness_threshold_variance
+        );
+    }
+    #[cfg(feature = "onnx")]
+    for profile in std::iter::once(&detector).chain(&shadow) {
+        if let Some(model) = &profile.model {
+            println!(
+                "🧠 {} decides with model {} (fall above {}, budget {} ms)",
+                profile.name, model.path, model.threshold, model.latency_budget_ms
+            );
+        }
+    }
+    let app_state = web::Data::new(AppState {
+        db,
+        tx,
+        telemetry: telemetry_config,
+        notifier,
+        exports: ExportJobs::new(ExportConfig::from_env()),
+        assessment_form: Arc::new(assessment_form),
+        risk: RiskConfig::from_env(),
+        detector,
+        shadow,
+    });
 
     println!("🚀 SYSTEM HEALTH: Server started at http://0.0.0.0:8080");
 
-    // 5. Start the HTTP Server
+    // 8. Start the HTTP Server
     HttpServer::new(move || {
         let cors = actix_cors::Cors::permissive();
 
         App::new()
             .wrap(cors) // Enable CORS
             .app_data(app_state.clone()) // Inject State
-            .route("/api/history", web::get().to(get_history)) // REST API
-            .route("/api/fhir/history", web::get().to(get_fhir_history)) // FHIR API
-            .route("/ws", web::get().to(ws_handler)) // WebSocket API
+            .configure(api::configure) // REST, FHIR and WebSocket routes
     })
     .bind(("0.0.0.0", 8080))?
     .run()
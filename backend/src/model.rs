@@ -1,3 +1,5 @@
+use crate::logic::Explanation;
+use crate::storage::stats::DETECTION_SEVERITIES;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
@@ -13,6 +15,8 @@ pub struct SensorData {
     pub wifi: i32,
     #[serde(default)]
     pub temp: f64, // Real temperature from MPU6050
+    #[serde(default)]
+    pub battery: Option<f64>, // % (sensors without a fuel gauge leave it out)
 }
 
 // 2. OUTPUT: Enriched Data (Live Stream)
@@ -25,23 +29,64 @@ pub struct EnrichedData {
 }
 
 // 3. DATABASE: Fall Log
-#[derive(Debug, Serialize, FromRow)]
+#[derive(Debug, Clone, Serialize, FromRow)]
 pub struct FallLog {
     pub id: i32,
     pub detected_at: chrono::DateTime<chrono::Utc>,
     pub severity: String,
     pub g_force_value: f64,
     pub is_false_alarm: bool,
+    pub device_id: Option<String>,  // Sensor that produced the event
+    pub patient_id: Option<String>, // Patient wearing it at the time
+    pub ward: Option<String>,
+    pub stillness_variance: Option<f64>, // (m/s²)² over the validation window
+    pub validation_ms: Option<i64>,      // Impact to verdict
+    pub orientation_change_deg: Option<f64>, // Before impact vs. at rest afterwards
+    pub dismissed_at: Option<chrono::DateTime<chrono::Utc>>, // Nurse cancelled it as a false alarm
+    #[serde(serialize_with = "optional_json_text")]
+    pub explanation: Option<String>, // JSON `logic::Explanation` of a detection
 }
 
 // 4. STATS: Risk Report (Upgrade 3)
 // "How many Forward falls vs Side falls?"
-#[derive(Debug, Serialize, FromRow)]
+#[derive(Debug, Clone, Serialize, FromRow)]
 pub struct RiskReport {
     pub severity: String,   // e.g., "Forward Fall"
     pub count: Option<i64>, // Postgres counts can be null, Option handles that
 }
 
+// 4b. STATS: One bar of a histogram (hour 0-23 or weekday 0=Sunday..6)
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct HistogramBucket {
+    pub bucket: i32,
+    pub count: i64,
+}
+
+// 4c. STATS: Outcome counts for one ward or patient
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct GroupBreakdown {
+    pub key: String,
+    pub total: i64,
+    pub critical: i64,
+    pub near_misses: i64,
+    pub false_alarms: i64,
+}
+
+// 4d. STATS: Full fall statistics report (GET /api/stats)
+#[derive(Debug, Clone, Serialize)]
+pub struct FallStatistics {
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+    pub total_events: i64,
+    pub by_severity: Vec<RiskReport>,
+    pub false_alarm_rate: f64, // Dismissed or "no-fall" alerts per "Critical" alert
+    pub near_miss_rate: f64,   // Share of impacts that were near misses
+    pub by_hour: Vec<HistogramBucket>,
+    pub by_weekday: Vec<HistogramBucket>,
+    pub by_ward: Vec<GroupBreakdown>,
+    pub by_patient: Vec<GroupBreakdown>,
+}
+
 // 5. COMPLIANCE: FHIR Observation (Upgrade 1)
 // The standard format hospitals use (HL7 FHIR R4)
 #[derive(Debug, Serialize)]
@@ -51,49 +96,633 @@ pub struct FhirObservation {
     pub id: String,
     pub status: String,
     pub code: serde_json::Value,
-    pub subject: serde_json::Value,
-    pub value_string: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<serde_json::Value>, // The patient, when one was assigned to the device
     pub effective_date_time: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value_quantity: Option<serde_json::Value>, // Peak G-force of a detection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_absent_reason: Option<serde_json::Value>, // Nurse actions carry no measurement
+    pub interpretation: Vec<serde_json::Value>, // Human-readable verdict
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub component: Vec<serde_json::Value>,
 }
 
+/// Every severity the server records (see `FallLog::fhir_status`).
+pub const FALL_SEVERITIES: [&str; 5] = [
+    "Critical",
+    "False Alarm",
+    "Assistance Sent",
+    "Resolved",
+    "Near Miss",
+];
+
+/// (system, code, display) of the codings on every fall Observation; `code` searches match these.
+pub const FALL_OBSERVATION_CODINGS: [(&str, &str, &str); 2] = [
+    (
+        "http://loinc.org",
+        "89020-2", // LOINC Fall risk assessment
+        "Fall risk assessment",
+    ),
+    ("http://snomed.info/sct", "1912002", "Fall (event)"),
+];
+
+/// Code system of the detection measurements reported as Observation components.
+pub const FALL_METRICS_SYSTEM: &str = "urn:fallguard:fall-metrics";
+
+/// Interpretation codes of the rule components (`H`/`L` when a rule fired, `N` when not).
+pub const OBSERVATION_INTERPRETATION_SYSTEM: &str =
+    "http://terminology.hl7.org/CodeSystem/v3-ObservationInterpretation";
+
+/// UCUM, for every `valueQuantity`.
+pub const UCUM: &str = "http://unitsofmeasure.org";
+
 // 6. INPUT: Client Command (Frontend Buttons)
 #[derive(Debug, Deserialize)]
 pub struct ClientCommand {
     pub action: String,
+    #[serde(default)]
+    pub alert_id: Option<i32>, // The alert answered (from ALERT_OPENED)
+}
+
+// 7. DATABASE: Alert
+// Lifecycle of a detected fall: "Active" -> "Confirmed" / "Cancelled" -> "Resolved"
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Alert {
+    pub id: i32,
+    pub event_id: i32, // The "Critical" event that raised this alert
+    pub status: String,
+    pub raised_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+// 8. DATABASE: Telemetry Sample (one stored SensorData reading)
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct TelemetrySample {
+    pub device_id: String,
+    pub recorded_at: chrono::DateTime<chrono::Utc>,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub g_force: f64,
+    pub wifi: i32,
+    pub temp: f64,
+}
+
+// 9. DATABASE: Waveform Snapshot
+// Signal around a detection: from a few seconds before the impact to the end of validation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Waveform {
+    pub event_id: i32, // Set once the detection event has been stored
+    pub device_id: String,
+    pub impact_at: chrono::DateTime<chrono::Utc>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub ended_at: chrono::DateTime<chrono::Utc>,
+    pub points: Vec<WaveformPoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaveformPoint {
+    pub offset_ms: i64, // Relative to impact_at (negative = before the impact)
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub g_force: f64,
+}
+
+// 10. DATABASE: Telemetry Rollup
+// Samples of one device aggregated into a fixed bucket (sums so buckets can be merged)
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub struct TelemetryRollup {
+    pub device_id: String,
+    pub resolution_seconds: i64,
+    pub bucket_start: chrono::DateTime<chrono::Utc>,
+    pub sample_count: i64,
+    pub g_min: f64,
+    pub g_max: f64,
+    pub g_sum: f64,
+    pub x_min: f64,
+    pub x_max: f64,
+    pub x_sum: f64,
+    pub y_min: f64,
+    pub y_max: f64,
+    pub y_sum: f64,
+    pub z_min: f64,
+    pub z_max: f64,
+    pub z_sum: f64,
+}
+
+// 10b. CHARTS: Min / max / mean of one signal within a bucket
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SignalSummary {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+// 10c. CHARTS: One point of a downsampled telemetry chart
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TelemetryBucket {
+    pub start: chrono::DateTime<chrono::Utc>,
+    pub count: i64,
+    pub g_force: SignalSummary,
+    pub x: SignalSummary,
+    pub y: SignalSummary,
+    pub z: SignalSummary,
+}
+
+// 10d. CHARTS: Downsampled telemetry (GET /api/devices/{id}/telemetry)
+#[derive(Debug, Clone, Serialize)]
+pub struct TelemetrySeries {
+    pub device_id: String,
+    pub from: chrono::DateTime<chrono::Utc>,
+    pub to: chrono::DateTime<chrono::Utc>,
+    pub resolution_seconds: i64,
+    pub source: String, // "raw" or the rollup it was built from, e.g. "rollup_60s"
+    pub buckets: Vec<TelemetryBucket>, // Empty buckets are omitted
+}
+
+// 11. DATABASE: Audit Log Entry
+// Who did what to which record (legal holds, retention purges, ...)
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub recorded_at: chrono::DateTime<chrono::Utc>,
+    pub actor: String,
+    pub action: String,      // e.g. "legal_hold.placed", "retention.purged"
+    pub entity_type: String, // e.g. "LegalHold", "events"
+    pub entity_id: Option<String>,
+    pub detail: Option<String>, // JSON object
+}
+
+// 12. DATABASE: Legal Hold
+// Exempts a patient's (or one alert's) records from retention purges until released
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct LegalHold {
+    pub id: i32,
+    pub patient_id: Option<String>,
+    pub alert_id: Option<i32>,
+    pub reason: String,
+    pub placed_by: String,
+    pub placed_at: chrono::DateTime<chrono::Utc>,
+    pub released_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl FallLog {
-    pub fn to_fhir(&self) -> FhirObservation {
-        use serde_json::json;
+    /// Why the detector reported this event (`None` for nurse actions and older detections).
+    pub fn explanation(&self) -> Option<Explanation> {
+        parse_explanation(self.explanation.as_deref())
+    }
 
-        // Map internal "Severity" to FHIR "ValueString" & "Status"
-        let (status, value) = match self.severity.as_str() {
+    /// FHIR Observation status and display value for an internal severity.
+    pub fn fhir_status(severity: &str) -> (&'static str, &'static str) {
+        match severity {
             "Critical" => ("final", "High Risk - Fall Detected"),
             "False Alarm" => ("entered-in-error", "Low Risk - False Alarm"),
             "Assistance Sent" => ("final", "Assessment in Progress"),
             "Resolved" => ("final", "Patient Stable"),
             "Near Miss" => ("final", "Near Miss - Movement Detected"), // Matches new status
             _ => ("preliminary", "Unknown Status"),
-        };
+        }
+    }
+
+    /// Status and display value of this event's Observation: a detection dismissed as a
+    /// false alarm is `entered-in-error`, whatever its severity.
+    pub fn observation_status(&self) -> (&'static str, &'static str) {
+        match self.dismissed_at {
+            Some(_) => ("entered-in-error", "False Alarm - Dismissed by Nurse"),
+            None => Self::fhir_status(&self.severity),
+        }
+    }
+
+    pub fn to_fhir(&self) -> FhirObservation {
+        use serde_json::json;
+
+        // Map internal "Severity" to FHIR status & a readable interpretation
+        let (status, value) = self.observation_status();
+        let coding: Vec<serde_json::Value> = FALL_OBSERVATION_CODINGS
+            .iter()
+            .map(|(system, code, display)| {
+                json!({ "system": system, "code": code, "display": display })
+            })
+            .collect();
+        let quantity = |value: f64, unit: &str, code: &str| json!({ "value": value, "unit": unit, "system": UCUM, "code": code });
+
+        // Only detections measured anything; nurse actions are logged with 0 G
+        let measured = DETECTION_SEVERITIES.contains(&self.severity.as_str());
+        let metrics = [
+            (
+                "stillness-variance",
+                "Stillness variance",
+                self.stillness_variance
+                    .map(|v| quantity(v, "m²/s⁴", "m2/s4")),
+            ),
+            (
+                "validation-duration",
+                "Validation duration",
+                self.validation_ms.map(|v| quantity(v as f64, "ms", "ms")),
+            ),
+            (
+                "orientation-change",
+                "Orientation change",
+                self.orientation_change_deg.map(|v| quantity(v, "°", "deg")),
+            ),
+        ];
+        let mut component: Vec<serde_json::Value> = metrics
+            .into_iter()
+            .filter_map(|(code, display, value)| {
+                value.map(|value| {
+                    json!({
+                        "code": { "coding": [{ "system": FALL_METRICS_SYSTEM, "code": code, "display": display }] },
+                        "valueQuantity": value
+                    })
+                })
+            })
+            .collect();
+
+        // Why the detector fired: each rule's value against its threshold (as the reference
+        // range: "H"/"L" when it fired, "N" when it did not), then the confidence
+        if let Some(explanation) = self.explanation() {
+            for rule in &explanation.rules {
+                let limit = quantity(rule.threshold, &rule.unit, &rule.unit);
+                let (range, fired_code) = match rule.comparator.as_str() {
+                    ">" => (json!({ "high": limit }), "H"),
+                    _ => (json!({ "low": limit }), "L"),
+                };
+                component.push(json!({
+                    "code": { "coding": [{
+                        "system": FALL_METRICS_SYSTEM,
+                        "code": format!("rule-{}", rule.rule),
+                        "display": format!("Detection rule: {}", rule.rule),
+                    }] },
+                    "valueQuantity": quantity(rule.measured, &rule.unit, &rule.unit),
+                    "referenceRange": [range],
+                    "interpretation": [{ "coding": [{
+                        "system": OBSERVATION_INTERPRETATION_SYSTEM,
+                        "code": if rule.fired { fired_code } else { "N" },
+                    }] }],
+                }));
+            }
+            component.push(json!({
+                "code": { "coding": [{ "system": FALL_METRICS_SYSTEM, "code": "detection-confidence", "display": "Detection confidence" }] },
+                "valueQuantity": quantity(explanation.confidence, "1", "1"),
+            }));
+        }
 
         FhirObservation {
             resource_type: "Observation".to_string(),
             id: self.id.to_string(),
             status: status.to_string(),
             code: json!({
-                "coding": [{
-                    "system": "http://loinc.org",
-                    "code": "89020-2", // LOINC Fall risk assessment
-                    "display": "Fall risk assessment"
-                }],
+                "coding": coding,
                 "text": "Fall Detection Event"
             }),
-            subject: json!({
-                "reference": "Patient/ICU-04",
-                "display": "John Doe"
-            }),
-            value_string: value.to_string(),
+            subject: self
+                .patient_id
+                .as_ref()
+                .map(|patient| json!({ "reference": format!("Patient/{}", patient) })),
             effective_date_time: self.detected_at.to_rfc3339(),
+            value_quantity: measured.then(|| quantity(self.g_force_value, "g", "[g]")),
+            data_absent_reason: (!measured).then(|| {
+                json!({ "coding": [{
+                    "system": "http://terminology.hl7.org/CodeSystem/data-absent-reason",
+                    "code": "not-applicable"
+                }] })
+            }),
+            interpretation: vec![json!({ "text": value })],
+            device: self
+                .device_id
+                .as_ref()
+                .map(|device| json!({ "reference": format!("Device/{}", device) })),
+            component,
         }
     }
 }
+
+// 13. REGISTRY: Patient
+// Demographics and current bed (kept current by the ADT feed), served as the FHIR Patient resource
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Patient {
+    pub id: String,
+    pub family_name: Option<String>,
+    pub given_name: Option<String>,
+    pub birth_date: Option<chrono::NaiveDate>,
+    pub gender: Option<String>, // FHIR administrative gender: male | female | other | unknown
+    pub ward: Option<String>,
+    pub room: Option<String>,
+    pub bed: Option<String>,
+    pub admitted_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub discharged_at: Option<chrono::DateTime<chrono::Utc>>, // Set while the patient is discharged
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+// 14. REGISTRY: Device
+// A sensor, its current wearer and the status it last reported (FHIR Device / DeviceMetric)
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Device {
+    pub id: String,
+    pub model: Option<String>,
+    pub patient_id: Option<String>, // Current wearer
+    pub ward: Option<String>,
+    pub calibration_state: String, // not-calibrated | calibration-required | calibrated | unspecified
+    pub calibrated_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub battery_level: Option<f64>, // %
+    pub wifi_signal: Option<i32>,   // As reported in SensorData::wifi
+    pub temperature: Option<f64>,   // °C at the sensor
+    pub last_seen_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub registered_at: chrono::DateTime<chrono::Utc>,
+}
+
+// 15. FHIR: Subscription
+// A rest-hook endpoint notified of new fall Observations or Flags (subscriptions backport)
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Subscription {
+    pub id: i32,
+    pub topic: String, // Canonical URL of the topic
+    pub reason: String,
+    pub endpoint: String,
+    pub headers: Option<String>,    // JSON array of "Name: value" strings
+    pub payload: String,            // empty | id-only | full-resource
+    pub patient_id: Option<String>, // Only events about this patient
+    pub heartbeat_seconds: Option<i32>,
+    pub status: String, // requested | active | error | off
+    pub events_since_start: i64,
+    pub error: Option<String>, // Why the last delivery failed
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_notified_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Subscription {
+    /// The `Name: value` headers sent with every notification.
+    pub fn header_lines(&self) -> Vec<String> {
+        self.headers
+            .as_deref()
+            .and_then(|h| serde_json::from_str(h).ok())
+            .unwrap_or_default()
+    }
+}
+
+// 16. HL7 v2: Outbound Message
+// One message for the interface engine (ORU^R01 per event), retried until acknowledged
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct OutboundMessage {
+    pub id: i32,
+    pub event_id: i32,
+    pub message_type: String, // MSH-9, e.g. "ORU^R01"
+    pub control_id: String,   // MSH-10
+    pub message: String,      // Segments separated by '\r'
+    pub status: String,       // pending | sent | failed
+    pub attempts: i32,
+    pub next_attempt_at: chrono::DateTime<chrono::Utc>,
+    pub last_error: Option<String>,
+    pub ack_code: Option<String>, // MSA-1 of the last ACK: AA, AE, AR (or CA, CE, CR)
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub sent_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// 17. CLINICAL: Post-fall Assessment
+// The form a nurse fills in after a confirmed fall (FHIR QuestionnaireResponse)
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Assessment {
+    pub id: i32,
+    pub alert_id: i32,
+    pub event_id: i32,
+    pub patient_id: Option<String>,
+    pub form_id: String, // Questionnaire answered
+    pub form_version: String,
+    pub status: String, // in-progress | completed | amended
+    #[serde(serialize_with = "json_text")]
+    pub answers: String, // JSON object keyed by item link_id
+    pub author: Option<String>,
+    pub due_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub completed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Assessment {
+    /// The stored answers (empty when nothing was saved yet).
+    pub fn answer_map(&self) -> serde_json::Map<String, serde_json::Value> {
+        serde_json::from_str(&self.answers).unwrap_or_default()
+    }
+}
+
+// 18. CLINICAL: Morse Fall Scale entry
+// Item points as scored by a clinician (history 0/25, diagnosis 0/15, aid 0/15/30, IV 0/20,
+// gait 0/10/20, mental status 0/15)
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct MorseScore {
+    pub id: i32,
+    pub patient_id: String,
+    pub history_of_falling: i32,
+    pub secondary_diagnosis: i32,
+    pub ambulatory_aid: i32,
+    pub iv_therapy: i32,
+    pub gait: i32,
+    pub mental_status: i32,
+    pub total: i32, // 0-125
+    pub assessed_by: String,
+    pub assessed_at: chrono::DateTime<chrono::Utc>,
+}
+
+// 19. CLINICAL: Fall risk score (FHIR RiskAssessment)
+// One row per recalculation, with the factors it was computed from
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct RiskScore {
+    pub id: i32,
+    pub patient_id: String,
+    pub calculated_at: chrono::DateTime<chrono::Utc>,
+    pub probability: f64,
+    pub rating: String, // low | moderate | high
+    pub near_misses: i32,
+    pub falls: i32, // Detected falls not dismissed as false alarms
+    pub false_alarms: i32,
+    pub night_share: f64, // Share of those detections at night (ward time)
+    pub morse_total: Option<i32>,
+    pub window_days: i32,
+    pub reason: String, // event | morse | request
+    pub event_id: Option<i32>,
+}
+
+// 20. DETECTOR: Outcome log
+// Every verdict of the fall detector with its features, whether or not it became an event
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Detection {
+    pub id: i32,
+    pub detected_at: chrono::DateTime<chrono::Utc>,
+    pub outcome: String, // validating | critical | near-miss
+    pub device_id: Option<String>,
+    pub patient_id: Option<String>,
+    pub ward: Option<String>,
+    pub peak_g: f64, // Impact G while validating, highest G in the window after
+    pub stillness_variance: Option<f64>, // Features of the validation window (unset while validating)
+    pub validation_ms: Option<i64>,      // Window length
+    pub orientation_change_deg: Option<f64>,
+    pub event_id: Option<i32>, // Event stored for the outcome, if any
+    #[serde(serialize_with = "optional_json_text")]
+    pub explanation: Option<String>, // JSON `logic::Explanation`
+    pub detector: String,      // Name of the detector profile that decided
+    pub shadow: bool,          // A candidate running in shadow mode (never alerts)
+}
+
+impl Detection {
+    /// Why the detector reported the outcome (`None` for rows logged without one).
+    pub fn explanation(&self) -> Option<Explanation> {
+        parse_explanation(self.explanation.as_deref())
+    }
+}
+
+// 21. LABELS: Nurse ground truth
+// What a nurse decided about the detection behind an alert (the detection's waveform is stored
+// under the same event id)
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub struct Label {
+    pub id: i32,
+    pub event_id: i32,
+    pub alert_id: i32,
+    pub label: String,   // fall | no-fall
+    pub command: String, // CONFIRM_FALL | CANCEL_ALERT
+    pub labelled_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Serializes a column holding JSON text as the JSON value itself.
+fn json_text<S: serde::Serializer>(text: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    let value: serde_json::Value = serde_json::from_str(text).map_err(serde::ser::Error::custom)?;
+    value.serialize(serializer)
+}
+
+/// Same as `json_text` for a nullable column (`null` when unset).
+fn optional_json_text<S: serde::Serializer>(
+    text: &Option<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match text {
+        Some(text) => json_text(text, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn parse_explanation(text: Option<&str>) -> Option<Explanation> {
+    text.and_then(|t| serde_json::from_str(t).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::FallMetrics;
+    use crate::test_support::fall_log;
+    use serde_json::json;
+
+    // Helper: the FHIR JSON of `log`
+    fn observation(log: &FallLog) -> serde_json::Value {
+        serde_json::to_value(log.to_fhir()).unwrap()
+    }
+
+    // Peak G is a UCUM quantity
+    #[test]
+    fn test_observation_peak_g() {
+        let obs = observation(&fall_log("Critical", 2.7));
+        assert_eq!(
+            obs["valueQuantity"],
+            json!({ "value": 2.7, "unit": "g", "system": "http://unitsofmeasure.org", "code": "[g]" })
+        );
+        assert!(obs.get("valueString").is_none());
+    }
+
+    // Falls are coded in SNOMED as well as LOINC, and reference the device
+    #[test]
+    fn test_observation_references() {
+        let obs = observation(&fall_log("Critical", 2.7));
+        assert_eq!(obs["code"]["coding"][1]["system"], "http://snomed.info/sct");
+        assert_eq!(obs["code"]["coding"][1]["code"], "1912002");
+        assert_eq!(obs["device"]["reference"], "Device/pi-01");
+        assert!(obs.get("performer").is_none()); // No CareTeam resource is served to point at
+    }
+
+    // Fall metrics are components with their own units
+    #[test]
+    fn test_observation_metric_components() {
+        let obs = observation(&fall_log("Critical", 2.7));
+        let components = obs["component"].as_array().unwrap();
+        assert_eq!(components.len(), 3);
+        assert_eq!(
+            components[0]["code"]["coding"][0]["code"],
+            "stillness-variance"
+        );
+        assert_eq!(components[0]["valueQuantity"]["value"], 0.42);
+        assert_eq!(components[1]["valueQuantity"]["value"], 2010.0);
+        assert_eq!(components[1]["valueQuantity"]["code"], "ms");
+        assert_eq!(components[2]["valueQuantity"]["code"], "deg");
+    }
+
+    // Nurse actions measured nothing
+    #[test]
+    fn test_nurse_action_observation() {
+        let obs = observation(&FallLog {
+            stillness_variance: None,
+            validation_ms: None,
+            orientation_change_deg: None,
+            ..fall_log("Assistance Sent", 0.0)
+        });
+        assert!(obs.get("valueQuantity").is_none());
+        assert!(obs.get("component").is_none());
+        assert_eq!(
+            obs["dataAbsentReason"]["coding"][0]["code"],
+            "not-applicable"
+        );
+        assert_eq!(obs["interpretation"][0]["text"], "Assessment in Progress");
+    }
+
+    // Helper: the FHIR components of a 3.2G fall explained by the detector, by code
+    fn explained_components() -> (Explanation, Vec<serde_json::Value>) {
+        let metrics = FallMetrics {
+            stillness_variance: 0.5,
+            validation_ms: 2003,
+            orientation_change_deg: 88.0,
+        };
+        let explanation = Explanation::verdict(3.2, &metrics);
+        let log = FallLog {
+            explanation: Some(serde_json::to_string(&explanation).unwrap()),
+            ..fall_log("Critical", 3.2)
+        };
+        let components = observation(&log)["component"].as_array().unwrap().clone();
+        (explanation, components)
+    }
+
+    // Helper: the component coded `code`
+    fn component<'a>(components: &'a [serde_json::Value], code: &str) -> &'a serde_json::Value {
+        components
+            .iter()
+            .find(|c| c["code"]["coding"][0]["code"] == code)
+            .unwrap()
+    }
+
+    // Each rule is a component: the value, its threshold as the reference range, H/L when fired
+    #[test]
+    fn test_observation_rule_components() {
+        let (_, components) = explained_components();
+        let impact = component(&components, "rule-impact");
+        assert_eq!(impact["valueQuantity"]["value"], 3.2);
+        assert_eq!(impact["referenceRange"][0]["high"]["value"], 1.6);
+        assert_eq!(impact["interpretation"][0]["coding"][0]["code"], "H");
+        let stillness = component(&components, "rule-stillness");
+        assert_eq!(stillness["referenceRange"][0]["low"]["value"], 3.5);
+        assert_eq!(stillness["interpretation"][0]["coding"][0]["code"], "L");
+    }
+
+    // The detector's confidence is a component, next to the fall metrics
+    #[test]
+    fn test_observation_confidence_component() {
+        let (explanation, components) = explained_components();
+        assert_eq!(
+            component(&components, "detection-confidence")["valueQuantity"]["value"],
+            explanation.confidence
+        );
+        assert_eq!(
+            component(&components, "stillness-variance")["valueQuantity"]["value"],
+            0.42
+        );
+    }
+}
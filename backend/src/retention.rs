@@ -0,0 +1,566 @@
+use crate::storage::{DataClass, NewAuditEntry, Repository, StorageError};
+use chrono::{DateTime, Duration, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::json;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Rows archived and deleted per round trip (one archive file each).
+pub const RETENTION_BATCH: i64 = 1000;
+
+/// Default time between retention runs.
+const DEFAULT_INTERVAL_MINUTES: u64 = 60;
+
+/// Actor recorded in the audit log for purges.
+const RETENTION_ACTOR: &str = "system:retention";
+
+/// **Retention Configuration**
+///
+/// One period per data class; unset classes are kept forever.
+/// - `RETENTION_TELEMETRY`, `RETENTION_ROLLUPS`, `RETENTION_WAVEFORMS`, `RETENTION_EVENTS`,
+///   `RETENTION_DETECTIONS`, `RETENTION_AUDIT`: periods such as `72h`, `30d` or `7y`
+///   (`TELEMETRY_RETENTION_HOURS` is still read for telemetry).
+/// - `ARCHIVE_DIR`: where expired rows are written before deletion (default `./archive`).
+/// - `RETENTION_INTERVAL_MINUTES`: time between runs (default 60).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionConfig {
+    pub telemetry: Option<Duration>,
+    pub rollups: Option<Duration>,
+    pub waveforms: Option<Duration>,
+    pub events: Option<Duration>,
+    pub detections: Option<Duration>,
+    pub audit: Option<Duration>,
+    pub archive_dir: PathBuf,
+    pub interval: std::time::Duration,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            telemetry: None,
+            rollups: None,
+            waveforms: None,
+            events: None,
+            detections: None,
+            audit: None,
+            archive_dir: PathBuf::from("archive"),
+            interval: std::time::Duration::from_secs(DEFAULT_INTERVAL_MINUTES * 60),
+        }
+    }
+}
+
+impl RetentionConfig {
+    pub fn from_env() -> Result<Self, String> {
+        let period = |name: &str| match std::env::var(name) {
+            Ok(value) => parse_period(&value)
+                .map(Some)
+                .ok_or_else(|| format!("{}: invalid retention period {:?}", name, value)),
+            Err(_) => Ok(None),
+        };
+        let legacy_telemetry = std::env::var("TELEMETRY_RETENTION_HOURS")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .filter(|h| *h > 0)
+            .map(Duration::hours);
+        let defaults = Self::default();
+        Ok(Self {
+            telemetry: period("RETENTION_TELEMETRY")?.or(legacy_telemetry),
+            rollups: period("RETENTION_ROLLUPS")?,
+            waveforms: period("RETENTION_WAVEFORMS")?,
+            events: period("RETENTION_EVENTS")?,
+            detections: period("RETENTION_DETECTIONS")?,
+            audit: period("RETENTION_AUDIT")?,
+            archive_dir: std::env::var("ARCHIVE_DIR")
+                .map(PathBuf::from)
+                .unwrap_or(defaults.archive_dir),
+            interval: std::env::var("RETENTION_INTERVAL_MINUTES")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|m| *m > 0)
+                .map(|m| std::time::Duration::from_secs(m * 60))
+                .unwrap_or(defaults.interval),
+        })
+    }
+
+    pub fn period(&self, class: DataClass) -> Option<Duration> {
+        match class {
+            DataClass::Telemetry => self.telemetry,
+            DataClass::Rollups => self.rollups,
+            DataClass::Waveforms => self.waveforms,
+            DataClass::Events => self.events,
+            DataClass::Detections => self.detections,
+            DataClass::Audit => self.audit,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        DataClass::ALL.iter().any(|c| self.period(*c).is_some())
+    }
+}
+
+/// Parses `<n>h`, `<n>d` or `<n>y` (365 days) into a positive period.
+pub fn parse_period(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let unit = value.chars().last()?;
+    let number: i64 = value[..value.len() - unit.len_utf8()].parse().ok()?;
+    if number <= 0 {
+        return None;
+    }
+    match unit {
+        'h' => Some(Duration::hours(number)),
+        'd' => Some(Duration::days(number)),
+        'y' => Some(Duration::days(number.checked_mul(365)?)),
+        _ => None,
+    }
+}
+
+/// **Retention Error**
+///
+/// A run stops at the first failure; rows are only deleted once their archive is on disk.
+#[derive(Debug)]
+pub enum RetentionError {
+    Storage(StorageError),
+    Archive(io::Error),
+}
+
+impl fmt::Display for RetentionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RetentionError::Storage(e) => write!(f, "{}", e),
+            RetentionError::Archive(e) => write!(f, "archive write failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RetentionError {}
+
+impl From<StorageError> for RetentionError {
+    fn from(e: StorageError) -> Self {
+        RetentionError::Storage(e)
+    }
+}
+
+impl From<io::Error> for RetentionError {
+    fn from(e: io::Error) -> Self {
+        RetentionError::Archive(e)
+    }
+}
+
+/// Outcome of one class in a retention run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PurgeReport {
+    pub class: DataClass,
+    pub cutoff: DateTime<Utc>,
+    pub purged: u64,
+    pub archives: Vec<PathBuf>,
+}
+
+/// Writes records as gzip-compressed NDJSON to `<dir>/<class>/<class>-<time>-<seq>.ndjson.gz`.
+/// The file is written under a temporary name and renamed once synced to disk.
+pub fn write_archive(
+    dir: &Path,
+    class: DataClass,
+    records: &[serde_json::Value],
+    at: DateTime<Utc>,
+    seq: usize,
+) -> io::Result<PathBuf> {
+    let class_dir = dir.join(class.name());
+    fs::create_dir_all(&class_dir)?;
+    let name = format!(
+        "{}-{}-{:04}.ndjson.gz",
+        class.name(),
+        at.format("%Y%m%dT%H%M%SZ"),
+        seq
+    );
+    let path = class_dir.join(&name);
+    let partial = class_dir.join(format!("{}.partial", name));
+
+    let mut encoder = GzEncoder::new(
+        BufWriter::new(File::create(&partial)?),
+        Compression::default(),
+    );
+    for record in records {
+        serde_json::to_writer(&mut encoder, record)?;
+        encoder.write_all(b"\n")?;
+    }
+    let file = encoder.finish()?.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    fs::rename(&partial, &path)?;
+    Ok(path)
+}
+
+/// Archives and purges every class whose retention period has passed, once.
+/// Each batch is archived before it is deleted, and every purge is written to the audit log.
+pub async fn run_once(
+    db: &dyn Repository,
+    config: &RetentionConfig,
+    now: DateTime<Utc>,
+) -> Result<Vec<PurgeReport>, RetentionError> {
+    let mut reports = Vec::new();
+    for class in DataClass::ALL {
+        let Some(period) = config.period(class) else {
+            continue;
+        };
+        let mut report = PurgeReport {
+            class,
+            cutoff: now - period,
+            purged: 0,
+            archives: Vec::new(),
+        };
+        loop {
+            let batch = db
+                .expired_records(class, report.cutoff, RETENTION_BATCH)
+                .await?;
+            if batch.is_empty() {
+                break;
+            }
+            let records: Vec<serde_json::Value> = batch.iter().map(|r| r.record.clone()).collect();
+            let path = write_archive(
+                &config.archive_dir,
+                class,
+                &records,
+                now,
+                report.archives.len(),
+            )?;
+            let ids: Vec<i64> = batch.iter().map(|r| r.id).collect();
+            report.purged += db.delete_records(class, &ids).await?;
+            report.archives.push(path);
+            if (batch.len() as i64) < RETENTION_BATCH {
+                break;
+            }
+        }
+        if report.purged > 0 {
+            db.record_audit(NewAuditEntry {
+                recorded_at: now,
+                actor: RETENTION_ACTOR.to_string(),
+                action: "retention.purged".to_string(),
+                entity_type: class.name().to_string(),
+                entity_id: None,
+                detail: Some(json!({
+                    "cutoff": report.cutoff,
+                    "purged": report.purged,
+                    "archives": report.archives,
+                })),
+            })
+            .await?;
+        }
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+/// Runs the retention job on `config.interval` (no-op when no class has a period).
+pub fn spawn(db: Arc<dyn Repository>, config: RetentionConfig) {
+    if !config.is_enabled() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval);
+        loop {
+            interval.tick().await;
+            match run_once(db.as_ref(), &config, Utc::now()).await {
+                Ok(reports) => {
+                    for r in reports.iter().filter(|r| r.purged > 0) {
+                        println!(
+                            "🧹 Retention: archived and purged {} {} rows older than {}",
+                            r.purged,
+                            r.class.name(),
+                            r.cutoff
+                        );
+                    }
+                }
+                Err(e) => eprintln!("❌ Retention run failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{FallLog, LegalHold, TelemetrySample, Waveform};
+    use crate::storage::{
+        rollup, DetectionQuery, DeviceRegistration, NewDetection, NewEvent, NewLegalHold,
+    };
+    use crate::test_support::{critical_event, on_every_backend};
+
+    // Rows 40 days old for a held patient, an expired one and one with an open alert, and a
+    // recent one, with what hangs off them: waveforms, detections, telemetry and audit
+    struct Fixture {
+        held: FallLog,
+        expired: FallLog,
+        open: FallLog,
+        recent: FallLog,
+        hold: LegalHold,
+        hour: DateTime<Utc>, // The old telemetry's one rollup bucket per device and resolution
+    }
+
+    async fn seed(repo: &dyn Repository) -> Fixture {
+        let old = Utc::now() - Duration::days(40);
+        let event = |patient: &str| NewEvent {
+            detected_at: old,
+            patient_id: Some(patient.to_string()),
+            ..critical_event(0, 2.5)
+        };
+        let held = repo
+            .insert_event(NewEvent {
+                device_id: Some("pi-held".to_string()),
+                ..event("P-HELD")
+            })
+            .await
+            .unwrap();
+        let expired = repo.insert_event(event("P-2")).await.unwrap();
+        let open = repo.insert_event(event("P-3")).await.unwrap();
+        let recent = repo.insert_event(critical_event(5, 2.5)).await.unwrap();
+
+        let alert = repo
+            .open_alert(expired.id, expired.detected_at)
+            .await
+            .unwrap();
+        repo.set_alert_status(alert.id, "Resolved", expired.detected_at)
+            .await
+            .unwrap();
+        repo.open_alert(open.id, open.detected_at).await.unwrap(); // Still "Active"
+        for e in [&held, &expired] {
+            repo.save_waveform(&Waveform {
+                event_id: e.id,
+                device_id: "pi-01".to_string(),
+                impact_at: e.detected_at,
+                started_at: e.detected_at,
+                ended_at: e.detected_at,
+                points: Vec::new(),
+            })
+            .await
+            .unwrap();
+        }
+        let detection = |at, patient: &str, event_id| NewDetection {
+            detected_at: at,
+            outcome: "critical".to_string(),
+            device_id: Some("pi-01".to_string()),
+            patient_id: Some(patient.to_string()),
+            ward: None,
+            peak_g: 2.5,
+            metrics: None,
+            event_id,
+            explanation: None,
+            detector: "default".to_string(),
+            shadow: false,
+        };
+        for (at, patient, event_id) in [
+            (old, "P-HELD", Some(held.id)),
+            (old, "P-2", Some(expired.id)),
+            (Utc::now(), "P-2", None),
+        ] {
+            repo.record_detection(&detection(at, patient, event_id))
+                .await
+                .unwrap();
+        }
+        // The held patient's devices: one reported their event, the other is registered to them
+        repo.register_device(&DeviceRegistration {
+            id: "pi-worn".to_string(),
+            model: None,
+            patient_id: Some("P-HELD".to_string()),
+            ward: None,
+            calibration_state: None,
+            calibrated_at: None,
+            at: old,
+        })
+        .await
+        .unwrap();
+        let hour = rollup::bucket_start(old, 3600);
+        let samples: Vec<TelemetrySample> = [("pi-02", 3), ("pi-held", 2), ("pi-worn", 1)]
+            .into_iter()
+            .flat_map(|(device, count)| {
+                (0..count).map(move |i| TelemetrySample {
+                    device_id: device.to_string(),
+                    recorded_at: hour + Duration::seconds(i),
+                    x: 0.0,
+                    y: 0.0,
+                    z: 9.8,
+                    g_force: 1.0,
+                    wifi: 90,
+                    temp: 36.5,
+                })
+            })
+            .collect();
+        repo.insert_telemetry(&samples).await.unwrap();
+        for (actor, action, entity_type, entity_id) in [
+            ("nurse-1", "alert.confirmed", "Alert", alert.id.to_string()),
+            ("hl7:PAS", "A01", "Patient", "P-HELD".to_string()),
+        ] {
+            repo.record_audit(NewAuditEntry {
+                recorded_at: Utc::now() - Duration::days(400),
+                actor: actor.to_string(),
+                action: action.to_string(),
+                entity_type: entity_type.to_string(),
+                entity_id: Some(entity_id),
+                detail: None,
+            })
+            .await
+            .unwrap();
+        }
+        let hold = repo
+            .place_legal_hold(NewLegalHold {
+                patient_id: Some("P-HELD".to_string()),
+                alert_id: None,
+                reason: "Incident review".to_string(),
+                placed_by: "legal".to_string(),
+                placed_at: Utc::now(),
+            })
+            .await
+            .unwrap();
+        Fixture {
+            held,
+            expired,
+            open,
+            recent,
+            hold,
+            hour,
+        }
+    }
+
+    fn config() -> RetentionConfig {
+        RetentionConfig {
+            telemetry: Some(Duration::days(7)),
+            rollups: Some(Duration::days(30)),
+            waveforms: Some(Duration::days(30)),
+            events: Some(Duration::days(30)),
+            detections: Some(Duration::days(30)),
+            audit: Some(Duration::days(365)),
+            archive_dir: std::env::temp_dir()
+                .join(format!("fallguard-archive-{}", uuid::Uuid::new_v4())),
+            ..RetentionConfig::default()
+        }
+    }
+
+    // Helper: one purge of the seeded rows; returns the fixture and the run's reports
+    async fn purge(repo: &dyn Repository, config: &RetentionConfig) -> (Fixture, Vec<PurgeReport>) {
+        let fixture = seed(repo).await;
+        let reports = run_once(repo, config, Utc::now()).await.unwrap();
+        (fixture, reports)
+    }
+
+    // Every class purges its expired rows, but not those of a held patient or an open alert
+    #[actix_web::test]
+    async fn test_purge_counts() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let config = config();
+            let (_, reports) = purge(repo, &config).await;
+            let purged =
+                |class: DataClass| reports.iter().find(|r| r.class == class).unwrap().purged;
+            assert_eq!(purged(DataClass::Telemetry), 3); // Not from the held patient's devices
+            assert_eq!(purged(DataClass::Rollups), 3); // pi-02 at every resolution
+            assert_eq!(purged(DataClass::Waveforms), 1); // The held patient's waveform stays
+            assert_eq!(purged(DataClass::Events), 1); // Not the held one, nor the open alert's
+            assert_eq!(purged(DataClass::Detections), 1); // The held patient's outcome stays
+            assert_eq!(purged(DataClass::Audit), 1); // Not the entry about the held patient
+            fs::remove_dir_all(&config.archive_dir).unwrap();
+        })
+        .await;
+    }
+
+    // Events are kept while held, while their alert is open, or until they expire
+    #[actix_web::test]
+    async fn test_kept_events() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let config = config();
+            let (fixture, _) = purge(repo, &config).await;
+            assert!(repo.get_event(fixture.expired.id).await.unwrap().is_none());
+            for kept in [&fixture.held, &fixture.open, &fixture.recent] {
+                assert!(repo.get_event(kept.id).await.unwrap().is_some());
+            }
+            assert!(repo.get_waveform(fixture.held.id).await.unwrap().is_some());
+            fs::remove_dir_all(&config.archive_dir).unwrap();
+        })
+        .await;
+    }
+
+    // Telemetry and rollups are kept for every device of a held patient
+    #[actix_web::test]
+    async fn test_held_devices() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let config = config();
+            let (fixture, _) = purge(repo, &config).await;
+            let hour = fixture.hour;
+            for device in ["pi-held", "pi-worn"] {
+                let kept = repo
+                    .telemetry_between(device, hour, hour + Duration::hours(1))
+                    .await
+                    .unwrap();
+                assert!(!kept.is_empty(), "{}", device);
+                let kept = repo
+                    .telemetry_rollups(device, 3600, hour, hour)
+                    .await
+                    .unwrap();
+                assert_eq!(kept.len(), 1, "{}", device);
+            }
+            assert!(repo
+                .telemetry_rollups("pi-02", 3600, hour, hour)
+                .await
+                .unwrap()
+                .is_empty());
+            fs::remove_dir_all(&config.archive_dir).unwrap();
+        })
+        .await;
+    }
+
+    // Archives are gzip NDJSON, one line per purged row
+    #[actix_web::test]
+    async fn test_archives() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let config = config();
+            let (_, reports) = purge(repo, &config).await;
+            let archive = &reports[0].archives[0];
+            let mut text = String::new();
+            io::Read::read_to_string(
+                &mut flate2::read::GzDecoder::new(File::open(archive).unwrap()),
+                &mut text,
+            )
+            .unwrap();
+            assert_eq!(text.lines().count(), 3);
+            fs::remove_dir_all(&config.archive_dir).unwrap();
+        })
+        .await;
+    }
+
+    // Releasing the hold lets the next run purge the patient's rows; the purges are audited
+    #[actix_web::test]
+    async fn test_released_hold() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let config = config();
+            let (fixture, _) = purge(repo, &config).await;
+            repo.release_legal_hold(fixture.hold.id, Utc::now())
+                .await
+                .unwrap();
+            assert!(repo.legal_holds(false).await.unwrap().is_empty());
+            assert_eq!(repo.legal_holds(true).await.unwrap().len(), 1);
+            run_once(repo, &config, Utc::now()).await.unwrap();
+
+            assert!(repo.get_event(fixture.held.id).await.unwrap().is_none());
+            assert!(repo.get_waveform(fixture.held.id).await.unwrap().is_none());
+            for class in [DataClass::Telemetry, DataClass::Rollups] {
+                let left = repo.expired_records(class, Utc::now(), 100).await.unwrap();
+                assert!(left.is_empty(), "{:?}", class);
+            }
+            let all = DetectionQuery {
+                limit: 10,
+                ..DetectionQuery::default()
+            };
+            let left = repo.query_detections(&all).await.unwrap();
+            assert_eq!(left.len(), 1); // The recent one
+            let audit = repo
+                .expired_records(DataClass::Audit, Utc::now() + Duration::hours(1), 100)
+                .await
+                .unwrap();
+            assert!(audit
+                .iter()
+                .all(|a| a.record["action"] == "retention.purged"));
+            fs::remove_dir_all(&config.archive_dir).unwrap();
+        })
+        .await;
+    }
+}
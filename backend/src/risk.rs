@@ -0,0 +1,620 @@
+use crate::model::{FallLog, MorseScore, RiskScore};
+use crate::storage::stats::DETECTION_SEVERITIES;
+use crate::storage::{
+    EventFilter, NewAuditEntry, NewMorseScore, NewRiskScore, Repository, StatsQuery, StorageError,
+    StorageResult,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use serde_json::json;
+
+/// Days of history a score is computed from when `RISK_WINDOW_DAYS` is not set.
+pub const DEFAULT_WINDOW_DAYS: i64 = 90;
+
+/// Qualitative ratings, lowest first (codes of the FHIR `risk-probability` code system).
+pub const RISK_RATINGS: [&str; 3] = ["low", "moderate", "high"];
+
+/// Why a score was recalculated: a new or dismissed detection, a Morse entry, or on request.
+pub const RISK_REASONS: [&str; 3] = ["event", "morse", "request"];
+
+/// Probabilities from which the model rates a patient moderate, then high risk.
+pub const RATING_PROBABILITIES: [f64; 2] = [0.15, 0.4];
+
+/// Morse Fall Scale totals from which the scale itself rates moderate, then high risk.
+pub const MORSE_RATING_TOTALS: [i32; 2] = [25, 45];
+
+/// Highest possible Morse Fall Scale total.
+pub const MORSE_MAX_TOTAL: i32 = 125;
+
+/// Ward-local hours counted as night (from 22:00 until 06:00).
+pub const NIGHT_HOURS: (i32, i32) = (22, 6);
+
+/// Audit log entity type of Morse Fall Scale entries.
+pub const MORSE_ENTITY: &str = "MorseScore";
+
+// Weights of the logistic model: log-odds of a fall for a patient with no history,
+// then what each factor adds
+const BASELINE_LOG_ODDS: f64 = -3.0;
+const FALL_WEIGHT: f64 = 1.5; // Per fall, up to MAX_COUNTED_FALLS
+const MAX_COUNTED_FALLS: i32 = 3;
+const NEAR_MISS_WEIGHT: f64 = 0.6; // Times ln(1 + near misses)
+const NIGHT_WEIGHT: f64 = 1.0; // Times the share of detections at night
+const MORSE_WEIGHT: f64 = 3.0; // Times the Morse total / MORSE_MAX_TOTAL
+
+/// **Risk Configuration**
+///
+/// - `RISK_WINDOW_DAYS`: days of detections (and of Morse entries) a score looks back on (default 90).
+/// - `RISK_UTC_OFFSET_MINUTES`: offset of ward time from UTC, to tell night from day (default 0).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RiskConfig {
+    pub window_days: i64,
+    pub utc_offset_minutes: i32,
+}
+
+impl Default for RiskConfig {
+    fn default() -> Self {
+        Self {
+            window_days: DEFAULT_WINDOW_DAYS,
+            utc_offset_minutes: 0,
+        }
+    }
+}
+
+impl RiskConfig {
+    pub fn from_env() -> Self {
+        let window_days = std::env::var("RISK_WINDOW_DAYS")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .filter(|d| *d > 0)
+            .unwrap_or(DEFAULT_WINDOW_DAYS);
+        let utc_offset_minutes = std::env::var("RISK_UTC_OFFSET_MINUTES")
+            .ok()
+            .and_then(|v| v.parse::<i32>().ok())
+            .filter(|m| m.abs() <= 14 * 60)
+            .unwrap_or(0);
+        Self {
+            window_days,
+            utc_offset_minutes,
+        }
+    }
+}
+
+/// **Morse Fall Scale Items**
+///
+/// Points scored on each item, as entered by a clinician:
+/// history of falling 0/25, secondary diagnosis 0/15, ambulatory aid 0/15/30 (none or nurse /
+/// crutches, cane or walker / furniture), IV or heparin lock 0/20, gait 0/10/20 (normal / weak /
+/// impaired), mental status 0/15 (oriented to own ability / forgets limitations).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct MorseItems {
+    pub history_of_falling: i32,
+    pub secondary_diagnosis: i32,
+    pub ambulatory_aid: i32,
+    pub iv_therapy: i32,
+    pub gait: i32,
+    pub mental_status: i32,
+}
+
+impl MorseItems {
+    /// Each item with the points it may score.
+    fn scored(&self) -> [(&'static str, i32, &'static [i32]); 6] {
+        [
+            ("history_of_falling", self.history_of_falling, &[0, 25]),
+            ("secondary_diagnosis", self.secondary_diagnosis, &[0, 15]),
+            ("ambulatory_aid", self.ambulatory_aid, &[0, 15, 30]),
+            ("iv_therapy", self.iv_therapy, &[0, 20]),
+            ("gait", self.gait, &[0, 10, 20]),
+            ("mental_status", self.mental_status, &[0, 15]),
+        ]
+    }
+
+    /// The scale total, or which item scored points the scale does not give.
+    pub fn total(&self) -> Result<i32, String> {
+        let mut total = 0;
+        for (item, points, allowed) in self.scored() {
+            if !allowed.contains(&points) {
+                return Err(format!(
+                    "{}: expected one of {:?}, got {}",
+                    item, allowed, points
+                ));
+            }
+            total += points;
+        }
+        Ok(total)
+    }
+}
+
+/// **Risk Factors**
+///
+/// What a score is computed from, over the configured window: near misses, detected falls
+/// (those a nurse dismissed as false alarms are counted apart and do not raise the score), the
+/// share of those detections that happened at night, and the latest Morse Fall Scale total.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RiskFactors {
+    pub near_misses: i32,
+    pub falls: i32,
+    pub false_alarms: i32,
+    pub night_share: f64,
+    pub morse_total: Option<i32>,
+}
+
+impl RiskFactors {
+    /// Probability of a fall from a logistic model over the factors.
+    pub fn probability(&self) -> f64 {
+        let log_odds = BASELINE_LOG_ODDS
+            + FALL_WEIGHT * self.falls.min(MAX_COUNTED_FALLS) as f64
+            + NEAR_MISS_WEIGHT * (self.near_misses as f64).ln_1p()
+            + NIGHT_WEIGHT * self.night_share
+            + MORSE_WEIGHT * self.morse_total.unwrap_or(0) as f64 / MORSE_MAX_TOTAL as f64;
+        1.0 / (1.0 + (-log_odds).exp())
+    }
+
+    /// Qualitative rating: the model's, raised to the Morse Fall Scale's own rating when the
+    /// clinician's entry puts the patient higher.
+    pub fn rating(&self) -> &'static str {
+        let probability = self.probability();
+        let model = RATING_PROBABILITIES
+            .iter()
+            .filter(|p| probability >= **p)
+            .count();
+        let morse = self.morse_total.map_or(0, |total| {
+            MORSE_RATING_TOTALS.iter().filter(|t| total >= **t).count()
+        });
+        RISK_RATINGS[model.max(morse)]
+    }
+}
+
+/// Plain-language summary of the factors behind a score.
+pub fn rationale(score: &RiskScore) -> String {
+    let mut text = format!(
+        "Over the last {} days: {} fall(s) detected ({} more dismissed as false alarms), {} near miss(es), {:.0}% of detections at night",
+        score.window_days,
+        score.falls,
+        score.false_alarms,
+        score.near_misses,
+        score.night_share * 100.0
+    );
+    match score.morse_total {
+        Some(total) => text.push_str(&format!("; Morse Fall Scale {}", total)),
+        None => text.push_str("; no Morse Fall Scale entry"),
+    }
+    text
+}
+
+fn is_night(local_hour: i32) -> bool {
+    local_hour >= NIGHT_HOURS.0 || local_hour < NIGHT_HOURS.1
+}
+
+/// Gathers the factors of a patient's score as of `now`.
+pub async fn factors(
+    db: &dyn Repository,
+    config: &RiskConfig,
+    patient_id: &str,
+    now: DateTime<Utc>,
+) -> StorageResult<RiskFactors> {
+    let since = now - Duration::days(config.window_days);
+    let filter = EventFilter {
+        from: Some(since),
+        to: Some(now),
+        severities: DETECTION_SEVERITIES.map(String::from).to_vec(),
+        patient_id: Some(patient_id.to_string()),
+        ..EventFilter::default()
+    };
+    let all = db
+        .fall_statistics(&StatsQuery {
+            filter: filter.clone(),
+            utc_offset_minutes: config.utc_offset_minutes,
+        })
+        .await?;
+    let kept = db
+        .fall_statistics(&StatsQuery {
+            filter: EventFilter {
+                dismissed: Some(false),
+                ..filter
+            },
+            utc_offset_minutes: config.utc_offset_minutes,
+        })
+        .await?;
+    let count = |stats: &crate::model::FallStatistics, severity: &str| {
+        stats
+            .by_severity
+            .iter()
+            .find(|r| r.severity == severity)
+            .and_then(|r| r.count)
+            .unwrap_or(0) as i32
+    };
+
+    let falls = count(&kept, "Critical");
+    let near_misses = count(&kept, "Near Miss");
+    let at_night: i64 = kept
+        .by_hour
+        .iter()
+        .filter(|b| is_night(b.bucket))
+        .map(|b| b.count)
+        .sum();
+    let detections = falls + near_misses;
+    let morse = db.latest_morse_score(patient_id, since).await?;
+    Ok(RiskFactors {
+        near_misses,
+        falls,
+        false_alarms: count(&all, "Critical") - falls,
+        night_share: if detections == 0 {
+            0.0
+        } else {
+            at_night as f64 / detections as f64
+        },
+        morse_total: morse.map(|m| m.total),
+    })
+}
+
+/// **Recalculate Risk**
+///
+/// Computes the patient's score as of `now` and stores it; earlier scores stay as the history.
+/// `reason` is one of `RISK_REASONS`, `event_id` the detection that prompted it.
+pub async fn recalculate(
+    db: &dyn Repository,
+    config: &RiskConfig,
+    patient_id: &str,
+    reason: &str,
+    event_id: Option<i32>,
+    now: DateTime<Utc>,
+) -> StorageResult<RiskScore> {
+    let factors = factors(db, config, patient_id, now).await?;
+    db.insert_risk_score(&NewRiskScore {
+        patient_id: patient_id.to_string(),
+        calculated_at: now,
+        probability: factors.probability(),
+        rating: factors.rating().to_string(),
+        near_misses: factors.near_misses,
+        falls: factors.falls,
+        false_alarms: factors.false_alarms,
+        night_share: factors.night_share,
+        morse_total: factors.morse_total,
+        window_days: config.window_days as i32,
+        reason: reason.to_string(),
+        event_id,
+    })
+    .await
+}
+
+/// Recalculates the score of the patient a new (or newly dismissed) detection belongs to.
+/// Events without a patient are skipped; failures are logged.
+pub async fn on_event(db: &dyn Repository, config: &RiskConfig, event: &FallLog) {
+    let Some(patient_id) = &event.patient_id else {
+        return;
+    };
+    if let Err(e) = recalculate(db, config, patient_id, "event", Some(event.id), Utc::now()).await {
+        eprintln!(
+            "❌ Failed to recalculate the fall risk of patient {}: {}",
+            patient_id, e
+        );
+    }
+}
+
+/// **Record Morse Fall Scale**
+///
+/// Stores a clinician's Morse entry (written to the audit log) and recalculates the patient's
+/// score with it.
+pub async fn record_morse(
+    db: &dyn Repository,
+    config: &RiskConfig,
+    patient_id: &str,
+    items: &MorseItems,
+    assessed_by: &str,
+    now: DateTime<Utc>,
+) -> StorageResult<(MorseScore, RiskScore)> {
+    if assessed_by.trim().is_empty() {
+        return Err(StorageError::InvalidQuery(
+            "assessed_by is required".to_string(),
+        ));
+    }
+    let total = items.total().map_err(StorageError::InvalidQuery)?;
+    let morse = db
+        .record_morse_score(&NewMorseScore {
+            patient_id: patient_id.to_string(),
+            history_of_falling: items.history_of_falling,
+            secondary_diagnosis: items.secondary_diagnosis,
+            ambulatory_aid: items.ambulatory_aid,
+            iv_therapy: items.iv_therapy,
+            gait: items.gait,
+            mental_status: items.mental_status,
+            total,
+            assessed_by: assessed_by.to_string(),
+            assessed_at: now,
+        })
+        .await?;
+    db.record_audit(NewAuditEntry {
+        recorded_at: now,
+        actor: assessed_by.to_string(),
+        action: "morse.recorded".to_string(),
+        entity_type: MORSE_ENTITY.to_string(),
+        entity_id: Some(morse.id.to_string()),
+        detail: Some(json!({ "patient_id": patient_id, "total": total })),
+    })
+    .await?;
+    let score = recalculate(db, config, patient_id, "morse", None, now).await?;
+    Ok((morse, score))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::{Explanation, FallMetrics};
+    use crate::storage::{AuditQuery, NewEvent, RiskQuery};
+    use crate::test_support::{critical_event, memory_state, newest_alert, on_every_backend};
+    use crate::websockets::{self, ConnectionParams};
+    use chrono::SubsecRound;
+
+    // Helper: a detection at a fixed UTC hour `days_ago`
+    fn detection(severity: &str, now: DateTime<Utc>, days_ago: i64, hour: u32) -> NewEvent {
+        NewEvent {
+            detected_at: (now - Duration::days(days_ago))
+                .date_naive()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap()
+                .and_utc(),
+            severity: severity.to_string(),
+            ..critical_event(0, 2.0)
+        }
+    }
+
+    // Helper: P-1001's detections over the window (night is 22:00-06:00): a fall at 23:00, a
+    // fall at 14:00 dismissed as a false alarm (returned) and near misses at 03:00, 10:00 and
+    // 11:00. A fall outside the window, P-2002's fall and a nurse action do not count.
+    async fn history(repo: &dyn Repository, now: DateTime<Utc>) -> Vec<FallLog> {
+        let mut falls = Vec::new();
+        for (days_ago, hour) in [(2, 23), (3, 14)] {
+            let event = detection("Critical", now, days_ago, hour);
+            falls.push(repo.insert_event(event).await.unwrap());
+        }
+        repo.dismiss_event(falls[1].id, now).await.unwrap();
+        for (days_ago, hour) in [(4, 3), (5, 10), (6, 11)] {
+            let event = detection("Near Miss", now, days_ago, hour);
+            repo.insert_event(event).await.unwrap();
+        }
+        for (severity, days_ago, patient) in [
+            ("Critical", DEFAULT_WINDOW_DAYS + 5, "P-1001"),
+            ("Critical", 2, "P-2002"),
+            ("Assistance Sent", 2, "P-1001"),
+        ] {
+            let event = NewEvent {
+                patient_id: Some(patient.to_string()),
+                ..detection(severity, now, days_ago, 12)
+            };
+            repo.insert_event(event).await.unwrap();
+        }
+        falls
+    }
+
+    // Helper: rn:jones's Morse entry scoring 25 + 15 + 15 + 10 = 65
+    fn morse_items() -> MorseItems {
+        MorseItems {
+            history_of_falling: 25,
+            secondary_diagnosis: 15,
+            ambulatory_aid: 15,
+            iv_therapy: 0,
+            gait: 10,
+            mental_status: 0,
+        }
+    }
+
+    // Without any factor, the risk is low
+    #[test]
+    fn test_baseline_rating() {
+        let baseline = RiskFactors::default();
+        assert!(baseline.probability() < 0.05);
+        assert_eq!(baseline.rating(), "low");
+    }
+
+    // One fall rates moderate, two rate high
+    #[test]
+    fn test_falls_rating() {
+        let falls = |falls| RiskFactors {
+            falls,
+            ..RiskFactors::default()
+        };
+        assert_eq!(falls(1).rating(), "moderate");
+        assert_eq!(falls(2).rating(), "high");
+    }
+
+    // Falls dismissed as false alarms do not raise the risk
+    #[test]
+    fn test_false_alarms_do_not_count() {
+        let dismissed = RiskFactors {
+            false_alarms: 5,
+            ..RiskFactors::default()
+        };
+        assert_eq!(
+            dismissed.probability(),
+            RiskFactors::default().probability()
+        );
+    }
+
+    // A high Morse total rates high by itself, whatever the model's probability
+    #[test]
+    fn test_morse_rating() {
+        let morse_high = RiskFactors {
+            morse_total: Some(45),
+            ..RiskFactors::default()
+        };
+        assert!(morse_high.probability() < 0.15);
+        assert_eq!(morse_high.rating(), "high");
+    }
+
+    // Detections at night raise the risk
+    #[test]
+    fn test_night_share() {
+        let night = RiskFactors {
+            near_misses: 2,
+            night_share: 1.0,
+            ..RiskFactors::default()
+        };
+        let day = RiskFactors {
+            night_share: 0.0,
+            ..night.clone()
+        };
+        assert!(night.probability() > day.probability());
+    }
+
+    // Morse items only score the points the scale gives
+    #[test]
+    fn test_morse_total() {
+        assert_eq!(morse_items().total(), Ok(65));
+        let items = MorseItems {
+            gait: 5,
+            ..morse_items()
+        };
+        assert_eq!(
+            items.total(),
+            Err("gait: expected one of [0, 10, 20], got 5".to_string())
+        );
+    }
+
+    // The factors count the patient's detections over the window and the latest Morse total
+    #[actix_web::test]
+    async fn test_factors() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3); // Within every backend's precision
+            let config = RiskConfig::default();
+            history(repo, now).await;
+            record_morse(repo, &config, "P-1001", &morse_items(), "rn:jones", now)
+                .await
+                .unwrap();
+            let factors = factors(repo, &config, "P-1001", now).await.unwrap();
+            assert_eq!(
+                factors,
+                RiskFactors {
+                    near_misses: 3,
+                    falls: 1,
+                    false_alarms: 1,
+                    night_share: 0.5, // The 23:00 fall and the 03:00 near miss
+                    morse_total: Some(65),
+                }
+            );
+        })
+        .await;
+    }
+
+    // A recalculated score is stored with its factors, window and triggering detection
+    #[actix_web::test]
+    async fn test_recalculate() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3);
+            let config = RiskConfig::default();
+            let falls = history(repo, now).await;
+            let factors = factors(repo, &config, "P-1001", now).await.unwrap();
+            let score = recalculate(repo, &config, "P-1001", "event", Some(falls[0].id), now)
+                .await
+                .unwrap();
+            assert_eq!(score.probability, factors.probability());
+            assert_eq!(score.rating, factors.rating());
+            assert_eq!(score.window_days, 90);
+            assert_eq!(score.event_id, Some(falls[0].id));
+            let other = recalculate(repo, &config, "P-2002", "request", None, now)
+                .await
+                .unwrap();
+            assert_eq!((other.falls, other.near_misses), (1, 0));
+        })
+        .await;
+    }
+
+    // A fall, a near miss, then the fall cancelled as a false alarm: recalculated each time
+    #[actix_web::test]
+    async fn test_recalculated_on_detections() {
+        let state = memory_state();
+        let source = ConnectionParams {
+            device_id: Some("pi-07".to_string()),
+            patient_id: Some("P-3003".to_string()),
+            ward: Some("ICU".to_string()),
+        };
+        let metrics = FallMetrics {
+            stillness_variance: 0.4,
+            validation_ms: 2000,
+            orientation_change_deg: 80.0,
+        };
+        let explain = |g_force| Explanation::verdict(g_force, &metrics);
+        websockets::raise_alert(
+            state.clone(),
+            2.6,
+            metrics,
+            explain(2.6),
+            source.clone(),
+            None,
+        )
+        .await;
+        websockets::record_near_miss(state.clone(), 1.8, metrics, explain(1.8), source, None).await;
+        websockets::record_action(
+            state.clone(),
+            newest_alert(state.db.as_ref()).await,
+            "CANCEL_ALERT",
+            "Refused",
+            true,
+            "Cancelled",
+        )
+        .await;
+        let query = RiskQuery {
+            patient_id: Some("P-3003".to_string()),
+            limit: 10,
+        };
+        let scores = state.db.query_risk_scores(&query).await.unwrap();
+        let counts: Vec<(i32, i32, i32)> = scores
+            .iter()
+            .map(|s| (s.falls, s.near_misses, s.false_alarms))
+            .collect();
+        assert_eq!(counts, vec![(0, 1, 1), (1, 1, 0), (1, 0, 0)]);
+        assert!(scores.iter().all(|s| s.reason == "event"));
+        assert_eq!(scores[0].event_id, scores[2].event_id); // The dismissed detection
+        assert_eq!(scores[2].rating, "moderate");
+        assert!(scores[0].probability < scores[1].probability);
+    }
+
+    // A Morse entry names who assessed the patient
+    #[actix_web::test]
+    async fn test_record_morse_requires_assessor() {
+        let state = memory_state();
+        let config = RiskConfig::default();
+        let recorded = record_morse(
+            state.db.as_ref(),
+            &config,
+            "P-3003",
+            &morse_items(),
+            " ",
+            Utc::now(),
+        )
+        .await;
+        assert!(matches!(recorded, Err(StorageError::InvalidQuery(_))));
+    }
+
+    // A Morse entry is audited and recalculates the score with it
+    #[actix_web::test]
+    async fn test_record_morse() {
+        let state = memory_state();
+        let db = state.db.as_ref();
+        let config = RiskConfig::default();
+        let (morse, score) = record_morse(
+            db,
+            &config,
+            "P-3003",
+            &morse_items(),
+            "rn:jones",
+            Utc::now(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(morse.total, 65);
+        assert_eq!(score.reason, "morse");
+        assert_eq!(score.morse_total, Some(65));
+        assert_eq!(score.rating, "high");
+        let audit = db
+            .audit_entries(&AuditQuery {
+                entity_type: Some(MORSE_ENTITY.to_string()),
+                limit: 10,
+                ..AuditQuery::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(audit.len(), 1);
+        assert_eq!(audit[0].actor, "rn:jones");
+        assert_eq!(audit[0].action, "morse.recorded");
+    }
+}
@@ -0,0 +1,415 @@
+use crate::model::Detection;
+use crate::storage::{DetectionQuery, LabelQuery, Repository, StorageError, StorageResult};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Verdicts on the same device this close together are taken to be the same impact.
+pub const MATCH_WINDOW_MS: i64 = 5_000;
+
+/// Most verdicts read from each side for one report.
+pub const SCAN_LIMIT: i64 = 100_000;
+
+/// Labels are looked up this many events at a time (keeps the `IN` list within bind limits).
+const LABEL_CHUNK: usize = 1_000;
+
+/// How a candidate's decision differed from production's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Disagreement {
+    CandidateFired,  // The candidate would have alerted, production did not
+    CandidateSilent, // Production alerted, the candidate would not have
+}
+
+/// **Disagreement**
+///
+/// One impact the candidate and production decided differently. Either side is `None` when it
+/// reported no verdict for the impact at all. `nurse_outcome` is the label (`fall` or
+/// `no-fall`) the nurse gave production's alert, when there was one.
+#[derive(Debug, Clone, Serialize)]
+pub struct Case {
+    pub kind: Disagreement,
+    pub detected_at: DateTime<Utc>,
+    pub device_id: Option<String>,
+    pub patient_id: Option<String>,
+    pub production: Option<Detection>,
+    pub candidate: Option<Detection>,
+    pub nurse_outcome: Option<String>,
+}
+
+/// **Shadow Report**
+///
+/// A candidate detector's verdicts set against production's over `from`..`to`. Only alert
+/// decisions count: a near-miss on one side and nothing on the other is an agreement.
+/// `missed_confirmed_falls` are falls nurses confirmed that the candidate stayed silent on;
+/// `avoided_false_alarms` are cancelled alerts it stayed silent on. The range should only cover
+/// time the candidate was running, or every production alert before it counts as silent.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShadowReport {
+    pub detector: String,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub production_verdicts: usize,
+    pub candidate_verdicts: usize,
+    pub agreements: usize,
+    pub candidate_fired: usize,
+    pub candidate_silent: usize,
+    pub missed_confirmed_falls: usize,
+    pub avoided_false_alarms: usize,
+    pub cases: Vec<Case>, // Newest first, at most `limit`
+}
+
+/// Compares the shadow-mode `detector` with production over `from`..`to`, keeping the newest
+/// `limit` disagreements.
+pub async fn disagreements(
+    db: &dyn Repository,
+    detector: &str,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    limit: usize,
+) -> StorageResult<ShadowReport> {
+    if detector.is_empty() {
+        return Err(StorageError::InvalidQuery(
+            "detector is required".to_string(),
+        ));
+    }
+    let verdicts = DetectionQuery {
+        outcomes: vec!["critical".to_string(), "near-miss".to_string()],
+        from,
+        to,
+        limit: SCAN_LIMIT,
+        ..DetectionQuery::default()
+    };
+    let production = db
+        .query_detections(&DetectionQuery {
+            shadow: Some(false),
+            ..verdicts.clone()
+        })
+        .await?;
+    let candidate = db
+        .query_detections(&DetectionQuery {
+            detector: Some(detector.to_string()),
+            shadow: Some(true),
+            ..verdicts
+        })
+        .await?;
+    let outcomes = nurse_outcomes(db, &production).await?;
+
+    let mut report = ShadowReport {
+        detector: detector.to_string(),
+        from,
+        to,
+        production_verdicts: production.len(),
+        candidate_verdicts: candidate.len(),
+        agreements: 0,
+        candidate_fired: 0,
+        candidate_silent: 0,
+        missed_confirmed_falls: 0,
+        avoided_false_alarms: 0,
+        cases: Vec::new(),
+    };
+    for (production, candidate) in pair(production, candidate) {
+        let fired = |d: &Option<Detection>| d.as_ref().is_some_and(|d| d.outcome == "critical");
+        let kind = match (fired(&production), fired(&candidate)) {
+            (false, true) => Disagreement::CandidateFired,
+            (true, false) => Disagreement::CandidateSilent,
+            _ => {
+                report.agreements += 1;
+                continue;
+            }
+        };
+        let nurse_outcome = production
+            .as_ref()
+            .and_then(|p| p.event_id)
+            .and_then(|id| outcomes.get(&id).cloned());
+        match kind {
+            Disagreement::CandidateFired => report.candidate_fired += 1,
+            Disagreement::CandidateSilent => {
+                report.candidate_silent += 1;
+                match nurse_outcome.as_deref() {
+                    Some("fall") => report.missed_confirmed_falls += 1,
+                    Some("no-fall") => report.avoided_false_alarms += 1,
+                    _ => {}
+                }
+            }
+        }
+        let seen = production.as_ref().or(candidate.as_ref()).unwrap();
+        report.cases.push(Case {
+            kind,
+            detected_at: seen.detected_at,
+            device_id: seen.device_id.clone(),
+            patient_id: seen.patient_id.clone(),
+            nurse_outcome,
+            production,
+            candidate,
+        });
+    }
+    report
+        .cases
+        .sort_by_key(|c| std::cmp::Reverse(c.detected_at));
+    report.cases.truncate(limit);
+    Ok(report)
+}
+
+/// A candidate verdict and its time, kept after the verdict is matched.
+type Timed = (DateTime<Utc>, Option<Detection>);
+
+/// Matches each production verdict with the closest unmatched candidate verdict on the same
+/// device within `MATCH_WINDOW_MS`. Verdicts left over are paired with `None`.
+fn pair(
+    production: Vec<Detection>,
+    candidate: Vec<Detection>,
+) -> Vec<(Option<Detection>, Option<Detection>)> {
+    let window = chrono::Duration::milliseconds(MATCH_WINDOW_MS);
+    // Per device, by time; a verdict is taken out once matched
+    let mut by_device: HashMap<Option<String>, Vec<Timed>> = HashMap::new();
+    for c in candidate {
+        let verdicts = by_device.entry(c.device_id.clone()).or_default();
+        verdicts.push((c.detected_at, Some(c)));
+    }
+    for verdicts in by_device.values_mut() {
+        verdicts.sort_by_key(|(at, _)| *at);
+    }
+
+    let mut pairs = Vec::new();
+    for p in production {
+        let matched = by_device.get_mut(&p.device_id).and_then(|verdicts| {
+            let start = verdicts.partition_point(|(at, _)| *at < p.detected_at - window);
+            let closest = (start..verdicts.len())
+                .take_while(|&i| verdicts[i].0 <= p.detected_at + window)
+                .filter(|&i| verdicts[i].1.is_some())
+                .min_by_key(|&i| (verdicts[i].0 - p.detected_at).num_milliseconds().abs())?;
+            verdicts[closest].1.take()
+        });
+        pairs.push((Some(p), matched));
+    }
+    for verdicts in by_device.into_values() {
+        pairs.extend(
+            verdicts
+                .into_iter()
+                .filter_map(|(_, c)| Some((None, Some(c?)))),
+        );
+    }
+    pairs
+}
+
+/// The nurse label of each production verdict's event.
+async fn nurse_outcomes(
+    db: &dyn Repository,
+    production: &[Detection],
+) -> StorageResult<HashMap<i32, String>> {
+    let event_ids: Vec<i32> = production
+        .iter()
+        .filter(|d| d.outcome == "critical")
+        .filter_map(|d| d.event_id)
+        .collect();
+    let mut outcomes = HashMap::new();
+    for chunk in event_ids.chunks(LABEL_CHUNK) {
+        let query = LabelQuery {
+            event_ids: chunk.to_vec(),
+            limit: chunk.len() as i64,
+            ..LabelQuery::default()
+        };
+        for label in db.query_labels(&query).await? {
+            outcomes.insert(label.event_id, label.label);
+        }
+    }
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{MemoryRepository, NewDetection, NewLabel};
+    use crate::test_support::critical_event;
+    use chrono::{Duration, SubsecRound};
+
+    // Helper: logs a `detector` verdict on `device` (production when "default")
+    async fn record(
+        db: &dyn Repository,
+        detector: &str,
+        outcome: &str,
+        device: &str,
+        at: DateTime<Utc>,
+        event_id: Option<i32>,
+    ) -> Detection {
+        let detection = NewDetection {
+            detected_at: at,
+            outcome: outcome.to_string(),
+            device_id: Some(device.to_string()),
+            patient_id: None,
+            ward: None,
+            peak_g: 2.0,
+            metrics: None,
+            event_id,
+            explanation: None,
+            detector: detector.to_string(),
+            shadow: detector != "default",
+        };
+        db.record_detection(&detection).await.unwrap()
+    }
+
+    // Helper: an hour of production and "sensitive" verdicts on three beds, two alerts
+    // labelled by the nurse; returns the candidate verdicts production had no alert for, the
+    // one on a bed production never saw first
+    async fn shadowed(db: &dyn Repository) -> [Detection; 2] {
+        // Within every backend's precision
+        let now = Utc::now().trunc_subsecs(3);
+        let at = |minutes_ago: i64| now - Duration::minutes(minutes_ago);
+        let confirmed = db.insert_event(critical_event(50, 2.0)).await.unwrap();
+        let cancelled = db.insert_event(critical_event(40, 2.0)).await.unwrap();
+        let agreed = db.insert_event(critical_event(10, 2.0)).await.unwrap();
+        for (event_id, label) in [(confirmed.id, "fall"), (cancelled.id, "no-fall")] {
+            let label = NewLabel {
+                event_id,
+                alert_id: event_id,
+                label: label.to_string(),
+                command: "CONFIRM_FALL".to_string(),
+                labelled_at: now,
+            };
+            db.record_label(&label).await.unwrap();
+        }
+        // A confirmed fall the candidate saw only as a near miss
+        let fall = at(50);
+        record(db, "default", "critical", "pi-01", fall, Some(confirmed.id)).await;
+        let near = fall + Duration::seconds(1);
+        record(db, "sensitive", "near-miss", "pi-01", near, None).await;
+        // A cancelled alert the candidate ignored
+        record(
+            db,
+            "default",
+            "critical",
+            "pi-01",
+            at(40),
+            Some(cancelled.id),
+        )
+        .await;
+        // The candidate would have alerted on production's near miss, and on a bed it saw alone
+        record(db, "default", "near-miss", "pi-02", at(30), None).await;
+        let later = at(30) + Duration::seconds(2);
+        let fired = record(db, "sensitive", "critical", "pi-02", later, None).await;
+        let alone = record(db, "sensitive", "critical", "pi-03", at(20), None).await;
+        // Both alerted; another candidate and a different bed are not matched
+        record(db, "default", "critical", "pi-01", at(10), Some(agreed.id)).await;
+        record(db, "sensitive", "critical", "pi-01", at(10), None).await;
+        record(db, "strict", "critical", "pi-03", at(20), None).await;
+        [alone, fired]
+    }
+
+    // Production and candidate verdicts are paired per impact and counted by how they differ
+    #[actix_web::test]
+    async fn test_disagreement_counts() {
+        let db = MemoryRepository::new();
+        shadowed(&db).await;
+        let report = disagreements(&db, "sensitive", None, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(report.detector, "sensitive");
+        assert_eq!(report.production_verdicts, 4);
+        assert_eq!(report.candidate_verdicts, 4);
+        assert_eq!(report.agreements, 1);
+        assert_eq!(report.candidate_fired, 2);
+        assert_eq!(report.candidate_silent, 2);
+    }
+
+    // Silent candidates are weighed against the nurse's verdict on production's alert
+    #[actix_web::test]
+    async fn test_disagreement_nurse_outcomes() {
+        let db = MemoryRepository::new();
+        shadowed(&db).await;
+        let report = disagreements(&db, "sensitive", None, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(report.missed_confirmed_falls, 1);
+        assert_eq!(report.avoided_false_alarms, 1);
+    }
+
+    // Cases come newest first
+    #[actix_web::test]
+    async fn test_disagreement_cases_newest_first() {
+        let db = MemoryRepository::new();
+        shadowed(&db).await;
+        let report = disagreements(&db, "sensitive", None, None, 10)
+            .await
+            .unwrap();
+        let kinds: Vec<Disagreement> = report.cases.iter().map(|c| c.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                Disagreement::CandidateFired,
+                Disagreement::CandidateFired,
+                Disagreement::CandidateSilent,
+                Disagreement::CandidateSilent
+            ]
+        );
+    }
+
+    // A candidate alert is shown with production's verdict on the impact, if it had one
+    #[actix_web::test]
+    async fn test_candidate_fired_cases() {
+        let db = MemoryRepository::new();
+        let [alone, fired] = shadowed(&db).await;
+        let report = disagreements(&db, "sensitive", None, None, 10)
+            .await
+            .unwrap();
+        let [first, second, ..] = &report.cases[..] else {
+            panic!("expected four cases")
+        };
+        assert_eq!(first.candidate.as_ref().unwrap().id, alone.id);
+        assert!(first.production.is_none());
+        assert_eq!(second.candidate.as_ref().unwrap().id, fired.id);
+        assert_eq!(second.production.as_ref().unwrap().outcome, "near-miss");
+    }
+
+    // A silent candidate is shown with the nurse's verdict and its own near miss, if any
+    #[actix_web::test]
+    async fn test_candidate_silent_cases() {
+        let db = MemoryRepository::new();
+        shadowed(&db).await;
+        let report = disagreements(&db, "sensitive", None, None, 10)
+            .await
+            .unwrap();
+        let [.., cancelled, confirmed] = &report.cases[..] else {
+            panic!("expected four cases")
+        };
+        assert_eq!(cancelled.nurse_outcome.as_deref(), Some("no-fall"));
+        assert!(cancelled.candidate.is_none());
+        assert_eq!(confirmed.nurse_outcome.as_deref(), Some("fall"));
+        assert_eq!(confirmed.candidate.as_ref().unwrap().outcome, "near-miss");
+    }
+
+    // Only verdicts within the range are compared
+    #[actix_web::test]
+    async fn test_disagreements_in_range() {
+        let db = MemoryRepository::new();
+        shadowed(&db).await;
+        let from = Some(Utc::now() - Duration::minutes(25));
+        let report = disagreements(&db, "sensitive", from, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(report.production_verdicts, 1);
+        assert_eq!(report.candidate_fired, 1);
+        assert_eq!(report.candidate_silent, 0);
+    }
+
+    // The limit keeps the newest cases but every disagreement is still counted
+    #[actix_web::test]
+    async fn test_disagreements_limit() {
+        let db = MemoryRepository::new();
+        let [alone, _] = shadowed(&db).await;
+        let report = disagreements(&db, "sensitive", None, None, 1)
+            .await
+            .unwrap();
+        assert_eq!(report.cases.len(), 1);
+        assert_eq!(report.cases[0].candidate.as_ref().unwrap().id, alone.id);
+        assert_eq!(report.candidate_fired, 2);
+    }
+
+    // The candidate must be named
+    #[actix_web::test]
+    async fn test_disagreements_require_detector() {
+        let db = MemoryRepository::new();
+        let err = disagreements(&db, "", None, None, 10).await.unwrap_err();
+        assert!(matches!(err, StorageError::InvalidQuery(_)));
+    }
+}
@@ -0,0 +1,59 @@
+use crate::model::{Alert, FallLog};
+use sqlx::{Database, Encode, QueryBuilder, Type};
+
+/// Columns selected for every `Alert` row.
+pub const ALERT_COLUMNS: &str = "id, event_id, status, raised_at, updated_at";
+
+/// Every state of the alert lifecycle: Active → Confirmed / Cancelled → Resolved.
+pub const ALERT_STATUSES: [&str; 4] = ["Active", "Confirmed", "Cancelled", "Resolved"];
+
+/// **Alert Query**
+///
+/// Alerts are returned newest first; set fields are combined with AND.
+/// `patient_id` matches the patient of the event that raised the alert.
+#[derive(Debug, Clone, Default)]
+pub struct AlertQuery {
+    pub statuses: Vec<String>, // Matches any of the listed statuses
+    pub event_id: Option<i32>,
+    pub patient_id: Option<String>,
+}
+
+impl AlertQuery {
+    /// In-Rust equivalent of `alerts_query` (used by the in-memory backend).
+    pub fn matches(&self, alert: &Alert, event: Option<&FallLog>) -> bool {
+        (self.statuses.is_empty() || self.statuses.contains(&alert.status))
+            && self.event_id.is_none_or(|id| alert.event_id == id)
+            && self
+                .patient_id
+                .as_ref()
+                .is_none_or(|patient| event.is_some_and(|e| e.patient_id.as_ref() == Some(patient)))
+    }
+}
+
+/// `SELECT` of the alerts matching `query`, newest first.
+pub fn alerts_query<'args, DB>(query: &AlertQuery) -> QueryBuilder<'args, DB>
+where
+    DB: Database,
+    String: Encode<'args, DB> + Type<DB>,
+    i32: Encode<'args, DB> + Type<DB>,
+{
+    let mut qb = QueryBuilder::new(format!("SELECT {} FROM alerts WHERE 1 = 1", ALERT_COLUMNS));
+    if !query.statuses.is_empty() {
+        qb.push(" AND status IN (");
+        let mut list = qb.separated(", ");
+        for status in &query.statuses {
+            list.push_bind(status.clone());
+        }
+        list.push_unseparated(")");
+    }
+    if let Some(event_id) = query.event_id {
+        qb.push(" AND event_id = ").push_bind(event_id);
+    }
+    if let Some(patient_id) = &query.patient_id {
+        qb.push(" AND event_id IN (SELECT id FROM events WHERE patient_id = ")
+            .push_bind(patient_id.clone())
+            .push(")");
+    }
+    qb.push(" ORDER BY id DESC");
+    qb
+}
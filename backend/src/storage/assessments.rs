@@ -0,0 +1,307 @@
+use crate::model::Assessment;
+use chrono::{DateTime, Utc};
+use sqlx::{Database, Encode, QueryBuilder, Type};
+
+/// QuestionnaireResponse statuses an assessment goes through: `in-progress` until the form is
+/// completed, `amended` when answers change afterwards.
+pub const ASSESSMENT_STATUSES: [&str; 3] = ["in-progress", "completed", "amended"];
+
+/// Columns selected for every `Assessment` row.
+pub(crate) const ASSESSMENT_COLUMNS: &str = "id, alert_id, event_id, patient_id, form_id, \
+     form_version, status, answers, author, due_at, created_at, updated_at, completed_at";
+
+/// Starts an assessment ($1..$7 in `NewAssessment` order; nothing answered yet).
+pub(crate) const INSERT_ASSESSMENT: &str = "INSERT INTO assessments \
+     (alert_id, event_id, patient_id, form_id, form_version, due_at, created_at, updated_at) \
+     VALUES ($1, $2, $3, $4, $5, $6, $7, $7)";
+
+/// Saves answers ($2..$6 in `AssessmentUpdate` order).
+pub(crate) const UPDATE_ASSESSMENT: &str = "UPDATE assessments \
+     SET status = $2, answers = $3, author = $4, updated_at = $5, completed_at = $6 WHERE id = $1";
+
+/// **New Assessment**
+///
+/// The post-fall assessment owed for a confirmed alert, due by `due_at`.
+#[derive(Debug, Clone)]
+pub struct NewAssessment {
+    pub alert_id: i32,
+    pub event_id: i32,
+    pub patient_id: Option<String>,
+    pub form_id: String,
+    pub form_version: String,
+    pub due_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Answers saved by a nurse (`answers` is the JSON object stored as is).
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssessmentUpdate {
+    pub status: String,
+    pub answers: String,
+    pub author: Option<String>,
+    pub updated_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// **Assessment Query**
+///
+/// Assessments are returned newest first; set fields are combined with AND.
+/// `due_before` keeps those due at or before that time (with `in-progress`: the overdue list).
+#[derive(Debug, Clone, Default)]
+pub struct AssessmentQuery {
+    pub statuses: Vec<String>, // Matches any of the listed statuses
+    pub alert_id: Option<i32>,
+    pub event_id: Option<i32>,
+    pub patient_id: Option<String>,
+    pub due_before: Option<DateTime<Utc>>,
+}
+
+impl AssessmentQuery {
+    /// In-Rust equivalent of `assessments_query` (used by the in-memory backend).
+    pub fn matches(&self, assessment: &Assessment) -> bool {
+        (self.statuses.is_empty() || self.statuses.contains(&assessment.status))
+            && self.alert_id.is_none_or(|id| assessment.alert_id == id)
+            && self.event_id.is_none_or(|id| assessment.event_id == id)
+            && self
+                .patient_id
+                .as_ref()
+                .is_none_or(|patient| assessment.patient_id.as_ref() == Some(patient))
+            && self.due_before.is_none_or(|at| assessment.due_at <= at)
+    }
+}
+
+/// `SELECT` of the assessments matching `query`, newest first.
+pub fn assessments_query<'args, DB>(query: &AssessmentQuery) -> QueryBuilder<'args, DB>
+where
+    DB: Database,
+    String: Encode<'args, DB> + Type<DB>,
+    i32: Encode<'args, DB> + Type<DB>,
+    DateTime<Utc>: Encode<'args, DB> + Type<DB>,
+{
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT {} FROM assessments WHERE 1 = 1",
+        ASSESSMENT_COLUMNS
+    ));
+    if !query.statuses.is_empty() {
+        qb.push(" AND status IN (");
+        let mut list = qb.separated(", ");
+        for status in &query.statuses {
+            list.push_bind(status.clone());
+        }
+        list.push_unseparated(")");
+    }
+    if let Some(alert_id) = query.alert_id {
+        qb.push(" AND alert_id = ").push_bind(alert_id);
+    }
+    if let Some(event_id) = query.event_id {
+        qb.push(" AND event_id = ").push_bind(event_id);
+    }
+    if let Some(patient_id) = &query.patient_id {
+        qb.push(" AND patient_id = ").push_bind(patient_id.clone());
+    }
+    if let Some(due_before) = query.due_before {
+        qb.push(" AND due_at <= ").push_bind(due_before);
+    }
+    qb.push(" ORDER BY id DESC");
+    qb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Alert;
+    use crate::storage::{DataClass, Repository};
+    use crate::test_support::{critical_event, on_every_backend};
+    use chrono::{Duration, SubsecRound};
+
+    // Helper: alerts for falls 90 and 30 minutes before now
+    async fn two_alerts(repo: &dyn Repository) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for minutes_ago in [90, 30] {
+            let event = repo
+                .insert_event(critical_event(minutes_ago, 2.5))
+                .await
+                .unwrap();
+            alerts.push(repo.open_alert(event.id, event.detected_at).await.unwrap());
+        }
+        alerts
+    }
+
+    // Helper: P-1001's assessment of `alert`, started 90 minutes before `at`
+    fn started(alert: &Alert, at: DateTime<Utc>, due_at: DateTime<Utc>) -> NewAssessment {
+        NewAssessment {
+            alert_id: alert.id,
+            event_id: alert.event_id,
+            patient_id: Some("P-1001".to_string()),
+            form_id: "post-fall-assessment".to_string(),
+            form_version: "1".to_string(),
+            due_at,
+            created_at: at - Duration::minutes(90),
+        }
+    }
+
+    // Helper: the ids of the assessments matching `query`
+    async fn ids(repo: &dyn Repository, query: AssessmentQuery) -> Vec<i32> {
+        let found = repo.query_assessments(&query).await.unwrap();
+        found.iter().map(|a| a.id).collect()
+    }
+
+    // Helper: P-1001 completing the form "no injury" at `at`
+    fn completed(at: DateTime<Utc>) -> AssessmentUpdate {
+        AssessmentUpdate {
+            status: "completed".to_string(),
+            answers: r#"{"injury":"none"}"#.to_string(),
+            author: Some("nurse:ward-3".to_string()),
+            updated_at: at,
+            completed_at: Some(at),
+        }
+    }
+
+    // An assessment starts in progress with nothing answered, and an alert has only one
+    #[actix_web::test]
+    async fn test_create_assessment() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let at = Utc::now().trunc_subsecs(3); // Within every backend's precision
+            let alerts = two_alerts(repo).await;
+            let assessment = repo
+                .create_assessment(&started(&alerts[0], at, at))
+                .await
+                .unwrap();
+            assert_eq!(assessment.status, "in-progress");
+            assert_eq!(assessment.answers, "{}");
+            assert_eq!(assessment.updated_at, assessment.created_at);
+            assert!(repo
+                .create_assessment(&started(&alerts[0], at, at))
+                .await
+                .is_err());
+        })
+        .await;
+    }
+
+    // Assessments are listed newest first, by alert, event and patient
+    #[actix_web::test]
+    async fn test_query_assessments() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let at = Utc::now().trunc_subsecs(3);
+            let alerts = two_alerts(repo).await;
+            let first = repo
+                .create_assessment(&started(&alerts[0], at, at))
+                .await
+                .unwrap();
+            let second = repo
+                .create_assessment(&started(&alerts[1], at, at))
+                .await
+                .unwrap();
+            let all = AssessmentQuery::default();
+            assert_eq!(ids(repo, all).await, vec![second.id, first.id]);
+            let by_alert = AssessmentQuery {
+                alert_id: Some(alerts[1].id),
+                ..AssessmentQuery::default()
+            };
+            assert_eq!(ids(repo, by_alert).await, vec![second.id]);
+            let by_event = AssessmentQuery {
+                event_id: Some(alerts[0].event_id),
+                patient_id: Some("P-1001".to_string()),
+                ..AssessmentQuery::default()
+            };
+            assert_eq!(ids(repo, by_event).await, vec![first.id]);
+            let other_patient = AssessmentQuery {
+                patient_id: Some("P-2002".to_string()),
+                ..AssessmentQuery::default()
+            };
+            assert!(ids(repo, other_patient).await.is_empty());
+        })
+        .await;
+    }
+
+    // The overdue list keeps assessments in progress past their due time, until completed
+    #[actix_web::test]
+    async fn test_overdue_assessments() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let at = Utc::now().trunc_subsecs(3);
+            let alerts = two_alerts(repo).await;
+            let overdue = repo
+                .create_assessment(&started(&alerts[0], at, at - Duration::minutes(30)))
+                .await
+                .unwrap();
+            repo.create_assessment(&started(&alerts[1], at, at + Duration::minutes(30)))
+                .await
+                .unwrap();
+            let overdue_query = AssessmentQuery {
+                statuses: vec!["in-progress".to_string()],
+                due_before: Some(at),
+                ..AssessmentQuery::default()
+            };
+            assert_eq!(ids(repo, overdue_query.clone()).await, vec![overdue.id]);
+            repo.update_assessment(overdue.id, &completed(at))
+                .await
+                .unwrap();
+            assert!(ids(repo, overdue_query).await.is_empty());
+        })
+        .await;
+    }
+
+    // Saved answers, author and completion time are stored; unknown assessments are errors
+    #[actix_web::test]
+    async fn test_update_assessment() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let at = Utc::now().trunc_subsecs(3);
+            let alerts = two_alerts(repo).await;
+            let assessment = repo
+                .create_assessment(&started(&alerts[0], at, at))
+                .await
+                .unwrap();
+            let saved = repo
+                .update_assessment(assessment.id, &completed(at))
+                .await
+                .unwrap();
+            assert_eq!(saved.status, "completed");
+            assert_eq!(saved.answer_map()["injury"], "none");
+            assert_eq!(saved.completed_at, Some(at));
+            let stored = repo.get_assessment(assessment.id).await.unwrap().unwrap();
+            assert_eq!(stored.author.as_deref(), Some("nurse:ward-3"));
+            assert!(repo.update_assessment(999, &completed(at)).await.is_err());
+            assert!(repo.get_assessment(999).await.unwrap().is_none());
+        })
+        .await;
+    }
+
+    // Purging an event archives and deletes its alert's assessment along with it
+    #[actix_web::test]
+    async fn test_purged_with_event() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let at = Utc::now().trunc_subsecs(3);
+            let alerts = two_alerts(repo).await;
+            let purged = repo
+                .create_assessment(&started(&alerts[0], at, at))
+                .await
+                .unwrap();
+            let kept = repo
+                .create_assessment(&started(&alerts[1], at, at))
+                .await
+                .unwrap();
+            repo.update_assessment(purged.id, &completed(at))
+                .await
+                .unwrap();
+            repo.set_alert_status(alerts[0].id, "Resolved", at)
+                .await
+                .unwrap();
+            let archived = repo
+                .expired_records(DataClass::Events, at + Duration::minutes(1), 10)
+                .await
+                .unwrap();
+            let record = archived
+                .iter()
+                .find(|r| r.id == alerts[0].event_id as i64)
+                .unwrap();
+            assert_eq!(record.record["assessments"][0]["id"], purged.id);
+            assert_eq!(record.record["assessments"][0]["answers"]["injury"], "none");
+            repo.delete_records(DataClass::Events, &[alerts[0].event_id as i64])
+                .await
+                .unwrap();
+            assert!(repo.get_assessment(purged.id).await.unwrap().is_none());
+            assert!(repo.get_assessment(kept.id).await.unwrap().is_some());
+        })
+        .await;
+    }
+}
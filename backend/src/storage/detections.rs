@@ -0,0 +1,314 @@
+use crate::logic::{Explanation, FallMetrics};
+use crate::model::Detection;
+use chrono::{DateTime, Utc};
+use sqlx::{Database, Encode, QueryBuilder, Type};
+
+/// Outcomes the detector reports: an impact starts `validating`, its window then ends as a
+/// `critical` fall or a `near-miss` (the wearer moved again).
+pub const DETECTION_OUTCOMES: [&str; 3] = ["validating", "critical", "near-miss"];
+
+/// Columns selected for every `Detection` row.
+pub(crate) const DETECTION_COLUMNS: &str =
+    "id, detected_at, outcome, device_id, patient_id, ward, \
+     peak_g, stillness_variance, validation_ms, orientation_change_deg, event_id, explanation, \
+     detector, shadow";
+
+/// Appends an outcome ($1..$13 in `NewDetection` order, metrics expanded, explanation as JSON).
+pub(crate) const INSERT_DETECTION: &str = "INSERT INTO detections \
+     (detected_at, outcome, device_id, patient_id, ward, peak_g, stillness_variance, \
+     validation_ms, orientation_change_deg, event_id, explanation, detector, shadow) \
+     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)";
+
+/// **New Detection**
+///
+/// A detector outcome to append to `detections`. `metrics` are `None` while validating.
+/// `explanation` is stored as JSON. `detector` names the profile that decided; `shadow` outcomes
+/// come from candidate detectors that never alert.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewDetection {
+    pub detected_at: DateTime<Utc>,
+    pub outcome: String, // One of DETECTION_OUTCOMES
+    pub device_id: Option<String>,
+    pub patient_id: Option<String>,
+    pub ward: Option<String>,
+    pub peak_g: f64,
+    pub metrics: Option<FallMetrics>,
+    pub event_id: Option<i32>,
+    pub explanation: Option<Explanation>,
+    pub detector: String,
+    pub shadow: bool,
+}
+
+/// **Detection Query**
+///
+/// The newest `limit` outcomes; set fields are combined with AND.
+#[derive(Debug, Clone, Default)]
+pub struct DetectionQuery {
+    pub outcomes: Vec<String>, // Matches any of the listed outcomes
+    pub device_id: Option<String>,
+    pub patient_id: Option<String>,
+    pub detector: Option<String>,
+    pub shadow: Option<bool>, // Production (`false`) or candidate (`true`) outcomes only
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: i64,
+}
+
+impl DetectionQuery {
+    /// In-Rust equivalent of `detections_query` (used by the in-memory backend).
+    pub fn matches(&self, detection: &Detection) -> bool {
+        (self.outcomes.is_empty() || self.outcomes.contains(&detection.outcome))
+            && self
+                .device_id
+                .as_ref()
+                .is_none_or(|device| detection.device_id.as_ref() == Some(device))
+            && self
+                .patient_id
+                .as_ref()
+                .is_none_or(|patient| detection.patient_id.as_ref() == Some(patient))
+            && self
+                .detector
+                .as_ref()
+                .is_none_or(|detector| &detection.detector == detector)
+            && self.shadow.is_none_or(|shadow| detection.shadow == shadow)
+            && self.from.is_none_or(|from| detection.detected_at >= from)
+            && self.to.is_none_or(|to| detection.detected_at <= to)
+    }
+}
+
+/// `SELECT` of the outcomes matching `query`, newest first.
+pub fn detections_query<'args, DB>(query: &DetectionQuery) -> QueryBuilder<'args, DB>
+where
+    DB: Database,
+    String: Encode<'args, DB> + Type<DB>,
+    i64: Encode<'args, DB> + Type<DB>,
+    bool: Encode<'args, DB> + Type<DB>,
+    DateTime<Utc>: Encode<'args, DB> + Type<DB>,
+{
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT {} FROM detections WHERE 1 = 1",
+        DETECTION_COLUMNS
+    ));
+    if !query.outcomes.is_empty() {
+        qb.push(" AND outcome IN (");
+        let mut list = qb.separated(", ");
+        for outcome in &query.outcomes {
+            list.push_bind(outcome.clone());
+        }
+        list.push_unseparated(")");
+    }
+    if let Some(device_id) = &query.device_id {
+        qb.push(" AND device_id = ").push_bind(device_id.clone());
+    }
+    if let Some(patient_id) = &query.patient_id {
+        qb.push(" AND patient_id = ").push_bind(patient_id.clone());
+    }
+    if let Some(detector) = &query.detector {
+        qb.push(" AND detector = ").push_bind(detector.clone());
+    }
+    if let Some(shadow) = query.shadow {
+        qb.push(" AND shadow = ").push_bind(shadow);
+    }
+    if let Some(from) = query.from {
+        qb.push(" AND detected_at >= ").push_bind(from);
+    }
+    if let Some(to) = query.to {
+        qb.push(" AND detected_at <= ").push_bind(to);
+    }
+    qb.push(" ORDER BY id DESC LIMIT ").push_bind(query.limit);
+    qb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Repository;
+    use crate::test_support::{critical_event, on_every_backend};
+    use chrono::{Duration, SubsecRound};
+
+    const METRICS: FallMetrics = FallMetrics {
+        stillness_variance: 0.8,
+        validation_ms: 2010,
+        orientation_change_deg: 72.5,
+    };
+
+    // Helper: P-1001's `outcome` on `device` `minutes_ago`, by the production detector
+    fn outcome(
+        outcome: &str,
+        now: DateTime<Utc>,
+        minutes_ago: i64,
+        device: &str,
+        metrics: Option<FallMetrics>,
+    ) -> NewDetection {
+        NewDetection {
+            detected_at: now - Duration::minutes(minutes_ago),
+            outcome: outcome.to_string(),
+            device_id: Some(device.to_string()),
+            patient_id: Some("P-1001".to_string()),
+            ward: Some("ICU".to_string()),
+            peak_g: 2.4,
+            metrics,
+            event_id: None,
+            explanation: metrics.map(|m| Explanation::verdict(2.4, &m)),
+            detector: "default".to_string(),
+            shadow: false,
+        }
+    }
+
+    // Helper: a validating impact on pi-01 30 minutes ago, its critical verdict a minute later
+    // and a near miss on pi-02 10 minutes ago, oldest first; then a shadow candidate's verdict
+    async fn logged(repo: &dyn Repository, now: DateTime<Utc>) -> Vec<i32> {
+        let mut ids = Vec::new();
+        for detection in [
+            outcome("validating", now, 30, "pi-01", None),
+            outcome("critical", now, 29, "pi-01", Some(METRICS)),
+            outcome("near-miss", now, 10, "pi-02", Some(METRICS)),
+            NewDetection {
+                detector: "candidate".to_string(),
+                shadow: true,
+                ..outcome("critical", now, 5, "pi-02", Some(METRICS))
+            },
+        ] {
+            ids.push(repo.record_detection(&detection).await.unwrap().id);
+        }
+        ids
+    }
+
+    // Helper: the ids of the outcomes matching `query`
+    async fn ids(repo: &dyn Repository, query: DetectionQuery) -> Vec<i32> {
+        let rows = repo.query_detections(&query).await.unwrap();
+        rows.iter().map(|d| d.id).collect()
+    }
+
+    // Helper: up to 10 of production's outcomes
+    fn production() -> DetectionQuery {
+        DetectionQuery {
+            limit: 10,
+            shadow: Some(false),
+            ..DetectionQuery::default()
+        }
+    }
+
+    // A verdict keeps its features, explanation, event and detector
+    #[actix_web::test]
+    async fn test_record_detection() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3); // Within every backend's precision
+            let event = repo.insert_event(critical_event(0, 2.4)).await.unwrap();
+            let critical = repo
+                .record_detection(&NewDetection {
+                    event_id: Some(event.id),
+                    ..outcome("critical", now, 29, "pi-01", Some(METRICS))
+                })
+                .await
+                .unwrap();
+            assert_eq!(critical.outcome, "critical");
+            assert_eq!(critical.detected_at, now - Duration::minutes(29));
+            assert_eq!(critical.stillness_variance, Some(0.8));
+            assert_eq!(critical.validation_ms, Some(2010));
+            assert_eq!(critical.orientation_change_deg, Some(72.5));
+            assert_eq!(critical.event_id, Some(event.id));
+            assert_eq!(
+                critical.explanation(),
+                Some(Explanation::verdict(2.4, &METRICS))
+            );
+            assert_eq!(critical.detector, "default");
+            assert!(!critical.shadow);
+        })
+        .await;
+    }
+
+    // An impact still validating has no features yet
+    #[actix_web::test]
+    async fn test_record_validating() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3);
+            let validating = repo
+                .record_detection(&outcome("validating", now, 30, "pi-01", None))
+                .await
+                .unwrap();
+            assert_eq!(validating.stillness_variance, None);
+            assert_eq!(validating.validation_ms, None);
+            assert_eq!(validating.explanation(), None);
+        })
+        .await;
+    }
+
+    // Production's outcomes are listed newest first, up to the limit
+    #[actix_web::test]
+    async fn test_query_detections() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3);
+            let logged = logged(repo, now).await;
+            assert_eq!(
+                ids(repo, production()).await,
+                vec![logged[2], logged[1], logged[0]]
+            );
+            let newest = DetectionQuery {
+                limit: 1,
+                ..production()
+            };
+            assert_eq!(ids(repo, newest).await, vec![logged[2]]);
+        })
+        .await;
+    }
+
+    // Outcome, device, time window and patient filters combine
+    #[actix_web::test]
+    async fn test_query_detections_filters() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3);
+            let logged = logged(repo, now).await;
+            let verdicts = DetectionQuery {
+                outcomes: vec!["critical".to_string(), "near-miss".to_string()],
+                ..production()
+            };
+            assert_eq!(
+                ids(repo, verdicts.clone()).await,
+                vec![logged[2], logged[1]]
+            );
+            let device = DetectionQuery {
+                device_id: Some("pi-01".to_string()),
+                ..verdicts
+            };
+            assert_eq!(ids(repo, device).await, vec![logged[1]]);
+            let window = DetectionQuery {
+                from: Some(now - Duration::minutes(29)),
+                to: Some(now - Duration::minutes(10)),
+                ..production()
+            };
+            assert_eq!(ids(repo, window).await, vec![logged[2], logged[1]]);
+            let other_patient = DetectionQuery {
+                patient_id: Some("P-2002".to_string()),
+                ..production()
+            };
+            assert!(ids(repo, other_patient).await.is_empty());
+        })
+        .await;
+    }
+
+    // Shadow candidates' outcomes are kept apart, by shadow flag or detector name
+    #[actix_web::test]
+    async fn test_query_shadow_detections() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3);
+            let logged = logged(repo, now).await;
+            let production_only = repo.query_detections(&production()).await.unwrap();
+            assert!(production_only
+                .iter()
+                .all(|d| d.detector == "default" && !d.shadow));
+            let shadow = DetectionQuery {
+                shadow: Some(true),
+                ..production()
+            };
+            assert_eq!(ids(repo, shadow).await, vec![logged[3]]);
+            let by_detector = DetectionQuery {
+                detector: Some("candidate".to_string()),
+                shadow: None,
+                ..production()
+            };
+            assert_eq!(ids(repo, by_detector).await, vec![logged[3]]);
+        })
+        .await;
+    }
+}
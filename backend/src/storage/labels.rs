@@ -0,0 +1,242 @@
+use crate::model::Label;
+use chrono::{DateTime, Utc};
+use sqlx::{Database, Encode, QueryBuilder, Type};
+
+/// Labels a nurse decision gives the detection: `CONFIRM_FALL` is a `fall`, `CANCEL_ALERT` a
+/// `no-fall`.
+pub const LABELS: [&str; 2] = ["fall", "no-fall"];
+
+/// Columns selected for every `Label` row.
+pub(crate) const LABEL_COLUMNS: &str = "id, event_id, alert_id, label, command, labelled_at";
+
+/// Stores a label ($1..$5 in `NewLabel` order).
+pub(crate) const INSERT_LABEL: &str = "INSERT INTO labels \
+     (event_id, alert_id, label, command, labelled_at) VALUES ($1, $2, $3, $4, $5)";
+
+/// **New Label**
+///
+/// The outcome a nurse gave the detection behind an alert. One label per detection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewLabel {
+    pub event_id: i32,
+    pub alert_id: i32,
+    pub label: String,   // One of LABELS
+    pub command: String, // The nurse command it came from
+    pub labelled_at: DateTime<Utc>,
+}
+
+/// **Label Query**
+///
+/// The newest `limit` labels; set fields are combined with AND. `from`/`to` bound the time of
+/// the decision.
+#[derive(Debug, Clone, Default)]
+pub struct LabelQuery {
+    pub label: Option<String>,
+    pub event_ids: Vec<i32>, // Matches any of the listed detections
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: i64,
+}
+
+impl LabelQuery {
+    /// In-Rust equivalent of `labels_query` (used by the in-memory backend).
+    pub fn matches(&self, label: &Label) -> bool {
+        self.label.as_ref().is_none_or(|l| &label.label == l)
+            && (self.event_ids.is_empty() || self.event_ids.contains(&label.event_id))
+            && self.from.is_none_or(|from| label.labelled_at >= from)
+            && self.to.is_none_or(|to| label.labelled_at <= to)
+    }
+}
+
+/// `SELECT` of the labels matching `query`, newest first.
+pub fn labels_query<'args, DB>(query: &LabelQuery) -> QueryBuilder<'args, DB>
+where
+    DB: Database,
+    String: Encode<'args, DB> + Type<DB>,
+    i32: Encode<'args, DB> + Type<DB>,
+    i64: Encode<'args, DB> + Type<DB>,
+    DateTime<Utc>: Encode<'args, DB> + Type<DB>,
+{
+    let mut qb = QueryBuilder::new(format!("SELECT {} FROM labels WHERE 1 = 1", LABEL_COLUMNS));
+    if let Some(label) = &query.label {
+        qb.push(" AND label = ").push_bind(label.clone());
+    }
+    if !query.event_ids.is_empty() {
+        qb.push(" AND event_id IN (");
+        let mut list = qb.separated(", ");
+        for id in &query.event_ids {
+            list.push_bind(*id);
+        }
+        list.push_unseparated(")");
+    }
+    if let Some(from) = query.from {
+        qb.push(" AND labelled_at >= ").push_bind(from);
+    }
+    if let Some(to) = query.to {
+        qb.push(" AND labelled_at <= ").push_bind(to);
+    }
+    qb.push(" ORDER BY id DESC LIMIT ").push_bind(query.limit);
+    qb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{FallLog, Waveform};
+    use crate::storage::{DataClass, Repository};
+    use crate::test_support::{critical_event, on_every_backend};
+    use chrono::{Duration, SubsecRound};
+
+    // Helper: the nurse's `label` on `event` `minutes_ago` (its alert id is the event's + 100)
+    fn label(event: &FallLog, label: &str, now: DateTime<Utc>, minutes_ago: i64) -> NewLabel {
+        let command = if label == "fall" {
+            "CONFIRM_FALL"
+        } else {
+            "CANCEL_ALERT"
+        };
+        NewLabel {
+            event_id: event.id,
+            alert_id: event.id + 100,
+            label: label.to_string(),
+            command: command.to_string(),
+            labelled_at: now - Duration::minutes(minutes_ago),
+        }
+    }
+
+    // Helper: a fall confirmed 19 minutes ago and a bump cancelled 9 minutes ago (the labels'
+    // ids, then the events)
+    async fn labelled(repo: &dyn Repository, now: DateTime<Utc>) -> (Vec<i32>, Vec<FallLog>) {
+        let fall = repo.insert_event(critical_event(20, 2.9)).await.unwrap();
+        let bump = repo.insert_event(critical_event(10, 1.9)).await.unwrap();
+        let confirmed = repo.record_label(&label(&fall, "fall", now, 19)).await;
+        let cancelled = repo.record_label(&label(&bump, "no-fall", now, 9)).await;
+        let ids = vec![confirmed.unwrap().id, cancelled.unwrap().id];
+        (ids, vec![fall, bump])
+    }
+
+    // Helper: the ids of the labels matching `query`
+    async fn ids(repo: &dyn Repository, query: LabelQuery) -> Vec<i32> {
+        let rows = repo.query_labels(&query).await.unwrap();
+        rows.iter().map(|l| l.id).collect()
+    }
+
+    // Helper: up to 10 labels
+    fn all() -> LabelQuery {
+        LabelQuery {
+            limit: 10,
+            ..LabelQuery::default()
+        }
+    }
+
+    // A label keeps its detection, alert, command and time
+    #[actix_web::test]
+    async fn test_record_label() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3); // Within every backend's precision
+            let fall = repo.insert_event(critical_event(20, 2.9)).await.unwrap();
+            let confirmed = repo
+                .record_label(&label(&fall, "fall", now, 19))
+                .await
+                .unwrap();
+            assert_eq!(confirmed.event_id, fall.id);
+            assert_eq!(confirmed.alert_id, fall.id + 100);
+            assert_eq!(confirmed.command, "CONFIRM_FALL");
+            assert_eq!(confirmed.labelled_at, now - Duration::minutes(19));
+        })
+        .await;
+    }
+
+    // A detection has only one label
+    #[actix_web::test]
+    async fn test_one_label_per_detection() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3);
+            let (_, events) = labelled(repo, now).await;
+            let relabelled = label(&events[0], "no-fall", now, 5);
+            assert!(repo.record_label(&relabelled).await.is_err());
+        })
+        .await;
+    }
+
+    // Labels are listed newest first, by label and detection
+    #[actix_web::test]
+    async fn test_query_labels() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3);
+            let (labels, events) = labelled(repo, now).await;
+            assert_eq!(ids(repo, all()).await, vec![labels[1], labels[0]]);
+            let falls = LabelQuery {
+                label: Some("fall".to_string()),
+                ..all()
+            };
+            assert_eq!(ids(repo, falls).await, vec![labels[0]]);
+            let bump = events[1].id;
+            let by_event = LabelQuery {
+                event_ids: vec![bump, bump + 1000],
+                ..all()
+            };
+            assert_eq!(ids(repo, by_event).await, vec![labels[1]]);
+        })
+        .await;
+    }
+
+    // Labels are listed by the time of the decision, up to the limit
+    #[actix_web::test]
+    async fn test_query_labels_by_time() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3);
+            let (labels, _) = labelled(repo, now).await;
+            let recent = LabelQuery {
+                from: Some(now - Duration::minutes(15)),
+                ..all()
+            };
+            assert_eq!(ids(repo, recent).await, vec![labels[1]]);
+            let older = LabelQuery {
+                to: Some(now - Duration::minutes(15)),
+                limit: 1,
+                ..LabelQuery::default()
+            };
+            assert_eq!(ids(repo, older).await, vec![labels[0]]);
+        })
+        .await;
+    }
+
+    // The export reads a page of waveforms at once; events without one are left out
+    #[actix_web::test]
+    async fn test_get_waveforms() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3);
+            let (_, events) = labelled(repo, now).await;
+            let fall = &events[0];
+            repo.save_waveform(&Waveform {
+                event_id: fall.id,
+                device_id: "pi-01".to_string(),
+                impact_at: fall.detected_at,
+                started_at: fall.detected_at,
+                ended_at: fall.detected_at,
+                points: Vec::new(),
+            })
+            .await
+            .unwrap();
+            let waveforms = repo.get_waveforms(&[fall.id, events[1].id]).await.unwrap();
+            let event_ids: Vec<i32> = waveforms.iter().map(|w| w.event_id).collect();
+            assert_eq!(event_ids, vec![fall.id]);
+            assert!(repo.get_waveforms(&[]).await.unwrap().is_empty());
+        })
+        .await;
+    }
+
+    // Purging an event deletes its label
+    #[actix_web::test]
+    async fn test_purged_with_event() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3);
+            let (labels, events) = labelled(repo, now).await;
+            repo.delete_records(DataClass::Events, &[events[1].id as i64])
+                .await
+                .unwrap();
+            assert_eq!(ids(repo, all()).await, vec![labels[0]]);
+        })
+        .await;
+    }
+}
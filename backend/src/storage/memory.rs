@@ -0,0 +1,1072 @@
+use super::{
+    explanation_json, retention, rollup, stats, AlertQuery, ArchiveRecord, AssessmentQuery,
+    AssessmentUpdate, AuditQuery, DataClass, DetectionQuery, DeviceRegistration, DeviceStatus,
+    EventPage, EventQuery, LabelQuery, NewAssessment, NewAuditEntry, NewDetection, NewEvent,
+    NewLabel, NewLegalHold, NewMorseScore, NewOutboundMessage, NewPatient, NewRiskScore,
+    NewSubscription, OutboundUpdate, Repository, RiskQuery, StatsQuery, StorageError,
+    StorageResult, OPEN_ALERT_STATUSES,
+};
+use crate::model::{
+    Alert, Assessment, AuditEntry, Detection, Device, FallLog, FallStatistics, Label, LegalHold,
+    MorseScore, OutboundMessage, Patient, RiskScore, Subscription, TelemetryRollup,
+    TelemetrySample, Waveform,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+
+/// **In-Memory Repository**
+///
+/// Keeps everything in process memory. Used when no `DATABASE_URL` is set and by the tests.
+#[derive(Default)]
+pub struct MemoryRepository {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    events: Vec<FallLog>,
+    detections: Vec<Detection>,
+    labels: Vec<Label>,
+    alerts: Vec<Alert>,
+    assessments: Vec<Assessment>,
+    morse_scores: Vec<MorseScore>,
+    risk_scores: Vec<RiskScore>,
+    telemetry: Vec<(i64, TelemetrySample)>, // With the row id a SQL backend would assign
+    rollups: BTreeMap<(String, i64, DateTime<Utc>), (i64, TelemetryRollup)>,
+    waveforms: Vec<Waveform>,
+    audit: Vec<AuditEntry>,
+    legal_holds: Vec<LegalHold>,
+    patients: BTreeMap<String, Patient>,
+    devices: BTreeMap<String, Device>,
+    subscriptions: BTreeMap<i32, Subscription>,
+    outbox: Vec<OutboundMessage>,
+    last_ids: LastIds,
+}
+
+/// Last id handed out per table, so ids are never reused after a purge (like SQL sequences).
+#[derive(Default)]
+struct LastIds {
+    event: i32,
+    detection: i32,
+    label: i32,
+    alert: i32,
+    assessment: i32,
+    morse: i32,
+    risk: i32,
+    telemetry: i32,
+    rollup: i32,
+    audit: i32,
+    subscription: i32,
+    outbound: i32,
+}
+
+fn next_id(last: &mut i32) -> i32 {
+    *last += 1;
+    *last
+}
+
+impl Inner {
+    /// Same rule as the SQL `HELD_EVENT` condition.
+    fn is_held(&self, event: &FallLog) -> bool {
+        self.legal_holds
+            .iter()
+            .filter(|h| h.released_at.is_none())
+            .any(|h| {
+                (h.patient_id.is_some() && h.patient_id == event.patient_id)
+                    || h.alert_id.is_some_and(|alert_id| {
+                        self.alerts
+                            .iter()
+                            .any(|a| a.id == alert_id && a.event_id == event.id)
+                    })
+            })
+    }
+
+    /// Same rule as the SQL `HELD_DETECTION` condition.
+    fn is_detection_held(&self, detection: &Detection) -> bool {
+        self.legal_holds
+            .iter()
+            .filter(|h| h.released_at.is_none())
+            .any(|h| {
+                (h.patient_id.is_some() && h.patient_id == detection.patient_id)
+                    || h.alert_id.is_some_and(|alert_id| {
+                        self.alerts
+                            .iter()
+                            .any(|a| a.id == alert_id && Some(a.event_id) == detection.event_id)
+                    })
+            })
+    }
+
+    /// Same rule as the SQL `held_device` condition.
+    fn is_device_held(&self, device_id: &str) -> bool {
+        self.legal_holds
+            .iter()
+            .filter(|h| h.released_at.is_none())
+            .any(|h| {
+                let worn = h.patient_id.is_some()
+                    && self
+                        .devices
+                        .get(device_id)
+                        .is_some_and(|d| d.patient_id == h.patient_id);
+                worn || self
+                    .events
+                    .iter()
+                    .filter(|e| e.device_id.as_deref() == Some(device_id))
+                    .any(|e| {
+                        (h.patient_id.is_some() && h.patient_id == e.patient_id)
+                            || h.alert_id.is_some_and(|alert_id| {
+                                self.alerts
+                                    .iter()
+                                    .any(|a| a.id == alert_id && a.event_id == e.id)
+                            })
+                    })
+            })
+    }
+
+    /// Same rule as the SQL `HELD_AUDIT` condition.
+    fn is_audit_held(&self, entry: &AuditEntry) -> bool {
+        let Some(entity_id) = entry.entity_id.as_deref() else {
+            return false;
+        };
+        let is = |id: i32| entity_id == id.to_string();
+        self.legal_holds
+            .iter()
+            .filter(|h| h.released_at.is_none())
+            .any(|h| {
+                let patient = |p: &Option<String>| h.patient_id.is_some() && &h.patient_id == p;
+                match entry.entity_type.as_str() {
+                    "LegalHold" => is(h.id),
+                    "Patient" => h.patient_id.as_deref() == Some(entity_id),
+                    "Alert" => self.alerts.iter().filter(|a| is(a.id)).any(|a| {
+                        h.alert_id == Some(a.id)
+                            || self
+                                .events
+                                .iter()
+                                .any(|e| e.id == a.event_id && patient(&e.patient_id))
+                    }),
+                    "Assessment" => self
+                        .assessments
+                        .iter()
+                        .filter(|s| is(s.id))
+                        .any(|s| h.alert_id == Some(s.alert_id) || patient(&s.patient_id)),
+                    "MorseScore" => self
+                        .morse_scores
+                        .iter()
+                        .filter(|m| is(m.id))
+                        .any(|m| h.patient_id.as_deref() == Some(m.patient_id.as_str())),
+                    _ => false,
+                }
+            })
+    }
+
+    fn held_event_ids(&self) -> Vec<i32> {
+        self.events
+            .iter()
+            .filter(|e| self.is_held(e))
+            .map(|e| e.id)
+            .collect()
+    }
+}
+
+/// A device row with the SQL column defaults.
+fn new_device(id: &str, at: DateTime<Utc>) -> Device {
+    Device {
+        id: id.to_string(),
+        model: None,
+        patient_id: None,
+        ward: None,
+        calibration_state: "unspecified".to_string(),
+        calibrated_at: None,
+        battery_level: None,
+        wifi_signal: None,
+        temperature: None,
+        last_seen_at: None,
+        registered_at: at,
+    }
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Repository for MemoryRepository {
+    async fn insert_event(&self, event: NewEvent) -> StorageResult<FallLog> {
+        let mut inner = self.inner.lock().unwrap();
+        let log = FallLog {
+            id: next_id(&mut inner.last_ids.event),
+            detected_at: event.detected_at,
+            severity: event.severity,
+            g_force_value: event.g_force_value,
+            is_false_alarm: event.is_false_alarm,
+            device_id: event.device_id,
+            patient_id: event.patient_id,
+            ward: event.ward,
+            stillness_variance: event.metrics.map(|m| m.stillness_variance),
+            validation_ms: event.metrics.map(|m| m.validation_ms),
+            orientation_change_deg: event.metrics.map(|m| m.orientation_change_deg),
+            dismissed_at: None,
+            explanation: explanation_json(event.explanation.as_ref()),
+        };
+        inner.events.push(log.clone());
+        Ok(log)
+    }
+
+    async fn get_event(&self, id: i32) -> StorageResult<Option<FallLog>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.events.iter().find(|e| e.id == id).cloned())
+    }
+
+    async fn query_events(&self, query: &EventQuery) -> StorageResult<EventPage> {
+        let inner = self.inner.lock().unwrap();
+        let mut matching: Vec<FallLog> = inner
+            .events
+            .iter()
+            .filter(|e| query.filter.matches(e))
+            .cloned()
+            .collect();
+        let total = matching.len() as i64;
+
+        matching.sort_by(|a, b| query.sort.compare(a, b));
+        let rows: Vec<FallLog> = matching
+            .into_iter()
+            .filter(|e| query.cursor.is_none_or(|c| c.precedes(e, &query.sort)))
+            .take(query.limit.max(0) as usize + 1)
+            .collect();
+
+        Ok(EventPage::from_rows(rows, total, query))
+    }
+
+    async fn fall_statistics(&self, query: &StatsQuery) -> StorageResult<FallStatistics> {
+        let inner = self.inner.lock().unwrap();
+        let false_alarm_ids = inner
+            .labels
+            .iter()
+            .filter(|l| l.label == stats::FALSE_ALARM_LABEL)
+            .map(|l| l.event_id)
+            .collect();
+        Ok(stats::compute(&inner.events, &false_alarm_ids, query))
+    }
+
+    async fn dismiss_event(&self, event_id: i32, at: DateTime<Utc>) -> StorageResult<FallLog> {
+        let mut inner = self.inner.lock().unwrap();
+        let event = inner
+            .events
+            .iter_mut()
+            .find(|e| e.id == event_id)
+            .ok_or_else(|| StorageError::NotFound(format!("event {}", event_id)))?;
+        event.dismissed_at.get_or_insert(at);
+        Ok(event.clone())
+    }
+
+    async fn events_after(&self, after_id: i32, limit: i64) -> StorageResult<Vec<FallLog>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .events
+            .iter()
+            .filter(|e| e.id > after_id)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn latest_event_id(&self) -> StorageResult<Option<i32>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.events.iter().map(|e| e.id).max())
+    }
+
+    async fn record_detection(&self, detection: &NewDetection) -> StorageResult<Detection> {
+        let mut inner = self.inner.lock().unwrap();
+        let row = Detection {
+            id: next_id(&mut inner.last_ids.detection),
+            detected_at: detection.detected_at,
+            outcome: detection.outcome.clone(),
+            device_id: detection.device_id.clone(),
+            patient_id: detection.patient_id.clone(),
+            ward: detection.ward.clone(),
+            peak_g: detection.peak_g,
+            stillness_variance: detection.metrics.map(|m| m.stillness_variance),
+            validation_ms: detection.metrics.map(|m| m.validation_ms),
+            orientation_change_deg: detection.metrics.map(|m| m.orientation_change_deg),
+            event_id: detection.event_id,
+            explanation: explanation_json(detection.explanation.as_ref()),
+            detector: detection.detector.clone(),
+            shadow: detection.shadow,
+        };
+        inner.detections.push(row.clone());
+        Ok(row)
+    }
+
+    async fn query_detections(&self, query: &DetectionQuery) -> StorageResult<Vec<Detection>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .detections
+            .iter()
+            .rev()
+            .filter(|d| query.matches(d))
+            .take(query.limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn record_label(&self, label: &NewLabel) -> StorageResult<Label> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.labels.iter().any(|l| l.event_id == label.event_id) {
+            return Err(StorageError::InvalidQuery(format!(
+                "event {} is already labelled",
+                label.event_id
+            )));
+        }
+        let row = Label {
+            id: next_id(&mut inner.last_ids.label),
+            event_id: label.event_id,
+            alert_id: label.alert_id,
+            label: label.label.clone(),
+            command: label.command.clone(),
+            labelled_at: label.labelled_at,
+        };
+        inner.labels.push(row.clone());
+        Ok(row)
+    }
+
+    async fn query_labels(&self, query: &LabelQuery) -> StorageResult<Vec<Label>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .labels
+            .iter()
+            .rev()
+            .filter(|l| query.matches(l))
+            .take(query.limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn open_alert(&self, event_id: i32, raised_at: DateTime<Utc>) -> StorageResult<Alert> {
+        let mut inner = self.inner.lock().unwrap();
+        let alert = Alert {
+            id: next_id(&mut inner.last_ids.alert),
+            event_id,
+            status: "Active".to_string(),
+            raised_at,
+            updated_at: raised_at,
+        };
+        inner.alerts.push(alert.clone());
+        Ok(alert)
+    }
+
+    async fn latest_open_alert(&self) -> StorageResult<Option<Alert>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .alerts
+            .iter()
+            .filter(|a| OPEN_ALERT_STATUSES.contains(&a.status.as_str()))
+            .max_by_key(|a| a.raised_at)
+            .cloned())
+    }
+
+    async fn get_alert(&self, id: i32) -> StorageResult<Option<Alert>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.alerts.iter().find(|a| a.id == id).cloned())
+    }
+
+    async fn query_alerts(&self, query: &AlertQuery) -> StorageResult<Vec<Alert>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .alerts
+            .iter()
+            .rev()
+            .filter(|a| {
+                let event = inner.events.iter().find(|e| e.id == a.event_id);
+                query.matches(a, event)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn set_alert_status(
+        &self,
+        alert_id: i32,
+        status: &str,
+        at: DateTime<Utc>,
+    ) -> StorageResult<Alert> {
+        let mut inner = self.inner.lock().unwrap();
+        let alert = inner
+            .alerts
+            .iter_mut()
+            .find(|a| a.id == alert_id)
+            .ok_or_else(|| StorageError::NotFound(format!("alert {}", alert_id)))?;
+        alert.status = status.to_string();
+        alert.updated_at = at;
+        Ok(alert.clone())
+    }
+
+    async fn create_assessment(&self, assessment: &NewAssessment) -> StorageResult<Assessment> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner
+            .assessments
+            .iter()
+            .any(|a| a.alert_id == assessment.alert_id)
+        {
+            return Err(StorageError::InvalidQuery(format!(
+                "alert {} already has an assessment",
+                assessment.alert_id
+            )));
+        }
+        let row = Assessment {
+            id: next_id(&mut inner.last_ids.assessment),
+            alert_id: assessment.alert_id,
+            event_id: assessment.event_id,
+            patient_id: assessment.patient_id.clone(),
+            form_id: assessment.form_id.clone(),
+            form_version: assessment.form_version.clone(),
+            status: "in-progress".to_string(),
+            answers: "{}".to_string(),
+            author: None,
+            due_at: assessment.due_at,
+            created_at: assessment.created_at,
+            updated_at: assessment.created_at,
+            completed_at: None,
+        };
+        inner.assessments.push(row.clone());
+        Ok(row)
+    }
+
+    async fn get_assessment(&self, id: i32) -> StorageResult<Option<Assessment>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.assessments.iter().find(|a| a.id == id).cloned())
+    }
+
+    async fn query_assessments(&self, query: &AssessmentQuery) -> StorageResult<Vec<Assessment>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .assessments
+            .iter()
+            .rev()
+            .filter(|a| query.matches(a))
+            .cloned()
+            .collect())
+    }
+
+    async fn update_assessment(
+        &self,
+        id: i32,
+        update: &AssessmentUpdate,
+    ) -> StorageResult<Assessment> {
+        let mut inner = self.inner.lock().unwrap();
+        let assessment = inner
+            .assessments
+            .iter_mut()
+            .find(|a| a.id == id)
+            .ok_or_else(|| StorageError::NotFound(format!("assessment {}", id)))?;
+        assessment.status = update.status.clone();
+        assessment.answers = update.answers.clone();
+        assessment.author = update.author.clone();
+        assessment.updated_at = update.updated_at;
+        assessment.completed_at = update.completed_at;
+        Ok(assessment.clone())
+    }
+
+    async fn record_morse_score(&self, score: &NewMorseScore) -> StorageResult<MorseScore> {
+        let mut inner = self.inner.lock().unwrap();
+        let row = MorseScore {
+            id: next_id(&mut inner.last_ids.morse),
+            patient_id: score.patient_id.clone(),
+            history_of_falling: score.history_of_falling,
+            secondary_diagnosis: score.secondary_diagnosis,
+            ambulatory_aid: score.ambulatory_aid,
+            iv_therapy: score.iv_therapy,
+            gait: score.gait,
+            mental_status: score.mental_status,
+            total: score.total,
+            assessed_by: score.assessed_by.clone(),
+            assessed_at: score.assessed_at,
+        };
+        inner.morse_scores.push(row.clone());
+        Ok(row)
+    }
+
+    async fn latest_morse_score(
+        &self,
+        patient_id: &str,
+        since: DateTime<Utc>,
+    ) -> StorageResult<Option<MorseScore>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .morse_scores
+            .iter()
+            .filter(|m| m.patient_id == patient_id && m.assessed_at >= since)
+            .max_by_key(|m| (m.assessed_at, m.id))
+            .cloned())
+    }
+
+    async fn insert_risk_score(&self, score: &NewRiskScore) -> StorageResult<RiskScore> {
+        let mut inner = self.inner.lock().unwrap();
+        let row = RiskScore {
+            id: next_id(&mut inner.last_ids.risk),
+            patient_id: score.patient_id.clone(),
+            calculated_at: score.calculated_at,
+            probability: score.probability,
+            rating: score.rating.clone(),
+            near_misses: score.near_misses,
+            falls: score.falls,
+            false_alarms: score.false_alarms,
+            night_share: score.night_share,
+            morse_total: score.morse_total,
+            window_days: score.window_days,
+            reason: score.reason.clone(),
+            event_id: score.event_id,
+        };
+        inner.risk_scores.push(row.clone());
+        Ok(row)
+    }
+
+    async fn get_risk_score(&self, id: i32) -> StorageResult<Option<RiskScore>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.risk_scores.iter().find(|r| r.id == id).cloned())
+    }
+
+    async fn query_risk_scores(&self, query: &RiskQuery) -> StorageResult<Vec<RiskScore>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .risk_scores
+            .iter()
+            .rev()
+            .filter(|r| query.matches(r))
+            .take(query.limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn insert_telemetry(&self, samples: &[TelemetrySample]) -> StorageResult<()> {
+        let mut inner = self.inner.lock().unwrap();
+        for sample in samples {
+            let id = next_id(&mut inner.last_ids.telemetry) as i64;
+            inner.telemetry.push((id, sample.clone()));
+        }
+        for partial in rollup::rollups_for(samples) {
+            let key = (
+                partial.device_id.clone(),
+                partial.resolution_seconds,
+                partial.bucket_start,
+            );
+            match inner.rollups.get_mut(&key) {
+                Some((_, r)) => r.absorb(&partial),
+                None => {
+                    let id = next_id(&mut inner.last_ids.rollup) as i64;
+                    inner.rollups.insert(key, (id, partial));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn telemetry_between(
+        &self,
+        device_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> StorageResult<Vec<TelemetrySample>> {
+        let inner = self.inner.lock().unwrap();
+        let mut samples: Vec<TelemetrySample> = inner
+            .telemetry
+            .iter()
+            .map(|(_, s)| s)
+            .filter(|s| s.device_id == device_id && s.recorded_at >= from && s.recorded_at <= to)
+            .cloned()
+            .collect();
+        samples.sort_by_key(|s| s.recorded_at);
+        Ok(samples)
+    }
+
+    async fn purge_telemetry_before(&self, cutoff: DateTime<Utc>) -> StorageResult<u64> {
+        let mut inner = self.inner.lock().unwrap();
+        let before = inner.telemetry.len();
+        inner.telemetry.retain(|(_, s)| s.recorded_at >= cutoff);
+        Ok((before - inner.telemetry.len()) as u64)
+    }
+
+    async fn telemetry_rollups(
+        &self,
+        device_id: &str,
+        resolution_seconds: i64,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> StorageResult<Vec<TelemetryRollup>> {
+        if from > to {
+            return Ok(Vec::new());
+        }
+        let inner = self.inner.lock().unwrap();
+        // Keys sort by (device, resolution, start), so this is already in time order
+        Ok(inner
+            .rollups
+            .range(
+                (device_id.to_string(), resolution_seconds, from)
+                    ..=(device_id.to_string(), resolution_seconds, to),
+            )
+            .map(|(_, (_, r))| r.clone())
+            .collect())
+    }
+
+    async fn save_waveform(&self, waveform: &Waveform) -> StorageResult<()> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.waveforms.push(waveform.clone());
+        Ok(())
+    }
+
+    async fn get_waveform(&self, event_id: i32) -> StorageResult<Option<Waveform>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .waveforms
+            .iter()
+            .find(|w| w.event_id == event_id)
+            .cloned())
+    }
+
+    async fn get_waveforms(&self, event_ids: &[i32]) -> StorageResult<Vec<Waveform>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .waveforms
+            .iter()
+            .filter(|w| event_ids.contains(&w.event_id))
+            .cloned()
+            .collect())
+    }
+
+    async fn upsert_patient(&self, patient: &NewPatient) -> StorageResult<Patient> {
+        patient.validate()?;
+        let row = Patient {
+            id: patient.id.clone(),
+            family_name: patient.family_name.clone(),
+            given_name: patient.given_name.clone(),
+            birth_date: patient.birth_date,
+            gender: patient.gender.clone(),
+            ward: patient.ward.clone(),
+            room: patient.room.clone(),
+            bed: patient.bed.clone(),
+            admitted_at: patient.admitted_at,
+            discharged_at: patient.discharged_at,
+            updated_at: patient.updated_at,
+        };
+        let mut inner = self.inner.lock().unwrap();
+        inner.patients.insert(row.id.clone(), row.clone());
+        Ok(row)
+    }
+
+    async fn get_patient(&self, id: &str) -> StorageResult<Option<Patient>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.patients.get(id).cloned())
+    }
+
+    async fn list_patients(&self) -> StorageResult<Vec<Patient>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.patients.values().cloned().collect())
+    }
+
+    async fn register_device(&self, registration: &DeviceRegistration) -> StorageResult<Device> {
+        registration.validate()?;
+        let mut inner = self.inner.lock().unwrap();
+        let device = inner
+            .devices
+            .entry(registration.id.clone())
+            .or_insert_with(|| new_device(&registration.id, registration.at));
+        // Same COALESCE rules as `registry::REGISTER_DEVICE`
+        if registration.model.is_some() {
+            device.model = registration.model.clone();
+        }
+        if registration.patient_id.is_some() {
+            device.patient_id = registration.patient_id.clone();
+        }
+        if registration.ward.is_some() {
+            device.ward = registration.ward.clone();
+        }
+        if let Some(state) = &registration.calibration_state {
+            device.calibration_state = state.clone();
+        }
+        if let Some(at) = registration.calibration_time() {
+            device.calibrated_at = Some(at);
+        }
+        Ok(device.clone())
+    }
+
+    async fn release_devices(&self, patient_id: &str) -> StorageResult<Vec<Device>> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut released = Vec::new();
+        for device in inner.devices.values_mut() {
+            if device.patient_id.as_deref() == Some(patient_id) {
+                device.patient_id = None;
+                released.push(device.clone());
+            }
+        }
+        Ok(released)
+    }
+
+    async fn record_device_status(&self, status: &DeviceStatus) -> StorageResult<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let device = inner
+            .devices
+            .entry(status.device_id.clone())
+            .or_insert_with(|| new_device(&status.device_id, status.seen_at));
+        device.last_seen_at = Some(status.seen_at);
+        device.wifi_signal = status.wifi_signal.or(device.wifi_signal);
+        device.temperature = status.temperature.or(device.temperature);
+        device.battery_level = status.battery_level.or(device.battery_level);
+        Ok(())
+    }
+
+    async fn get_device(&self, id: &str) -> StorageResult<Option<Device>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.devices.get(id).cloned())
+    }
+
+    async fn list_devices(&self) -> StorageResult<Vec<Device>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.devices.values().cloned().collect())
+    }
+
+    async fn create_subscription(
+        &self,
+        subscription: &NewSubscription,
+    ) -> StorageResult<Subscription> {
+        let mut inner = self.inner.lock().unwrap();
+        let row = Subscription {
+            id: next_id(&mut inner.last_ids.subscription),
+            topic: subscription.topic.clone(),
+            reason: subscription.reason.clone(),
+            endpoint: subscription.endpoint.clone(),
+            headers: subscription.headers_json(),
+            payload: subscription.payload.clone(),
+            patient_id: subscription.patient_id.clone(),
+            heartbeat_seconds: subscription.heartbeat_seconds,
+            status: "requested".to_string(),
+            events_since_start: 0,
+            error: None,
+            created_at: subscription.created_at,
+            last_notified_at: None,
+        };
+        inner.subscriptions.insert(row.id, row.clone());
+        Ok(row)
+    }
+
+    async fn get_subscription(&self, id: i32) -> StorageResult<Option<Subscription>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.subscriptions.get(&id).cloned())
+    }
+
+    async fn list_subscriptions(&self) -> StorageResult<Vec<Subscription>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.subscriptions.values().cloned().collect())
+    }
+
+    async fn delete_subscription(&self, id: i32) -> StorageResult<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        Ok(inner.subscriptions.remove(&id).is_some())
+    }
+
+    async fn next_subscription_event(&self, id: i32) -> StorageResult<i64> {
+        let mut inner = self.inner.lock().unwrap();
+        let subscription = inner
+            .subscriptions
+            .get_mut(&id)
+            .ok_or_else(|| StorageError::NotFound(format!("subscription {}", id)))?;
+        subscription.events_since_start += 1;
+        Ok(subscription.events_since_start)
+    }
+
+    async fn set_subscription_status(
+        &self,
+        id: i32,
+        status: &str,
+        error: Option<&str>,
+        notified_at: Option<DateTime<Utc>>,
+    ) -> StorageResult<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let subscription = inner
+            .subscriptions
+            .get_mut(&id)
+            .ok_or_else(|| StorageError::NotFound(format!("subscription {}", id)))?;
+        subscription.status = status.to_string();
+        subscription.error = error.map(String::from);
+        if notified_at.is_some() {
+            subscription.last_notified_at = notified_at;
+        }
+        Ok(())
+    }
+
+    async fn enqueue_outbound(
+        &self,
+        message: &NewOutboundMessage,
+    ) -> StorageResult<OutboundMessage> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner
+            .outbox
+            .iter()
+            .any(|m| m.control_id == message.control_id)
+        {
+            return Err(StorageError::InvalidQuery(format!(
+                "duplicate message control id: {}",
+                message.control_id
+            )));
+        }
+        let row = OutboundMessage {
+            id: next_id(&mut inner.last_ids.outbound),
+            event_id: message.event_id,
+            message_type: message.message_type.clone(),
+            control_id: message.control_id.clone(),
+            message: message.message.clone(),
+            status: "pending".to_string(),
+            attempts: 0,
+            next_attempt_at: message.created_at,
+            last_error: None,
+            ack_code: None,
+            created_at: message.created_at,
+            sent_at: None,
+        };
+        inner.outbox.push(row.clone());
+        Ok(row)
+    }
+
+    async fn unqueued_events(&self, after_id: i32, limit: i64) -> StorageResult<Vec<FallLog>> {
+        let inner = self.inner.lock().unwrap();
+        let queued: HashSet<i32> = inner.outbox.iter().map(|m| m.event_id).collect();
+        Ok(inner
+            .events
+            .iter()
+            .filter(|e| e.id > after_id && !queued.contains(&e.id))
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn first_queued_event(&self) -> StorageResult<Option<i32>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.outbox.iter().map(|m| m.event_id).min())
+    }
+
+    async fn outbound_messages(
+        &self,
+        status: Option<&str>,
+        limit: i64,
+    ) -> StorageResult<Vec<OutboundMessage>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .outbox
+            .iter()
+            .filter(|m| status.is_none_or(|s| m.status == s))
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn update_outbound(&self, id: i32, update: &OutboundUpdate) -> StorageResult<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let message = inner
+            .outbox
+            .iter_mut()
+            .find(|m| m.id == id)
+            .ok_or_else(|| StorageError::NotFound(format!("outbound message {}", id)))?;
+        message.status = update.status.clone();
+        message.attempts = update.attempts;
+        message.next_attempt_at = update.next_attempt_at;
+        message.last_error = update.last_error.clone();
+        message.ack_code = update.ack_code.clone();
+        message.sent_at = update.sent_at;
+        Ok(())
+    }
+
+    async fn record_audit(&self, entry: NewAuditEntry) -> StorageResult<AuditEntry> {
+        let mut inner = self.inner.lock().unwrap();
+        let row = AuditEntry {
+            id: next_id(&mut inner.last_ids.audit) as i64,
+            recorded_at: entry.recorded_at,
+            actor: entry.actor,
+            action: entry.action,
+            entity_type: entry.entity_type,
+            entity_id: entry.entity_id,
+            detail: entry.detail.map(|d| d.to_string()),
+        };
+        inner.audit.push(row.clone());
+        Ok(row)
+    }
+
+    async fn get_audit_entry(&self, id: i64) -> StorageResult<Option<AuditEntry>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.audit.iter().find(|a| a.id == id).cloned())
+    }
+
+    async fn audit_entries(&self, query: &AuditQuery) -> StorageResult<Vec<AuditEntry>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .audit
+            .iter()
+            .rev()
+            .filter(|a| query.matches(a))
+            .take(query.limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn place_legal_hold(&self, hold: NewLegalHold) -> StorageResult<LegalHold> {
+        let mut inner = self.inner.lock().unwrap();
+        let row = LegalHold {
+            id: inner.legal_holds.len() as i32 + 1,
+            patient_id: hold.patient_id,
+            alert_id: hold.alert_id,
+            reason: hold.reason,
+            placed_by: hold.placed_by,
+            placed_at: hold.placed_at,
+            released_at: None,
+        };
+        inner.legal_holds.push(row.clone());
+        Ok(row)
+    }
+
+    async fn legal_holds(&self, include_released: bool) -> StorageResult<Vec<LegalHold>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .legal_holds
+            .iter()
+            .filter(|h| include_released || h.released_at.is_none())
+            .cloned()
+            .collect())
+    }
+
+    async fn release_legal_hold(
+        &self,
+        hold_id: i32,
+        at: DateTime<Utc>,
+    ) -> StorageResult<LegalHold> {
+        let mut inner = self.inner.lock().unwrap();
+        let hold = inner
+            .legal_holds
+            .iter_mut()
+            .find(|h| h.id == hold_id)
+            .ok_or_else(|| StorageError::NotFound(format!("legal hold {}", hold_id)))?;
+        hold.released_at.get_or_insert(at);
+        Ok(hold.clone())
+    }
+
+    async fn expired_records(
+        &self,
+        class: DataClass,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> StorageResult<Vec<ArchiveRecord>> {
+        let inner = self.inner.lock().unwrap();
+        let limit = limit.max(0) as usize;
+        match class {
+            DataClass::Telemetry => inner
+                .telemetry
+                .iter()
+                .filter(|(_, s)| s.recorded_at < cutoff && !inner.is_device_held(&s.device_id))
+                .take(limit)
+                .map(|(id, s)| ArchiveRecord::new(*id, s))
+                .collect(),
+            DataClass::Rollups => {
+                let mut rollups: Vec<&(i64, TelemetryRollup)> = inner
+                    .rollups
+                    .values()
+                    .filter(|(_, r)| r.bucket_start < cutoff && !inner.is_device_held(&r.device_id))
+                    .collect();
+                rollups.sort_by_key(|(id, _)| *id);
+                rollups
+                    .into_iter()
+                    .take(limit)
+                    .map(|(id, r)| ArchiveRecord::new(*id, r))
+                    .collect()
+            }
+            DataClass::Waveforms => {
+                let held = inner.held_event_ids();
+                let mut waveforms: Vec<&Waveform> = inner
+                    .waveforms
+                    .iter()
+                    .filter(|w| w.impact_at < cutoff && !held.contains(&w.event_id))
+                    .collect();
+                waveforms.sort_by_key(|w| w.event_id);
+                waveforms
+                    .into_iter()
+                    .take(limit)
+                    .map(|w| ArchiveRecord::new(w.event_id as i64, w))
+                    .collect()
+            }
+            DataClass::Events => {
+                let events: Vec<FallLog> = inner
+                    .events
+                    .iter()
+                    .filter(|e| e.detected_at < cutoff && !inner.is_held(e))
+                    .filter(|e| {
+                        !inner.alerts.iter().any(|a| {
+                            a.event_id == e.id && OPEN_ALERT_STATUSES.contains(&a.status.as_str())
+                        })
+                    })
+                    .take(limit)
+                    .cloned()
+                    .collect();
+                retention::event_records(
+                    &events,
+                    &inner.alerts,
+                    &inner.assessments,
+                    &inner.waveforms,
+                )
+            }
+            DataClass::Detections => inner
+                .detections
+                .iter()
+                .filter(|d| d.detected_at < cutoff && !inner.is_detection_held(d))
+                .take(limit)
+                .map(|d| ArchiveRecord::new(d.id as i64, d))
+                .collect(),
+            DataClass::Audit => inner
+                .audit
+                .iter()
+                .filter(|a| a.recorded_at < cutoff && !inner.is_audit_held(a))
+                .take(limit)
+                .map(|a| ArchiveRecord::new(a.id, a))
+                .collect(),
+        }
+    }
+
+    async fn delete_records(&self, class: DataClass, ids: &[i64]) -> StorageResult<u64> {
+        let mut inner = self.inner.lock().unwrap();
+        let event_id = |id: i32| ids.contains(&(id as i64));
+        let removed = match class {
+            DataClass::Telemetry => {
+                let before = inner.telemetry.len();
+                inner.telemetry.retain(|(id, _)| !ids.contains(id));
+                before - inner.telemetry.len()
+            }
+            DataClass::Rollups => {
+                let before = inner.rollups.len();
+                inner.rollups.retain(|_, (id, _)| !ids.contains(id));
+                before - inner.rollups.len()
+            }
+            DataClass::Waveforms => {
+                let before = inner.waveforms.len();
+                inner.waveforms.retain(|w| !event_id(w.event_id));
+                before - inner.waveforms.len()
+            }
+            DataClass::Events => {
+                inner.assessments.retain(|a| !event_id(a.event_id));
+                inner.alerts.retain(|a| !event_id(a.event_id));
+                inner.waveforms.retain(|w| !event_id(w.event_id));
+                inner.labels.retain(|l| !event_id(l.event_id));
+                let before = inner.events.len();
+                inner.events.retain(|e| !event_id(e.id));
+                before - inner.events.len()
+            }
+            DataClass::Detections => {
+                let before = inner.detections.len();
+                inner.detections.retain(|d| !ids.contains(&(d.id as i64)));
+                before - inner.detections.len()
+            }
+            DataClass::Audit => {
+                let before = inner.audit.len();
+                inner.audit.retain(|a| !ids.contains(&a.id));
+                before - inner.audit.len()
+            }
+        };
+        Ok(removed as u64)
+    }
+}
@@ -0,0 +1,163 @@
+use chrono::{DateTime, Utc};
+
+/// States of an outbound message: `pending` until acknowledged (`sent`), or `failed`
+/// once every attempt was used up.
+pub const OUTBOX_STATUSES: [&str; 3] = ["pending", "sent", "failed"];
+
+/// Columns selected for every `OutboundMessage` row.
+pub(crate) const OUTBOX_COLUMNS: &str = "id, event_id, message_type, control_id, message, status, \
+     attempts, next_attempt_at, last_error, ack_code, created_at, sent_at";
+
+/// Queues a message ($1..$5 in `NewOutboundMessage` order; the first attempt is due at once).
+pub(crate) const INSERT_OUTBOUND: &str = "INSERT INTO hl7_outbox \
+     (event_id, message_type, control_id, message, created_at, next_attempt_at) \
+     VALUES ($1, $2, $3, $4, $5, $5)";
+
+/// Records an attempt ($2..$7 in `OutboundUpdate` order).
+pub(crate) const UPDATE_OUTBOUND: &str = "UPDATE hl7_outbox \
+     SET status = $2, attempts = $3, next_attempt_at = $4, last_error = $5, ack_code = $6, \
+     sent_at = $7 WHERE id = $1";
+
+/// **New Outbound Message**
+///
+/// An encoded HL7 v2 message reporting one event.
+#[derive(Debug, Clone)]
+pub struct NewOutboundMessage {
+    pub event_id: i32,
+    pub message_type: String,
+    pub control_id: String,
+    pub message: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Outcome of a delivery attempt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutboundUpdate {
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub ack_code: Option<String>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Repository;
+    use crate::test_support::{critical_event, on_every_backend};
+    use chrono::{Duration, SubsecRound};
+
+    // Helper: stores events 3, 2 and 1 minutes old and returns their ids
+    async fn three_events(repo: &dyn Repository) -> Vec<i32> {
+        let mut ids = Vec::new();
+        for minutes_ago in [3, 2, 1] {
+            let event = repo.insert_event(critical_event(minutes_ago, 2.5)).await;
+            ids.push(event.unwrap().id);
+        }
+        ids
+    }
+
+    // Helper: the ORU^R01 reporting `event_id`, queued at `at`
+    fn oru(event_id: i32, at: DateTime<Utc>) -> NewOutboundMessage {
+        NewOutboundMessage {
+            event_id,
+            message_type: "ORU^R01".to_string(),
+            control_id: format!("FG{}", event_id),
+            message: "MSH|^~\\&|FALLGUARD\r".to_string(),
+            created_at: at,
+        }
+    }
+
+    // The interface follows events by id, in batches
+    #[actix_web::test]
+    async fn test_events_after() {
+        on_every_backend(async |repo: &dyn Repository| {
+            assert_eq!(repo.latest_event_id().await.unwrap(), None);
+            let ids = three_events(repo).await;
+            assert_eq!(repo.latest_event_id().await.unwrap(), Some(ids[2]));
+            let after: Vec<i32> = repo
+                .events_after(ids[0], 10)
+                .await
+                .unwrap()
+                .iter()
+                .map(|e| e.id)
+                .collect();
+            assert_eq!(after, vec![ids[1], ids[2]]);
+            assert_eq!(repo.events_after(ids[0], 1).await.unwrap().len(), 1);
+        })
+        .await;
+    }
+
+    // Events with a message queued are left out, even below a later one that has none
+    #[actix_web::test]
+    async fn test_unqueued_events() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let at = Utc::now().trunc_subsecs(3);
+            let ids = three_events(repo).await;
+            repo.enqueue_outbound(&oru(ids[0], at)).await.unwrap();
+            repo.enqueue_outbound(&oru(ids[2], at)).await.unwrap();
+            let unqueued: Vec<i32> = repo
+                .unqueued_events(0, 10)
+                .await
+                .unwrap()
+                .iter()
+                .map(|e| e.id)
+                .collect();
+            assert_eq!(unqueued, vec![ids[1]]);
+            assert!(repo.unqueued_events(ids[1], 10).await.unwrap().is_empty());
+        })
+        .await;
+    }
+
+    // Messages are queued once per event and due at once; the first one queued is remembered
+    #[actix_web::test]
+    async fn test_enqueue_outbound() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let at = Utc::now().trunc_subsecs(3); // Within every backend's precision
+            let ids = three_events(repo).await;
+            assert_eq!(repo.first_queued_event().await.unwrap(), None);
+            let first = repo.enqueue_outbound(&oru(ids[0], at)).await.unwrap();
+            assert_eq!(first.status, "pending");
+            assert_eq!(first.attempts, 0);
+            assert_eq!(first.next_attempt_at, at);
+            repo.enqueue_outbound(&oru(ids[1], at)).await.unwrap();
+            assert!(repo.enqueue_outbound(&oru(ids[1], at)).await.is_err());
+            assert_eq!(repo.first_queued_event().await.unwrap(), Some(ids[0]));
+        })
+        .await;
+    }
+
+    // Every attempt is recorded, and the outbox lists messages by status
+    #[actix_web::test]
+    async fn test_update_outbound() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let at = Utc::now().trunc_subsecs(3);
+            let ids = three_events(repo).await;
+            let first = repo.enqueue_outbound(&oru(ids[0], at)).await.unwrap();
+            repo.enqueue_outbound(&oru(ids[1], at)).await.unwrap();
+            let update = OutboundUpdate {
+                status: "sent".to_string(),
+                attempts: 2,
+                next_attempt_at: at + Duration::seconds(10),
+                last_error: None,
+                ack_code: Some("AA".to_string()),
+                sent_at: Some(at + Duration::seconds(10)),
+            };
+            repo.update_outbound(first.id, &update).await.unwrap();
+            assert!(repo.update_outbound(999, &update).await.is_err());
+
+            let pending = repo.outbound_messages(Some("pending"), 10).await.unwrap();
+            assert_eq!(pending.len(), 1);
+            assert_eq!(pending[0].event_id, ids[1]);
+            let all = repo.outbound_messages(None, 10).await.unwrap();
+            assert_eq!(all.len(), 2);
+            assert_eq!(all[0].status, "sent");
+            assert_eq!(all[0].attempts, 2);
+            assert_eq!(all[0].ack_code.as_deref(), Some("AA"));
+            assert_eq!(all[0].sent_at, update.sent_at);
+            assert_eq!(repo.outbound_messages(None, 1).await.unwrap().len(), 1);
+        })
+        .await;
+    }
+}
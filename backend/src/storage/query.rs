@@ -0,0 +1,424 @@
+use super::{StorageError, StorageResult};
+use crate::model::FallLog;
+use chrono::{DateTime, Utc};
+use sqlx::{Database, Encode, QueryBuilder, Type};
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// Columns selected for every `FallLog` row.
+pub const EVENT_COLUMNS: &str = "id, detected_at, severity, g_force_value, is_false_alarm, \
+     device_id, patient_id, ward, stillness_variance, validation_ms, orientation_change_deg, dismissed_at, explanation";
+
+/// Default and maximum page sizes for event queries.
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 500;
+
+/// **Event Filter**
+///
+/// Every field is optional; set fields are combined with AND.
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub severities: Vec<String>, // Matches any of the listed severities
+    pub device_id: Option<String>,
+    pub patient_id: Option<String>,
+    pub ward: Option<String>,
+    pub is_false_alarm: Option<bool>,
+    pub dismissed: Option<bool>, // Cancelled by a nurse as a false alarm
+    pub include_dismissed: bool, // Dismissed events match `severities` whatever their severity
+}
+
+impl EventFilter {
+    /// In-Rust equivalent of the SQL WHERE clause (used by the in-memory backend).
+    pub fn matches(&self, log: &FallLog) -> bool {
+        self.from.is_none_or(|from| log.detected_at >= from)
+            && self.to.is_none_or(|to| log.detected_at <= to)
+            && (self.severities.is_empty()
+                || self.severities.contains(&log.severity)
+                || (self.include_dismissed && log.dismissed_at.is_some()))
+            && matches_opt(&self.device_id, &log.device_id)
+            && matches_opt(&self.patient_id, &log.patient_id)
+            && matches_opt(&self.ward, &log.ward)
+            && self.is_false_alarm.is_none_or(|f| log.is_false_alarm == f)
+            && self
+                .dismissed
+                .is_none_or(|d| log.dismissed_at.is_some() == d)
+    }
+}
+
+fn matches_opt(wanted: &Option<String>, actual: &Option<String>) -> bool {
+    match wanted {
+        Some(w) => actual.as_deref() == Some(w.as_str()),
+        None => true,
+    }
+}
+
+/// Column an event page is ordered by (ties are broken by `id`).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SortKey {
+    #[default]
+    DetectedAt,
+    GForce,
+}
+
+/// **Event Sort**
+///
+/// Parsed from `detected_at`, `g_force_value`, or either with a `-` prefix for descending.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EventSort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Default for EventSort {
+    // Newest first, like the original history endpoint
+    fn default() -> Self {
+        Self {
+            key: SortKey::DetectedAt,
+            descending: true,
+        }
+    }
+}
+
+impl FromStr for EventSort {
+    type Err = StorageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (descending, field) = match s.strip_prefix('-') {
+            Some(field) => (true, field),
+            None => (false, s),
+        };
+        let key = match field {
+            "detected_at" => SortKey::DetectedAt,
+            "g_force_value" => SortKey::GForce,
+            other => {
+                return Err(StorageError::InvalidQuery(format!(
+                    "unsupported sort field: {}",
+                    other
+                )))
+            }
+        };
+        Ok(Self { key, descending })
+    }
+}
+
+impl EventSort {
+    fn column(&self) -> &'static str {
+        match self.key {
+            SortKey::DetectedAt => "detected_at",
+            SortKey::GForce => "g_force_value",
+        }
+    }
+
+    fn direction(&self) -> &'static str {
+        if self.descending {
+            "DESC"
+        } else {
+            "ASC"
+        }
+    }
+
+    /// Orders two logs the same way the SQL `ORDER BY` does.
+    pub fn compare(&self, a: &FallLog, b: &FallLog) -> Ordering {
+        let ord = match self.key {
+            SortKey::DetectedAt => a.detected_at.cmp(&b.detected_at),
+            SortKey::GForce => a.g_force_value.total_cmp(&b.g_force_value),
+        }
+        .then(a.id.cmp(&b.id));
+        if self.descending {
+            ord.reverse()
+        } else {
+            ord
+        }
+    }
+}
+
+/// Position after the last row of a page: the sort value plus the row id.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CursorValue {
+    DetectedAt(DateTime<Utc>),
+    GForce(f64),
+}
+
+/// **Event Cursor**
+///
+/// Keyset pagination token, rendered as `<sort value>_<id>`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EventCursor {
+    pub value: CursorValue,
+    pub id: i32,
+}
+
+impl EventCursor {
+    pub fn after(log: &FallLog, sort: &EventSort) -> Self {
+        let value = match sort.key {
+            SortKey::DetectedAt => CursorValue::DetectedAt(log.detected_at),
+            SortKey::GForce => CursorValue::GForce(log.g_force_value),
+        };
+        Self { value, id: log.id }
+    }
+
+    pub fn encode(&self) -> String {
+        match self.value {
+            CursorValue::DetectedAt(t) => {
+                format!(
+                    "{}_{}",
+                    t.timestamp_nanos_opt().unwrap_or_default(),
+                    self.id
+                )
+            }
+            CursorValue::GForce(g) => format!("{}_{}", g, self.id),
+        }
+    }
+
+    /// Parses a token produced by `encode` for the same sort key.
+    pub fn decode(token: &str, sort: &EventSort) -> StorageResult<Self> {
+        let invalid = || StorageError::InvalidQuery(format!("invalid cursor: {}", token));
+        let (value, id) = token.rsplit_once('_').ok_or_else(invalid)?;
+        let id: i32 = id.parse().map_err(|_| invalid())?;
+        let value = match sort.key {
+            SortKey::DetectedAt => {
+                let nanos: i64 = value.parse().map_err(|_| invalid())?;
+                CursorValue::DetectedAt(DateTime::from_timestamp_nanos(nanos))
+            }
+            SortKey::GForce => CursorValue::GForce(value.parse().map_err(|_| invalid())?),
+        };
+        Ok(Self { value, id })
+    }
+
+    /// True when `log` comes after this cursor in `sort` order.
+    pub fn precedes(&self, log: &FallLog, sort: &EventSort) -> bool {
+        let ord = match self.value {
+            CursorValue::DetectedAt(t) => log.detected_at.cmp(&t),
+            CursorValue::GForce(g) => log.g_force_value.total_cmp(&g),
+        }
+        .then(log.id.cmp(&self.id));
+        if sort.descending {
+            ord == Ordering::Less
+        } else {
+            ord == Ordering::Greater
+        }
+    }
+}
+
+/// **Event Query**
+///
+/// Filters, ordering and page position for `Repository::query_events`.
+#[derive(Debug, Clone)]
+pub struct EventQuery {
+    pub filter: EventFilter,
+    pub sort: EventSort,
+    pub cursor: Option<EventCursor>,
+    pub limit: i64,
+}
+
+impl Default for EventQuery {
+    fn default() -> Self {
+        Self {
+            filter: EventFilter::default(),
+            sort: EventSort::default(),
+            cursor: None,
+            limit: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+/// One page of events, with the total matching the filter (ignoring the cursor).
+#[derive(Debug, Clone)]
+pub struct EventPage {
+    pub items: Vec<FallLog>,
+    pub total: i64,
+    pub next_cursor: Option<EventCursor>,
+}
+
+impl EventPage {
+    /// Builds a page from up to `limit + 1` fetched rows; the extra row only signals "more".
+    pub fn from_rows(mut rows: Vec<FallLog>, total: i64, query: &EventQuery) -> Self {
+        let limit = query.limit.max(0) as usize;
+        let has_more = rows.len() > limit;
+        rows.truncate(limit);
+        let next_cursor = if has_more {
+            rows.last().map(|log| EventCursor::after(log, &query.sort))
+        } else {
+            None
+        };
+        Self {
+            items: rows,
+            total,
+            next_cursor,
+        }
+    }
+}
+
+/// Appends the filter (and optionally the cursor) as a WHERE clause.
+/// Shared by the Postgres and SQLite backends, which accept the same SQL here.
+pub fn push_event_conditions<'args, DB>(
+    qb: &mut QueryBuilder<'args, DB>,
+    query: &EventQuery,
+    with_cursor: bool,
+) where
+    DB: Database,
+    DateTime<Utc>: Encode<'args, DB> + Type<DB>,
+    String: Encode<'args, DB> + Type<DB>,
+    bool: Encode<'args, DB> + Type<DB>,
+    f64: Encode<'args, DB> + Type<DB>,
+    i32: Encode<'args, DB> + Type<DB>,
+{
+    push_filter_conditions(qb, &query.filter);
+
+    if let (true, Some(cursor)) = (with_cursor, &query.cursor) {
+        let column = query.sort.column();
+        let op = if query.sort.descending { "<" } else { ">" };
+        qb.push(format!(" AND ({} {} ", column, op));
+        push_cursor_value(qb, cursor);
+        qb.push(format!(" OR ({} = ", column));
+        push_cursor_value(qb, cursor);
+        qb.push(format!(" AND id {} ", op)).push_bind(cursor.id);
+        qb.push("))");
+    }
+}
+
+/// Appends `filter` as a WHERE clause (always emitted, so callers can add `AND ...`).
+pub fn push_filter_conditions<'args, DB>(qb: &mut QueryBuilder<'args, DB>, filter: &EventFilter)
+where
+    DB: Database,
+    DateTime<Utc>: Encode<'args, DB> + Type<DB>,
+    String: Encode<'args, DB> + Type<DB>,
+    bool: Encode<'args, DB> + Type<DB>,
+{
+    qb.push(" WHERE 1 = 1");
+
+    if let Some(from) = filter.from {
+        qb.push(" AND detected_at >= ").push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        qb.push(" AND detected_at <= ").push_bind(to);
+    }
+    if !filter.severities.is_empty() {
+        qb.push(" AND (severity IN (");
+        let mut list = qb.separated(", ");
+        for severity in &filter.severities {
+            list.push_bind(severity.clone());
+        }
+        list.push_unseparated(")");
+        if filter.include_dismissed {
+            qb.push(" OR dismissed_at IS NOT NULL");
+        }
+        qb.push(")");
+    }
+    if let Some(device_id) = &filter.device_id {
+        qb.push(" AND device_id = ").push_bind(device_id.clone());
+    }
+    if let Some(patient_id) = &filter.patient_id {
+        qb.push(" AND patient_id = ").push_bind(patient_id.clone());
+    }
+    if let Some(ward) = &filter.ward {
+        qb.push(" AND ward = ").push_bind(ward.clone());
+    }
+    if let Some(is_false_alarm) = filter.is_false_alarm {
+        qb.push(" AND is_false_alarm = ").push_bind(is_false_alarm);
+    }
+    match filter.dismissed {
+        Some(true) => qb.push(" AND dismissed_at IS NOT NULL"),
+        Some(false) => qb.push(" AND dismissed_at IS NULL"),
+        None => qb,
+    };
+}
+
+fn push_cursor_value<'args, DB>(qb: &mut QueryBuilder<'args, DB>, cursor: &EventCursor)
+where
+    DB: Database,
+    DateTime<Utc>: Encode<'args, DB> + Type<DB>,
+    f64: Encode<'args, DB> + Type<DB>,
+{
+    match cursor.value {
+        CursorValue::DetectedAt(t) => qb.push_bind(t),
+        CursorValue::GForce(g) => qb.push_bind(g),
+    };
+}
+
+/// Appends ORDER BY and LIMIT (one extra row to detect a following page).
+pub fn push_event_order<'args, DB>(qb: &mut QueryBuilder<'args, DB>, query: &EventQuery)
+where
+    DB: Database,
+    i64: Encode<'args, DB> + Type<DB>,
+{
+    let dir = query.sort.direction();
+    qb.push(format!(
+        " ORDER BY {} {}, id {} LIMIT ",
+        query.sort.column(),
+        dir,
+        dir
+    ))
+    .push_bind(query.limit.max(0) + 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Repository;
+    use crate::test_support::{critical_event, on_every_backend};
+    use chrono::{Duration, Utc};
+
+    // Helper: 7 events 10 minutes apart, every other one a Ward-B near miss
+    async fn insert_events(repo: &dyn Repository) {
+        for i in 0..7 {
+            let mut event = critical_event(i * 10, 1.5 + i as f64 / 10.0);
+            if i % 2 == 1 {
+                event.severity = "Near Miss".to_string();
+                event.ward = Some("Ward-B".to_string());
+            }
+            repo.insert_event(event).await.unwrap();
+        }
+    }
+
+    // Walking every page of 3, oldest first, gives no duplicates and no gaps
+    #[actix_web::test]
+    async fn test_cursor_pagination() {
+        on_every_backend(async |repo: &dyn Repository| {
+            insert_events(repo).await;
+            let mut query = EventQuery {
+                sort: "detected_at".parse::<EventSort>().unwrap(),
+                limit: 3,
+                ..EventQuery::default()
+            };
+            let mut seen = Vec::new();
+            loop {
+                let page = repo.query_events(&query).await.unwrap();
+                assert_eq!(page.total, 7);
+                seen.extend(page.items.iter().map(|e| e.detected_at));
+                match page.next_cursor {
+                    Some(cursor) => query.cursor = Some(cursor),
+                    None => break,
+                }
+            }
+            assert_eq!(seen.len(), 7);
+            assert!(seen.windows(2).all(|w| w[0] < w[1]));
+        })
+        .await;
+    }
+
+    // Filters combine with AND
+    #[actix_web::test]
+    async fn test_filters() {
+        on_every_backend(async |repo: &dyn Repository| {
+            insert_events(repo).await;
+            let query = EventQuery {
+                filter: EventFilter {
+                    severities: vec!["Near Miss".to_string()],
+                    ward: Some("Ward-B".to_string()),
+                    from: Some(Utc::now() - Duration::minutes(35)),
+                    ..EventFilter::default()
+                },
+                sort: "-g_force_value".parse::<EventSort>().unwrap(),
+                ..EventQuery::default()
+            };
+            let page = repo.query_events(&query).await.unwrap();
+            assert_eq!(page.total, 2); // 10 and 30 minutes ago
+            assert!(page.items[0].g_force_value > page.items[1].g_force_value);
+            assert!(page.next_cursor.is_none());
+        })
+        .await;
+    }
+}
@@ -0,0 +1,334 @@
+use super::{StorageError, StorageResult};
+use chrono::{DateTime, NaiveDate, Utc};
+
+/// FHIR DeviceMetric calibration states a device can be registered with.
+pub const CALIBRATION_STATES: [&str; 4] = [
+    "not-calibrated",
+    "calibration-required",
+    "calibrated",
+    "unspecified",
+];
+
+/// FHIR administrative genders.
+pub const GENDERS: [&str; 4] = ["male", "female", "other", "unknown"];
+
+/// Columns selected for every `Patient` row.
+pub(crate) const PATIENT_COLUMNS: &str = "id, family_name, given_name, birth_date, gender, ward, \
+     room, bed, admitted_at, discharged_at, updated_at";
+
+/// Columns selected for every `Device` row.
+pub(crate) const DEVICE_COLUMNS: &str = "id, model, patient_id, ward, calibration_state, \
+     calibrated_at, battery_level, wifi_signal, temperature, last_seen_at, registered_at";
+
+/// Inserts or replaces a patient ($1..$11 in `PATIENT_COLUMNS` order).
+pub(crate) const UPSERT_PATIENT: &str = "INSERT INTO patients \
+     (id, family_name, given_name, birth_date, gender, ward, room, bed, admitted_at, \
+     discharged_at, updated_at) \
+     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) \
+     ON CONFLICT (id) DO UPDATE SET family_name = EXCLUDED.family_name, \
+     given_name = EXCLUDED.given_name, birth_date = EXCLUDED.birth_date, \
+     gender = EXCLUDED.gender, ward = EXCLUDED.ward, room = EXCLUDED.room, bed = EXCLUDED.bed, \
+     admitted_at = EXCLUDED.admitted_at, discharged_at = EXCLUDED.discharged_at, \
+     updated_at = EXCLUDED.updated_at";
+
+/// Registers a device or updates the fields that were given (`DeviceRegistration` order).
+pub(crate) const REGISTER_DEVICE: &str = "INSERT INTO devices \
+     (id, model, patient_id, ward, calibration_state, calibrated_at, registered_at) \
+     VALUES ($1, $2, $3, $4, COALESCE($5, 'unspecified'), $6, $7) \
+     ON CONFLICT (id) DO UPDATE SET model = COALESCE(EXCLUDED.model, devices.model), \
+     patient_id = COALESCE(EXCLUDED.patient_id, devices.patient_id), \
+     ward = COALESCE(EXCLUDED.ward, devices.ward), \
+     calibration_state = COALESCE($5, devices.calibration_state), \
+     calibrated_at = COALESCE(EXCLUDED.calibrated_at, devices.calibrated_at)";
+
+/// Ends every device assignment of patient $1.
+pub(crate) const RELEASE_DEVICES: &str =
+    "UPDATE devices SET patient_id = NULL WHERE patient_id = $1";
+
+/// Stores a status report, registering unknown devices on the way (`DeviceStatus` order).
+pub(crate) const RECORD_DEVICE_STATUS: &str = "INSERT INTO devices \
+     (id, last_seen_at, registered_at, wifi_signal, temperature, battery_level) \
+     VALUES ($1, $2, $2, $3, $4, $5) \
+     ON CONFLICT (id) DO UPDATE SET last_seen_at = EXCLUDED.last_seen_at, \
+     wifi_signal = COALESCE(EXCLUDED.wifi_signal, devices.wifi_signal), \
+     temperature = COALESCE(EXCLUDED.temperature, devices.temperature), \
+     battery_level = COALESCE(EXCLUDED.battery_level, devices.battery_level)";
+
+/// **New Patient**
+///
+/// Demographics and location to store under `id`; every field replaces the stored one.
+#[derive(Debug, Clone)]
+pub struct NewPatient {
+    pub id: String,
+    pub family_name: Option<String>,
+    pub given_name: Option<String>,
+    pub birth_date: Option<NaiveDate>,
+    pub gender: Option<String>,
+    pub ward: Option<String>,
+    pub room: Option<String>,
+    pub bed: Option<String>,
+    pub admitted_at: Option<DateTime<Utc>>,
+    pub discharged_at: Option<DateTime<Utc>>, // Set while the patient is discharged
+    pub updated_at: DateTime<Utc>,
+}
+
+impl NewPatient {
+    pub fn validate(&self) -> StorageResult<()> {
+        if self.id.trim().is_empty() {
+            return Err(StorageError::InvalidQuery("patient id is required".into()));
+        }
+        match &self.gender {
+            Some(g) if !GENDERS.contains(&g.as_str()) => Err(StorageError::InvalidQuery(format!(
+                "unknown gender: {} (expected one of {})",
+                g,
+                GENDERS.join(", ")
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// **Device Registration**
+///
+/// Registers `id` or updates it; fields left as `None` keep their stored value.
+/// Marking a device `calibrated` without a time stamps it with `at`.
+#[derive(Debug, Clone)]
+pub struct DeviceRegistration {
+    pub id: String,
+    pub model: Option<String>,
+    pub patient_id: Option<String>,
+    pub ward: Option<String>,
+    pub calibration_state: Option<String>,
+    pub calibrated_at: Option<DateTime<Utc>>,
+    pub at: DateTime<Utc>,
+}
+
+impl DeviceRegistration {
+    pub fn validate(&self) -> StorageResult<()> {
+        if self.id.trim().is_empty() {
+            return Err(StorageError::InvalidQuery("device id is required".into()));
+        }
+        match &self.calibration_state {
+            Some(s) if !CALIBRATION_STATES.contains(&s.as_str()) => {
+                Err(StorageError::InvalidQuery(format!(
+                    "unknown calibration state: {} (expected one of {})",
+                    s,
+                    CALIBRATION_STATES.join(", ")
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// `calibrated_at` as stored.
+    pub fn calibration_time(&self) -> Option<DateTime<Utc>> {
+        match self.calibration_state.as_deref() {
+            Some("calibrated") => self.calibrated_at.or(Some(self.at)),
+            _ => self.calibrated_at,
+        }
+    }
+}
+
+/// **Device Status**
+///
+/// What a sensor last reported; `None` fields keep the previous reading.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceStatus {
+    pub device_id: String,
+    pub seen_at: DateTime<Utc>,
+    pub wifi_signal: Option<i32>,
+    pub temperature: Option<f64>,
+    pub battery_level: Option<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Repository;
+    use crate::test_support::on_every_backend;
+    use chrono::SubsecRound;
+
+    // Helper: P-1001 admitted to ICU bed 101A at `at`
+    fn admitted(at: DateTime<Utc>) -> NewPatient {
+        NewPatient {
+            id: "P-1001".to_string(),
+            family_name: Some("Perera".to_string()),
+            given_name: Some("Nimal".to_string()),
+            birth_date: NaiveDate::from_ymd_opt(1941, 5, 2),
+            gender: Some("male".to_string()),
+            ward: Some("ICU".to_string()),
+            room: Some("101".to_string()),
+            bed: Some("A".to_string()),
+            admitted_at: Some(at),
+            discharged_at: None,
+            updated_at: at,
+        }
+    }
+
+    // Helper: registers pi-01 at `at`, changing only the fields given
+    fn registration(
+        at: DateTime<Utc>,
+        patient_id: Option<&str>,
+        calibration_state: Option<&str>,
+    ) -> DeviceRegistration {
+        DeviceRegistration {
+            id: "pi-01".to_string(),
+            model: None,
+            patient_id: patient_id.map(String::from),
+            ward: None,
+            calibration_state: calibration_state.map(String::from),
+            calibrated_at: None,
+            at,
+        }
+    }
+
+    // Helper: a status report from `device` at `at`
+    fn status(device: &str, at: DateTime<Utc>, battery: Option<f64>) -> DeviceStatus {
+        DeviceStatus {
+            device_id: device.to_string(),
+            seen_at: at,
+            wifi_signal: Some(72),
+            temperature: Some(31.5),
+            battery_level: battery,
+        }
+    }
+
+    // Upserting a patient replaces every field, including the ones cleared
+    #[actix_web::test]
+    async fn test_upsert_patient() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let at = Utc::now().trunc_subsecs(3); // Within every backend's precision
+            let patient = admitted(at);
+            repo.upsert_patient(&patient).await.unwrap();
+            let moved = repo
+                .upsert_patient(&NewPatient {
+                    ward: Some("Ward-B".to_string()),
+                    bed: None,
+                    given_name: None,
+                    ..patient.clone()
+                })
+                .await
+                .unwrap();
+            assert_eq!(moved.ward.as_deref(), Some("Ward-B"));
+            assert_eq!(moved.room.as_deref(), Some("101"));
+            assert_eq!(moved.bed, None);
+            assert_eq!(moved.admitted_at, Some(at));
+            assert_eq!(moved.given_name, None);
+            assert_eq!(repo.list_patients().await.unwrap().len(), 1);
+        })
+        .await;
+    }
+
+    // Patients are looked up by id
+    #[actix_web::test]
+    async fn test_get_patient() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let patient = admitted(Utc::now().trunc_subsecs(3));
+            repo.upsert_patient(&patient).await.unwrap();
+            let stored = repo.get_patient("P-1001").await.unwrap().unwrap();
+            assert_eq!(stored.birth_date, patient.birth_date);
+            assert!(repo.get_patient("P-404").await.unwrap().is_none());
+        })
+        .await;
+    }
+
+    // Genders outside the FHIR value set are rejected
+    #[actix_web::test]
+    async fn test_patient_rejects_unknown_gender() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let robot = NewPatient {
+                gender: Some("robot".to_string()),
+                ..admitted(Utc::now())
+            };
+            assert!(repo.upsert_patient(&robot).await.is_err());
+        })
+        .await;
+    }
+
+    // Registration keeps what it isn't told, and stamps the calibration time
+    #[actix_web::test]
+    async fn test_register_device() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let at = Utc::now().trunc_subsecs(3);
+            let device = repo
+                .register_device(&DeviceRegistration {
+                    model: Some("MPU-6050 / Pi Zero 2 W".to_string()),
+                    ..registration(at, Some("P-1001"), None)
+                })
+                .await
+                .unwrap();
+            assert_eq!(device.calibration_state, "unspecified");
+            assert_eq!(device.calibrated_at, None);
+            let device = repo
+                .register_device(&registration(at, None, Some("calibrated")))
+                .await
+                .unwrap();
+            assert_eq!(device.patient_id.as_deref(), Some("P-1001"));
+            assert_eq!(device.model.as_deref(), Some("MPU-6050 / Pi Zero 2 W"));
+            assert_eq!(device.calibrated_at, Some(at));
+        })
+        .await;
+    }
+
+    // Calibration states outside the FHIR value set are rejected
+    #[actix_web::test]
+    async fn test_register_device_rejects_unknown_calibration_state() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let sort_of = registration(Utc::now(), None, Some("sort-of"));
+            assert!(repo.register_device(&sort_of).await.is_err());
+        })
+        .await;
+    }
+
+    // Status reports update the readings given and register unknown sensors
+    #[actix_web::test]
+    async fn test_device_status() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let at = Utc::now().trunc_subsecs(3);
+            repo.register_device(&registration(at, None, Some("calibrated")))
+                .await
+                .unwrap();
+            repo.record_device_status(&status("pi-01", at, Some(64.0)))
+                .await
+                .unwrap();
+            repo.record_device_status(&status("pi-01", at, None))
+                .await
+                .unwrap();
+            repo.record_device_status(&status("pi-02", at, None))
+                .await
+                .unwrap();
+            let device = repo.get_device("pi-01").await.unwrap().unwrap();
+            assert_eq!(device.battery_level, Some(64.0));
+            assert_eq!(device.wifi_signal, Some(72));
+            assert_eq!(device.temperature, Some(31.5));
+            assert_eq!(device.last_seen_at, Some(at));
+            assert_eq!(device.calibration_state, "calibrated");
+            let devices = repo.list_devices().await.unwrap();
+            assert_eq!(devices.len(), 2);
+            assert_eq!(devices[1].id, "pi-02");
+            assert_eq!(devices[1].calibration_state, "unspecified");
+            assert!(repo.get_device("pi-404").await.unwrap().is_none());
+        })
+        .await;
+    }
+
+    // Discharge ends the assignment and leaves the rest of the device alone
+    #[actix_web::test]
+    async fn test_release_devices() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let at = Utc::now();
+            repo.register_device(&registration(at, Some("P-1001"), Some("calibrated")))
+                .await
+                .unwrap();
+            let released = repo.release_devices("P-1001").await.unwrap();
+            assert_eq!(released.len(), 1);
+            assert_eq!(released[0].id, "pi-01");
+            assert_eq!(released[0].patient_id, None);
+            let device = repo.get_device("pi-01").await.unwrap().unwrap();
+            assert_eq!(device.patient_id, None);
+            assert_eq!(device.calibration_state, "calibrated");
+            assert!(repo.release_devices("P-1001").await.unwrap().is_empty());
+        })
+        .await;
+    }
+}
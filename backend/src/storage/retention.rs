@@ -0,0 +1,356 @@
+use super::detections::DETECTION_COLUMNS;
+use super::query::EVENT_COLUMNS;
+use super::rollup::ROLLUP_COLUMNS;
+use super::{StorageError, StorageResult, OPEN_ALERT_STATUSES};
+use crate::model::{
+    Alert, Assessment, AuditEntry, FallLog, TelemetryRollup, TelemetrySample, Waveform,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Database, Encode, QueryBuilder, Type};
+
+/// **Data Class**
+///
+/// Groups of records that share a retention period:
+/// - `Telemetry`: raw sensor samples (`telemetry`)
+/// - `Rollups`: downsampled telemetry behind the charts (`telemetry_rollups`)
+/// - `Waveforms`: signal snapshots around detections (`waveforms`)
+/// - `Events`: fall events together with their alerts and waveform (`events`, `alerts`)
+/// - `Detections`: the detector's outcome log (`detections`)
+/// - `Audit`: the audit trail (`audit_log`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataClass {
+    Telemetry,
+    Rollups,
+    Waveforms,
+    Events,
+    Detections,
+    Audit,
+}
+
+impl DataClass {
+    pub const ALL: [DataClass; 6] = [
+        DataClass::Telemetry,
+        DataClass::Rollups,
+        DataClass::Waveforms,
+        DataClass::Events,
+        DataClass::Detections,
+        DataClass::Audit,
+    ];
+
+    /// Name used in configuration, archive file names and audit entries.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataClass::Telemetry => "telemetry",
+            DataClass::Rollups => "rollups",
+            DataClass::Waveforms => "waveforms",
+            DataClass::Events => "events",
+            DataClass::Detections => "detections",
+            DataClass::Audit => "audit",
+        }
+    }
+}
+
+/// One expired row, serialized for the archive. `id` is what `delete_records` takes back
+/// (the row id, or the event id for waveforms).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveRecord {
+    pub id: i64,
+    pub record: serde_json::Value,
+}
+
+impl ArchiveRecord {
+    pub fn new(id: i64, record: &impl Serialize) -> StorageResult<Self> {
+        let record = serde_json::to_value(record)
+            .map_err(|e| StorageError::Corrupt(format!("archive record {}: {}", id, e)))?;
+        Ok(Self { id, record })
+    }
+}
+
+/// An event as archived: the row plus everything that is deleted with it.
+#[derive(Debug, Serialize)]
+struct ArchivedEvent<'a> {
+    event: &'a FallLog,
+    alerts: Vec<&'a Alert>,
+    assessments: Vec<&'a Assessment>,
+    waveform: Option<&'a Waveform>,
+}
+
+/// Bundles expired events with their alerts, assessments and waveform.
+pub fn event_records(
+    events: &[FallLog],
+    alerts: &[Alert],
+    assessments: &[Assessment],
+    waveforms: &[Waveform],
+) -> StorageResult<Vec<ArchiveRecord>> {
+    events
+        .iter()
+        .map(|event| {
+            let archived = ArchivedEvent {
+                event,
+                alerts: alerts.iter().filter(|a| a.event_id == event.id).collect(),
+                assessments: assessments
+                    .iter()
+                    .filter(|a| a.event_id == event.id)
+                    .collect(),
+                waveform: waveforms.iter().find(|w| w.event_id == event.id),
+            };
+            ArchiveRecord::new(event.id as i64, &archived)
+        })
+        .collect()
+}
+
+/// **New Audit Entry**
+///
+/// A row to be written to `audit_log`; `detail` is stored as JSON text.
+#[derive(Debug, Clone)]
+pub struct NewAuditEntry {
+    pub recorded_at: DateTime<Utc>,
+    pub actor: String,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: Option<String>,
+    pub detail: Option<serde_json::Value>,
+}
+
+/// Columns selected for every `AuditEntry` row.
+pub const AUDIT_COLUMNS: &str = "id, recorded_at, actor, action, entity_type, entity_id, detail";
+
+/// **Audit Query**
+///
+/// The newest `limit` audit entries, optionally only those about `entity_type`
+/// (and then one of `entity_ids`, when any are given).
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    pub entity_type: Option<String>,
+    pub entity_ids: Vec<String>, // Matches any of the listed ids
+    pub limit: i64,
+}
+
+impl AuditQuery {
+    /// In-Rust equivalent of `audit_query` (used by the in-memory backend).
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.entity_type
+            .as_ref()
+            .is_none_or(|t| &entry.entity_type == t)
+            && (self.entity_ids.is_empty()
+                || entry
+                    .entity_id
+                    .as_ref()
+                    .is_some_and(|id| self.entity_ids.contains(id)))
+    }
+}
+
+/// `SELECT` of the audit entries matching `query`, newest first.
+pub fn audit_query<'args, DB>(query: &AuditQuery) -> QueryBuilder<'args, DB>
+where
+    DB: Database,
+    String: Encode<'args, DB> + Type<DB>,
+    i64: Encode<'args, DB> + Type<DB>,
+{
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT {} FROM audit_log WHERE 1 = 1",
+        AUDIT_COLUMNS
+    ));
+    if let Some(entity_type) = &query.entity_type {
+        qb.push(" AND entity_type = ")
+            .push_bind(entity_type.clone());
+    }
+    if !query.entity_ids.is_empty() {
+        qb.push(" AND entity_id IN (");
+        let mut list = qb.separated(", ");
+        for id in &query.entity_ids {
+            list.push_bind(id.clone());
+        }
+        list.push_unseparated(")");
+    }
+    qb.push(" ORDER BY id DESC LIMIT ").push_bind(query.limit);
+    qb
+}
+
+/// **New Legal Hold**
+///
+/// Covers every record of `patient_id`, or the event behind `alert_id` (at least one is set).
+#[derive(Debug, Clone)]
+pub struct NewLegalHold {
+    pub patient_id: Option<String>,
+    pub alert_id: Option<i32>,
+    pub reason: String,
+    pub placed_by: String,
+    pub placed_at: DateTime<Utc>,
+}
+
+/// `telemetry` row with its id, as read for archiving.
+#[derive(sqlx::FromRow)]
+pub(crate) struct TelemetryRow {
+    pub id: i64,
+    #[sqlx(flatten)]
+    pub sample: TelemetrySample,
+}
+
+/// `telemetry_rollups` row with its id, as read for archiving.
+#[derive(sqlx::FromRow)]
+pub(crate) struct RollupRow {
+    pub id: i64,
+    #[sqlx(flatten)]
+    pub rollup: TelemetryRollup,
+}
+
+/// Events under an active legal hold, by patient or through one of their alerts.
+const HELD_EVENT: &str = "EXISTS (SELECT 1 FROM legal_holds h WHERE h.released_at IS NULL \
+     AND (h.patient_id = events.patient_id \
+     OR h.alert_id IN (SELECT a.id FROM alerts a WHERE a.event_id = events.id)))";
+
+/// Detector outcomes under an active legal hold, by patient or through the alert of their event.
+const HELD_DETECTION: &str = "EXISTS (SELECT 1 FROM legal_holds h WHERE h.released_at IS NULL \
+     AND (h.patient_id = detections.patient_id \
+     OR h.alert_id IN (SELECT a.id FROM alerts a WHERE a.event_id = detections.event_id)))";
+
+/// Rows of `table` from a device under an active legal hold: one a held patient wears, or one
+/// that reported an event of a held patient or a held alert.
+fn held_device(table: &str) -> String {
+    format!(
+        "EXISTS (SELECT 1 FROM legal_holds h WHERE h.released_at IS NULL \
+         AND ({table}.device_id IN (SELECT d.id FROM devices d WHERE d.patient_id = h.patient_id) \
+         OR {table}.device_id IN (SELECT e.device_id FROM events e WHERE e.patient_id = h.patient_id \
+         OR e.id IN (SELECT a.event_id FROM alerts a WHERE a.id = h.alert_id))))"
+    )
+}
+
+/// Audit entries under an active legal hold: those about the hold itself, or about the held
+/// patient, alert, assessment or Morse score.
+const HELD_AUDIT: &str = "EXISTS (SELECT 1 FROM legal_holds h WHERE h.released_at IS NULL AND ( \
+     (audit_log.entity_type = 'LegalHold' AND audit_log.entity_id = CAST(h.id AS TEXT)) \
+     OR (audit_log.entity_type = 'Patient' AND audit_log.entity_id = h.patient_id) \
+     OR (audit_log.entity_type = 'Alert' AND audit_log.entity_id IN (SELECT CAST(a.id AS TEXT) \
+         FROM alerts a JOIN events e ON e.id = a.event_id \
+         WHERE a.id = h.alert_id OR e.patient_id = h.patient_id)) \
+     OR (audit_log.entity_type = 'Assessment' AND audit_log.entity_id IN (SELECT CAST(s.id AS TEXT) \
+         FROM assessments s WHERE s.alert_id = h.alert_id OR s.patient_id = h.patient_id)) \
+     OR (audit_log.entity_type = 'MorseScore' AND audit_log.entity_id IN (SELECT CAST(m.id AS TEXT) \
+         FROM morse_scores m WHERE m.patient_id = h.patient_id))))";
+
+/// Up to `limit` rows of `class` older than `cutoff` that may be purged, oldest id first.
+/// Held rows are skipped, and so are events whose alert is still open.
+pub fn expired_query<'args, DB>(
+    class: DataClass,
+    cutoff: DateTime<Utc>,
+    limit: i64,
+) -> QueryBuilder<'args, DB>
+where
+    DB: Database,
+    DateTime<Utc>: Encode<'args, DB> + Type<DB>,
+    String: Encode<'args, DB> + Type<DB>,
+    i64: Encode<'args, DB> + Type<DB>,
+{
+    let mut qb = match class {
+        DataClass::Telemetry => QueryBuilder::new(
+            "SELECT id, device_id, recorded_at, x, y, z, g_force, wifi, temp \
+             FROM telemetry WHERE recorded_at < ",
+        ),
+        DataClass::Rollups => QueryBuilder::new(format!(
+            "SELECT id, {} FROM telemetry_rollups WHERE bucket_start < ",
+            ROLLUP_COLUMNS
+        )),
+        DataClass::Waveforms => QueryBuilder::new(
+            "SELECT event_id, device_id, impact_at, started_at, ended_at, points \
+             FROM waveforms WHERE impact_at < ",
+        ),
+        DataClass::Events => QueryBuilder::new(format!(
+            "SELECT {} FROM events WHERE detected_at < ",
+            EVENT_COLUMNS
+        )),
+        DataClass::Detections => QueryBuilder::new(format!(
+            "SELECT {} FROM detections WHERE detected_at < ",
+            DETECTION_COLUMNS
+        )),
+        DataClass::Audit => QueryBuilder::new(format!(
+            "SELECT {} FROM audit_log WHERE recorded_at < ",
+            AUDIT_COLUMNS
+        )),
+    };
+    qb.push_bind(cutoff);
+
+    let order = match class {
+        DataClass::Waveforms => {
+            qb.push(format!(
+                " AND event_id NOT IN (SELECT events.id FROM events WHERE {})",
+                HELD_EVENT
+            ));
+            "event_id"
+        }
+        DataClass::Events => {
+            qb.push(format!(" AND NOT {}", HELD_EVENT));
+            qb.push(" AND NOT EXISTS (SELECT 1 FROM alerts a WHERE a.event_id = events.id AND a.status IN (");
+            let mut list = qb.separated(", ");
+            for status in OPEN_ALERT_STATUSES {
+                list.push_bind(status.to_string());
+            }
+            list.push_unseparated("))");
+            "id"
+        }
+        DataClass::Detections => {
+            qb.push(format!(" AND NOT {}", HELD_DETECTION));
+            "id"
+        }
+        DataClass::Telemetry => {
+            qb.push(format!(" AND NOT {}", held_device("telemetry")));
+            "id"
+        }
+        DataClass::Rollups => {
+            qb.push(format!(" AND NOT {}", held_device("telemetry_rollups")));
+            "id"
+        }
+        DataClass::Audit => {
+            qb.push(format!(" AND NOT {}", HELD_AUDIT));
+            "id"
+        }
+    };
+    qb.push(format!(" ORDER BY {} LIMIT ", order))
+        .push_bind(limit);
+    qb
+}
+
+/// `SELECT <columns> FROM <table> WHERE <column> IN (<ids>)`
+pub fn select_by_ids<'args, DB>(
+    columns: &str,
+    table: &str,
+    column: &str,
+    ids: &[i64],
+) -> QueryBuilder<'args, DB>
+where
+    DB: Database,
+    i64: Encode<'args, DB> + Type<DB>,
+{
+    let mut qb = QueryBuilder::new(format!("SELECT {} FROM {}", columns, table));
+    push_id_list(&mut qb, column, ids);
+    qb
+}
+
+/// `DELETE FROM <table> WHERE <column> IN (<ids>)`
+pub fn delete_by_ids<'args, DB>(table: &str, column: &str, ids: &[i64]) -> QueryBuilder<'args, DB>
+where
+    DB: Database,
+    i64: Encode<'args, DB> + Type<DB>,
+{
+    let mut qb = QueryBuilder::new(format!("DELETE FROM {}", table));
+    push_id_list(&mut qb, column, ids);
+    qb
+}
+
+fn push_id_list<'args, DB>(qb: &mut QueryBuilder<'args, DB>, column: &str, ids: &[i64])
+where
+    DB: Database,
+    i64: Encode<'args, DB> + Type<DB>,
+{
+    if ids.is_empty() {
+        qb.push(" WHERE 1 = 0");
+        return;
+    }
+    qb.push(format!(" WHERE {} IN (", column));
+    let mut list = qb.separated(", ");
+    for id in ids {
+        list.push_bind(*id);
+    }
+    list.push_unseparated(")");
+}
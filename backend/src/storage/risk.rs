@@ -0,0 +1,213 @@
+use crate::model::RiskScore;
+use chrono::{DateTime, Utc};
+use sqlx::{Database, Encode, QueryBuilder, Type};
+
+/// Columns selected for every `MorseScore` row.
+pub(crate) const MORSE_COLUMNS: &str = "id, patient_id, history_of_falling, secondary_diagnosis, \
+     ambulatory_aid, iv_therapy, gait, mental_status, total, assessed_by, assessed_at";
+
+/// Records a Morse Fall Scale entry ($1..$10 in `NewMorseScore` order).
+pub(crate) const INSERT_MORSE: &str = "INSERT INTO morse_scores \
+     (patient_id, history_of_falling, secondary_diagnosis, ambulatory_aid, iv_therapy, gait, \
+     mental_status, total, assessed_by, assessed_at) \
+     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)";
+
+/// Columns selected for every `RiskScore` row.
+pub(crate) const RISK_COLUMNS: &str = "id, patient_id, calculated_at, probability, rating, \
+     near_misses, falls, false_alarms, night_share, morse_total, window_days, reason, event_id";
+
+/// Stores a recalculated score ($1..$12 in `NewRiskScore` order).
+pub(crate) const INSERT_RISK: &str = "INSERT INTO risk_scores \
+     (patient_id, calculated_at, probability, rating, near_misses, falls, false_alarms, \
+     night_share, morse_total, window_days, reason, event_id) \
+     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)";
+
+/// **New Morse Score**
+///
+/// A clinician's Morse Fall Scale entry; items hold the points scored (see `risk::MORSE_ITEMS`).
+#[derive(Debug, Clone)]
+pub struct NewMorseScore {
+    pub patient_id: String,
+    pub history_of_falling: i32,
+    pub secondary_diagnosis: i32,
+    pub ambulatory_aid: i32,
+    pub iv_therapy: i32,
+    pub gait: i32,
+    pub mental_status: i32,
+    pub total: i32,
+    pub assessed_by: String,
+    pub assessed_at: DateTime<Utc>,
+}
+
+/// A recalculated fall risk score and the factors behind it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewRiskScore {
+    pub patient_id: String,
+    pub calculated_at: DateTime<Utc>,
+    pub probability: f64,
+    pub rating: String,
+    pub near_misses: i32,
+    pub falls: i32,
+    pub false_alarms: i32,
+    pub night_share: f64,
+    pub morse_total: Option<i32>,
+    pub window_days: i32,
+    pub reason: String,
+    pub event_id: Option<i32>,
+}
+
+/// **Risk Query**
+///
+/// The newest `limit` scores (the history), optionally of one patient only.
+#[derive(Debug, Clone, Default)]
+pub struct RiskQuery {
+    pub patient_id: Option<String>,
+    pub limit: i64,
+}
+
+impl RiskQuery {
+    /// In-Rust equivalent of `risk_query` (used by the in-memory backend).
+    pub fn matches(&self, score: &RiskScore) -> bool {
+        self.patient_id
+            .as_ref()
+            .is_none_or(|patient| &score.patient_id == patient)
+    }
+}
+
+/// `SELECT` of the scores matching `query`, newest first.
+pub fn risk_query<'args, DB>(query: &RiskQuery) -> QueryBuilder<'args, DB>
+where
+    DB: Database,
+    String: Encode<'args, DB> + Type<DB>,
+    i64: Encode<'args, DB> + Type<DB>,
+{
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT {} FROM risk_scores WHERE 1 = 1",
+        RISK_COLUMNS
+    ));
+    if let Some(patient_id) = &query.patient_id {
+        qb.push(" AND patient_id = ").push_bind(patient_id.clone());
+    }
+    qb.push(" ORDER BY id DESC LIMIT ").push_bind(query.limit);
+    qb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Repository;
+    use crate::test_support::on_every_backend;
+    use chrono::{Duration, SubsecRound};
+
+    // Helper: rn:jones's Morse entry for P-1001 `days_ago`, history of falling scoring `history`
+    fn morse(history: i32, days_ago: i64, now: DateTime<Utc>) -> NewMorseScore {
+        NewMorseScore {
+            patient_id: "P-1001".to_string(),
+            history_of_falling: history,
+            secondary_diagnosis: 15,
+            ambulatory_aid: 0,
+            iv_therapy: 0,
+            gait: 0,
+            mental_status: 0,
+            total: history + 15,
+            assessed_by: "rn:jones".to_string(),
+            assessed_at: now - Duration::days(days_ago),
+        }
+    }
+
+    // Helper: a moderate score of `patient` calculated at `at`
+    fn score(patient: &str, at: DateTime<Utc>) -> NewRiskScore {
+        NewRiskScore {
+            patient_id: patient.to_string(),
+            calculated_at: at,
+            probability: 0.2,
+            rating: "moderate".to_string(),
+            near_misses: 3,
+            falls: 1,
+            false_alarms: 1,
+            night_share: 0.5,
+            morse_total: Some(15),
+            window_days: 90,
+            reason: "event".to_string(),
+            event_id: Some(7),
+        }
+    }
+
+    // Helper: the ids of the scores matching `query`
+    async fn ids(repo: &dyn Repository, patient: Option<&str>, limit: i64) -> Vec<i32> {
+        let query = RiskQuery {
+            patient_id: patient.map(String::from),
+            limit,
+        };
+        let scores = repo.query_risk_scores(&query).await.unwrap();
+        scores.iter().map(|s| s.id).collect()
+    }
+
+    // A Morse entry is stored as entered
+    #[actix_web::test]
+    async fn test_record_morse_score() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3); // Within every backend's precision
+            let recorded = repo.record_morse_score(&morse(0, 1, now)).await.unwrap();
+            assert_eq!(recorded.total, 15);
+            assert_eq!(recorded.assessed_by, "rn:jones");
+            assert_eq!(recorded.assessed_at, now - Duration::days(1));
+        })
+        .await;
+    }
+
+    // The latest Morse entry is the patient's newest since the given time
+    #[actix_web::test]
+    async fn test_latest_morse_score() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3);
+            repo.record_morse_score(&morse(25, 91, now)).await.unwrap();
+            let recorded = repo.record_morse_score(&morse(0, 1, now)).await.unwrap();
+            let since = now - Duration::days(90);
+            let latest = repo.latest_morse_score("P-1001", since).await.unwrap();
+            assert_eq!(latest.map(|m| m.id), Some(recorded.id));
+            let none = repo.latest_morse_score("P-1001", now).await.unwrap();
+            assert!(none.is_none());
+            let other = repo.latest_morse_score("P-2002", since).await.unwrap();
+            assert!(other.is_none());
+        })
+        .await;
+    }
+
+    // A stored score keeps its factors; unknown scores are None
+    #[actix_web::test]
+    async fn test_get_risk_score() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3);
+            let inserted = repo.insert_risk_score(&score("P-1001", now)).await.unwrap();
+            let stored = repo.get_risk_score(inserted.id).await.unwrap().unwrap();
+            assert_eq!(stored.calculated_at, now);
+            assert_eq!(stored.night_share, 0.5);
+            assert_eq!(stored.morse_total, Some(15));
+            assert_eq!(stored.event_id, Some(7));
+            assert!(repo.get_risk_score(999).await.unwrap().is_none());
+        })
+        .await;
+    }
+
+    // The history is newest first, of one patient or all, up to the limit
+    #[actix_web::test]
+    async fn test_query_risk_scores() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let now = Utc::now().trunc_subsecs(3);
+            let first = repo.insert_risk_score(&score("P-1001", now)).await.unwrap();
+            let other = repo.insert_risk_score(&score("P-2002", now)).await.unwrap();
+            let second = repo.insert_risk_score(&score("P-1001", now)).await.unwrap();
+            assert_eq!(
+                ids(repo, Some("P-1001"), 10).await,
+                vec![second.id, first.id]
+            );
+            assert_eq!(ids(repo, Some("P-1001"), 1).await, vec![second.id]);
+            assert_eq!(
+                ids(repo, None, 10).await,
+                vec![second.id, other.id, first.id]
+            );
+        })
+        .await;
+    }
+}
@@ -0,0 +1,426 @@
+use super::{Repository, StorageError, StorageResult};
+use crate::model::{
+    SignalSummary, TelemetryBucket, TelemetryRollup, TelemetrySample, TelemetrySeries,
+};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{Database, Encode, QueryBuilder, Type};
+use std::collections::BTreeMap;
+
+/// Bucket sizes (seconds) kept up to date in `telemetry_rollups`, finest first.
+pub const ROLLUP_RESOLUTIONS: [i64; 3] = [10, 60, 3600];
+
+/// Resolutions chosen when the client doesn't ask for one: the finest giving at most
+/// `TARGET_BUCKETS` points.
+const AUTO_RESOLUTIONS: [i64; 9] = [1, 10, 60, 300, 900, 3600, 21_600, 86_400, 604_800];
+pub const TARGET_BUCKETS: i64 = 500;
+
+/// Upper bound on the points one query may return (a week at one-minute resolution fits).
+pub const MAX_BUCKETS: i64 = 20_000;
+
+/// Range used when `from` is omitted.
+pub const DEFAULT_RANGE_HOURS: i64 = 24;
+
+/// Columns of a `telemetry_rollups` row, in the order `push_rollup_upsert` binds them.
+pub const ROLLUP_COLUMNS: &str = "device_id, resolution_seconds, bucket_start, sample_count, \
+     g_min, g_max, g_sum, x_min, x_max, x_sum, y_min, y_max, y_sum, z_min, z_max, z_sum";
+
+/// Rows per multi-row rollup upsert (16 binds per row).
+pub const ROLLUP_CHUNK: usize = 500;
+
+/// **Telemetry Query**
+///
+/// One device over `[from, to]`, downsampled to `resolution_seconds`. Buckets are aligned to
+/// multiples of the resolution since the Unix epoch and always cover whole buckets, so the first
+/// and last one may include samples just outside the requested range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TelemetryQuery {
+    pub device_id: String,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub resolution_seconds: i64,
+}
+
+impl TelemetryQuery {
+    /// Validates the range and resolution; picks a resolution from the range when none is given.
+    pub fn new(
+        device_id: String,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        resolution_seconds: Option<i64>,
+    ) -> StorageResult<Self> {
+        if from > to {
+            return Err(StorageError::InvalidQuery(
+                "`from` must not be after `to`".to_string(),
+            ));
+        }
+        let resolution_seconds = resolution_seconds.unwrap_or_else(|| auto_resolution(from, to));
+        if resolution_seconds <= 0 {
+            return Err(StorageError::InvalidQuery(format!(
+                "resolution must be positive: {}",
+                resolution_seconds
+            )));
+        }
+        let query = Self {
+            device_id,
+            from,
+            to,
+            resolution_seconds,
+        };
+        if query.bucket_count() > MAX_BUCKETS {
+            return Err(StorageError::InvalidQuery(format!(
+                "{} buckets requested, at most {} allowed: use a coarser resolution",
+                query.bucket_count(),
+                MAX_BUCKETS
+            )));
+        }
+        Ok(query)
+    }
+
+    /// Start of the first bucket.
+    pub fn aligned_from(&self) -> DateTime<Utc> {
+        bucket_start(self.from, self.resolution_seconds)
+    }
+
+    /// Last instant of the final bucket.
+    pub fn aligned_to(&self) -> DateTime<Utc> {
+        bucket_start(self.to, self.resolution_seconds) + Duration::seconds(self.resolution_seconds)
+            - Duration::nanoseconds(1)
+    }
+
+    fn bucket_count(&self) -> i64 {
+        (self.to - self.aligned_from()).num_seconds() / self.resolution_seconds + 1
+    }
+
+    /// Coarsest precomputed rollup the result can be built from, or `None` to use raw samples.
+    pub fn source_resolution(&self) -> Option<i64> {
+        ROLLUP_RESOLUTIONS
+            .iter()
+            .rev()
+            .copied()
+            .find(|level| self.resolution_seconds % level == 0)
+    }
+}
+
+/// Parses `30s`, `5m`, `1h`, `1d` or a plain number of seconds.
+pub fn parse_resolution(value: &str) -> StorageResult<i64> {
+    let invalid = || StorageError::InvalidQuery(format!("invalid resolution: {}", value));
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(split) => value.split_at(split),
+        None => (value, "s"),
+    };
+    let number: i64 = number.parse().map_err(|_| invalid())?;
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(unit).ok_or_else(invalid)
+}
+
+/// Finest "round" resolution that keeps the chart under `TARGET_BUCKETS` points.
+pub fn auto_resolution(from: DateTime<Utc>, to: DateTime<Utc>) -> i64 {
+    let span = (to - from).num_seconds().max(1);
+    AUTO_RESOLUTIONS
+        .iter()
+        .copied()
+        .find(|r| span / r < TARGET_BUCKETS)
+        .unwrap_or(AUTO_RESOLUTIONS[AUTO_RESOLUTIONS.len() - 1])
+}
+
+/// Start of the epoch-aligned bucket containing `at`.
+pub fn bucket_start(at: DateTime<Utc>, resolution_seconds: i64) -> DateTime<Utc> {
+    let secs = at.timestamp().div_euclid(resolution_seconds) * resolution_seconds;
+    DateTime::from_timestamp(secs, 0).unwrap_or(at)
+}
+
+impl TelemetryRollup {
+    fn from_sample(sample: &TelemetrySample, resolution_seconds: i64) -> Self {
+        Self {
+            device_id: sample.device_id.clone(),
+            resolution_seconds,
+            bucket_start: bucket_start(sample.recorded_at, resolution_seconds),
+            sample_count: 1,
+            g_min: sample.g_force,
+            g_max: sample.g_force,
+            g_sum: sample.g_force,
+            x_min: sample.x,
+            x_max: sample.x,
+            x_sum: sample.x,
+            y_min: sample.y,
+            y_max: sample.y,
+            y_sum: sample.y,
+            z_min: sample.z,
+            z_max: sample.z,
+            z_sum: sample.z,
+        }
+    }
+
+    /// Merges another partial bucket into this one (same as the SQL upsert).
+    pub fn absorb(&mut self, other: &TelemetryRollup) {
+        self.sample_count += other.sample_count;
+        self.g_min = self.g_min.min(other.g_min);
+        self.g_max = self.g_max.max(other.g_max);
+        self.g_sum += other.g_sum;
+        self.x_min = self.x_min.min(other.x_min);
+        self.x_max = self.x_max.max(other.x_max);
+        self.x_sum += other.x_sum;
+        self.y_min = self.y_min.min(other.y_min);
+        self.y_max = self.y_max.max(other.y_max);
+        self.y_sum += other.y_sum;
+        self.z_min = self.z_min.min(other.z_min);
+        self.z_max = self.z_max.max(other.z_max);
+        self.z_sum += other.z_sum;
+    }
+
+    fn to_bucket(&self) -> TelemetryBucket {
+        let n = self.sample_count.max(1) as f64;
+        let summary = |min: f64, max: f64, sum: f64| SignalSummary {
+            min,
+            max,
+            mean: sum / n,
+        };
+        TelemetryBucket {
+            start: self.bucket_start,
+            count: self.sample_count,
+            g_force: summary(self.g_min, self.g_max, self.g_sum),
+            x: summary(self.x_min, self.x_max, self.x_sum),
+            y: summary(self.y_min, self.y_max, self.y_sum),
+            z: summary(self.z_min, self.z_max, self.z_sum),
+        }
+    }
+}
+
+/// Aggregates samples into one rollup per device and bucket at `resolution_seconds`.
+pub fn summarize(samples: &[TelemetrySample], resolution_seconds: i64) -> Vec<TelemetryRollup> {
+    let mut buckets: BTreeMap<(&str, DateTime<Utc>), TelemetryRollup> = BTreeMap::new();
+    for sample in samples {
+        let rollup = TelemetryRollup::from_sample(sample, resolution_seconds);
+        buckets
+            .entry((sample.device_id.as_str(), rollup.bucket_start))
+            .and_modify(|b| b.absorb(&rollup))
+            .or_insert(rollup);
+    }
+    buckets.into_values().collect()
+}
+
+/// Partial rollups for a telemetry batch at every maintained resolution.
+pub fn rollups_for(samples: &[TelemetrySample]) -> Vec<TelemetryRollup> {
+    ROLLUP_RESOLUTIONS
+        .iter()
+        .flat_map(|r| summarize(samples, *r))
+        .collect()
+}
+
+/// Re-buckets finer rollups into `resolution_seconds` chart points.
+pub fn merge_buckets(rows: &[TelemetryRollup], resolution_seconds: i64) -> Vec<TelemetryBucket> {
+    let mut buckets: BTreeMap<DateTime<Utc>, TelemetryRollup> = BTreeMap::new();
+    for row in rows {
+        let start = bucket_start(row.bucket_start, resolution_seconds);
+        buckets
+            .entry(start)
+            .and_modify(|b| b.absorb(row))
+            .or_insert_with(|| TelemetryRollup {
+                bucket_start: start,
+                resolution_seconds,
+                ..row.clone()
+            });
+    }
+    buckets.values().map(TelemetryRollup::to_bucket).collect()
+}
+
+/// Builds the chart from the coarsest rollup that fits, falling back to raw samples
+/// for resolutions finer than (or not a multiple of) the smallest rollup.
+pub async fn downsample(
+    db: &dyn Repository,
+    query: &TelemetryQuery,
+) -> StorageResult<TelemetrySeries> {
+    let (from, to) = (query.aligned_from(), query.aligned_to());
+    let (rows, source) = match query.source_resolution() {
+        Some(level) => (
+            db.telemetry_rollups(&query.device_id, level, from, to)
+                .await?,
+            format!("rollup_{}s", level),
+        ),
+        None => {
+            let samples = db.telemetry_between(&query.device_id, from, to).await?;
+            (
+                summarize(&samples, query.resolution_seconds),
+                "raw".to_string(),
+            )
+        }
+    };
+    Ok(TelemetrySeries {
+        device_id: query.device_id.clone(),
+        from: query.from,
+        to: query.to,
+        resolution_seconds: query.resolution_seconds,
+        source,
+        buckets: merge_buckets(&rows, query.resolution_seconds),
+    })
+}
+
+/// Multi-row INSERT of partial rollups that merges into existing buckets on conflict.
+/// CASE is used instead of LEAST/GREATEST so Postgres and SQLite share the statement.
+pub fn push_rollup_upsert<'args, DB>(rows: &[TelemetryRollup]) -> QueryBuilder<'args, DB>
+where
+    DB: Database,
+    DateTime<Utc>: Encode<'args, DB> + Type<DB>,
+    String: Encode<'args, DB> + Type<DB>,
+    f64: Encode<'args, DB> + Type<DB>,
+    i64: Encode<'args, DB> + Type<DB>,
+{
+    let mut qb = QueryBuilder::new(format!(
+        "INSERT INTO telemetry_rollups ({}) ",
+        ROLLUP_COLUMNS
+    ));
+    qb.push_values(rows, |mut row, r| {
+        row.push_bind(r.device_id.clone())
+            .push_bind(r.resolution_seconds)
+            .push_bind(r.bucket_start)
+            .push_bind(r.sample_count)
+            .push_bind(r.g_min)
+            .push_bind(r.g_max)
+            .push_bind(r.g_sum)
+            .push_bind(r.x_min)
+            .push_bind(r.x_max)
+            .push_bind(r.x_sum)
+            .push_bind(r.y_min)
+            .push_bind(r.y_max)
+            .push_bind(r.y_sum)
+            .push_bind(r.z_min)
+            .push_bind(r.z_max)
+            .push_bind(r.z_sum);
+    });
+    let merges: Vec<String> = ["g", "x", "y", "z"]
+        .iter()
+        .flat_map(|s| {
+            [
+                format!(
+                    "{s}_min = CASE WHEN excluded.{s}_min < telemetry_rollups.{s}_min \
+                     THEN excluded.{s}_min ELSE telemetry_rollups.{s}_min END"
+                ),
+                format!(
+                    "{s}_max = CASE WHEN excluded.{s}_max > telemetry_rollups.{s}_max \
+                     THEN excluded.{s}_max ELSE telemetry_rollups.{s}_max END"
+                ),
+                format!("{s}_sum = telemetry_rollups.{s}_sum + excluded.{s}_sum"),
+            ]
+        })
+        .collect();
+    qb.push(format!(
+        " ON CONFLICT (device_id, resolution_seconds, bucket_start) DO UPDATE SET \
+         sample_count = telemetry_rollups.sample_count + excluded.sample_count, {}",
+        merges.join(", ")
+    ));
+    qb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryRepository;
+    use crate::test_support::on_every_backend;
+
+    fn start() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-03-02T08:00:00Z")
+            .unwrap()
+            .to_utc()
+    }
+
+    // Helper: 3 minutes at 2 Hz, written in two batches so rollups are merged on the second write
+    async fn insert_samples(repo: &dyn Repository) {
+        let samples: Vec<TelemetrySample> = (0..360)
+            .map(|i| TelemetrySample {
+                device_id: "pi-01".to_string(),
+                recorded_at: start() + Duration::milliseconds(500 * i),
+                x: (i % 4) as f64 * 0.5,
+                y: -1.0,
+                z: 9.5,
+                g_force: 1.0 + (i % 8) as f64 * 0.25,
+                wifi: 80,
+                temp: 36.0,
+            })
+            .collect();
+        repo.insert_telemetry(&samples[..100]).await.unwrap();
+        repo.insert_telemetry(&samples[100..]).await.unwrap();
+    }
+
+    fn query(device: &str, resolution: i64) -> TelemetryQuery {
+        let end = start() + Duration::minutes(3) - Duration::seconds(1);
+        TelemetryQuery::new(device.to_string(), start(), end, Some(resolution)).unwrap()
+    }
+
+    // Helper: the charts at every source (rollups, merged rollups and raw samples)
+    async fn charts(repo: &dyn Repository) -> [TelemetrySeries; 3] {
+        insert_samples(repo).await;
+        let chart = async |resolution| downsample(repo, &query("pi-01", resolution)).await.unwrap();
+        [chart(60).await, chart(90).await, chart(15).await]
+    }
+
+    // Resolutions are seconds, or a number of minutes, hours or days
+    #[test]
+    fn test_parse_resolution() {
+        assert_eq!(parse_resolution("90").unwrap(), 90);
+        assert_eq!(parse_resolution("5m").unwrap(), 300);
+        assert_eq!(parse_resolution("1d").unwrap(), 86_400);
+        assert!(parse_resolution("5 weeks").is_err());
+    }
+
+    // A resolution with its own rollup reads it
+    #[actix_web::test]
+    async fn test_rollup_buckets() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let [minutes, _, _] = charts(repo).await;
+            assert_eq!(minutes.source, "rollup_60s");
+            assert_eq!(minutes.buckets.len(), 3);
+            assert_eq!(minutes.buckets[0].count, 120);
+            assert_eq!(minutes.buckets[0].g_force.min, 1.0);
+            assert_eq!(minutes.buckets[0].g_force.max, 2.75);
+            assert_eq!(minutes.buckets[0].g_force.mean, 1.875);
+            assert_eq!(minutes.buckets[0].y.mean, -1.0);
+        })
+        .await;
+    }
+
+    // Coarser buckets merged from rollups match the same buckets built from raw samples
+    #[actix_web::test]
+    async fn test_merged_buckets() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let [_, merged, raw] = charts(repo).await;
+            assert_eq!(merged.source, "rollup_10s");
+            assert_eq!(raw.source, "raw");
+            assert_eq!(raw.buckets.len(), 12);
+            let rows = repo
+                .telemetry_between("pi-01", start(), start() + Duration::minutes(3))
+                .await
+                .unwrap();
+            assert_eq!(merged.buckets, merge_buckets(&summarize(&rows, 90), 90));
+        })
+        .await;
+    }
+
+    // Other devices stay empty
+    #[actix_web::test]
+    async fn test_other_device() {
+        on_every_backend(async |repo: &dyn Repository| {
+            insert_samples(repo).await;
+            let other = downsample(repo, &query("pi-02", 60)).await.unwrap();
+            assert!(other.buckets.is_empty());
+        })
+        .await;
+    }
+
+    // SQL rollups agree with the in-memory ones
+    #[actix_web::test]
+    async fn test_backends_agree() {
+        let buckets = |charts: [TelemetrySeries; 3]| charts.map(|c| c.buckets);
+        let expected = buckets(charts(&MemoryRepository::new()).await);
+        on_every_backend(async |repo: &dyn Repository| {
+            assert_eq!(buckets(charts(repo).await), expected);
+        })
+        .await;
+    }
+}
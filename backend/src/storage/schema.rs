@@ -0,0 +1,186 @@
+use super::{StorageError, StorageResult};
+use sqlx::migrate::{Migrate, Migrator};
+use sqlx::{Database, Pool};
+use std::collections::HashMap;
+
+/// Postgres migrations (`migrations/`), embedded at compile time.
+pub static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// SQLite migrations (`migrations/sqlite/`), embedded at compile time.
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+/// **Migration Mode**
+///
+/// - `Verify`: refuse to run unless the schema matches this binary exactly (the default).
+/// - `Apply`: apply pending migrations first (`--migrate`, `--migrate-only` or `MIGRATE_ON_STARTUP=true`).
+///
+/// A schema that is *newer* than the binary is refused in both modes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MigrationMode {
+    #[default]
+    Verify,
+    Apply,
+}
+
+impl MigrationMode {
+    pub fn from_env() -> Self {
+        match std::env::var("MIGRATE_ON_STARTUP").as_deref() {
+            Ok("1") | Ok("true") | Ok("yes") => MigrationMode::Apply,
+            _ => MigrationMode::Verify,
+        }
+    }
+}
+
+/// What `prepare_schema` found and did.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaReport {
+    pub version: Option<i64>, // Latest applied migration
+    pub applied_now: usize,
+}
+
+/// Whether no migration has been applied to the database yet.
+pub async fn is_unmigrated<DB>(pool: &Pool<DB>) -> StorageResult<bool>
+where
+    DB: Database,
+    DB::Connection: Migrate,
+{
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    Ok(conn.list_applied_migrations().await?.is_empty())
+}
+
+/// Compares the database's `_sqlx_migrations` history with the embedded migrations,
+/// then applies pending ones in `Apply` mode.
+pub async fn prepare_schema<DB>(
+    pool: &Pool<DB>,
+    migrator: &Migrator,
+    mode: MigrationMode,
+) -> StorageResult<SchemaReport>
+where
+    DB: Database,
+    DB::Connection: Migrate,
+{
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    if let Some(version) = conn.dirty_version().await? {
+        return Err(StorageError::Schema(format!(
+            "migration {} failed part-way; repair the database before starting",
+            version
+        )));
+    }
+    let applied = conn.list_applied_migrations().await?;
+    drop(conn);
+
+    let known: HashMap<i64, &[u8]> = migrator
+        .iter()
+        .map(|m| (m.version, m.checksum.as_ref()))
+        .collect();
+    let latest_known = migrator.iter().map(|m| m.version).max();
+
+    if let Some(unknown) = applied.iter().find(|a| !known.contains_key(&a.version)) {
+        return Err(StorageError::Schema(format!(
+            "database schema version {} is newer than this binary (latest known migration: {}); \
+             deploy a build that includes it instead of downgrading",
+            unknown.version,
+            latest_known.map_or("none".to_string(), |v| v.to_string())
+        )));
+    }
+    if let Some(changed) = applied
+        .iter()
+        .find(|a| known.get(&a.version) != Some(&a.checksum.as_ref()))
+    {
+        return Err(StorageError::Schema(format!(
+            "migration {} was modified after it was applied",
+            changed.version
+        )));
+    }
+
+    let pending: Vec<_> = migrator
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .collect();
+    let applied_version = applied.iter().map(|a| a.version).max();
+    match (pending.first(), mode) {
+        (None, _) => Ok(SchemaReport {
+            version: applied_version,
+            applied_now: 0,
+        }),
+        (Some(first), MigrationMode::Verify) => Err(StorageError::Schema(format!(
+            "database schema is behind this binary: {} pending migration(s), starting with {} ({}); \
+             run `backend --migrate-only` or start with --migrate / MIGRATE_ON_STARTUP=true",
+            pending.len(),
+            first.version,
+            first.description
+        ))),
+        (Some(_), MigrationMode::Apply) => {
+            migrator.run(pool).await?;
+            Ok(SchemaReport {
+                version: latest_known,
+                applied_now: pending.len(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use sqlx::SqlitePool;
+
+    async fn empty_database() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    // Pending migrations are refused unless asked to apply them
+    #[actix_web::test]
+    async fn test_pending_migrations() {
+        let pool = empty_database().await;
+        let err = prepare_schema(&pool, &SQLITE_MIGRATOR, MigrationMode::Verify)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("pending migration"));
+    }
+
+    // Applied migrations then pass the check, with nothing left to apply
+    #[actix_web::test]
+    async fn test_applied_migrations() {
+        let pool = empty_database().await;
+        let migrator = &SQLITE_MIGRATOR;
+        let report = prepare_schema(&pool, migrator, MigrationMode::Apply)
+            .await
+            .unwrap();
+        assert_eq!(report.applied_now, migrator.iter().count());
+        let report = prepare_schema(&pool, migrator, MigrationMode::Verify)
+            .await
+            .unwrap();
+        assert_eq!(report.applied_now, 0);
+        assert_eq!(report.version, migrator.iter().map(|m| m.version).max());
+    }
+
+    // A schema newer than the binary is always refused
+    #[actix_web::test]
+    async fn test_newer_schema() {
+        let pool = empty_database().await;
+        prepare_schema(&pool, &SQLITE_MIGRATOR, MigrationMode::Apply)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time) \
+             VALUES (20991231000000, 'from the future', 1, x'00', 0)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        for mode in [MigrationMode::Verify, MigrationMode::Apply] {
+            let err = prepare_schema(&pool, &SQLITE_MIGRATOR, mode)
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("newer than this binary"));
+        }
+    }
+}
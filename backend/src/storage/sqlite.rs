@@ -0,0 +1,1094 @@
+use super::alerts::{self, ALERT_COLUMNS};
+use super::assessments::{self, ASSESSMENT_COLUMNS, INSERT_ASSESSMENT, UPDATE_ASSESSMENT};
+use super::detections::{self, DETECTION_COLUMNS, INSERT_DETECTION};
+use super::labels::{self, INSERT_LABEL, LABEL_COLUMNS};
+use super::outbox::{INSERT_OUTBOUND, OUTBOX_COLUMNS, UPDATE_OUTBOUND};
+use super::query::{push_event_conditions, push_event_order, EVENT_COLUMNS};
+use super::registry::{self, DEVICE_COLUMNS, PATIENT_COLUMNS};
+use super::retention::{self, RollupRow, TelemetryRow};
+use super::risk::{self, INSERT_MORSE, INSERT_RISK, MORSE_COLUMNS, RISK_COLUMNS};
+use super::rollup::{self, ROLLUP_CHUNK, ROLLUP_COLUMNS};
+use super::schema::{self, MigrationMode, SchemaReport};
+use super::stats::{self, Dialect, PATIENT_BREAKDOWN_LIMIT};
+use super::subscriptions::{
+    INSERT_SUBSCRIPTION, NEXT_SUBSCRIPTION_EVENT, SET_SUBSCRIPTION_STATUS, SUBSCRIPTION_COLUMNS,
+};
+use super::{
+    explanation_json, AlertQuery, ArchiveRecord, AssessmentQuery, AssessmentUpdate, AuditQuery,
+    DataClass, DetectionQuery, DeviceRegistration, DeviceStatus, EventPage, EventQuery, LabelQuery,
+    NewAssessment, NewAuditEntry, NewDetection, NewEvent, NewLabel, NewLegalHold, NewMorseScore,
+    NewOutboundMessage, NewPatient, NewRiskScore, NewSubscription, OutboundUpdate, Repository,
+    RiskQuery, StatsQuery, StorageError, StorageResult, WaveformRow, LEGAL_HOLD_COLUMNS,
+    OPEN_ALERT_STATUSES,
+};
+use crate::model::{
+    Alert, Assessment, AuditEntry, Detection, Device, FallLog, FallStatistics, GroupBreakdown,
+    HistogramBucket, Label, LegalHold, MorseScore, OutboundMessage, Patient, RiskReport, RiskScore,
+    Subscription, TelemetryRollup, TelemetrySample, Waveform,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+use std::str::FromStr;
+
+/// Rows per multi-row INSERT when writing telemetry batches (8 binds per row).
+const TELEMETRY_CHUNK: usize = 500;
+
+/// **SQLite Repository**
+///
+/// Zero-setup backend for laptops and CI. A new database gets the embedded schema from
+/// `migrations/sqlite` on connect, so `sqlite::memory:` or `sqlite://fallguard.db` work without
+/// any extra tooling; an existing one is only migrated in `MigrationMode::Apply`.
+pub struct SqliteRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteRepository {
+    /// Connects and applies pending migrations.
+    pub async fn connect(url: &str) -> StorageResult<Self> {
+        Ok(Self::connect_with_report(url, MigrationMode::Apply)
+            .await?
+            .0)
+    }
+
+    /// Connects and checks the schema, reporting its version. A database with no migrations yet
+    /// is created in either mode; one behind the binary is refused unless `mode` is `Apply`, and
+    /// one newer than the binary is always refused.
+    pub async fn connect_with_report(
+        url: &str,
+        mode: MigrationMode,
+    ) -> StorageResult<(Self, SchemaReport)> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+
+        // Every connection to `:memory:` is a separate database, so keep exactly one.
+        let max_connections = if url.contains(":memory:") { 1 } else { 5 };
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await?;
+
+        // Nothing to protect in a brand-new database, so it is always given the schema
+        let mode = if schema::is_unmigrated(&pool).await? {
+            MigrationMode::Apply
+        } else {
+            mode
+        };
+        let report = schema::prepare_schema(&pool, &schema::SQLITE_MIGRATOR, mode).await?;
+
+        Ok((Self { pool }, report))
+    }
+
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+}
+
+#[async_trait]
+impl Repository for SqliteRepository {
+    async fn insert_event(&self, event: NewEvent) -> StorageResult<FallLog> {
+        let log = sqlx::query_as::<_, FallLog>(&format!(
+            r#"
+            INSERT INTO events (detected_at, severity, g_force_value, is_false_alarm, device_id, patient_id, ward,
+                                stillness_variance, validation_ms, orientation_change_deg, explanation)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            RETURNING {}
+            "#,
+            EVENT_COLUMNS
+        ))
+        .bind(event.detected_at)
+        .bind(event.severity)
+        .bind(event.g_force_value)
+        .bind(event.is_false_alarm)
+        .bind(event.device_id)
+        .bind(event.patient_id)
+        .bind(event.ward)
+        .bind(event.metrics.map(|m| m.stillness_variance))
+        .bind(event.metrics.map(|m| m.validation_ms))
+        .bind(event.metrics.map(|m| m.orientation_change_deg))
+        .bind(explanation_json(event.explanation.as_ref()))
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(log)
+    }
+
+    async fn get_event(&self, id: i32) -> StorageResult<Option<FallLog>> {
+        let log = sqlx::query_as::<_, FallLog>(&format!(
+            "SELECT {} FROM events WHERE id = $1",
+            EVENT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(log)
+    }
+
+    async fn query_events(&self, query: &EventQuery) -> StorageResult<EventPage> {
+        let mut count: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT COUNT(*) FROM events");
+        push_event_conditions(&mut count, query, false);
+        let total: i64 = count.build_query_scalar().fetch_one(&self.pool).await?;
+
+        let mut select: QueryBuilder<Sqlite> =
+            QueryBuilder::new(format!("SELECT {} FROM events", EVENT_COLUMNS));
+        push_event_conditions(&mut select, query, true);
+        push_event_order(&mut select, query);
+        let rows = select
+            .build_query_as::<FallLog>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(EventPage::from_rows(rows, total, query))
+    }
+
+    async fn fall_statistics(&self, query: &StatsQuery) -> StorageResult<FallStatistics> {
+        let filter = &query.filter;
+        let offset = query.utc_offset_minutes;
+
+        let mut qb = stats::breakdown_query::<Sqlite>(filter, None, 1);
+        let totals: GroupBreakdown = qb.build_query_as().fetch_one(&self.pool).await?;
+
+        let mut qb = stats::severity_query::<Sqlite>(filter);
+        let by_severity: Vec<RiskReport> = qb.build_query_as().fetch_all(&self.pool).await?;
+
+        let mut qb = stats::histogram_query::<Sqlite>(filter, &Dialect::Sqlite.hour_expr(offset));
+        let by_hour: Vec<HistogramBucket> = qb.build_query_as().fetch_all(&self.pool).await?;
+
+        let mut qb =
+            stats::histogram_query::<Sqlite>(filter, &Dialect::Sqlite.weekday_expr(offset));
+        let by_weekday: Vec<HistogramBucket> = qb.build_query_as().fetch_all(&self.pool).await?;
+
+        let mut qb = stats::breakdown_query::<Sqlite>(filter, Some("ward"), i64::MAX);
+        let by_ward: Vec<GroupBreakdown> = qb.build_query_as().fetch_all(&self.pool).await?;
+
+        let mut qb =
+            stats::breakdown_query::<Sqlite>(filter, Some("patient_id"), PATIENT_BREAKDOWN_LIMIT);
+        let by_patient: Vec<GroupBreakdown> = qb.build_query_as().fetch_all(&self.pool).await?;
+
+        Ok(stats::assemble(
+            query,
+            totals,
+            by_severity,
+            by_hour,
+            by_weekday,
+            by_ward,
+            by_patient,
+        ))
+    }
+
+    async fn dismiss_event(&self, event_id: i32, at: DateTime<Utc>) -> StorageResult<FallLog> {
+        sqlx::query_as::<_, FallLog>(&format!(
+            r#"
+            UPDATE events SET dismissed_at = COALESCE(dismissed_at, $2)
+            WHERE id = $1
+            RETURNING {}
+            "#,
+            EVENT_COLUMNS
+        ))
+        .bind(event_id)
+        .bind(at)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| StorageError::NotFound(format!("event {}", event_id)))
+    }
+
+    async fn events_after(&self, after_id: i32, limit: i64) -> StorageResult<Vec<FallLog>> {
+        let rows = sqlx::query_as::<_, FallLog>(&format!(
+            "SELECT {} FROM events WHERE id > $1 ORDER BY id LIMIT $2",
+            EVENT_COLUMNS
+        ))
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn latest_event_id(&self) -> StorageResult<Option<i32>> {
+        let id: Option<i32> = sqlx::query_scalar("SELECT MAX(id) FROM events")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(id)
+    }
+
+    async fn record_detection(&self, detection: &NewDetection) -> StorageResult<Detection> {
+        let row = sqlx::query_as::<_, Detection>(&format!(
+            "{} RETURNING {}",
+            INSERT_DETECTION, DETECTION_COLUMNS
+        ))
+        .bind(detection.detected_at)
+        .bind(&detection.outcome)
+        .bind(&detection.device_id)
+        .bind(&detection.patient_id)
+        .bind(&detection.ward)
+        .bind(detection.peak_g)
+        .bind(detection.metrics.map(|m| m.stillness_variance))
+        .bind(detection.metrics.map(|m| m.validation_ms))
+        .bind(detection.metrics.map(|m| m.orientation_change_deg))
+        .bind(detection.event_id)
+        .bind(explanation_json(detection.explanation.as_ref()))
+        .bind(&detection.detector)
+        .bind(detection.shadow)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn query_detections(&self, query: &DetectionQuery) -> StorageResult<Vec<Detection>> {
+        let mut qb = detections::detections_query::<Sqlite>(query);
+        let rows: Vec<Detection> = qb.build_query_as().fetch_all(&self.pool).await?;
+        Ok(rows)
+    }
+
+    async fn record_label(&self, label: &NewLabel) -> StorageResult<Label> {
+        let row =
+            sqlx::query_as::<_, Label>(&format!("{} RETURNING {}", INSERT_LABEL, LABEL_COLUMNS))
+                .bind(label.event_id)
+                .bind(label.alert_id)
+                .bind(&label.label)
+                .bind(&label.command)
+                .bind(label.labelled_at)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(row)
+    }
+
+    async fn query_labels(&self, query: &LabelQuery) -> StorageResult<Vec<Label>> {
+        let mut qb = labels::labels_query::<Sqlite>(query);
+        let rows: Vec<Label> = qb.build_query_as().fetch_all(&self.pool).await?;
+        Ok(rows)
+    }
+
+    async fn open_alert(&self, event_id: i32, raised_at: DateTime<Utc>) -> StorageResult<Alert> {
+        let alert = sqlx::query_as::<_, Alert>(
+            r#"
+            INSERT INTO alerts (event_id, status, raised_at, updated_at)
+            VALUES ($1, 'Active', $2, $2)
+            RETURNING id, event_id, status, raised_at, updated_at
+            "#,
+        )
+        .bind(event_id)
+        .bind(raised_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(alert)
+    }
+
+    async fn latest_open_alert(&self) -> StorageResult<Option<Alert>> {
+        let alert = sqlx::query_as::<_, Alert>(
+            r#"
+            SELECT id, event_id, status, raised_at, updated_at
+            FROM alerts
+            WHERE status = $1 OR status = $2
+            ORDER BY raised_at DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(OPEN_ALERT_STATUSES[0])
+        .bind(OPEN_ALERT_STATUSES[1])
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(alert)
+    }
+
+    async fn get_alert(&self, id: i32) -> StorageResult<Option<Alert>> {
+        let alert = sqlx::query_as::<_, Alert>(&format!(
+            "SELECT {} FROM alerts WHERE id = $1",
+            ALERT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(alert)
+    }
+
+    async fn query_alerts(&self, query: &AlertQuery) -> StorageResult<Vec<Alert>> {
+        let mut qb = alerts::alerts_query::<Sqlite>(query);
+        let rows: Vec<Alert> = qb.build_query_as().fetch_all(&self.pool).await?;
+        Ok(rows)
+    }
+
+    async fn set_alert_status(
+        &self,
+        alert_id: i32,
+        status: &str,
+        at: DateTime<Utc>,
+    ) -> StorageResult<Alert> {
+        sqlx::query_as::<_, Alert>(
+            r#"
+            UPDATE alerts SET status = $2, updated_at = $3
+            WHERE id = $1
+            RETURNING id, event_id, status, raised_at, updated_at
+            "#,
+        )
+        .bind(alert_id)
+        .bind(status)
+        .bind(at)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| StorageError::NotFound(format!("alert {}", alert_id)))
+    }
+
+    async fn create_assessment(&self, assessment: &NewAssessment) -> StorageResult<Assessment> {
+        let row = sqlx::query_as::<_, Assessment>(&format!(
+            "{} RETURNING {}",
+            INSERT_ASSESSMENT, ASSESSMENT_COLUMNS
+        ))
+        .bind(assessment.alert_id)
+        .bind(assessment.event_id)
+        .bind(&assessment.patient_id)
+        .bind(&assessment.form_id)
+        .bind(&assessment.form_version)
+        .bind(assessment.due_at)
+        .bind(assessment.created_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn get_assessment(&self, id: i32) -> StorageResult<Option<Assessment>> {
+        let row = sqlx::query_as::<_, Assessment>(&format!(
+            "SELECT {} FROM assessments WHERE id = $1",
+            ASSESSMENT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn query_assessments(&self, query: &AssessmentQuery) -> StorageResult<Vec<Assessment>> {
+        let mut qb = assessments::assessments_query::<Sqlite>(query);
+        let rows: Vec<Assessment> = qb.build_query_as().fetch_all(&self.pool).await?;
+        Ok(rows)
+    }
+
+    async fn update_assessment(
+        &self,
+        id: i32,
+        update: &AssessmentUpdate,
+    ) -> StorageResult<Assessment> {
+        sqlx::query_as::<_, Assessment>(&format!(
+            "{} RETURNING {}",
+            UPDATE_ASSESSMENT, ASSESSMENT_COLUMNS
+        ))
+        .bind(id)
+        .bind(&update.status)
+        .bind(&update.answers)
+        .bind(&update.author)
+        .bind(update.updated_at)
+        .bind(update.completed_at)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| StorageError::NotFound(format!("assessment {}", id)))
+    }
+
+    async fn record_morse_score(&self, score: &NewMorseScore) -> StorageResult<MorseScore> {
+        let row = sqlx::query_as::<_, MorseScore>(&format!(
+            "{} RETURNING {}",
+            INSERT_MORSE, MORSE_COLUMNS
+        ))
+        .bind(&score.patient_id)
+        .bind(score.history_of_falling)
+        .bind(score.secondary_diagnosis)
+        .bind(score.ambulatory_aid)
+        .bind(score.iv_therapy)
+        .bind(score.gait)
+        .bind(score.mental_status)
+        .bind(score.total)
+        .bind(&score.assessed_by)
+        .bind(score.assessed_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn latest_morse_score(
+        &self,
+        patient_id: &str,
+        since: DateTime<Utc>,
+    ) -> StorageResult<Option<MorseScore>> {
+        let row = sqlx::query_as::<_, MorseScore>(&format!(
+            "SELECT {} FROM morse_scores WHERE patient_id = $1 AND assessed_at >= $2 \
+             ORDER BY assessed_at DESC, id DESC LIMIT 1",
+            MORSE_COLUMNS
+        ))
+        .bind(patient_id)
+        .bind(since)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn insert_risk_score(&self, score: &NewRiskScore) -> StorageResult<RiskScore> {
+        let row =
+            sqlx::query_as::<_, RiskScore>(&format!("{} RETURNING {}", INSERT_RISK, RISK_COLUMNS))
+                .bind(&score.patient_id)
+                .bind(score.calculated_at)
+                .bind(score.probability)
+                .bind(&score.rating)
+                .bind(score.near_misses)
+                .bind(score.falls)
+                .bind(score.false_alarms)
+                .bind(score.night_share)
+                .bind(score.morse_total)
+                .bind(score.window_days)
+                .bind(&score.reason)
+                .bind(score.event_id)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(row)
+    }
+
+    async fn get_risk_score(&self, id: i32) -> StorageResult<Option<RiskScore>> {
+        let row = sqlx::query_as::<_, RiskScore>(&format!(
+            "SELECT {} FROM risk_scores WHERE id = $1",
+            RISK_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn query_risk_scores(&self, query: &RiskQuery) -> StorageResult<Vec<RiskScore>> {
+        let mut qb = risk::risk_query::<Sqlite>(query);
+        let rows: Vec<RiskScore> = qb.build_query_as().fetch_all(&self.pool).await?;
+        Ok(rows)
+    }
+
+    async fn insert_telemetry(&self, samples: &[TelemetrySample]) -> StorageResult<()> {
+        // Raw rows and rollups are written together so charts never disagree with the samples
+        let mut tx = self.pool.begin().await?;
+        for chunk in samples.chunks(TELEMETRY_CHUNK) {
+            let mut qb: QueryBuilder<Sqlite> = QueryBuilder::new(
+                "INSERT INTO telemetry (device_id, recorded_at, x, y, z, g_force, wifi, temp) ",
+            );
+            qb.push_values(chunk, |mut row, s| {
+                row.push_bind(&s.device_id)
+                    .push_bind(s.recorded_at)
+                    .push_bind(s.x)
+                    .push_bind(s.y)
+                    .push_bind(s.z)
+                    .push_bind(s.g_force)
+                    .push_bind(s.wifi)
+                    .push_bind(s.temp);
+            });
+            qb.build().execute(&mut *tx).await?;
+        }
+        let rollups = rollup::rollups_for(samples);
+        for chunk in rollups.chunks(ROLLUP_CHUNK) {
+            rollup::push_rollup_upsert::<Sqlite>(chunk)
+                .build()
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn telemetry_between(
+        &self,
+        device_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> StorageResult<Vec<TelemetrySample>> {
+        let samples = sqlx::query_as::<_, TelemetrySample>(
+            r#"
+            SELECT device_id, recorded_at, x, y, z, g_force, wifi, temp
+            FROM telemetry
+            WHERE device_id = $1 AND recorded_at >= $2 AND recorded_at <= $3
+            ORDER BY recorded_at ASC
+            "#,
+        )
+        .bind(device_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(samples)
+    }
+
+    async fn purge_telemetry_before(&self, cutoff: DateTime<Utc>) -> StorageResult<u64> {
+        let result = sqlx::query("DELETE FROM telemetry WHERE recorded_at < $1")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn telemetry_rollups(
+        &self,
+        device_id: &str,
+        resolution_seconds: i64,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> StorageResult<Vec<TelemetryRollup>> {
+        let rows = sqlx::query_as::<_, TelemetryRollup>(&format!(
+            r#"
+            SELECT {}
+            FROM telemetry_rollups
+            WHERE device_id = $1 AND resolution_seconds = $2
+              AND bucket_start >= $3 AND bucket_start <= $4
+            ORDER BY bucket_start ASC
+            "#,
+            ROLLUP_COLUMNS
+        ))
+        .bind(device_id)
+        .bind(resolution_seconds)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn save_waveform(&self, waveform: &Waveform) -> StorageResult<()> {
+        let points = serde_json::to_string(&waveform.points)
+            .map_err(|e| StorageError::Corrupt(e.to_string()))?;
+        sqlx::query(
+            r#"
+            INSERT INTO waveforms (event_id, device_id, impact_at, started_at, ended_at, points)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(waveform.event_id)
+        .bind(&waveform.device_id)
+        .bind(waveform.impact_at)
+        .bind(waveform.started_at)
+        .bind(waveform.ended_at)
+        .bind(points)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_waveform(&self, event_id: i32) -> StorageResult<Option<Waveform>> {
+        let row = sqlx::query_as::<_, WaveformRow>(
+            r#"
+            SELECT event_id, device_id, impact_at, started_at, ended_at, points
+            FROM waveforms
+            WHERE event_id = $1
+            "#,
+        )
+        .bind(event_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(WaveformRow::into_waveform).transpose()
+    }
+
+    async fn get_waveforms(&self, event_ids: &[i32]) -> StorageResult<Vec<Waveform>> {
+        let ids: Vec<i64> = event_ids.iter().map(|id| *id as i64).collect();
+        retention::select_by_ids::<Sqlite>(
+            "event_id, device_id, impact_at, started_at, ended_at, points",
+            "waveforms",
+            "event_id",
+            &ids,
+        )
+        .build_query_as::<WaveformRow>()
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(WaveformRow::into_waveform)
+        .collect()
+    }
+
+    async fn upsert_patient(&self, patient: &NewPatient) -> StorageResult<Patient> {
+        patient.validate()?;
+        let row = sqlx::query_as::<_, Patient>(&format!(
+            "{} RETURNING {}",
+            registry::UPSERT_PATIENT,
+            PATIENT_COLUMNS
+        ))
+        .bind(&patient.id)
+        .bind(&patient.family_name)
+        .bind(&patient.given_name)
+        .bind(patient.birth_date)
+        .bind(&patient.gender)
+        .bind(&patient.ward)
+        .bind(&patient.room)
+        .bind(&patient.bed)
+        .bind(patient.admitted_at)
+        .bind(patient.discharged_at)
+        .bind(patient.updated_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn get_patient(&self, id: &str) -> StorageResult<Option<Patient>> {
+        let row = sqlx::query_as::<_, Patient>(&format!(
+            "SELECT {} FROM patients WHERE id = $1",
+            PATIENT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn list_patients(&self) -> StorageResult<Vec<Patient>> {
+        let rows = sqlx::query_as::<_, Patient>(&format!(
+            "SELECT {} FROM patients ORDER BY id",
+            PATIENT_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn register_device(&self, registration: &DeviceRegistration) -> StorageResult<Device> {
+        registration.validate()?;
+        let row = sqlx::query_as::<_, Device>(&format!(
+            "{} RETURNING {}",
+            registry::REGISTER_DEVICE,
+            DEVICE_COLUMNS
+        ))
+        .bind(&registration.id)
+        .bind(&registration.model)
+        .bind(&registration.patient_id)
+        .bind(&registration.ward)
+        .bind(&registration.calibration_state)
+        .bind(registration.calibration_time())
+        .bind(registration.at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn release_devices(&self, patient_id: &str) -> StorageResult<Vec<Device>> {
+        let rows = sqlx::query_as::<_, Device>(&format!(
+            "{} RETURNING {}",
+            registry::RELEASE_DEVICES,
+            DEVICE_COLUMNS
+        ))
+        .bind(patient_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn record_device_status(&self, status: &DeviceStatus) -> StorageResult<()> {
+        sqlx::query(registry::RECORD_DEVICE_STATUS)
+            .bind(&status.device_id)
+            .bind(status.seen_at)
+            .bind(status.wifi_signal)
+            .bind(status.temperature)
+            .bind(status.battery_level)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_device(&self, id: &str) -> StorageResult<Option<Device>> {
+        let row = sqlx::query_as::<_, Device>(&format!(
+            "SELECT {} FROM devices WHERE id = $1",
+            DEVICE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn list_devices(&self) -> StorageResult<Vec<Device>> {
+        let rows = sqlx::query_as::<_, Device>(&format!(
+            "SELECT {} FROM devices ORDER BY id",
+            DEVICE_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn create_subscription(
+        &self,
+        subscription: &NewSubscription,
+    ) -> StorageResult<Subscription> {
+        let row = sqlx::query_as::<_, Subscription>(&format!(
+            "{} RETURNING {}",
+            INSERT_SUBSCRIPTION, SUBSCRIPTION_COLUMNS
+        ))
+        .bind(&subscription.topic)
+        .bind(&subscription.reason)
+        .bind(&subscription.endpoint)
+        .bind(subscription.headers_json())
+        .bind(&subscription.payload)
+        .bind(&subscription.patient_id)
+        .bind(subscription.heartbeat_seconds)
+        .bind(subscription.created_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn get_subscription(&self, id: i32) -> StorageResult<Option<Subscription>> {
+        let row = sqlx::query_as::<_, Subscription>(&format!(
+            "SELECT {} FROM subscriptions WHERE id = $1",
+            SUBSCRIPTION_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn list_subscriptions(&self) -> StorageResult<Vec<Subscription>> {
+        let rows = sqlx::query_as::<_, Subscription>(&format!(
+            "SELECT {} FROM subscriptions ORDER BY id",
+            SUBSCRIPTION_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn delete_subscription(&self, id: i32) -> StorageResult<bool> {
+        let result = sqlx::query("DELETE FROM subscriptions WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn next_subscription_event(&self, id: i32) -> StorageResult<i64> {
+        sqlx::query_scalar::<_, i64>(NEXT_SUBSCRIPTION_EVENT)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| StorageError::NotFound(format!("subscription {}", id)))
+    }
+
+    async fn set_subscription_status(
+        &self,
+        id: i32,
+        status: &str,
+        error: Option<&str>,
+        notified_at: Option<DateTime<Utc>>,
+    ) -> StorageResult<()> {
+        let result = sqlx::query(SET_SUBSCRIPTION_STATUS)
+            .bind(id)
+            .bind(status)
+            .bind(error)
+            .bind(notified_at)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(StorageError::NotFound(format!("subscription {}", id)));
+        }
+        Ok(())
+    }
+
+    async fn enqueue_outbound(
+        &self,
+        message: &NewOutboundMessage,
+    ) -> StorageResult<OutboundMessage> {
+        let row = sqlx::query_as::<_, OutboundMessage>(&format!(
+            "{} RETURNING {}",
+            INSERT_OUTBOUND, OUTBOX_COLUMNS
+        ))
+        .bind(message.event_id)
+        .bind(&message.message_type)
+        .bind(&message.control_id)
+        .bind(&message.message)
+        .bind(message.created_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn unqueued_events(&self, after_id: i32, limit: i64) -> StorageResult<Vec<FallLog>> {
+        let rows = sqlx::query_as::<_, FallLog>(&format!(
+            "SELECT {} FROM events WHERE id > $1 AND NOT EXISTS \
+             (SELECT 1 FROM hl7_outbox WHERE hl7_outbox.event_id = events.id) \
+             ORDER BY id LIMIT $2",
+            EVENT_COLUMNS
+        ))
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn first_queued_event(&self) -> StorageResult<Option<i32>> {
+        let id: Option<i32> = sqlx::query_scalar("SELECT MIN(event_id) FROM hl7_outbox")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(id)
+    }
+
+    async fn outbound_messages(
+        &self,
+        status: Option<&str>,
+        limit: i64,
+    ) -> StorageResult<Vec<OutboundMessage>> {
+        let rows = sqlx::query_as::<_, OutboundMessage>(&format!(
+            "SELECT {} FROM hl7_outbox WHERE ($1 IS NULL OR status = $1) ORDER BY id LIMIT $2",
+            OUTBOX_COLUMNS
+        ))
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn update_outbound(&self, id: i32, update: &OutboundUpdate) -> StorageResult<()> {
+        let result = sqlx::query(UPDATE_OUTBOUND)
+            .bind(id)
+            .bind(&update.status)
+            .bind(update.attempts)
+            .bind(update.next_attempt_at)
+            .bind(&update.last_error)
+            .bind(&update.ack_code)
+            .bind(update.sent_at)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(StorageError::NotFound(format!("outbound message {}", id)));
+        }
+        Ok(())
+    }
+
+    async fn record_audit(&self, entry: NewAuditEntry) -> StorageResult<AuditEntry> {
+        let detail = entry.detail.map(|d| d.to_string());
+        let row = sqlx::query_as::<_, AuditEntry>(
+            r#"
+            INSERT INTO audit_log (recorded_at, actor, action, entity_type, entity_id, detail)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, recorded_at, actor, action, entity_type, entity_id, detail
+            "#,
+        )
+        .bind(entry.recorded_at)
+        .bind(entry.actor)
+        .bind(entry.action)
+        .bind(entry.entity_type)
+        .bind(entry.entity_id)
+        .bind(detail)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn get_audit_entry(&self, id: i64) -> StorageResult<Option<AuditEntry>> {
+        let row = sqlx::query_as::<_, AuditEntry>(&format!(
+            "SELECT {} FROM audit_log WHERE id = $1",
+            retention::AUDIT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn audit_entries(&self, query: &AuditQuery) -> StorageResult<Vec<AuditEntry>> {
+        let mut qb = retention::audit_query::<Sqlite>(query);
+        let rows: Vec<AuditEntry> = qb.build_query_as().fetch_all(&self.pool).await?;
+        Ok(rows)
+    }
+
+    async fn place_legal_hold(&self, hold: NewLegalHold) -> StorageResult<LegalHold> {
+        let row = sqlx::query_as::<_, LegalHold>(&format!(
+            r#"
+            INSERT INTO legal_holds (patient_id, alert_id, reason, placed_by, placed_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING {}
+            "#,
+            LEGAL_HOLD_COLUMNS
+        ))
+        .bind(hold.patient_id)
+        .bind(hold.alert_id)
+        .bind(hold.reason)
+        .bind(hold.placed_by)
+        .bind(hold.placed_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn legal_holds(&self, include_released: bool) -> StorageResult<Vec<LegalHold>> {
+        let rows = sqlx::query_as::<_, LegalHold>(&format!(
+            "SELECT {} FROM legal_holds WHERE $1 OR released_at IS NULL ORDER BY id",
+            LEGAL_HOLD_COLUMNS
+        ))
+        .bind(include_released)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn release_legal_hold(
+        &self,
+        hold_id: i32,
+        at: DateTime<Utc>,
+    ) -> StorageResult<LegalHold> {
+        // Releasing twice keeps the original release time
+        sqlx::query_as::<_, LegalHold>(&format!(
+            r#"
+            UPDATE legal_holds SET released_at = COALESCE(released_at, $2)
+            WHERE id = $1
+            RETURNING {}
+            "#,
+            LEGAL_HOLD_COLUMNS
+        ))
+        .bind(hold_id)
+        .bind(at)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| StorageError::NotFound(format!("legal hold {}", hold_id)))
+    }
+
+    async fn expired_records(
+        &self,
+        class: DataClass,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> StorageResult<Vec<ArchiveRecord>> {
+        let mut qb = retention::expired_query::<Sqlite>(class, cutoff, limit);
+        match class {
+            DataClass::Telemetry => {
+                let rows: Vec<TelemetryRow> = qb.build_query_as().fetch_all(&self.pool).await?;
+                rows.iter()
+                    .map(|r| ArchiveRecord::new(r.id, &r.sample))
+                    .collect()
+            }
+            DataClass::Rollups => {
+                let rows: Vec<RollupRow> = qb.build_query_as().fetch_all(&self.pool).await?;
+                rows.iter()
+                    .map(|r| ArchiveRecord::new(r.id, &r.rollup))
+                    .collect()
+            }
+            DataClass::Waveforms => {
+                let rows: Vec<WaveformRow> = qb.build_query_as().fetch_all(&self.pool).await?;
+                rows.into_iter()
+                    .map(|r| {
+                        let waveform = r.into_waveform()?;
+                        ArchiveRecord::new(waveform.event_id as i64, &waveform)
+                    })
+                    .collect()
+            }
+            DataClass::Events => {
+                let events: Vec<FallLog> = qb.build_query_as().fetch_all(&self.pool).await?;
+                let ids: Vec<i64> = events.iter().map(|e| e.id as i64).collect();
+                let alerts: Vec<Alert> = retention::select_by_ids::<Sqlite>(
+                    "id, event_id, status, raised_at, updated_at",
+                    "alerts",
+                    "event_id",
+                    &ids,
+                )
+                .build_query_as()
+                .fetch_all(&self.pool)
+                .await?;
+                let assessments: Vec<Assessment> = retention::select_by_ids::<Sqlite>(
+                    ASSESSMENT_COLUMNS,
+                    "assessments",
+                    "event_id",
+                    &ids,
+                )
+                .build_query_as()
+                .fetch_all(&self.pool)
+                .await?;
+                let waveforms = retention::select_by_ids::<Sqlite>(
+                    "event_id, device_id, impact_at, started_at, ended_at, points",
+                    "waveforms",
+                    "event_id",
+                    &ids,
+                )
+                .build_query_as::<WaveformRow>()
+                .fetch_all(&self.pool)
+                .await?
+                .into_iter()
+                .map(WaveformRow::into_waveform)
+                .collect::<StorageResult<Vec<Waveform>>>()?;
+                retention::event_records(&events, &alerts, &assessments, &waveforms)
+            }
+            DataClass::Detections => {
+                let rows: Vec<Detection> = qb.build_query_as().fetch_all(&self.pool).await?;
+                rows.iter()
+                    .map(|r| ArchiveRecord::new(r.id as i64, r))
+                    .collect()
+            }
+            DataClass::Audit => {
+                let rows: Vec<AuditEntry> = qb.build_query_as().fetch_all(&self.pool).await?;
+                rows.iter().map(|r| ArchiveRecord::new(r.id, r)).collect()
+            }
+        }
+    }
+
+    async fn delete_records(&self, class: DataClass, ids: &[i64]) -> StorageResult<u64> {
+        let (table, column) = match class {
+            DataClass::Telemetry => ("telemetry", "id"),
+            DataClass::Rollups => ("telemetry_rollups", "id"),
+            DataClass::Waveforms => ("waveforms", "event_id"),
+            DataClass::Events => ("events", "id"),
+            DataClass::Detections => ("detections", "id"),
+            DataClass::Audit => ("audit_log", "id"),
+        };
+        let mut tx = self.pool.begin().await?;
+        if class == DataClass::Events {
+            for dependent in ["assessments", "alerts", "waveforms", "labels"] {
+                retention::delete_by_ids::<Sqlite>(dependent, "event_id", ids)
+                    .build()
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+        let result = retention::delete_by_ids::<Sqlite>(table, column, ids)
+            .build()
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{connect_with, StorageConfig};
+
+    // Helper: a SQLite database file of its own, as configured by DATABASE_URL
+    fn database_file() -> (std::path::PathBuf, StorageConfig) {
+        let path = std::env::temp_dir().join(format!("fallguard-{}.db", uuid::Uuid::new_v4()));
+        let config = StorageConfig::from_url(&format!("sqlite://{}", path.display())).unwrap();
+        (path, config)
+    }
+
+    // A new database gets the schema, even when migrations are only verified
+    #[actix_web::test]
+    async fn test_new_database() {
+        let (path, config) = database_file();
+        let (_, report) = connect_with(&config, MigrationMode::Verify).await.unwrap();
+        let latest = schema::SQLITE_MIGRATOR.iter().map(|m| m.version).max();
+        assert_eq!(report.unwrap().version, latest);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // An outdated database is refused unless the migrations are applied
+    #[actix_web::test]
+    async fn test_outdated_database() {
+        let (path, config) = database_file();
+        connect_with(&config, MigrationMode::Verify).await.unwrap();
+        // Roll the history back by one migration, as if the binary had been upgraded
+        let repo = SqliteRepository::connect(&format!("sqlite://{}", path.display()))
+            .await
+            .unwrap();
+        let latest = schema::SQLITE_MIGRATOR.iter().map(|m| m.version).max();
+        sqlx::query("DELETE FROM _sqlx_migrations WHERE version = $1")
+            .bind(latest)
+            .execute(repo.pool())
+            .await
+            .unwrap();
+        repo.pool().close().await;
+
+        let err = connect_with(&config, MigrationMode::Verify)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("pending migration"));
+        let (_, report) = connect_with(&config, MigrationMode::Apply).await.unwrap();
+        assert_eq!(report.unwrap().applied_now, 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
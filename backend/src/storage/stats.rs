@@ -0,0 +1,395 @@
+use super::query::push_filter_conditions;
+use super::EventFilter;
+use crate::model::{FallLog, FallStatistics, GroupBreakdown, HistogramBucket, RiskReport};
+use chrono::{Datelike, Duration, Timelike};
+use sqlx::{Database, Encode, QueryBuilder, Type};
+use std::collections::{BTreeMap, HashSet};
+
+/// Detector outcomes counted in the time-of-day and day-of-week histograms.
+pub const DETECTION_SEVERITIES: [&str; 2] = ["Critical", "Near Miss"];
+
+/// Patients listed in `by_patient` (highest number of critical falls first).
+pub const PATIENT_BREAKDOWN_LIMIT: i64 = 100;
+
+/// **Statistics Query**
+///
+/// Event filter for the report plus the offset used to bucket hours and weekdays
+/// in ward-local time (timestamps are stored in UTC).
+#[derive(Debug, Clone, Default)]
+pub struct StatsQuery {
+    pub filter: EventFilter,
+    pub utc_offset_minutes: i32,
+}
+
+/// SQL flavour for the date functions, which differ between Postgres and SQLite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    Postgres,
+    Sqlite,
+}
+
+impl Dialect {
+    /// Expression for the local hour (0-23) of `detected_at`.
+    pub fn hour_expr(&self, utc_offset_minutes: i32) -> String {
+        match self {
+            Dialect::Postgres => format!(
+                "CAST(EXTRACT(HOUR FROM (detected_at AT TIME ZONE 'UTC') + INTERVAL '{} minutes') AS INTEGER)",
+                utc_offset_minutes
+            ),
+            Dialect::Sqlite => format!(
+                "CAST(strftime('%H', detected_at, '{:+} minutes') AS INTEGER)",
+                utc_offset_minutes
+            ),
+        }
+    }
+
+    /// Expression for the local weekday of `detected_at` (0 = Sunday).
+    pub fn weekday_expr(&self, utc_offset_minutes: i32) -> String {
+        match self {
+            Dialect::Postgres => format!(
+                "CAST(EXTRACT(DOW FROM (detected_at AT TIME ZONE 'UTC') + INTERVAL '{} minutes') AS INTEGER)",
+                utc_offset_minutes
+            ),
+            Dialect::Sqlite => format!(
+                "CAST(strftime('%w', detected_at, '{:+} minutes') AS INTEGER)",
+                utc_offset_minutes
+            ),
+        }
+    }
+}
+
+/// Label a nurse gives an alert that was not a fall.
+pub const FALSE_ALARM_LABEL: &str = "no-fall";
+
+// A false alarm is a critical event a nurse dismissed or labelled `no-fall`; nurse action rows
+// ("Refused", ...) are not counted, so each alert counts once however it was handled
+const OUTCOME_COLUMNS: &str = "COUNT(*) AS total, \
+     COALESCE(SUM(CASE WHEN severity = 'Critical' THEN 1 ELSE 0 END), 0) AS critical, \
+     COALESCE(SUM(CASE WHEN severity = 'Near Miss' THEN 1 ELSE 0 END), 0) AS near_misses, \
+     COALESCE(SUM(CASE WHEN severity = 'Critical' AND (dismissed_at IS NOT NULL OR EXISTS \
+         (SELECT 1 FROM labels WHERE labels.event_id = events.id AND labels.label = 'no-fall')) \
+         THEN 1 ELSE 0 END), 0) AS false_alarms";
+
+/// `RiskReport` rows: number of events per severity.
+pub fn severity_query<'args, DB>(filter: &EventFilter) -> QueryBuilder<'args, DB>
+where
+    DB: Database,
+    chrono::DateTime<chrono::Utc>: Encode<'args, DB> + Type<DB>,
+    String: Encode<'args, DB> + Type<DB>,
+    bool: Encode<'args, DB> + Type<DB>,
+{
+    let mut qb = QueryBuilder::new("SELECT severity, COUNT(*) AS count FROM events");
+    push_filter_conditions(&mut qb, filter);
+    qb.push(" GROUP BY severity ORDER BY count DESC, severity");
+    qb
+}
+
+/// `GroupBreakdown` rows grouped by `group_column`, or a single "All" row when `None`.
+pub fn breakdown_query<'args, DB>(
+    filter: &EventFilter,
+    group_column: Option<&str>,
+    limit: i64,
+) -> QueryBuilder<'args, DB>
+where
+    DB: Database,
+    chrono::DateTime<chrono::Utc>: Encode<'args, DB> + Type<DB>,
+    String: Encode<'args, DB> + Type<DB>,
+    bool: Encode<'args, DB> + Type<DB>,
+    i64: Encode<'args, DB> + Type<DB>,
+{
+    let key = match group_column {
+        Some(column) => format!("COALESCE({}, 'Unassigned')", column),
+        None => "'All'".to_string(),
+    };
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT {} AS key, {} FROM events",
+        key, OUTCOME_COLUMNS
+    ));
+    push_filter_conditions(&mut qb, filter);
+    if group_column.is_some() {
+        qb.push(format!(
+            " GROUP BY {} ORDER BY critical DESC, total DESC, key LIMIT ",
+            key
+        ))
+        .push_bind(limit);
+    }
+    qb
+}
+
+/// `HistogramBucket` rows of detector outcomes bucketed by `bucket_expr`.
+pub fn histogram_query<'args, DB>(
+    filter: &EventFilter,
+    bucket_expr: &str,
+) -> QueryBuilder<'args, DB>
+where
+    DB: Database,
+    chrono::DateTime<chrono::Utc>: Encode<'args, DB> + Type<DB>,
+    String: Encode<'args, DB> + Type<DB>,
+    bool: Encode<'args, DB> + Type<DB>,
+{
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT {} AS bucket, COUNT(*) AS count FROM events",
+        bucket_expr
+    ));
+    push_filter_conditions(&mut qb, filter);
+    qb.push(" AND severity IN (");
+    let mut list = qb.separated(", ");
+    for severity in DETECTION_SEVERITIES {
+        list.push_bind(severity.to_string());
+    }
+    list.push_unseparated(")");
+    qb.push(" GROUP BY 1 ORDER BY 1");
+    qb
+}
+
+/// Combines the per-query results into the report (rates, zero-filled histograms).
+pub fn assemble(
+    query: &StatsQuery,
+    totals: GroupBreakdown,
+    by_severity: Vec<RiskReport>,
+    by_hour: Vec<HistogramBucket>,
+    by_weekday: Vec<HistogramBucket>,
+    by_ward: Vec<GroupBreakdown>,
+    by_patient: Vec<GroupBreakdown>,
+) -> FallStatistics {
+    let impacts = totals.critical + totals.near_misses;
+    FallStatistics {
+        from: query.filter.from,
+        to: query.filter.to,
+        total_events: totals.total,
+        by_severity,
+        false_alarm_rate: ratio(totals.false_alarms, totals.critical),
+        near_miss_rate: ratio(totals.near_misses, impacts),
+        by_hour: fill_buckets(by_hour, 24),
+        by_weekday: fill_buckets(by_weekday, 7),
+        by_ward,
+        by_patient,
+    }
+}
+
+fn ratio(numerator: i64, denominator: i64) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+fn fill_buckets(rows: Vec<HistogramBucket>, size: i32) -> Vec<HistogramBucket> {
+    let counts: BTreeMap<i32, i64> = rows.into_iter().map(|b| (b.bucket, b.count)).collect();
+    (0..size)
+        .map(|bucket| HistogramBucket {
+            bucket,
+            count: counts.get(&bucket).copied().unwrap_or(0),
+        })
+        .collect()
+}
+
+/// Same report computed in Rust, for the in-memory backend. `false_alarm_ids` are the events
+/// labelled `FALSE_ALARM_LABEL`.
+pub fn compute(
+    events: &[FallLog],
+    false_alarm_ids: &HashSet<i32>,
+    query: &StatsQuery,
+) -> FallStatistics {
+    let matching: Vec<&FallLog> = events.iter().filter(|e| query.filter.matches(e)).collect();
+    let offset = Duration::minutes(query.utc_offset_minutes as i64);
+
+    let breakdown = |key: String, rows: &[&FallLog]| GroupBreakdown {
+        key,
+        total: rows.len() as i64,
+        critical: rows.iter().filter(|e| e.severity == "Critical").count() as i64,
+        near_misses: rows.iter().filter(|e| e.severity == "Near Miss").count() as i64,
+        false_alarms: rows
+            .iter()
+            .filter(|e| e.severity == "Critical")
+            .filter(|e| e.dismissed_at.is_some() || false_alarm_ids.contains(&e.id))
+            .count() as i64,
+    };
+    let grouped = |key_of: fn(&FallLog) -> Option<String>, limit: i64| {
+        let mut groups: BTreeMap<String, Vec<&FallLog>> = BTreeMap::new();
+        for e in &matching {
+            let key = key_of(e).unwrap_or_else(|| "Unassigned".to_string());
+            groups.entry(key).or_default().push(e);
+        }
+        let mut rows: Vec<GroupBreakdown> = groups
+            .into_iter()
+            .map(|(key, rows)| breakdown(key, &rows))
+            .collect();
+        rows.sort_by(|a, b| {
+            b.critical
+                .cmp(&a.critical)
+                .then(b.total.cmp(&a.total))
+                .then(a.key.cmp(&b.key))
+        });
+        rows.truncate(limit.max(0) as usize);
+        rows
+    };
+
+    let mut severities: BTreeMap<String, i64> = BTreeMap::new();
+    let mut hours: BTreeMap<i32, i64> = BTreeMap::new();
+    let mut weekdays: BTreeMap<i32, i64> = BTreeMap::new();
+    for e in &matching {
+        *severities.entry(e.severity.clone()).or_default() += 1;
+        if DETECTION_SEVERITIES.contains(&e.severity.as_str()) {
+            let local = e.detected_at + offset;
+            *hours.entry(local.hour() as i32).or_default() += 1;
+            *weekdays
+                .entry(local.weekday().num_days_from_sunday() as i32)
+                .or_default() += 1;
+        }
+    }
+    let mut by_severity: Vec<RiskReport> = severities
+        .into_iter()
+        .map(|(severity, count)| RiskReport {
+            severity,
+            count: Some(count),
+        })
+        .collect();
+    by_severity.sort_by_key(|r| std::cmp::Reverse(r.count));
+    let buckets = |map: BTreeMap<i32, i64>| {
+        map.into_iter()
+            .map(|(bucket, count)| HistogramBucket { bucket, count })
+            .collect()
+    };
+
+    assemble(
+        query,
+        breakdown("All".to_string(), &matching),
+        by_severity,
+        buckets(hours),
+        buckets(weekdays),
+        grouped(|e| e.ward.clone(), i64::MAX),
+        grouped(|e| e.patient_id.clone(), PATIENT_BREAKDOWN_LIMIT),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{MemoryRepository, NewEvent, NewLabel, Repository};
+    use crate::test_support::on_every_backend;
+    use chrono::{DateTime, Utc};
+
+    fn at(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().to_utc()
+    }
+
+    // Helper: a month of events and nurse verdicts, and their report in UTC+1
+    async fn march_report(repo: &dyn Repository) -> FallStatistics {
+        let rows = [
+            ("2026-03-02T08:15:00Z", "Critical", false, "ICU", "P-1"), // Monday
+            ("2026-03-02T08:45:00Z", "Refused", true, "ICU", "P-1"),
+            ("2026-03-02T08:46:00Z", "Refused", true, "ICU", "P-1"), // Refused twice: one alert
+            ("2026-03-03T23:30:00Z", "Critical", false, "ICU", "P-2"), // Tuesday
+            ("2026-03-04T14:00:00Z", "Near Miss", false, "Ward-B", "P-3"),
+            ("2026-03-04T14:05:00Z", "Near Miss", false, "Ward-B", "P-3"),
+            ("2026-03-04T15:00:00Z", "Critical", false, "Ward-B", "P-3"),
+            ("2026-04-01T10:00:00Z", "Critical", false, "ICU", "P-1"), // Outside the range
+        ];
+        let mut ids = Vec::new();
+        for (t, severity, is_false_alarm, ward, patient) in rows {
+            let event = repo
+                .insert_event(NewEvent {
+                    detected_at: at(t),
+                    severity: severity.to_string(),
+                    g_force_value: 2.0,
+                    is_false_alarm,
+                    device_id: Some("pi-01".to_string()),
+                    patient_id: Some(patient.to_string()),
+                    ward: Some(ward.to_string()),
+                    metrics: None,
+                    explanation: None,
+                })
+                .await
+                .unwrap();
+            ids.push(event.id);
+        }
+        // The Monday alert is cancelled (dismissed and labelled), the Ward-B one only labelled
+        // no-fall after the fact, and the Tuesday one confirmed
+        repo.dismiss_event(ids[0], at("2026-03-02T08:46:00Z"))
+            .await
+            .unwrap();
+        for (event_id, label, command) in [
+            (ids[0], FALSE_ALARM_LABEL, "CANCEL_ALERT"),
+            (ids[6], FALSE_ALARM_LABEL, "CANCEL_ALERT"),
+            (ids[3], "fall", "CONFIRM_FALL"),
+        ] {
+            repo.record_label(&NewLabel {
+                event_id,
+                alert_id: event_id,
+                label: label.to_string(),
+                command: command.to_string(),
+                labelled_at: at("2026-03-05T00:00:00Z"),
+            })
+            .await
+            .unwrap();
+        }
+
+        let query = StatsQuery {
+            filter: EventFilter {
+                from: Some(at("2026-03-01T00:00:00Z")),
+                to: Some(at("2026-03-31T23:59:59Z")),
+                ..EventFilter::default()
+            },
+            utc_offset_minutes: 60,
+        };
+        repo.fall_statistics(&query).await.unwrap()
+    }
+
+    // Totals and rates: false alarms are distinct critical events dismissed or labelled no-fall
+    #[actix_web::test]
+    async fn test_rates() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let report = march_report(repo).await;
+            assert_eq!(report.total_events, 7);
+            assert_eq!(report.by_severity[0].severity, "Critical");
+            assert_eq!(report.by_severity[0].count, Some(3));
+            assert!((report.false_alarm_rate - 2.0 / 3.0).abs() < 1e-9); // 2 of 3 critical
+            assert_eq!(report.near_miss_rate, 0.4); // 2 near misses / 5 impacts
+        })
+        .await;
+    }
+
+    // Hour and weekday buckets are in the caller's local time
+    #[actix_web::test]
+    async fn test_local_buckets() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let report = march_report(repo).await;
+            assert_eq!(report.by_hour.len(), 24);
+            assert_eq!(report.by_hour[9].count, 1); // 08:15 UTC -> 09:15 local
+            assert_eq!(report.by_hour[15].count, 2);
+            assert_eq!(report.by_hour[16].count, 1);
+            assert_eq!(report.by_hour[0].count, 1); // 23:30 Tuesday UTC -> 00:30 Wednesday
+            assert_eq!(report.by_weekday[1].count, 1);
+            assert_eq!(report.by_weekday[3].count, 4);
+        })
+        .await;
+    }
+
+    // Breakdowns by ward and by patient
+    #[actix_web::test]
+    async fn test_breakdowns() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let report = march_report(repo).await;
+            assert_eq!(report.by_ward[0].key, "ICU");
+            assert_eq!(report.by_ward[0].critical, 2);
+            assert_eq!(report.by_ward[0].false_alarms, 1); // The refusals are not counted again
+            assert_eq!(report.by_ward[1].key, "Ward-B");
+            assert_eq!(report.by_ward[1].false_alarms, 1);
+            assert_eq!(report.by_patient.len(), 3);
+        })
+        .await;
+    }
+
+    // SQL aggregation agrees with the in-memory computation
+    #[actix_web::test]
+    async fn test_backends_agree() {
+        let expected = serde_json::to_value(march_report(&MemoryRepository::new()).await).unwrap();
+        on_every_backend(async |repo: &dyn Repository| {
+            let report = serde_json::to_value(march_report(repo).await).unwrap();
+            assert_eq!(report, expected);
+        })
+        .await;
+    }
+}
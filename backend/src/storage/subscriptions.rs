@@ -0,0 +1,209 @@
+use super::{StorageError, StorageResult};
+use chrono::{DateTime, Utc};
+
+/// FHIR Subscription statuses: `requested` until the handshake succeeds, `error` while
+/// deliveries fail, `off` once the server gave up (or the subscription was turned off).
+pub const SUBSCRIPTION_STATUSES: [&str; 4] = ["requested", "active", "error", "off"];
+
+/// How much of the focus resource a notification carries.
+pub const PAYLOAD_CONTENTS: [&str; 3] = ["empty", "id-only", "full-resource"];
+
+/// Columns selected for every `Subscription` row.
+pub(crate) const SUBSCRIPTION_COLUMNS: &str = "id, topic, reason, endpoint, headers, payload, \
+     patient_id, heartbeat_seconds, status, events_since_start, error, created_at, last_notified_at";
+
+/// Inserts a subscription ($1..$8 in `NewSubscription` order).
+pub(crate) const INSERT_SUBSCRIPTION: &str = "INSERT INTO subscriptions \
+     (topic, reason, endpoint, headers, payload, patient_id, heartbeat_seconds, created_at) \
+     VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+
+/// Numbers the next event of a subscription.
+pub(crate) const NEXT_SUBSCRIPTION_EVENT: &str = "UPDATE subscriptions \
+     SET events_since_start = events_since_start + 1 WHERE id = $1 RETURNING events_since_start";
+
+/// Records a delivery outcome; the notification time is only moved when one got through.
+pub(crate) const SET_SUBSCRIPTION_STATUS: &str = "UPDATE subscriptions \
+     SET status = $2, error = $3, last_notified_at = COALESCE($4, last_notified_at) WHERE id = $1";
+
+/// **New Subscription**
+///
+/// A rest-hook channel on one topic. `headers` are `Name: value` lines sent with every
+/// notification (typically `Authorization`).
+#[derive(Debug, Clone)]
+pub struct NewSubscription {
+    pub topic: String,
+    pub reason: String,
+    pub endpoint: String,
+    pub headers: Vec<String>,
+    pub payload: String,
+    pub patient_id: Option<String>,
+    pub heartbeat_seconds: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl NewSubscription {
+    pub fn validate(&self) -> StorageResult<()> {
+        let invalid = |message: String| Err(StorageError::InvalidQuery(message));
+        match url::Url::parse(&self.endpoint) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            _ => {
+                return invalid(format!(
+                    "endpoint must be an http(s) URL: {}",
+                    self.endpoint
+                ))
+            }
+        }
+        if !PAYLOAD_CONTENTS.contains(&self.payload.as_str()) {
+            return invalid(format!(
+                "unknown payload content: {} (expected one of {})",
+                self.payload,
+                PAYLOAD_CONTENTS.join(", ")
+            ));
+        }
+        if let Some(header) = self.headers.iter().find(|h| !h.contains(':')) {
+            return invalid(format!("header must be \"Name: value\": {}", header));
+        }
+        if self.heartbeat_seconds.is_some_and(|s| s <= 0) {
+            return invalid("heartbeat period must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// `headers` as stored (JSON text, `None` when there are none).
+    pub fn headers_json(&self) -> Option<String> {
+        if self.headers.is_empty() {
+            return None;
+        }
+        serde_json::to_string(&self.headers).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fhir::subscription::FALL_FLAG_TOPIC;
+    use crate::storage::Repository;
+    use crate::test_support::on_every_backend;
+    use chrono::{Duration, SubsecRound};
+
+    // Helper: the ward EHR's subscription to P-1001's Flags, created at `at`
+    fn ward_ehr(at: DateTime<Utc>) -> NewSubscription {
+        NewSubscription {
+            topic: FALL_FLAG_TOPIC.to_string(),
+            reason: "Ward EHR".to_string(),
+            endpoint: "https://ehr.example/notify".to_string(),
+            headers: vec!["Authorization: Bearer s3cret".to_string()],
+            payload: "id-only".to_string(),
+            patient_id: Some("P-1001".to_string()),
+            heartbeat_seconds: Some(60),
+            created_at: at,
+        }
+    }
+
+    // Subscriptions start as requested with no events, keeping their headers
+    #[actix_web::test]
+    async fn test_create_subscription() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let at = Utc::now().trunc_subsecs(3); // Within every backend's precision
+            let new = ward_ehr(at);
+            let created = repo.create_subscription(&new).await.unwrap();
+            assert_eq!(created.status, "requested");
+            assert_eq!(created.events_since_start, 0);
+            assert_eq!(created.header_lines(), new.headers);
+            assert_eq!(created.created_at, at);
+            let bare = repo
+                .create_subscription(&NewSubscription {
+                    headers: Vec::new(),
+                    patient_id: None,
+                    ..new
+                })
+                .await
+                .unwrap();
+            assert_eq!(bare.headers, None);
+        })
+        .await;
+    }
+
+    // Events are numbered per subscription
+    #[actix_web::test]
+    async fn test_subscription_event_numbers() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let first = repo
+                .create_subscription(&ward_ehr(Utc::now()))
+                .await
+                .unwrap();
+            let second = repo
+                .create_subscription(&ward_ehr(Utc::now()))
+                .await
+                .unwrap();
+            assert_eq!(repo.next_subscription_event(first.id).await.unwrap(), 1);
+            assert_eq!(repo.next_subscription_event(first.id).await.unwrap(), 2);
+            assert_eq!(repo.next_subscription_event(second.id).await.unwrap(), 1);
+            assert_eq!(
+                repo.get_subscription(first.id)
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .events_since_start,
+                2
+            );
+            assert!(repo.next_subscription_event(999).await.is_err());
+        })
+        .await;
+    }
+
+    // A failure keeps the last good delivery time, a success clears the error
+    #[actix_web::test]
+    async fn test_subscription_status() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let at = Utc::now().trunc_subsecs(3);
+            let id = repo.create_subscription(&ward_ehr(at)).await.unwrap().id;
+            repo.set_subscription_status(id, "active", None, Some(at))
+                .await
+                .unwrap();
+            repo.set_subscription_status(id, "error", Some("HTTP 503"), None)
+                .await
+                .unwrap();
+            let stored = repo.get_subscription(id).await.unwrap().unwrap();
+            assert_eq!(stored.status, "error");
+            assert_eq!(stored.error.as_deref(), Some("HTTP 503"));
+            assert_eq!(stored.last_notified_at, Some(at));
+            repo.set_subscription_status(id, "active", None, Some(at + Duration::seconds(5)))
+                .await
+                .unwrap();
+            let stored = repo.get_subscription(id).await.unwrap().unwrap();
+            assert_eq!(stored.error, None);
+            assert_eq!(stored.last_notified_at, Some(at + Duration::seconds(5)));
+            assert!(repo
+                .set_subscription_status(999, "active", None, None)
+                .await
+                .is_err());
+        })
+        .await;
+    }
+
+    // Subscriptions are listed in creation order and deleted once
+    #[actix_web::test]
+    async fn test_delete_subscription() {
+        on_every_backend(async |repo: &dyn Repository| {
+            let first = repo
+                .create_subscription(&ward_ehr(Utc::now()))
+                .await
+                .unwrap();
+            let second = repo
+                .create_subscription(&ward_ehr(Utc::now()))
+                .await
+                .unwrap();
+            let listed = repo.list_subscriptions().await.unwrap();
+            assert_eq!(
+                listed.iter().map(|s| s.id).collect::<Vec<_>>(),
+                vec![first.id, second.id]
+            );
+            assert!(repo.delete_subscription(first.id).await.unwrap());
+            assert!(!repo.delete_subscription(first.id).await.unwrap());
+            assert!(repo.get_subscription(first.id).await.unwrap().is_none());
+            assert_eq!(repo.list_subscriptions().await.unwrap().len(), 1);
+        })
+        .await;
+    }
+}
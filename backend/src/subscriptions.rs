@@ -0,0 +1,638 @@
+use crate::fhir::alerts::flag_resource;
+use crate::fhir::subscription::{
+    default_base_url, notification_bundle, Notification, NotificationEvent, FALL_FLAG_TOPIC,
+    FALL_OBSERVATION_TOPIC,
+};
+use crate::model::Subscription;
+use crate::storage::{Repository, StorageResult};
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Content type of notification bodies.
+const NOTIFICATION_CONTENT_TYPE: &str = "application/fhir+json";
+
+/// **Subscription Delivery Configuration**
+///
+/// - `FHIR_BASE_URL`: base URL used in notification references (default `http://localhost:8080/fhir`).
+/// - `SUBSCRIPTION_MAX_ATTEMPTS`: tries per notification before the subscription goes to `error` (default 5).
+/// - `SUBSCRIPTION_RETRY_SECONDS`: delay before the first retry, doubled after each one (default 2).
+/// - `SUBSCRIPTION_TIMEOUT_SECONDS`: time allowed for the endpoint to answer (default 10).
+/// - `SUBSCRIPTION_HEARTBEAT_CHECK_SECONDS`: how often heartbeats are due-checked (default 5).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionConfig {
+    pub base_url: String,
+    pub max_attempts: u32,
+    pub retry_delay: Duration,
+    pub timeout: Duration,
+    pub heartbeat_check: Duration,
+}
+
+impl Default for SubscriptionConfig {
+    fn default() -> Self {
+        Self {
+            base_url: default_base_url(),
+            max_attempts: 5,
+            retry_delay: Duration::from_secs(2),
+            timeout: Duration::from_secs(10),
+            heartbeat_check: Duration::from_secs(5),
+        }
+    }
+}
+
+impl SubscriptionConfig {
+    pub fn from_env() -> Self {
+        let seconds = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|s| *s > 0)
+                .map(Duration::from_secs)
+        };
+        let defaults = Self::default();
+        Self {
+            base_url: std::env::var("FHIR_BASE_URL")
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or(defaults.base_url),
+            max_attempts: std::env::var("SUBSCRIPTION_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|v| v.parse::<u32>().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(defaults.max_attempts),
+            retry_delay: seconds("SUBSCRIPTION_RETRY_SECONDS").unwrap_or(defaults.retry_delay),
+            timeout: seconds("SUBSCRIPTION_TIMEOUT_SECONDS").unwrap_or(defaults.timeout),
+            heartbeat_check: seconds("SUBSCRIPTION_HEARTBEAT_CHECK_SECONDS")
+                .unwrap_or(defaults.heartbeat_check),
+        }
+    }
+}
+
+/// Something subscribers may need to hear about.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    Handshake(i32),   // A subscription was just created
+    Observation(i32), // A fall Observation was stored or marked entered-in-error
+    Flag(i32),        // An alert was raised or changed status
+}
+
+/// **Notifier**
+///
+/// Handle used by request handlers and the WebSocket loop to trigger notifications. Sending
+/// never blocks; the delivery task does the rest. A `disabled` notifier drops everything.
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    tx: Option<mpsc::UnboundedSender<Trigger>>,
+}
+
+impl Notifier {
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn notify(&self, trigger: Trigger) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(trigger);
+        }
+    }
+}
+
+/// Starts the delivery task and returns the handle that feeds it.
+/// Every subscription gets its own queue, so notifications reach an endpoint in order and a
+/// slow or failing endpoint does not hold up the others.
+pub fn spawn(db: Arc<dyn Repository>, config: SubscriptionConfig) -> Notifier {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let client = match reqwest::Client::builder().timeout(config.timeout).build() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!(
+                "❌ Subscriptions disabled: HTTP client failed to start: {}",
+                e
+            );
+            return Notifier::disabled();
+        }
+    };
+    let dispatcher = Dispatcher {
+        db,
+        config: Arc::new(config),
+        client,
+        queues: HashMap::new(),
+        heartbeats_sent: HashMap::new(),
+    };
+    tokio::spawn(dispatcher.run(rx));
+    Notifier { tx: Some(tx) }
+}
+
+/// Turns triggers into numbered notifications and hands them to per-subscription workers.
+struct Dispatcher {
+    db: Arc<dyn Repository>,
+    config: Arc<SubscriptionConfig>,
+    client: reqwest::Client,
+    queues: HashMap<i32, mpsc::UnboundedSender<Notification>>,
+    heartbeats_sent: HashMap<i32, DateTime<Utc>>, // Queued but possibly not yet delivered
+}
+
+impl Dispatcher {
+    async fn run(mut self, mut rx: mpsc::UnboundedReceiver<Trigger>) {
+        let mut heartbeat = tokio::time::interval(self.config.heartbeat_check);
+        loop {
+            tokio::select! {
+                trigger = rx.recv() => match trigger {
+                    Some(trigger) => {
+                        if let Err(e) = self.dispatch(trigger).await {
+                            eprintln!("❌ Failed to dispatch {:?} to subscribers: {}", trigger, e);
+                        }
+                    }
+                    None => break,
+                },
+                _ = heartbeat.tick() => {
+                    if let Err(e) = self.heartbeats(Utc::now()).await {
+                        eprintln!("❌ Failed to check subscription heartbeats: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn dispatch(&mut self, trigger: Trigger) -> StorageResult<()> {
+        let now = Utc::now();
+        let (topic, focus, resource, patient_id) = match trigger {
+            Trigger::Handshake(id) => {
+                self.enqueue(id, Notification::Handshake);
+                return Ok(());
+            }
+            Trigger::Observation(event_id) => {
+                let Some(log) = self.db.get_event(event_id).await? else {
+                    return Ok(());
+                };
+                let resource = serde_json::to_value(log.to_fhir()).unwrap_or(Value::Null);
+                (
+                    FALL_OBSERVATION_TOPIC,
+                    format!("Observation/{}", log.id),
+                    resource,
+                    log.patient_id,
+                )
+            }
+            Trigger::Flag(alert_id) => {
+                let Some(alert) = self.db.get_alert(alert_id).await? else {
+                    return Ok(());
+                };
+                let event = self.db.get_event(alert.event_id).await?;
+                (
+                    FALL_FLAG_TOPIC,
+                    format!("Flag/{}", alert.id),
+                    flag_resource(&alert, event.as_ref()),
+                    event.and_then(|e| e.patient_id),
+                )
+            }
+        };
+        for subscription in self.db.list_subscriptions().await? {
+            let wanted = subscription.topic == topic
+                && subscription.status != "off"
+                && subscription
+                    .patient_id
+                    .as_ref()
+                    .is_none_or(|p| patient_id.as_ref() == Some(p));
+            if !wanted {
+                continue;
+            }
+            let number = self.db.next_subscription_event(subscription.id).await?;
+            let event = NotificationEvent {
+                number,
+                at: now,
+                focus: focus.clone(),
+                resource: resource.clone(),
+            };
+            self.enqueue(subscription.id, Notification::Event(event));
+        }
+        Ok(())
+    }
+
+    /// Queues a heartbeat for every subscription that has been quiet for its heartbeat period.
+    async fn heartbeats(&mut self, now: DateTime<Utc>) -> StorageResult<()> {
+        let subscriptions = self.db.list_subscriptions().await?;
+        self.heartbeats_sent
+            .retain(|id, _| subscriptions.iter().any(|s| s.id == *id));
+        for subscription in subscriptions {
+            if heartbeat_due(
+                &subscription,
+                self.heartbeats_sent.get(&subscription.id),
+                now,
+            ) {
+                self.heartbeats_sent.insert(subscription.id, now);
+                self.enqueue(subscription.id, Notification::Heartbeat);
+            }
+        }
+        Ok(())
+    }
+
+    fn enqueue(&mut self, id: i32, notification: Notification) {
+        if let Some(queue) = self.queues.get(&id) {
+            match queue.send(notification) {
+                Ok(()) => return,
+                // The worker stopped (subscription deleted); start over with a fresh one
+                Err(mpsc::error::SendError(returned)) => {
+                    self.queues.remove(&id);
+                    return self.enqueue(id, returned);
+                }
+            }
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        let _ = tx.send(notification);
+        tokio::spawn(deliver_queue(
+            self.db.clone(),
+            self.config.clone(),
+            self.client.clone(),
+            id,
+            rx,
+        ));
+        self.queues.insert(id, tx);
+    }
+}
+
+/// Whether an active subscription with a heartbeat period has been quiet for that long
+/// (counting from its last delivery, or the last heartbeat queued for it).
+pub fn heartbeat_due(
+    subscription: &Subscription,
+    last_queued: Option<&DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> bool {
+    let Some(period) = subscription.heartbeat_seconds else {
+        return false;
+    };
+    if !matches!(subscription.status.as_str(), "active" | "error") {
+        return false;
+    }
+    let last = subscription
+        .last_notified_at
+        .unwrap_or(subscription.created_at)
+        .max(last_queued.copied().unwrap_or(subscription.created_at));
+    now - last >= chrono::Duration::seconds(period.into())
+}
+
+/// Delivers one subscription's notifications in order until it is deleted.
+async fn deliver_queue(
+    db: Arc<dyn Repository>,
+    config: Arc<SubscriptionConfig>,
+    client: reqwest::Client,
+    id: i32,
+    mut rx: mpsc::UnboundedReceiver<Notification>,
+) {
+    while let Some(notification) = rx.recv().await {
+        let subscription = match db.get_subscription(id).await {
+            Ok(Some(subscription)) => subscription,
+            Ok(None) => return,
+            Err(e) => {
+                eprintln!("❌ Failed to load subscription {}: {}", id, e);
+                continue;
+            }
+        };
+        if subscription.status == "off" {
+            continue;
+        }
+        deliver(db.as_ref(), &config, &client, &subscription, &notification).await;
+    }
+}
+
+/// POSTs one notification, retrying with exponential backoff. Success marks the subscription
+/// `active`; running out of attempts marks it `error` with the reason.
+pub async fn deliver(
+    db: &dyn Repository,
+    config: &SubscriptionConfig,
+    client: &reqwest::Client,
+    subscription: &Subscription,
+    notification: &Notification,
+) -> bool {
+    let body = notification_bundle(subscription, &config.base_url, notification);
+    let mut delay = config.retry_delay;
+    let mut error = String::new();
+    for attempt in 1..=config.max_attempts {
+        match post(client, subscription, &body).await {
+            Ok(()) => {
+                if let Err(e) = db
+                    .set_subscription_status(subscription.id, "active", None, Some(Utc::now()))
+                    .await
+                {
+                    eprintln!(
+                        "❌ Failed to update subscription {}: {}",
+                        subscription.id, e
+                    );
+                }
+                return true;
+            }
+            Err(e) => error = e,
+        }
+        if attempt < config.max_attempts {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+    eprintln!(
+        "❌ Subscription {}: {} not delivered after {} attempt(s): {}",
+        subscription.id,
+        notification.kind(),
+        config.max_attempts,
+        error
+    );
+    let error = format!("{} delivery failed: {}", notification.kind(), error);
+    if let Err(e) = db
+        .set_subscription_status(subscription.id, "error", Some(&error), None)
+        .await
+    {
+        eprintln!(
+            "❌ Failed to update subscription {}: {}",
+            subscription.id, e
+        );
+    }
+    false
+}
+
+async fn post(
+    client: &reqwest::Client,
+    subscription: &Subscription,
+    body: &Value,
+) -> Result<(), String> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(NOTIFICATION_CONTENT_TYPE),
+    );
+    for line in subscription.header_lines() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|e| format!("invalid header name {:?}: {}", name.trim(), e))?;
+        let value = HeaderValue::from_str(value.trim())
+            .map_err(|e| format!("invalid value for header {}: {}", name, e))?;
+        headers.insert(name, value);
+    }
+    let body = serde_json::to_vec(body).map_err(|e| e.to_string())?;
+    let response = client
+        .post(&subscription.endpoint)
+        .headers(headers)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("endpoint answered HTTP {}", response.status()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{MemoryRepository, NewEvent, NewSubscription};
+    use crate::test_support::critical_event;
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    // Helper: an EHR endpoint on 127.0.0.1 that records the notifications it accepts.
+    // `/notify` answers 503 to the first `failures` requests, `/down` always does.
+    #[derive(Default)]
+    struct Receiver {
+        failures: AtomicUsize,
+        received: Mutex<Vec<(Option<String>, Value)>>, // (Authorization, Bundle)
+    }
+
+    impl Receiver {
+        async fn start(failures: usize) -> (String, web::Data<Receiver>) {
+            let receiver = web::Data::new(Receiver {
+                failures: failures.into(),
+                ..Receiver::default()
+            });
+            let server = HttpServer::new({
+                let receiver = receiver.clone();
+                move || {
+                    App::new()
+                        .app_data(receiver.clone())
+                        .route("/notify", web::post().to(receive))
+                        .route("/down", web::post().to(HttpResponse::ServiceUnavailable))
+                }
+            })
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+            let endpoint = format!("http://{}", server.addrs()[0]);
+            actix_rt::spawn(server.run());
+            (endpoint, receiver)
+        }
+
+        fn of_kind(&self, kind: &str) -> Vec<(Option<String>, Value)> {
+            self.received
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, bundle)| status_parameter(bundle, "type")["valueCode"] == kind)
+                .cloned()
+                .collect()
+        }
+    }
+
+    async fn receive(
+        req: HttpRequest,
+        receiver: web::Data<Receiver>,
+        body: web::Bytes,
+    ) -> HttpResponse {
+        if receiver
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+        {
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+        let authorization = req
+            .headers()
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        let bundle = serde_json::from_slice(&body).unwrap();
+        receiver
+            .received
+            .lock()
+            .unwrap()
+            .push((authorization, bundle));
+        HttpResponse::Ok().finish()
+    }
+
+    // Helper: a parameter of the SubscriptionStatus heading a notification Bundle
+    fn status_parameter(bundle: &Value, name: &str) -> Value {
+        let parameters = &bundle["entry"][0]["resource"]["parameter"];
+        parameters
+            .as_array()
+            .and_then(|list| list.iter().find(|p| p["name"] == name))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // Helper: polls `check` until it holds (deliveries happen in the background)
+    async fn eventually(what: &str, check: impl AsyncFn() -> bool) {
+        for _ in 0..100 {
+            if check().await {
+                return;
+            }
+            actix_rt::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("timed out waiting for {}", what);
+    }
+
+    // Helper: a notifier that retries quickly, over an empty store
+    fn notifier() -> (Arc<dyn Repository>, Notifier) {
+        let db: Arc<dyn Repository> = Arc::new(MemoryRepository::new());
+        let config = SubscriptionConfig {
+            base_url: "https://fallguard.example/fhir".to_string(),
+            max_attempts: 3,
+            retry_delay: Duration::from_millis(20),
+            timeout: Duration::from_secs(2),
+            heartbeat_check: Duration::from_millis(100),
+        };
+        (db.clone(), spawn(db, config))
+    }
+
+    // Helper: subscribes `endpoint` to P-1001's fall Observations and sends the handshake
+    async fn subscribe(
+        db: &dyn Repository,
+        notifier: &Notifier,
+        endpoint: String,
+        payload: &str,
+        heartbeat_seconds: Option<i32>,
+    ) -> i32 {
+        let subscription = db
+            .create_subscription(&NewSubscription {
+                topic: FALL_OBSERVATION_TOPIC.to_string(),
+                reason: "Ward EHR".to_string(),
+                endpoint,
+                headers: vec!["Authorization: Bearer s3cret".to_string()],
+                payload: payload.to_string(),
+                patient_id: Some("P-1001".to_string()),
+                heartbeat_seconds,
+                created_at: Utc::now(),
+            })
+            .await
+            .unwrap();
+        notifier.notify(Trigger::Handshake(subscription.id));
+        subscription.id
+    }
+
+    // Helper: waits until subscription `id` reaches `status`
+    async fn wait_for_status(db: &dyn Repository, id: i32, status: &str) {
+        eventually(status, async || {
+            db.get_subscription(id).await.unwrap().unwrap().status == status
+        })
+        .await;
+    }
+
+    // The handshake is retried until the endpoint accepts it, then the subscription is active
+    #[actix_web::test]
+    async fn test_handshake() {
+        let (endpoint, receiver) = Receiver::start(2).await;
+        let (db, notifier) = notifier();
+        let id = subscribe(
+            db.as_ref(),
+            &notifier,
+            format!("{}/notify", endpoint),
+            "id-only",
+            None,
+        )
+        .await;
+        wait_for_status(db.as_ref(), id, "active").await;
+        let handshakes = receiver.of_kind("handshake");
+        assert_eq!(handshakes.len(), 1);
+        assert_eq!(handshakes[0].0.as_deref(), Some("Bearer s3cret"));
+        assert_eq!(handshakes[0].1["type"], "history");
+    }
+
+    // Only the subscribed patient's fall is delivered, numbered, with the Observation itself
+    #[actix_web::test]
+    async fn test_event_notification() {
+        let (endpoint, receiver) = Receiver::start(0).await;
+        let (db, notifier) = notifier();
+        let id = subscribe(
+            db.as_ref(),
+            &notifier,
+            format!("{}/notify", endpoint),
+            "full-resource",
+            None,
+        )
+        .await;
+        wait_for_status(db.as_ref(), id, "active").await;
+
+        let other = db
+            .insert_event(NewEvent {
+                patient_id: Some("P-2002".to_string()),
+                ..critical_event(2, 3.0)
+            })
+            .await
+            .unwrap();
+        notifier.notify(Trigger::Observation(other.id));
+        let fall = db.insert_event(critical_event(1, 2.5)).await.unwrap();
+        notifier.notify(Trigger::Observation(fall.id));
+        eventually("the event notification", async || {
+            !receiver.of_kind("event-notification").is_empty()
+        })
+        .await;
+
+        let events = receiver.of_kind("event-notification");
+        assert_eq!(events.len(), 1);
+        let bundle = &events[0].1;
+        assert_eq!(
+            status_parameter(bundle, "events-since-subscription-start")["valueString"],
+            "1"
+        );
+        let event = status_parameter(bundle, "notification-event");
+        assert_eq!(event["part"][0]["valueString"], "1");
+        assert_eq!(
+            event["part"][2]["valueReference"]["reference"],
+            format!("https://fallguard.example/fhir/Observation/{}", fall.id)
+        );
+        assert_eq!(
+            bundle["entry"][1]["resource"]["resourceType"],
+            "Observation"
+        );
+        assert_eq!(bundle["entry"][1]["resource"]["id"], fall.id.to_string());
+    }
+
+    // A subscription that stays quiet for its heartbeat period gets a heartbeat
+    #[actix_web::test]
+    async fn test_heartbeat() {
+        let (endpoint, receiver) = Receiver::start(0).await;
+        let (db, notifier) = notifier();
+        subscribe(
+            db.as_ref(),
+            &notifier,
+            format!("{}/notify", endpoint),
+            "id-only",
+            Some(1),
+        )
+        .await;
+        eventually("a heartbeat", async || {
+            !receiver.of_kind("heartbeat").is_empty()
+        })
+        .await;
+    }
+
+    // An endpoint that never answers puts the subscription in error, with the reason
+    #[actix_web::test]
+    async fn test_failing_endpoint() {
+        let (endpoint, _receiver) = Receiver::start(0).await;
+        let (db, notifier) = notifier();
+        let id = subscribe(
+            db.as_ref(),
+            &notifier,
+            format!("{}/down", endpoint),
+            "id-only",
+            None,
+        )
+        .await;
+        wait_for_status(db.as_ref(), id, "error").await;
+        let stored = db.get_subscription(id).await.unwrap().unwrap();
+        assert!(stored.error.unwrap().contains("503"));
+    }
+}
@@ -0,0 +1,247 @@
+use crate::logic::{calculate_g_force, GRAVITY};
+use crate::model::{SensorData, TelemetrySample, Waveform, WaveformPoint};
+use crate::storage::{DeviceStatus, Repository};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// Seconds of signal kept before the impact in each waveform snapshot.
+pub const DEFAULT_PRE_IMPACT_SECONDS: i64 = 5;
+
+/// Samples buffered per connection before they are written (~1 second at 20 Hz).
+const FLUSH_EVERY: usize = 20;
+
+/// **Telemetry Configuration**
+///
+/// - `WAVEFORM_PRE_IMPACT_SECONDS`: signal kept before the impact in each snapshot (default 5).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TelemetryConfig {
+    pub pre_impact: Duration,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            pre_impact: Duration::seconds(DEFAULT_PRE_IMPACT_SECONDS),
+        }
+    }
+}
+
+impl TelemetryConfig {
+    pub fn from_env() -> Self {
+        let pre_impact = std::env::var("WAVEFORM_PRE_IMPACT_SECONDS")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .filter(|s| *s >= 0)
+            .unwrap_or(DEFAULT_PRE_IMPACT_SECONDS);
+        Self {
+            pre_impact: Duration::seconds(pre_impact),
+        }
+    }
+}
+
+/// **Telemetry Recorder**
+///
+/// One per sensor connection. Batches samples for storage and keeps a short rolling
+/// history so a waveform can be cut from before the impact to the end of validation.
+pub struct TelemetryRecorder {
+    device_id: String,
+    pre_impact: Duration,
+    history: VecDeque<TelemetrySample>,
+    pending: Vec<TelemetrySample>,
+    impact_at: Option<DateTime<Utc>>,
+    battery: Option<f64>, // Last level reported (not every sample carries one)
+}
+
+impl TelemetryRecorder {
+    pub fn new(device_id: String, config: &TelemetryConfig) -> Self {
+        Self {
+            device_id,
+            pre_impact: config.pre_impact,
+            history: VecDeque::new(),
+            pending: Vec::new(),
+            impact_at: None,
+            battery: None,
+        }
+    }
+
+    /// Adds a sample. Returns a batch when it is time to write one.
+    pub fn record(&mut self, data: &SensorData, at: DateTime<Utc>) -> Option<Vec<TelemetrySample>> {
+        let sample = TelemetrySample {
+            device_id: self.device_id.clone(),
+            recorded_at: at,
+            x: data.x,
+            y: data.y,
+            z: data.z,
+            g_force: calculate_g_force(data.x, data.y, data.z) / GRAVITY,
+            wifi: data.wifi,
+            temp: data.temp,
+        };
+        self.history.push_back(sample.clone());
+        self.pending.push(sample);
+        self.battery = data.battery.or(self.battery);
+
+        // Keep the pre-impact window, or everything a pending snapshot still needs
+        let horizon = match self.impact_at {
+            Some(impact_at) => impact_at - self.pre_impact,
+            None => at - self.pre_impact,
+        };
+        while self
+            .history
+            .front()
+            .is_some_and(|s| s.recorded_at < horizon)
+        {
+            self.history.pop_front();
+        }
+
+        if self.pending.len() >= FLUSH_EVERY {
+            Some(self.drain())
+        } else {
+            None
+        }
+    }
+
+    /// Samples not yet written (call on disconnect).
+    pub fn drain(&mut self) -> Vec<TelemetrySample> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Latest readings for the device registry (`None` before the first sample).
+    pub fn status(&self) -> Option<DeviceStatus> {
+        let last = self.history.back()?;
+        Some(DeviceStatus {
+            device_id: self.device_id.clone(),
+            seen_at: last.recorded_at,
+            wifi_signal: Some(last.wifi),
+            temperature: Some(last.temp),
+            battery_level: self.battery,
+        })
+    }
+
+    /// Called when the detector starts validating an impact.
+    pub fn mark_impact(&mut self, at: DateTime<Utc>) {
+        self.impact_at = Some(at);
+    }
+
+    /// Called when the detector reaches a decision: cuts the snapshot ending at `until`.
+    /// `event_id` is left at 0 for the caller to fill in once the event is stored.
+    pub fn take_waveform(&mut self, until: DateTime<Utc>) -> Option<Waveform> {
+        let impact_at = self.impact_at.take()?;
+        let started_at = impact_at - self.pre_impact;
+        let points: Vec<WaveformPoint> = self
+            .history
+            .iter()
+            .filter(|s| s.recorded_at >= started_at && s.recorded_at <= until)
+            .map(|s| WaveformPoint {
+                offset_ms: (s.recorded_at - impact_at).num_milliseconds(),
+                x: s.x,
+                y: s.y,
+                z: s.z,
+                g_force: s.g_force,
+            })
+            .collect();
+        Some(Waveform {
+            event_id: 0,
+            device_id: self.device_id.clone(),
+            impact_at,
+            started_at,
+            ended_at: until,
+            points,
+        })
+    }
+}
+
+/// Writes a telemetry batch, logging (not propagating) failures.
+pub async fn store_batch(db: Arc<dyn Repository>, batch: Vec<TelemetrySample>) {
+    if batch.is_empty() {
+        return;
+    }
+    if let Err(e) = db.insert_telemetry(&batch).await {
+        eprintln!(
+            "❌ Failed to store {} telemetry samples: {}",
+            batch.len(),
+            e
+        );
+    }
+}
+
+/// Updates the device registry with a sensor's latest status, logging (not propagating) failures.
+pub async fn store_status(db: Arc<dyn Repository>, status: Option<DeviceStatus>) {
+    let Some(status) = status else {
+        return;
+    };
+    if let Err(e) = db.record_device_status(&status).await {
+        eprintln!(
+            "❌ Failed to update status of device {}: {}",
+            status.device_id, e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Waveform capture spans the pre-impact window to the detector's decision
+    #[test]
+    fn test_capture_window() {
+        let config = TelemetryConfig {
+            pre_impact: Duration::seconds(2),
+        };
+        let mut recorder = TelemetryRecorder::new("pi-01".to_string(), &config);
+        let start = Utc::now();
+        let still = SensorData {
+            x: 0.0,
+            y: 0.0,
+            z: 9.8,
+            timestamp: 0.0,
+            wifi: 100,
+            temp: 36.5,
+            battery: Some(80.0),
+        };
+
+        // 10 seconds of 20 Hz data, impact at 6 s, decision at 8 s
+        let mut batches = 0;
+        for i in 0..200 {
+            let at = start + Duration::milliseconds(50 * i);
+            if recorder.record(&still, at).is_some() {
+                batches += 1;
+            }
+            if i == 120 {
+                recorder.mark_impact(at);
+            }
+            if i == 160 {
+                let waveform = recorder.take_waveform(at).unwrap();
+                assert_eq!(waveform.points.first().unwrap().offset_ms, -2000);
+                assert_eq!(waveform.points.last().unwrap().offset_ms, 2000);
+                assert_eq!(waveform.points.len(), 81);
+                assert!((waveform.points[0].g_force - 1.0).abs() < 1e-9);
+            }
+        }
+        assert_eq!(batches, 10); // Flushed every 20 samples
+        assert!(recorder.drain().is_empty());
+        assert!(recorder.take_waveform(Utc::now()).is_none());
+    }
+
+    // The status reports the last sample's readings and the latest battery level
+    #[test]
+    fn test_status() {
+        let mut recorder = TelemetryRecorder::new("pi-01".to_string(), &TelemetryConfig::default());
+        assert!(recorder.status().is_none());
+        let sample = |battery: Option<f64>| SensorData {
+            x: 0.0,
+            y: 0.0,
+            z: 9.8,
+            timestamp: 0.0,
+            wifi: 88,
+            temp: 30.5,
+            battery,
+        };
+        recorder.record(&sample(Some(91.0)), Utc::now());
+        recorder.record(&sample(None), Utc::now());
+        let status = recorder.status().unwrap();
+        assert_eq!(status.battery_level, Some(91.0));
+        assert_eq!(status.wifi_signal, Some(88));
+        assert_eq!(status.temperature, Some(30.5));
+    }
+}
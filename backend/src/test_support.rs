@@ -0,0 +1,179 @@
+// Fixtures shared by the unit tests of every module
+
+use crate::assessment::AssessmentForm;
+use crate::fhir::bulk::ExportJobs;
+use crate::logic::DetectorProfile;
+use crate::model::{Alert, FallLog};
+use crate::risk::RiskConfig;
+use crate::storage::{
+    self, schema, MemoryRepository, MigrationMode, NewEvent, PgRepository, Repository,
+    StorageConfig,
+};
+use crate::subscriptions::Notifier;
+use crate::telemetry::TelemetryConfig;
+use crate::{websockets, AppState};
+use actix_web::dev::ServiceResponse;
+use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+use actix_web::{web, App};
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex};
+
+// Tests run in parallel, but there is only one scratch Postgres database
+static POSTGRES: Mutex<()> = Mutex::const_new(());
+
+// Helper: an in-memory app state for the HTTP tests
+pub(crate) fn memory_state() -> web::Data<AppState> {
+    web::Data::new(memory_app_state())
+}
+
+// Helper: the same, for tests that replace some of it
+pub(crate) fn memory_app_state() -> AppState {
+    let (tx, _rx) = broadcast::channel(100);
+    AppState {
+        db: Arc::new(MemoryRepository::new()),
+        tx,
+        telemetry: TelemetryConfig::default(),
+        notifier: Notifier::disabled(),
+        exports: ExportJobs::default(),
+        assessment_form: Arc::new(AssessmentForm::default()),
+        risk: RiskConfig::default(),
+        detector: DetectorProfile::default(),
+        shadow: Vec::new(),
+    }
+}
+
+// Helper: sends `req` to every HTTP route (API and FHIR) over `state`
+pub(crate) async fn call(state: &web::Data<AppState>, req: TestRequest) -> ServiceResponse {
+    let app = init_service(
+        App::new()
+            .app_data(state.clone())
+            .configure(crate::api::configure),
+    )
+    .await;
+    call_service(&app, req.to_request()).await
+}
+
+// Helper: GET `uri`
+pub(crate) async fn get(state: &web::Data<AppState>, uri: &str) -> ServiceResponse {
+    call(state, TestRequest::get().uri(uri)).await
+}
+
+// Helper: GET `uri`, as JSON
+pub(crate) async fn get_json(state: &web::Data<AppState>, uri: &str) -> serde_json::Value {
+    read_body_json(get(state, uri).await).await
+}
+
+// Helper: GET `uri` as JSON, addressed to `host` so that absolute links can be checked
+pub(crate) async fn get_json_from(
+    state: &web::Data<AppState>,
+    host: &str,
+    uri: &str,
+) -> serde_json::Value {
+    read_body_json(
+        call(
+            state,
+            TestRequest::get().uri(uri).insert_header(("Host", host)),
+        )
+        .await,
+    )
+    .await
+}
+
+// Helper: a "Critical" event detected `minutes_ago`
+pub(crate) fn critical_event(minutes_ago: i64, g_force: f64) -> NewEvent {
+    NewEvent {
+        detected_at: Utc::now() - Duration::minutes(minutes_ago),
+        severity: "Critical".to_string(),
+        g_force_value: g_force,
+        is_false_alarm: false,
+        device_id: Some("pi-01".to_string()),
+        patient_id: Some("P-1001".to_string()),
+        ward: Some("ICU".to_string()),
+        metrics: None,
+        explanation: None,
+    }
+}
+
+// Helper: a stored `severity` event (id 7) from P-1001's pi-01, with fall metrics
+pub(crate) fn fall_log(severity: &str, g_force: f64) -> FallLog {
+    FallLog {
+        id: 7,
+        detected_at: Utc::now(),
+        severity: severity.to_string(),
+        g_force_value: g_force,
+        is_false_alarm: false,
+        device_id: Some("pi-01".to_string()),
+        patient_id: Some("P-1001".to_string()),
+        ward: Some("ICU".to_string()),
+        stillness_variance: Some(0.42),
+        validation_ms: Some(2010),
+        orientation_change_deg: Some(87.0),
+        dismissed_at: None,
+        explanation: None,
+    }
+}
+
+// Helper: the newest open alert, as a dashboard learns its id from ALERT_OPENED
+pub(crate) async fn newest_alert(db: &dyn Repository) -> Alert {
+    db.latest_open_alert().await.unwrap().unwrap()
+}
+
+// Helper: a fall 20 minutes ago whose alert the nurse answered with CONFIRM_FALL or
+// CANCEL_ALERT from the dashboard; returns the alert as it was opened
+pub(crate) async fn answered_fall(state: &web::Data<AppState>, command: &str) -> Alert {
+    let (severity, is_false_alarm, status) = match command {
+        "CONFIRM_FALL" => ("Assistance Sent", false, "Confirmed"),
+        _ => ("Refused", true, "Cancelled"),
+    };
+    let event = state
+        .db
+        .insert_event(critical_event(20, 2.5))
+        .await
+        .unwrap();
+    let alert = state
+        .db
+        .open_alert(event.id, event.detected_at)
+        .await
+        .unwrap();
+    websockets::record_action(
+        state.clone(),
+        alert.clone(),
+        command,
+        severity,
+        is_false_alarm,
+        status,
+    )
+    .await;
+    alert
+}
+
+// Helper: runs shared checks that every backend must pass, each on an empty store: in memory,
+// on SQLite (schema applied on connect, no Docker needed) and on Postgres, only when
+// TEST_DATABASE_URL points at a scratch database. That one is migrated and every table is
+// truncated first, so never point it at real data.
+pub(crate) async fn on_every_backend(check: impl AsyncFn(&dyn Repository)) {
+    check(&MemoryRepository::new()).await;
+    let sqlite = storage::connect(&StorageConfig::from_url("sqlite::memory:").unwrap())
+        .await
+        .unwrap();
+    check(sqlite.as_ref()).await;
+
+    let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+        return;
+    };
+    let _scratch = POSTGRES.lock().await;
+    let repo = PgRepository::connect(&url).await.unwrap();
+    schema::prepare_schema(
+        repo.pool(),
+        &schema::POSTGRES_MIGRATOR,
+        MigrationMode::Apply,
+    )
+    .await
+    .unwrap();
+    sqlx::query("TRUNCATE events, alerts, telemetry, telemetry_rollups, waveforms, audit_log, legal_holds, patients, devices, subscriptions, hl7_outbox, assessments, morse_scores, risk_scores, detections, labels RESTART IDENTITY CASCADE")
+        .execute(repo.pool())
+        .await
+        .unwrap();
+    check(&repo).await;
+}
@@ -0,0 +1,632 @@
+use crate::evaluation::{self, Recording, Report};
+use crate::logic::DetectorProfile;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+
+/// Values one parameter is searched over: `min` to `max` (inclusive) in steps of `step`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ParamRange {
+    pub min: f64,
+    pub max: f64,
+    pub step: f64,
+}
+
+impl ParamRange {
+    pub fn new(min: f64, max: f64, step: f64) -> Self {
+        Self { min, max, step }
+    }
+
+    pub fn fixed(value: f64) -> Self {
+        Self::new(value, value, 1.0)
+    }
+
+    /// Reads `MIN:MAX:STEP`, or a single value to keep the parameter fixed. Every parameter
+    /// tuned is a threshold or a window, so values must be positive.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let parts: Vec<f64> = text
+            .split(':')
+            .map(|p| p.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("expected MIN:MAX:STEP or a number: {}", text))?;
+        let range = match parts[..] {
+            [value] => Self::fixed(value),
+            [min, max, step] => Self::new(min, max, step),
+            _ => return Err(format!("expected MIN:MAX:STEP or a number: {}", text)),
+        };
+        if !(range.step > 0.0 && range.min <= range.max) {
+            return Err(format!("empty range: {}", text));
+        }
+        if range.min <= 0.0 {
+            return Err(format!("values must be positive: {}", text));
+        }
+        Ok(range)
+    }
+
+    /// Every grid value (rounded to the step's precision, so 0.1 steps stay readable).
+    pub fn values(&self) -> Vec<f64> {
+        let steps = ((self.max - self.min) / self.step + 1e-9).floor() as usize;
+        (0..=steps)
+            .map(|i| round(self.min + i as f64 * self.step))
+            .collect()
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> f64 {
+        if self.min == self.max {
+            return self.min;
+        }
+        round(rng.random_range(self.min..=self.max))
+    }
+}
+
+fn round(value: f64) -> f64 {
+    (value * 1e6).round() / 1e6
+}
+
+/// **Search Space**
+///
+/// The detector parameters tuning may change. The validation window stays at the default
+/// unless given a range.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SearchSpace {
+    pub impact_threshold_g: ParamRange,
+    pub stillness_threshold_variance: ParamRange,
+    pub validation_ms: ParamRange,
+}
+
+impl Default for SearchSpace {
+    fn default() -> Self {
+        let profile = DetectorProfile::default();
+        Self {
+            impact_threshold_g: ParamRange::new(1.2, 3.0, 0.1),
+            stillness_threshold_variance: ParamRange::new(0.5, 6.0, 0.5),
+            validation_ms: ParamRange::fixed(profile.validation_ms as f64),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Search {
+    Grid,
+    Random { samples: usize, seed: u64 },
+}
+
+/// What a tuned profile maximises.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Goal {
+    Sensitivity,
+    Specificity,
+    Youden, // Sensitivity + specificity - 1
+}
+
+impl std::str::FromStr for Goal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sensitivity" => Ok(Self::Sensitivity),
+            "specificity" => Ok(Self::Specificity),
+            "youden" => Ok(Self::Youden),
+            _ => Err(format!(
+                "unknown objective {:?} (expected sensitivity, specificity or youden)",
+                s
+            )),
+        }
+    }
+}
+
+/// **Tuning Objective**
+///
+/// The goal to maximise among the candidates that meet every constraint, e.g. the highest
+/// sensitivity at no more than 2 false alarms per day. Ties go to the candidate that is better
+/// on the other rate, then has fewer false alarms, then alerts sooner.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Objective {
+    pub goal: Goal,
+    pub max_false_alarms_per_day: Option<f64>,
+    pub min_sensitivity: Option<f64>,
+}
+
+impl Default for Objective {
+    fn default() -> Self {
+        Self {
+            goal: Goal::Youden,
+            max_false_alarms_per_day: None,
+            min_sensitivity: None,
+        }
+    }
+}
+
+impl Objective {
+    fn allows(&self, c: &Candidate) -> bool {
+        self.max_false_alarms_per_day
+            .is_none_or(|max| c.false_alarms_per_day <= max)
+            && self.min_sensitivity.is_none_or(|min| c.sensitivity >= min)
+    }
+
+    /// Sort key: higher is better.
+    fn key(&self, c: &Candidate) -> (f64, f64, f64, f64) {
+        let (goal, other) = match self.goal {
+            Goal::Sensitivity => (c.sensitivity, c.specificity),
+            Goal::Specificity => (c.specificity, c.sensitivity),
+            Goal::Youden => (c.sensitivity + c.specificity - 1.0, c.sensitivity),
+        };
+        let latency = c.median_latency_ms.map_or(f64::MAX, |l| l as f64);
+        (goal, other, -c.false_alarms_per_day, -latency)
+    }
+}
+
+/// One profile tried, and how it did.
+#[derive(Debug, Clone, Serialize)]
+pub struct Candidate {
+    pub profile: DetectorProfile,
+    pub sensitivity: f64,
+    pub specificity: f64,
+    pub false_alarms_per_day: f64,
+    pub median_latency_ms: Option<i64>,
+}
+
+impl Candidate {
+    fn from_report(report: Report) -> Self {
+        Self {
+            sensitivity: report.sensitivity.unwrap_or(0.0),
+            specificity: report.specificity.unwrap_or(1.0),
+            false_alarms_per_day: report.false_alarms_per_hour.unwrap_or(0.0) * 24.0,
+            median_latency_ms: report.latency.map(|l| l.median_ms),
+            profile: report.profile,
+        }
+    }
+}
+
+/// One ROC point: the tuned profile with another impact threshold.
+#[derive(Debug, Clone, Serialize)]
+pub struct RocPoint {
+    pub impact_threshold_g: f64,
+    pub false_positive_rate: f64,
+    pub true_positive_rate: f64,
+    pub false_alarms_per_day: f64,
+}
+
+/// **Tuning Result**
+///
+/// The best profile, how many candidates were tried and met the constraints, and the ROC curve
+/// of the best profile over the impact thresholds searched (ordered by false positive rate).
+#[derive(Debug, Clone, Serialize)]
+pub struct Tuning {
+    pub objective: Objective,
+    pub evaluated: usize,
+    pub feasible: usize,
+    pub best: Candidate,
+    pub roc: Vec<RocPoint>,
+    pub auc: f64,
+}
+
+impl Tuning {
+    /// The ROC curve as CSV.
+    pub fn roc_csv(&self) -> String {
+        let mut out =
+            "impact_threshold_g,false_positive_rate,true_positive_rate,false_alarms_per_day\n"
+                .to_string();
+        for p in &self.roc {
+            out.push_str(&format!(
+                "{},{:.4},{:.4},{:.3}\n",
+                p.impact_threshold_g,
+                p.false_positive_rate,
+                p.true_positive_rate,
+                p.false_alarms_per_day
+            ));
+        }
+        out
+    }
+
+    /// The result as a plain-text summary.
+    pub fn table(&self) -> String {
+        let b = &self.best;
+        let mut out = format!(
+            "Tuned profile: {} (impact > {} g, stillness < {} m²/s⁴, window {} ms)\n\
+             Candidates: {} evaluated, {} within the constraints\n\n\
+             {:<24}{:.1}%\n{:<24}{:.1}%\n{:<24}{:.2}\n{:<24}{}\n\n\
+             ROC (AUC {:.3})\n{:>10}{:>10}{:>10}{:>12}\n",
+            b.profile.name,
+            b.profile.impact_threshold_g,
+            b.profile.stillness_threshold_variance,
+            b.profile.validation_ms,
+            self.evaluated,
+            self.feasible,
+            "Sensitivity",
+            b.sensitivity * 100.0,
+            "Specificity",
+            b.specificity * 100.0,
+            "False alarms per day",
+            b.false_alarms_per_day,
+            "Median latency",
+            b.median_latency_ms
+                .map_or("n/a".to_string(), |l| format!("{} ms", l)),
+            self.auc,
+            "Impact g",
+            "FPR",
+            "TPR",
+            "FA/day",
+        );
+        for p in &self.roc {
+            out.push_str(&format!(
+                "{:>10}{:>10.3}{:>10.3}{:>12.2}\n",
+                p.impact_threshold_g,
+                p.false_positive_rate,
+                p.true_positive_rate,
+                p.false_alarms_per_day
+            ));
+        }
+        out
+    }
+}
+
+/// Searches `space` for the profile that best meets `objective` on the recordings, which must
+/// include falls and activities. The profile is called `name`. Candidates the detector would
+/// refuse to load (e.g. a window rounded down to 0 ms) are skipped.
+pub async fn tune(
+    recordings: &[Recording],
+    space: &SearchSpace,
+    search: Search,
+    objective: &Objective,
+    name: &str,
+) -> Result<Tuning, String> {
+    if !recordings.iter().any(|r| r.fall) || recordings.iter().all(|r| r.fall) {
+        return Err("tuning needs both fall and activity recordings".to_string());
+    }
+    let profile = |impact: f64, stillness: f64, window: f64| {
+        DetectorProfile::rules(name, impact, stillness, window.round() as i64)
+    };
+    let profiles: Vec<DetectorProfile> = match search {
+        Search::Grid => {
+            let mut all = Vec::new();
+            for impact in space.impact_threshold_g.values() {
+                for stillness in space.stillness_threshold_variance.values() {
+                    for window in space.validation_ms.values() {
+                        all.push(profile(impact, stillness, window));
+                    }
+                }
+            }
+            all
+        }
+        Search::Random { samples, seed } => {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..samples)
+                .map(|_| {
+                    profile(
+                        space.impact_threshold_g.sample(&mut rng),
+                        space.stillness_threshold_variance.sample(&mut rng),
+                        space.validation_ms.sample(&mut rng),
+                    )
+                })
+                .collect()
+        }
+    };
+    let profiles: Vec<DetectorProfile> =
+        profiles.into_iter().filter(|p| p.check().is_ok()).collect();
+    if profiles.is_empty() {
+        return Err("the search space has no usable profile".to_string());
+    }
+
+    let mut candidates: Vec<Candidate> = Vec::with_capacity(profiles.len());
+    for p in &profiles {
+        candidates.push(Candidate::from_report(
+            evaluation::evaluate(p, recordings).await,
+        ));
+    }
+    let feasible: Vec<&Candidate> = candidates.iter().filter(|c| objective.allows(c)).collect();
+    let best = feasible
+        .iter()
+        .max_by(|a, b| {
+            let (a, b) = (objective.key(a), objective.key(b));
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .map(|c| (*c).clone())
+        .ok_or_else(|| {
+            let fewest = candidates
+                .iter()
+                .map(|c| c.false_alarms_per_day)
+                .fold(f64::INFINITY, f64::min);
+            format!(
+                "none of the {} candidates meets the constraints (fewest false alarms per day: {:.2})",
+                candidates.len(),
+                fewest
+            )
+        })?;
+
+    let mut roc: Vec<RocPoint> = Vec::new();
+    for impact in space.impact_threshold_g.values() {
+        let p = DetectorProfile {
+            impact_threshold_g: impact,
+            ..best.profile.clone()
+        };
+        if p.check().is_err() {
+            continue;
+        }
+        let c = Candidate::from_report(evaluation::evaluate(&p, recordings).await);
+        roc.push(RocPoint {
+            impact_threshold_g: impact,
+            false_positive_rate: 1.0 - c.specificity,
+            true_positive_rate: c.sensitivity,
+            false_alarms_per_day: c.false_alarms_per_day,
+        });
+    }
+    roc.sort_by(|a, b| {
+        (a.false_positive_rate, a.true_positive_rate)
+            .partial_cmp(&(b.false_positive_rate, b.true_positive_rate))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    // Trapezoids from (0, 0) through the curve to (1, 1)
+    let mut auc = 0.0;
+    let mut previous = (0.0, 0.0);
+    let ends = roc
+        .iter()
+        .map(|p| (p.false_positive_rate, p.true_positive_rate))
+        .chain(std::iter::once((1.0, 1.0)));
+    for point in ends {
+        auc += (point.0 - previous.0) * (point.1 + previous.1) / 2.0;
+        previous = point;
+    }
+
+    Ok(Tuning {
+        objective: *objective,
+        evaluated: candidates.len(),
+        feasible: feasible.len(),
+        best,
+        roc,
+        auc,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Helper: 1 s upright, an impact of `peak` g, then 3 s lying still or walking on (50 Hz),
+    // followed by `hours` of activity
+    fn trial(name: &str, fall: bool, peak: f64, still: bool, hours: f64) -> serde_json::Value {
+        let mut samples = Vec::new();
+        for i in 0..200 {
+            let (x, z) = match i {
+                0..=49 => (0.0, 9.8),
+                50 => (peak * 9.8, 0.0),
+                _ if still => (9.8, 0.0),
+                _ => (if i % 2 == 0 { 8.0 } else { -8.0 }, 9.8),
+            };
+            samples.push(serde_json::json!({"x": x, "y": 0.0, "z": z, "t": i as f64 / 50.0}));
+        }
+        if hours > 0.0 {
+            samples.push(serde_json::json!({"x": 0.0, "y": 0.0, "z": 9.8, "t": hours * 3600.0}));
+        }
+        let fall_at = fall.then_some(1.0);
+        serde_json::json!({"name": name, "fall": fall, "fall_at": fall_at, "samples": samples})
+    }
+
+    // Helper: a hard fall, a slow fall, a sit-down that looks like a fall (once in an hour)
+    // and a stumble
+    fn recordings() -> Vec<Recording> {
+        let dataset = serde_json::json!([
+            trial("hard-fall", true, 2.5, true, 0.0),
+            trial("slow-fall", true, 1.7, true, 0.0),
+            trial("sit-down", false, 1.75, true, 1.0),
+            trial("stumble", false, 2.2, false, 0.0),
+        ]);
+        evaluation::parse_json("lab", &dataset.to_string()).unwrap()
+    }
+
+    // Helper: impact thresholds 1.5 to 2.4 g, stillness thresholds 1 to 4 (40 candidates)
+    fn space() -> SearchSpace {
+        SearchSpace {
+            impact_threshold_g: ParamRange::parse("1.5:2.4:0.1").unwrap(),
+            stillness_threshold_variance: ParamRange::parse("1:4:1").unwrap(),
+            ..SearchSpace::default()
+        }
+    }
+
+    // Helper: the highest sensitivity
+    fn sensitivity() -> Objective {
+        Objective {
+            goal: Goal::Sensitivity,
+            ..Objective::default()
+        }
+    }
+
+    // Helper: the highest sensitivity at no more than 10 false alarms per day
+    fn capped() -> Objective {
+        Objective {
+            max_false_alarms_per_day: Some(10.0),
+            ..sensitivity()
+        }
+    }
+
+    // Helper: the grid search for `objective`, naming the profile ward-3
+    async fn grid(objective: &Objective) -> Result<Tuning, String> {
+        tune(&recordings(), &space(), Search::Grid, objective, "ward-3").await
+    }
+
+    // A range covers min to max by step, both ends included
+    #[test]
+    fn test_param_range() {
+        let impact = ParamRange::parse("1.5:2.4:0.1").unwrap();
+        assert_eq!(impact.values().len(), 10);
+        assert_eq!(impact.values()[9], 2.4);
+    }
+
+    // A single value is a fixed range
+    #[test]
+    fn test_param_range_fixed() {
+        assert_eq!(ParamRange::parse("3.5").unwrap().values(), vec![3.5]);
+    }
+
+    // A range must not end before it starts
+    #[test]
+    fn test_param_range_rejects_reversed() {
+        assert!(ParamRange::parse("2:1:0.1").is_err());
+    }
+
+    // A range must not reach zero or below: thresholds and windows are positive
+    #[test]
+    fn test_param_range_rejects_non_positive() {
+        assert_eq!(
+            ParamRange::parse("0:2:0.5").unwrap_err(),
+            "values must be positive: 0:2:0.5"
+        );
+        assert!(ParamRange::parse("-1").is_err());
+    }
+
+    // Candidates the detector would refuse are skipped, so the tuned profile always loads
+    #[actix_web::test]
+    async fn test_tune_skips_unusable_profiles() {
+        let space = SearchSpace {
+            impact_threshold_g: ParamRange::new(-0.5, 2.0, 0.5),
+            ..space()
+        };
+        let tuned = tune(
+            &recordings(),
+            &space,
+            Search::Grid,
+            &Objective::default(),
+            "ward-3",
+        )
+        .await
+        .unwrap();
+        assert!(tuned.best.profile.impact_threshold_g > 0.0);
+        assert!(tuned.roc.iter().all(|p| p.impact_threshold_g > 0.0));
+        let json = serde_json::to_string(&tuned.best.profile).unwrap();
+        assert!(DetectorProfile::parse(&json).is_ok());
+    }
+
+    // A search space with no usable profile is refused
+    #[actix_web::test]
+    async fn test_tune_needs_usable_profile() {
+        let space = SearchSpace {
+            validation_ms: ParamRange::new(0.1, 0.4, 0.1),
+            ..space()
+        };
+        let err = tune(
+            &recordings(),
+            &space,
+            Search::Grid,
+            &Objective::default(),
+            "ward-3",
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err, "the search space has no usable profile");
+    }
+
+    // Highest sensitivity: a low impact threshold catches the slow fall, and the sit-down
+    #[actix_web::test]
+    async fn test_tune_sensitivity() {
+        let tuned = grid(&sensitivity()).await.unwrap();
+        assert_eq!(tuned.evaluated, 40);
+        assert_eq!(tuned.feasible, 40);
+        let best = &tuned.best;
+        assert_eq!(best.profile.name, "ward-3");
+        assert!(best.profile.impact_threshold_g < 1.7);
+        assert_eq!((best.sensitivity, best.specificity), (1.0, 0.5));
+        assert!((best.false_alarms_per_day - 24.0).abs() < 0.1);
+        assert_eq!(best.median_latency_ms, Some(2000));
+    }
+
+    // At most 10 false alarms per day: the sit-down must not alarm, so the slow fall is missed
+    #[actix_web::test]
+    async fn test_tune_false_alarm_cap() {
+        let tuned = grid(&capped()).await.unwrap();
+        let best = &tuned.best;
+        assert!(best.profile.impact_threshold_g >= 1.75);
+        assert_eq!(
+            (
+                best.sensitivity,
+                best.specificity,
+                best.false_alarms_per_day
+            ),
+            (0.5, 1.0, 0.0)
+        );
+        assert!(tuned.feasible < tuned.evaluated);
+    }
+
+    // The tuned profile is written ready to use
+    #[actix_web::test]
+    async fn test_tuned_profile_parses() {
+        let tuned = grid(&capped()).await.unwrap();
+        let written = serde_json::to_string_pretty(&tuned.best.profile).unwrap();
+        assert_eq!(
+            DetectorProfile::parse(&written).unwrap(),
+            tuned.best.profile
+        );
+    }
+
+    // The ROC curve runs over the impact thresholds, at the tuned stillness threshold
+    #[actix_web::test]
+    async fn test_roc() {
+        let tuned = grid(&capped()).await.unwrap();
+        assert_eq!(tuned.roc.len(), 10);
+        let first = &tuned.roc[0];
+        let last = &tuned.roc[9];
+        assert_eq!(
+            (first.false_positive_rate, first.true_positive_rate),
+            (0.0, 0.5)
+        );
+        assert_eq!(
+            (last.false_positive_rate, last.true_positive_rate),
+            (0.5, 1.0)
+        );
+        assert!((tuned.auc - 0.75).abs() < 1e-9); // 1.7 g only misses the slow fall itself
+    }
+
+    // The ROC curve is written as CSV, one line per threshold
+    #[actix_web::test]
+    async fn test_roc_csv() {
+        let csv = grid(&capped()).await.unwrap().roc_csv();
+        assert!(csv.starts_with("impact_threshold_g,false_positive_rate,true_positive_rate"));
+        assert_eq!(csv.lines().count(), 11);
+    }
+
+    // The result prints as a table naming the tuned profile
+    #[actix_web::test]
+    async fn test_table() {
+        let table = grid(&capped()).await.unwrap().table();
+        assert!(table.contains("Tuned profile: ward-3"));
+    }
+
+    // Constraints nothing meets are reported rather than ignored
+    #[actix_web::test]
+    async fn test_tune_infeasible() {
+        let impossible = Objective {
+            min_sensitivity: Some(1.0),
+            ..capped()
+        };
+        let err = grid(&impossible).await.unwrap_err();
+        assert!(err.contains("none of the 40 candidates"), "{}", err);
+    }
+
+    // Random search tries the requested number of candidates within the space, reproducibly
+    #[actix_web::test]
+    async fn test_random_search() {
+        let random = Search::Random {
+            samples: 25,
+            seed: 7,
+        };
+        let recordings = recordings();
+        let a = tune(&recordings, &space(), random, &sensitivity(), "r")
+            .await
+            .unwrap();
+        let b = tune(&recordings, &space(), random, &sensitivity(), "r")
+            .await
+            .unwrap();
+        assert_eq!(a.evaluated, 25);
+        assert_eq!(a.best.profile, b.best.profile);
+        assert!((1.5..=2.4).contains(&a.best.profile.impact_threshold_g));
+    }
+
+    // Tuning needs both falls and activities
+    #[actix_web::test]
+    async fn test_tune_needs_both_classes() {
+        let falls_only = &recordings()[..2];
+        let tuned = tune(falls_only, &space(), Search::Grid, &sensitivity(), "x").await;
+        assert!(tuned.is_err());
+    }
+}
@@ -1,23 +1,480 @@
-use crate::logic::{DetectionEvent, FallDetector};
-use crate::model::{ClientCommand, SensorData};
+use crate::assessment;
+use crate::labels;
+use crate::logic::{DetectionEvent, Detector, Explanation, FallMetrics};
+use crate::model::{Alert, ClientCommand, SensorData, Waveform};
+use crate::risk;
+use crate::storage::{
+    DeviceRegistration, NewAuditEntry, NewDetection, NewEvent, Repository, OPEN_ALERT_STATUSES,
+};
+use crate::subscriptions::Trigger;
+use crate::telemetry::{self, TelemetryRecorder};
 use crate::AppState;
 use actix_web::{web, HttpRequest, Responder};
 use actix_ws::Message;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use futures_util::StreamExt;
-use rand;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// **Connection Parameters**
+///
+/// Query string on `/ws`. Sensors identify themselves so their events can be filtered later:
+/// `/ws?device_id=pi-01&patient_id=P-1001&ward=ICU`. Dashboards connect without them.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ConnectionParams {
+    pub device_id: Option<String>,
+    pub patient_id: Option<String>,
+    pub ward: Option<String>,
+}
+
+impl ConnectionParams {
+    /// An outcome of `detector` on this connection, not yet linked to an event.
+    pub(crate) fn detection(
+        &self,
+        detector: &str,
+        outcome: &str,
+        g_force: f64,
+        metrics: Option<FallMetrics>,
+        explanation: &Explanation,
+        at: DateTime<Utc>,
+    ) -> NewDetection {
+        NewDetection {
+            detected_at: at,
+            outcome: outcome.to_string(),
+            device_id: self.device_id.clone(),
+            patient_id: self.patient_id.clone(),
+            ward: self.ward.clone(),
+            peak_g: g_force,
+            metrics,
+            event_id: None,
+            explanation: Some(explanation.clone()),
+            detector: detector.to_string(),
+            shadow: false,
+        }
+    }
+}
+
+/// Device id used for telemetry from sensors that connect without `device_id`.
+const UNREGISTERED_DEVICE: &str = "unregistered";
+
+/// Actor recorded in the audit log for decisions taken on the ward dashboard.
+pub const NURSE_ACTOR: &str = "nurse:dashboard";
+
+/// Broadcast of a detector outcome:
+/// `{"type": "CRITICAL_FALL" | "NEAR_MISS" | "VALIDATING", "g_force": 2.6, "explanation": {...}}`.
+pub(crate) fn outcome_message(kind: &str, g_force: f64, explanation: &Explanation) -> String {
+    json!({ "type": kind, "g_force": g_force, "explanation": explanation }).to_string()
+}
+
+/// Appends a detector outcome to the `detections` log; failures are logged.
+pub(crate) async fn record_detection(db: Arc<dyn Repository>, detection: NewDetection) {
+    if let Err(e) = db.record_detection(&detection).await {
+        eprintln!("❌ Failed to log {} detection: {}", detection.outcome, e);
+    }
+}
+
+/// Stores a "Critical" event with the detector's explanation, opens an alert for it and
+/// attaches the captured waveform. The outcome is logged with its features, subscribers are
+/// notified of the new Observation and Flag, and the patient's fall risk is recalculated.
+pub(crate) async fn raise_alert(
+    data: web::Data<AppState>,
+    g_force: f64,
+    metrics: FallMetrics,
+    explanation: Explanation,
+    source: ConnectionParams,
+    waveform: Option<Waveform>,
+) {
+    let db = data.db.as_ref();
+    let now = Utc::now();
+    let mut detection = source.detection(
+        &data.detector.name,
+        "critical",
+        g_force,
+        Some(metrics),
+        &explanation,
+        now,
+    );
+    let event = NewEvent {
+        detected_at: now,
+        severity: "Critical".to_string(),
+        g_force_value: g_force,
+        is_false_alarm: false,
+        device_id: source.device_id,
+        patient_id: source.patient_id,
+        ward: source.ward,
+        metrics: Some(metrics),
+        explanation: Some(explanation),
+    };
+    let stored = db.insert_event(event).await;
+    detection.event_id = stored.as_ref().ok().map(|log| log.id);
+    record_detection(data.db.clone(), detection).await;
+    let log = match stored {
+        Ok(log) => log,
+        Err(e) => {
+            eprintln!("❌ Failed to store alert: {}", e);
+            return;
+        }
+    };
+    data.notifier.notify(Trigger::Observation(log.id));
+    match db.open_alert(log.id, now).await {
+        Ok(alert) => {
+            // Dashboards answer the alert by this id
+            let opened =
+                json!({ "type": "ALERT_OPENED", "alert_id": alert.id, "event_id": log.id });
+            let _ = data.tx.send(opened.to_string());
+            data.notifier.notify(Trigger::Flag(alert.id));
+        }
+        Err(e) => eprintln!("❌ Failed to open alert for event {}: {}", log.id, e),
+    }
+    if let Some(mut waveform) = waveform {
+        waveform.event_id = log.id;
+        if let Err(e) = db.save_waveform(&waveform).await {
+            eprintln!("❌ Failed to store waveform for event {}: {}", log.id, e);
+        }
+    }
+    risk::on_event(db, &data.risk, &log).await;
+}
+
+/// Stores a "Near Miss" event (an impact the wearer recovered from) with the features of its
+/// validation window, the detector's explanation and the captured waveform, logs the outcome
+/// and recalculates the patient's fall risk. Subscribers are notified of the new Observation.
+pub(crate) async fn record_near_miss(
+    data: web::Data<AppState>,
+    g_force: f64,
+    metrics: FallMetrics,
+    explanation: Explanation,
+    source: ConnectionParams,
+    waveform: Option<Waveform>,
+) {
+    let now = Utc::now();
+    let mut detection = source.detection(
+        &data.detector.name,
+        "near-miss",
+        g_force,
+        Some(metrics),
+        &explanation,
+        now,
+    );
+    let event = NewEvent {
+        detected_at: now,
+        severity: "Near Miss".to_string(),
+        g_force_value: g_force,
+        is_false_alarm: false,
+        device_id: source.device_id,
+        patient_id: source.patient_id,
+        ward: source.ward,
+        metrics: Some(metrics),
+        explanation: Some(explanation),
+    };
+    let stored = data.db.insert_event(event).await;
+    detection.event_id = stored.as_ref().ok().map(|log| log.id);
+    record_detection(data.db.clone(), detection).await;
+    let log = match stored {
+        Ok(log) => log,
+        Err(e) => {
+            eprintln!("❌ Failed to store near miss: {}", e);
+            return;
+        }
+    };
+    data.notifier.notify(Trigger::Observation(log.id));
+    if let Some(mut waveform) = waveform {
+        waveform.event_id = log.id;
+        if let Err(e) = data.db.save_waveform(&waveform).await {
+            eprintln!("❌ Failed to store waveform for event {}: {}", log.id, e);
+        }
+    }
+    risk::on_event(data.db.as_ref(), &data.risk, &log).await;
+}
+
+/// Samples queued for a connection's shadow candidates (about 5 s at 50 Hz).
+pub(crate) const SHADOW_QUEUE: usize = 256;
+
+/// A live sample and the time it was received.
+type ShadowSample = (SensorData, DateTime<Utc>);
+
+/// **Shadow Feed**
+///
+/// Hands a connection's live samples to its shadow-mode candidates, which run in a task of
+/// their own: a slow candidate (e.g. a model near its latency budget) never holds back
+/// production's verdict or the raw-data broadcast. Once the candidates are `SHADOW_QUEUE`
+/// samples behind, samples are dropped for them instead of waited on.
+pub(crate) struct ShadowFeed {
+    queue: mpsc::Sender<ShadowSample>,
+    dropping: bool,
+}
+
+impl ShadowFeed {
+    /// Starts `candidates` on samples from `source`; `None` when there are none.
+    pub(crate) fn spawn(
+        db: Arc<dyn Repository>,
+        candidates: Vec<Box<dyn Detector>>,
+        source: ConnectionParams,
+    ) -> Option<Self> {
+        if candidates.is_empty() {
+            return None;
+        }
+        let (queue, samples) = mpsc::channel(SHADOW_QUEUE);
+        actix_rt::spawn(run_shadows(db, candidates, source, samples));
+        Some(Self {
+            queue,
+            dropping: false,
+        })
+    }
+
+    /// Queues a sample without waiting; `false` when it was dropped.
+    pub(crate) fn offer(&mut self, data: SensorData, at: DateTime<Utc>) -> bool {
+        match self.queue.try_send((data, at)) {
+            Ok(()) => {
+                self.dropping = false;
+                true
+            }
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                if !self.dropping {
+                    eprintln!(
+                        "⚠️ Shadow candidates are behind; dropping samples until they catch up"
+                    );
+                    self.dropping = true;
+                }
+                false
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        }
+    }
+}
+
+/// Runs the candidates over queued samples until the connection closes, logging their verdicts.
+pub(crate) async fn run_shadows(
+    db: Arc<dyn Repository>,
+    mut candidates: Vec<Box<dyn Detector>>,
+    source: ConnectionParams,
+    mut samples: mpsc::Receiver<ShadowSample>,
+) {
+    while let Some((data, at)) = samples.recv().await {
+        for candidate in candidates.iter_mut() {
+            if let Some(detection) =
+                shadow_verdict(candidate.as_mut(), &source, data.clone(), at).await
+            {
+                record_detection(db.clone(), detection).await;
+            }
+        }
+    }
+}
+
+/// Feeds a sample to a shadow-mode candidate detector. Its verdicts (critical or near-miss)
+/// come back as `shadow` outcomes to log next to production's; it never raises an event, an
+/// alert or a broadcast.
+pub(crate) async fn shadow_verdict(
+    detector: &mut dyn Detector,
+    source: &ConnectionParams,
+    data: SensorData,
+    at: DateTime<Utc>,
+) -> Option<NewDetection> {
+    let (outcome, g_force, metrics, explanation) =
+        match detector.process_at(data, at.timestamp_millis()).await? {
+            DetectionEvent::Validating { .. } => return None,
+            DetectionEvent::CriticalFall {
+                g_force,
+                metrics,
+                explanation,
+            } => ("critical", g_force, metrics, explanation),
+            DetectionEvent::NearMiss {
+                g_force,
+                metrics,
+                explanation,
+            } => ("near-miss", g_force, metrics, explanation),
+        };
+    let name = &detector.profile().name;
+    Some(NewDetection {
+        shadow: true,
+        ..source.detection(name, outcome, g_force, Some(metrics), &explanation, at)
+    })
+}
+
+/// Records which patient and ward a connecting sensor is assigned to in the device registry.
+async fn register_device(db: Arc<dyn Repository>, source: ConnectionParams) {
+    let Some(id) = source.device_id else {
+        return;
+    };
+    let registration = DeviceRegistration {
+        id,
+        model: None,
+        patient_id: source.patient_id,
+        ward: source.ward,
+        calibration_state: None,
+        calibrated_at: None,
+        at: Utc::now(),
+    };
+    if let Err(e) = db.register_device(&registration).await {
+        eprintln!("❌ Failed to register device {}: {}", registration.id, e);
+    }
+}
+
+/// What a nurse command on an alert does: the broadcast acknowledging it, the action logged to
+/// `events` and the status the alert moves to.
+struct NurseAction {
+    command: &'static str,
+    ack: &'static str,
+    severity: &'static str,
+    is_false_alarm: bool,
+    alert_status: &'static str,
+}
+
+const NURSE_ACTIONS: [NurseAction; 3] = [
+    NurseAction {
+        command: "CANCEL_ALERT",
+        ack: "CANCEL_ALERT",
+        severity: "Refused",
+        is_false_alarm: true,
+        alert_status: "Cancelled",
+    },
+    NurseAction {
+        command: "CONFIRM_FALL",
+        ack: "CONFIRMED",
+        severity: "Assistance Sent",
+        is_false_alarm: false,
+        alert_status: "Confirmed",
+    },
+    NurseAction {
+        command: "RESET_SYSTEM",
+        ack: "RESET_COMPLETE",
+        severity: "Resolved",
+        is_false_alarm: false,
+        alert_status: "Resolved",
+    },
+];
+
+/// The alert a nurse command answers: it must name one (`alert_id`, broadcast in
+/// `ALERT_OPENED`) that is still open, so a verdict never lands on another alert.
+pub(crate) async fn command_alert(
+    db: &dyn Repository,
+    alert_id: Option<i32>,
+) -> Result<Alert, String> {
+    let alert_id = alert_id.ok_or("alert_id is required")?;
+    let alert = db
+        .get_alert(alert_id)
+        .await
+        .map_err(|e| format!("cannot look up alert {}: {}", alert_id, e))?
+        .ok_or_else(|| format!("alert {} not found", alert_id))?;
+    if !OPEN_ALERT_STATUSES.contains(&alert.status.as_str()) {
+        return Err(format!("alert {} is already {}", alert_id, alert.status));
+    }
+    Ok(alert)
+}
+
+/// Logs a nurse action on `alert` to `events` and moves the alert to `alert_status`.
+/// The action is attributed to the device, patient and ward of the alert it answers, and the
+/// decision is written to the audit log. Cancelling an alert dismisses its detection as a false alarm;
+/// confirming it starts the post-fall assessment. Either verdict labels the detection (and its
+/// stored waveform) as a fall or not.
+/// Subscribers are notified of the Flag's new status (and of the dismissed Observation, whose
+/// patient's fall risk is recalculated without it).
+pub(crate) async fn record_action(
+    data: web::Data<AppState>,
+    alert: Alert,
+    command: &str,
+    severity: &str,
+    is_false_alarm: bool,
+    alert_status: &str,
+) {
+    let db = data.db.as_ref();
+    let now = Utc::now();
+    let source = db.get_event(alert.event_id).await.ok().flatten();
+
+    let event = NewEvent {
+        detected_at: now,
+        severity: severity.to_string(),
+        g_force_value: 0.0,
+        is_false_alarm,
+        device_id: source.as_ref().and_then(|s| s.device_id.clone()),
+        patient_id: source.as_ref().and_then(|s| s.patient_id.clone()),
+        ward: source.as_ref().and_then(|s| s.ward.clone()),
+        metrics: None,
+        explanation: None,
+    };
+    let action = match db.insert_event(event).await {
+        Ok(log) => Some(log),
+        Err(e) => {
+            eprintln!("❌ Failed to log nurse action: {}", e);
+            None
+        }
+    };
+
+    if let Err(e) = db.set_alert_status(alert.id, alert_status, now).await {
+        eprintln!("❌ Failed to update alert {}: {}", alert.id, e);
+        return;
+    }
+    data.notifier.notify(Trigger::Flag(alert.id));
+    labels::record(db, &alert, command, now).await;
+    if alert_status == "Cancelled" {
+        match db.dismiss_event(alert.event_id, now).await {
+            Ok(dismissed) => {
+                data.notifier.notify(Trigger::Observation(alert.event_id));
+                risk::on_event(db, &data.risk, &dismissed).await;
+            }
+            Err(e) => eprintln!("❌ Failed to dismiss event {}: {}", alert.event_id, e),
+        }
+    }
+    if alert_status == "Confirmed" {
+        let form = &data.assessment_form;
+        if let Err(e) = assessment::open(db, form, &alert, source.as_ref(), now).await {
+            eprintln!(
+                "❌ Failed to start the assessment of alert {}: {}",
+                alert.id, e
+            );
+        }
+    }
+    let audit = NewAuditEntry {
+        recorded_at: now,
+        actor: NURSE_ACTOR.to_string(),
+        action: format!("alert.{}", alert_status.to_lowercase()),
+        entity_type: "Alert".to_string(),
+        entity_id: Some(alert.id.to_string()),
+        detail: Some(json!({
+            "command": command,
+            "event_id": alert.event_id,
+            "action_event_id": action.map(|a| a.id),
+            "status": alert_status,
+        })),
+    };
+    if let Err(e) = db.record_audit(audit).await {
+        eprintln!(
+            "❌ Failed to audit nurse action on alert {}: {}",
+            alert.id, e
+        );
+    }
+}
 
 pub async fn ws_handler(
     req: HttpRequest,
     stream: web::Payload,
     data: web::Data<AppState>,
 ) -> Result<impl Responder, actix_web::Error> {
+    let params = web::Query::<ConnectionParams>::from_query(req.query_string())
+        .map(|q| q.into_inner())
+        .unwrap_or_default();
     let (res, mut session, mut msg_stream) = actix_ws::handle(&req, stream)?;
     let mut rx = data.tx.subscribe();
     let tx = data.tx.clone();
 
-    // Each connection has its own stateful detector
-    let mut detector = FallDetector::new();
+    // Each connection has its own stateful detector and telemetry recorder
+    let mut detector = data.detector.detector();
+    let candidates = data.shadow.iter().map(|p| p.detector()).collect();
+    let mut shadows = ShadowFeed::spawn(data.db.clone(), candidates, params.clone());
+    let mut recorder = TelemetryRecorder::new(
+        params
+            .device_id
+            .clone()
+            .unwrap_or_else(|| UNREGISTERED_DEVICE.to_string()),
+        &data.telemetry,
+    );
+
+    // Sensors that identify themselves are kept in the device registry
+    let registered = params.device_id.is_some();
+    if registered {
+        actix_rt::spawn(register_device(data.db.clone(), params.clone()));
+    }
 
     actix_rt::spawn(async move {
         loop {
@@ -27,60 +484,63 @@ pub async fn ws_handler(
                         Some(Ok(Message::Text(text))) => {
                             // 1. Try Command
                             if let Ok(cmd) = serde_json::from_str::<ClientCommand>(&text) {
-                                let pool = data.db.clone();
-                                let tx_clone = tx.clone();
-
-                                if cmd.action == "CANCEL_ALERT" {
-                                    let _ = tx_clone.send("CANCEL_ALERT".to_string());
-                                    actix_rt::spawn(async move {
-                                        let _ = sqlx::query!(
-                                            "INSERT INTO events (id, detected_at, g_force_value, severity, is_false_alarm) VALUES ($1, $2, $3, $4, $5)",
-                                            rand::random::<i32>(), Utc::now(), 0.0, "Refused", true
-                                        ).execute(&pool).await;
-                                    });
-                                } else if cmd.action == "CONFIRM_FALL" {
-                                    let _ = tx_clone.send("CONFIRMED".to_string());
-                                    actix_rt::spawn(async move {
-                                        let _ = sqlx::query!(
-                                            "INSERT INTO events (id, detected_at, g_force_value, severity, is_false_alarm) VALUES ($1, $2, $3, $4, $5)",
-                                            rand::random::<i32>(), Utc::now(), 0.0, "Assistance Sent", false
-                                        ).execute(&pool).await;
-                                    });
-                                } else if cmd.action == "RESET_SYSTEM" {
-                                    let _ = tx_clone.send("RESET_COMPLETE".to_string());
-                                    actix_rt::spawn(async move {
-                                        let _ = sqlx::query!(
-                                            "INSERT INTO events (id, detected_at, g_force_value, severity, is_false_alarm) VALUES ($1, $2, $3, $4, $5)",
-                                            rand::random::<i32>(), Utc::now(), 0.0, "Resolved", false
-                                        ).execute(&pool).await;
-                                    });
+                                if let Some(action) = NURSE_ACTIONS.iter().find(|a| a.command == cmd.action) {
+                                    // The verdict only lands on the alert the nurse answered
+                                    match command_alert(data.db.as_ref(), cmd.alert_id).await {
+                                        Ok(alert) => {
+                                            let _ = tx.send(action.ack.to_string());
+                                            actix_rt::spawn(record_action(data.clone(), alert, action.command, action.severity, action.is_false_alarm, action.alert_status));
+                                        }
+                                        Err(reason) => {
+                                            println!("⚠️ {} rejected: {}", cmd.action, reason);
+                                            let rejected = json!({ "type": "COMMAND_REJECTED", "action": cmd.action, "reason": reason });
+                                            let _ = session.text(rejected.to_string()).await;
+                                        }
+                                    }
                                 }
                             }
                             // 2. Try Sensor Data
-                            else if let Ok(mut sensor_data) = serde_json::from_str::<SensorData>(&text) {
+                            else if let Ok(sensor_data) = serde_json::from_str::<SensorData>(&text) {
+                                // Persist raw telemetry in batches
+                                let received_at = Utc::now();
+                                if let Some(batch) = recorder.record(&sensor_data, received_at) {
+                                    actix_rt::spawn(telemetry::store_batch(data.db.clone(), batch));
+                                    if registered {
+                                        actix_rt::spawn(telemetry::store_status(data.db.clone(), recorder.status()));
+                                    }
+                                }
+
                                 // Feed into Logic
-                                if let Some(event) = detector.process(sensor_data.clone()) {
+                                if let Some(event) = detector.process(sensor_data.clone()).await {
                                     match event {
-                                        DetectionEvent::Validating => {
+                                        DetectionEvent::Validating { g_force, explanation } => {
                                             println!("🟡 State: VALIDATING (Buffer Started)");
-                                            let _ = tx.send("VALIDATING".to_string());
+                                            recorder.mark_impact(received_at);
+                                            let _ = tx.send(outcome_message("VALIDATING", g_force, &explanation));
+                                            let detection = params.detection(&data.detector.name, "validating", g_force, None, &explanation, received_at);
+                                            actix_rt::spawn(record_detection(data.db.clone(), detection));
                                         }
-                                        DetectionEvent::CriticalFall { g_force } => {
-                                            println!("🔴 State: CRITICAL FALL CONFIRMED! (G: {:.2})", g_force);
-                                            // Send alert with G-Force
-                                            let alert_msg = serde_json::json!({
-                                                "type": "CRITICAL_FALL",
-                                                "g_force": g_force
-                                            }).to_string();
-                                            let _ = tx.send(alert_msg);
+                                        DetectionEvent::CriticalFall { g_force, metrics, explanation } => {
+                                            println!("🔴 State: CRITICAL FALL CONFIRMED! (G: {:.2}, rules: {:?}, confidence: {:.2})", g_force, explanation.fired(), explanation.confidence);
+                                            // Send alert with G-Force and why it fired
+                                            let _ = tx.send(outcome_message("CRITICAL_FALL", g_force, &explanation));
+                                            let waveform = recorder.take_waveform(received_at);
+                                            actix_rt::spawn(raise_alert(data.clone(), g_force, metrics, explanation, params.clone(), waveform));
                                         }
-                                        DetectionEvent::NearMiss => {
+                                        DetectionEvent::NearMiss { g_force, metrics, explanation } => {
                                              println!("⚪ State: NEAR MISS (Movement Detected)");
-                                             let _ = tx.send("NEAR_MISS".to_string());
+                                             let _ = tx.send(outcome_message("NEAR_MISS", g_force, &explanation));
+                                             let waveform = recorder.take_waveform(received_at);
+                                             actix_rt::spawn(record_near_miss(data.clone(), g_force, metrics, explanation, params.clone(), waveform));
                                         }
                                     }
                                 }
 
+                                // Candidates see the same samples off this loop, and only their verdicts are logged
+                                if let Some(shadows) = shadows.as_mut() {
+                                    shadows.offer(sensor_data.clone(), received_at);
+                                }
+
                                 // Broadcast raw data for charts
                                 let _ = tx.send(text.to_string());
                             } else {
@@ -88,8 +548,13 @@ pub async fn ws_handler(
                                 println!("⚠️ Received Unknown format: {}", text);
                             }
                         }
-                        Some(Ok(Message::Close(_))) => break,
-                        None => break,
+                        Some(Ok(Message::Close(_))) | None => {
+                            actix_rt::spawn(telemetry::store_batch(data.db.clone(), recorder.drain()));
+                            if registered {
+                                actix_rt::spawn(telemetry::store_status(data.db.clone(), recorder.status()));
+                            }
+                            break;
+                        }
                         _ => {}
                     }
                 }
@@ -104,3 +569,354 @@ pub async fn ws_handler(
 
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::{DetectorProfile, FallDetector};
+    use crate::model::{Detection, WaveformPoint};
+    use crate::storage::{DetectionQuery, EventQuery, LabelQuery};
+    use crate::test_support::{answered_fall, critical_event, memory_state};
+
+    // Helper: pi-09, worn by P-4004 on Ward-C
+    fn source() -> ConnectionParams {
+        ConnectionParams {
+            device_id: Some("pi-09".to_string()),
+            patient_id: Some("P-4004".to_string()),
+            ward: Some("Ward-C".to_string()),
+        }
+    }
+
+    // Helper: the newest logged outcome
+    async fn newest_detection(db: &dyn Repository) -> Detection {
+        let query = DetectionQuery {
+            limit: 1,
+            ..DetectionQuery::default()
+        };
+        db.query_detections(&query).await.unwrap().remove(0)
+    }
+
+    // An impact is logged as validating, from its connection, before any event exists
+    #[actix_web::test]
+    async fn test_impact_logged() {
+        let state = memory_state();
+        let impact = source().detection(
+            "default",
+            "validating",
+            2.1,
+            None,
+            &Explanation::impact(2.1),
+            Utc::now(),
+        );
+        record_detection(state.db.clone(), impact).await;
+        let logged = newest_detection(state.db.as_ref()).await;
+        assert_eq!(logged.outcome, "validating");
+        assert_eq!(logged.device_id.as_deref(), Some("pi-09"));
+        assert_eq!(logged.patient_id.as_deref(), Some("P-4004"));
+        assert_eq!(logged.ward.as_deref(), Some("Ward-C"));
+        assert_eq!(logged.peak_g, 2.1);
+        assert_eq!(logged.event_id, None);
+        assert_eq!(logged.stillness_variance, None);
+    }
+
+    // A near miss is logged with the window's features and stored as an event, waveform kept
+    #[actix_web::test]
+    async fn test_near_miss_logged() {
+        let state = memory_state();
+        let moving = FallMetrics {
+            stillness_variance: 7.9,
+            validation_ms: 2012,
+            orientation_change_deg: 12.0,
+        };
+        let now = Utc::now();
+        let capture = Waveform {
+            event_id: 0,
+            device_id: "pi-09".to_string(),
+            impact_at: now,
+            started_at: now,
+            ended_at: now,
+            points: vec![WaveformPoint {
+                offset_ms: 0,
+                x: 0.0,
+                y: 0.0,
+                z: 22.5,
+                g_force: 2.3,
+            }],
+        };
+        let why = Explanation::verdict(2.3, &moving);
+        record_near_miss(state.clone(), 2.3, moving, why, source(), Some(capture)).await;
+        let logged = newest_detection(state.db.as_ref()).await;
+        assert_eq!(logged.outcome, "near-miss");
+        assert_eq!(logged.peak_g, 2.3);
+        assert_eq!(logged.stillness_variance, Some(7.9));
+        assert_eq!(logged.validation_ms, Some(2012));
+        assert_eq!(logged.orientation_change_deg, Some(12.0));
+        let event_id = logged.event_id.unwrap();
+        let event = state.db.get_event(event_id).await.unwrap().unwrap();
+        assert_eq!(event.severity, "Near Miss");
+        assert_eq!(event.stillness_variance, Some(7.9));
+        assert_eq!(event.orientation_change_deg, Some(12.0));
+        let waveform = state.db.get_waveform(event_id).await.unwrap().unwrap();
+        assert_eq!(waveform.points[0].g_force, 2.3);
+    }
+
+    // A fall is logged with the window's features and stored as a Critical event
+    #[actix_web::test]
+    async fn test_fall_logged() {
+        let state = memory_state();
+        let still = FallMetrics {
+            stillness_variance: 0.6,
+            validation_ms: 2005,
+            orientation_change_deg: 85.0,
+        };
+        let why = Explanation::verdict(3.4, &still);
+        raise_alert(state.clone(), 3.4, still, why, source(), None).await;
+        let logged = newest_detection(state.db.as_ref()).await;
+        assert_eq!(logged.outcome, "critical");
+        assert_eq!(logged.stillness_variance, Some(0.6));
+        let fall = state
+            .db
+            .get_event(logged.event_id.unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (fall.severity.as_str(), fall.g_force_value),
+            ("Critical", 3.4)
+        );
+    }
+
+    // The outcome is broadcast with its explanation
+    #[test]
+    fn test_outcome_message() {
+        let fall = Explanation::impact(3.2);
+        let message = outcome_message("CRITICAL_FALL", 3.2, &fall);
+        let message: serde_json::Value = serde_json::from_str(&message).unwrap();
+        assert_eq!(message["type"], "CRITICAL_FALL");
+        assert_eq!(message["g_force"], 3.2);
+        assert_eq!(message["explanation"]["rules"][0]["rule"], "impact");
+        assert_eq!(message["explanation"]["confidence"], fall.confidence);
+    }
+
+    // A fall's explanation is stored with its event
+    #[actix_web::test]
+    async fn test_explanation_stored() {
+        let state = memory_state();
+        let still = FallMetrics {
+            stillness_variance: 0.5,
+            validation_ms: 2003,
+            orientation_change_deg: 88.0,
+        };
+        let why = Explanation::verdict(3.2, &still);
+        raise_alert(state.clone(), 3.2, still, why.clone(), source(), None).await;
+        let id = state.db.latest_event_id().await.unwrap().unwrap();
+        let event = state.db.get_event(id).await.unwrap().unwrap();
+        assert_eq!(event.explanation(), Some(why));
+    }
+
+    // Nurse actions have nothing to explain
+    #[actix_web::test]
+    async fn test_nurse_action_unexplained() {
+        let state = memory_state();
+        answered_fall(&state, "CONFIRM_FALL").await;
+        let id = state.db.latest_event_id().await.unwrap().unwrap();
+        let action = state.db.get_event(id).await.unwrap().unwrap();
+        assert_eq!(action.severity, "Assistance Sent");
+        assert_eq!(action.explanation, None);
+    }
+
+    // Helper: two alerts still waiting for an answer, the older first
+    async fn two_open_alerts(db: &dyn Repository) -> [Alert; 2] {
+        let mut alerts = Vec::new();
+        for minutes_ago in [10, 5] {
+            let event = db
+                .insert_event(critical_event(minutes_ago, 2.5))
+                .await
+                .unwrap();
+            alerts.push(db.open_alert(event.id, event.detected_at).await.unwrap());
+        }
+        alerts.try_into().unwrap()
+    }
+
+    // A nurse command must name its alert
+    #[actix_web::test]
+    async fn test_command_alert_required() {
+        let state = memory_state();
+        let err = command_alert(state.db.as_ref(), None).await.unwrap_err();
+        assert_eq!(err, "alert_id is required");
+    }
+
+    // A nurse command on an unknown alert is refused
+    #[actix_web::test]
+    async fn test_command_alert_unknown() {
+        let state = memory_state();
+        let err = command_alert(state.db.as_ref(), Some(99))
+            .await
+            .unwrap_err();
+        assert_eq!(err, "alert 99 not found");
+    }
+
+    // A nurse command on an alert already answered is refused
+    #[actix_web::test]
+    async fn test_command_alert_closed() {
+        let state = memory_state();
+        let alert = answered_fall(&state, "CANCEL_ALERT").await;
+        let err = command_alert(state.db.as_ref(), Some(alert.id))
+            .await
+            .unwrap_err();
+        assert_eq!(err, format!("alert {} is already Cancelled", alert.id));
+    }
+
+    // A verdict on an older alert never lands on a newer one
+    #[actix_web::test]
+    async fn test_command_older_alert() {
+        let state = memory_state();
+        let db = state.db.as_ref();
+        let [older, newer] = two_open_alerts(db).await;
+        let answered = command_alert(db, Some(older.id)).await.unwrap();
+        assert_eq!(answered.id, older.id);
+        record_action(
+            state.clone(),
+            answered,
+            "CANCEL_ALERT",
+            "Refused",
+            true,
+            "Cancelled",
+        )
+        .await;
+        let query = LabelQuery {
+            limit: 10,
+            ..LabelQuery::default()
+        };
+        let labels = db.query_labels(&query).await.unwrap();
+        assert_eq!(labels.len(), 1);
+        assert_eq!(
+            (labels[0].alert_id, labels[0].event_id),
+            (older.id, older.event_id)
+        );
+        let newer = db.get_alert(newer.id).await.unwrap().unwrap();
+        assert_eq!(newer.status, "Active");
+    }
+
+    // Helper: a shadow-mode candidate that alerts from `impact_threshold_g`
+    fn candidate(name: &str, impact_threshold_g: f64) -> FallDetector {
+        FallDetector::with_profile(DetectorProfile {
+            name: name.to_string(),
+            impact_threshold_g,
+            ..DetectorProfile::default()
+        })
+    }
+
+    // Helper: a 1.5 g knock, then lying still for the validation window at 50 Hz
+    fn knock_samples() -> Vec<ShadowSample> {
+        let start = Utc::now();
+        (0..=110i64)
+            .map(|i| {
+                let sample = SensorData {
+                    x: if i == 0 { 14.7 } else { 9.8 },
+                    y: 0.0,
+                    z: 0.0,
+                    timestamp: 0.0,
+                    wifi: 0,
+                    temp: 0.0,
+                    battery: None,
+                };
+                (sample, start + chrono::Duration::milliseconds(i * 20))
+            })
+            .collect()
+    }
+
+    // Helper: the candidate's verdicts on the knock
+    async fn knock(detector: &mut FallDetector) -> Vec<NewDetection> {
+        let mut verdicts = Vec::new();
+        for (sample, at) in knock_samples() {
+            verdicts.extend(shadow_verdict(detector, &source(), sample, at).await);
+        }
+        verdicts
+    }
+
+    // A candidate's verdict is a shadow outcome under its own name, from the connection
+    #[actix_web::test]
+    async fn test_shadow_verdict() {
+        let verdicts = knock(&mut candidate("sensitive", 1.3)).await;
+        assert_eq!(verdicts.len(), 1);
+        let verdict = &verdicts[0];
+        assert_eq!(verdict.outcome, "critical");
+        assert_eq!(verdict.detector, "sensitive");
+        assert!(verdict.shadow);
+        assert_eq!(verdict.device_id.as_deref(), Some("pi-09"));
+        assert_eq!(verdict.event_id, None);
+    }
+
+    // A candidate that sees no impact reports nothing
+    #[actix_web::test]
+    async fn test_shadow_verdict_below_threshold() {
+        assert!(knock(&mut candidate("strict", 4.0)).await.is_empty());
+    }
+
+    // A candidate's verdict is only logged: no event, alert or broadcast
+    #[actix_web::test]
+    async fn test_shadow_verdict_logged_only() {
+        let state = memory_state();
+        let mut rx = state.tx.subscribe();
+        let verdict = knock(&mut candidate("sensitive", 1.3)).await.remove(0);
+        record_detection(state.db.clone(), verdict).await;
+        assert!(newest_detection(state.db.as_ref()).await.shadow);
+        let query = EventQuery {
+            limit: 10,
+            ..EventQuery::default()
+        };
+        let events = state.db.query_events(&query).await.unwrap();
+        assert!(events.items.is_empty());
+        assert!(state.db.latest_open_alert().await.unwrap().is_none());
+        assert!(rx.try_recv().is_err());
+    }
+
+    // Queued samples reach every candidate, and their verdicts are logged once the queue drains
+    #[actix_web::test]
+    async fn test_run_shadows() {
+        let state = memory_state();
+        let (queue, samples) = mpsc::channel(SHADOW_QUEUE);
+        for sample in knock_samples() {
+            queue.send(sample).await.unwrap();
+        }
+        drop(queue);
+        let candidates: Vec<Box<dyn Detector>> = vec![
+            Box::new(candidate("sensitive", 1.3)),
+            Box::new(candidate("strict", 4.0)),
+        ];
+        run_shadows(state.db.clone(), candidates, source(), samples).await;
+        let logged = newest_detection(state.db.as_ref()).await;
+        assert_eq!(
+            (logged.detector.as_str(), logged.shadow),
+            ("sensitive", true)
+        );
+        let query = DetectionQuery {
+            shadow: Some(true),
+            limit: 10,
+            ..DetectionQuery::default()
+        };
+        assert_eq!(state.db.query_detections(&query).await.unwrap().len(), 1);
+    }
+
+    // Candidates that fall behind miss samples rather than holding up the connection
+    #[actix_web::test]
+    async fn test_shadow_feed_drops_when_full() {
+        let (queue, mut samples) = mpsc::channel(SHADOW_QUEUE);
+        let mut feed = ShadowFeed {
+            queue,
+            dropping: false,
+        };
+        let mut offered = knock_samples().into_iter().cycle();
+        for _ in 0..SHADOW_QUEUE {
+            let (sample, at) = offered.next().unwrap();
+            assert!(feed.offer(sample, at));
+        }
+        let (sample, at) = offered.next().unwrap();
+        assert!(!feed.offer(sample, at));
+        // Once the candidates catch up, samples flow again
+        samples.recv().await.unwrap();
+        let (sample, at) = offered.next().unwrap();
+        assert!(feed.offer(sample, at));
+    }
+}